@@ -0,0 +1,99 @@
+// Abbreviations expanded when a word boundary is typed, read from
+// settings/abbreviations.json: a table per file extension plus "*" for every
+// file, e.g. {"*": {"teh": "the", ";;d": "{date}"}, "md": {"tm": "™"}}
+
+use std::{collections::HashMap, sync::OnceLock, time::{SystemTime, UNIX_EPOCH}};
+
+use log::error;
+
+const ABBREVIATIONS: &str = "settings/abbreviations.json";
+
+type Table = HashMap<String, HashMap<String, String>>;
+
+static TABLE: OnceLock<Table> = OnceLock::new();
+
+fn table() -> &'static Table {
+    TABLE.get_or_init(|| {
+        let Ok(file) = std::fs::File::open(ABBREVIATIONS) else {
+            return Table::new();
+        };
+        serde_json::from_reader(file).unwrap_or_else(|e| {
+            error!("{}: {}", ABBREVIATIONS, e);
+            Table::new()
+        })
+    })
+}
+
+pub fn is_boundary(c: char) -> bool {
+    !(c.is_alphanumeric() || c == '_')
+}
+
+// Finds the abbreviation ending `before`, the text typed before the boundary.
+// The whole run since the last space is tried first so keys like `;;d` can
+// hold punctuation, then the trailing word. Returns its length in chars and
+// the expansion
+pub fn lookup(ext: &str, before: &str) -> Option<(usize, String)> {
+    find(table(), ext, before)
+}
+
+fn find(table: &Table, ext: &str, before: &str) -> Option<(usize, String)> {
+    // Byte just past the last char matching `f`, however long it is in UTF-8
+    let after = |s: &str, f: fn(char) -> bool| s.char_indices().rev().find(|(_, c)| f(*c)).map_or(0, |(i, c)| i + c.len_utf8());
+    let run = &before[after(before, char::is_whitespace)..];
+    let word = &run[after(run, is_boundary)..];
+    let get = |key: &str| {
+        [ext, "*"].iter()
+            .find_map(|t| table.get(*t).and_then(|t| t.get(key)))
+    };
+    [run, word].into_iter()
+        .filter(|k| !k.is_empty())
+        .find_map(|k| get(k).map(|v| (k.chars().count(), placeholders(v))))
+}
+
+// `{date}` and `{time}` in an expansion become the current UTC date and time
+pub fn placeholders(text: &str) -> String {
+    if !text.contains('{') {
+        return text.to_string();
+    }
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (y, m, d) = civil_date(secs / 86400);
+    let (hh, mm) = (secs / 3600 % 24, secs / 60 % 60);
+    text.replace("{date}", &format!("{:04}-{:02}-{:02}", y, m, d))
+        .replace("{time}", &format!("{:02}:{:02}", hh, mm))
+}
+
+// Days since 1970-01-01 to a year, month and day, after Howard Hinnant's
+// civil_from_days
+fn civil_date(days: u64) -> (u64, u64, u64) {
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + (m <= 2) as u64;
+    (y, m, d)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn finds_abbreviations() {
+        let table: Table = serde_json::from_str(r#"{"*": {"teh": "the", ";;d": "x"}, "md": {"teh": "THE"}}"#).unwrap();
+        assert_eq!(find(&table, "rs", "fix teh"), Some((3, "the".to_string())));
+        assert_eq!(find(&table, "md", "fix teh"), Some((3, "THE".to_string())));
+        assert_eq!(find(&table, "rs", "(teh"), Some((3, "the".to_string())));
+        assert_eq!(find(&table, "rs", "at ;;d"), Some((3, "x".to_string())));
+        assert_eq!(find(&table, "rs", "tehx"), None);
+        assert_eq!(find(&table, "rs", ""), None);
+        assert_eq!(find(&table, "rs", "\u{3000}teh"), Some((3, "the".to_string())));
+        assert_eq!(find(&table, "rs", "、teh"), Some((3, "the".to_string())));
+        assert_eq!(find(&table, "rs", "é、"), None);
+        assert_eq!(civil_date(0), (1970, 1, 1));
+        assert_eq!(civil_date(20742), (2026, 10, 16));
+    }
+}
@@ -1,22 +1,96 @@
 use std::{fs, path::{Path, PathBuf}};
 
-use crate::{Action, KeymapState};
+use crate::{announce, calc, crypt::{Cipher, Encryption}, job::JobTask, layout::Direction, lsp, popup::Popup, profiler, remote::{self, Remote}, search, tab::{git, results::{self, Location}, shell, Size}, tutor, Action, KeymapState};
 use anyhow::{Error, Result};
 pub enum ActionReturn {
     Continue,
-    Stop,
     Err(Error),
     Excute(Action),
     ExcuteLine(String),
     NewBuffer(Option<PathBuf>),
     NewDir(PathBuf),
-    NewShell,
+    // Directory tab of the file's parent, focused or opened, with the file selected
+    RevealFile(PathBuf),
+    NewTab(String, Vec<Option<String>>),
+    NewRemote(Remote, String),
+    NewEncrypted(PathBuf, Encryption, String),
+    // Focuses the scratch buffer of that name, creating it; the text replaces its contents
+    NewScratch(String, Option<String>),
+    // RunCommand output: the text goes at the end of the read-only scratch
+    // buffer of that name, which the first output of the run creates and
+    // focuses, and the title, if any, names its tab
+    CommandOutput(String, String, Option<String>, bool),
+    NewView,
+    SetWorkspace(PathBuf),
+    // Settings of a tab to keep in the project's .zutto.json
+    WriteSettings(serde_json::Map<String, serde_json::Value>),
     State(KeymapState),
     Notice(String),
+    // Match counts of the focused buffer's search, for the Find prompt
+    Matches(search::Counts),
     ChangeTab(isize),
-    CloseTab(usize),
+    MoveTab(isize),
+    GotoTab(usize),
+    // Opens a Diff of two tabs, the focused one when the first is None
+    DiffBuffers(Option<usize>, usize),
+    ClickTab(u16),
+    CheckDisk(Option<bool>),
+    // Picker of the bookmarks of every buffer, or jumping to the picked one
+    ListBookmarks(Option<usize>),
+    LastTab,
+    RenameTab(Option<String>),
+    Resize(Size),
+    // Reopens the tabs of the session a run that did not quit left, or drops it
+    RestoreSession(bool),
+    // Saves every buffer that Buffer::autosaves
+    Autosave,
+    // Splits the focused pane, showing another tab next to it
+    Split(Direction),
+    ChangePane(bool),
+    ClosePane,
+    // Closes the tab, minding its unsaved text as the answer says
+    CloseTab(usize, Unsaved),
+    // Stops the editor, minding unsaved buffers as the answer says
+    Quit(Unsaved),
+    CloseOthers(bool, bool),
+    Job(String, JobTask),
+    // A job that may write files open in buffers, which are read-only until it finishes
+    FileJob(String, JobTask),
+    CancelJob(Option<usize>),
+    Lsp(lsp::Request),
+    Popup(Option<Popup>),
+    OpenAt(Location),
+    NewResults(String, Vec<Location>),
+    JumpBack,
 } 
 
+// What to do with unsaved buffers that Quit or CloseTab would drop: ask
+// first, or what the prompt was answered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unsaved {
+    Ask,
+    Save,
+    Discard,
+}
+
+// The answer to the save / discard / cancel prompt, Ask without one and None
+// to cancel
+fn unsaved_answer(answer: Option<&Option<String>>) -> Option<Unsaved> {
+    match answer.cloned().flatten().map(|s| s.trim().to_lowercase()) {
+        None => Some(Unsaved::Ask),
+        Some(s) if s.starts_with('s') => Some(Unsaved::Save),
+        Some(s) if s.starts_with('d') => Some(Unsaved::Discard),
+        Some(_) => None,
+    }
+}
+
+fn cancelled() -> Vec<ActionReturn> {
+    vec![
+        ActionReturn::Notice("Cancelled".to_string()),
+        ActionReturn::State(KeymapState::Normal),
+    ]
+}
+
 pub fn normal_mode(_: &Action) -> Result<Vec<ActionReturn>> {
     Ok(vec![ActionReturn::State(KeymapState::Normal)])
 }
@@ -25,12 +99,36 @@ pub fn cmd_mode(_: &Action) -> Result<Vec<ActionReturn>> {
     Ok(vec![ActionReturn::State(KeymapState::Cmd)])
 }
 
-pub fn quit(_: &Action) -> Result<Vec<ActionReturn>> {
-    Ok(vec![ActionReturn::Stop])
+// The arguments are actions run first, in order, e.g. InsertMode(InsertNewlineBelow,CursorDown)
+pub fn insert_mode(action: &Action) -> Result<Vec<ActionReturn>> {
+    let mut rtn = vec![ActionReturn::State(KeymapState::Insert)];
+    for name in action.args.iter().flatten() {
+        rtn.push(ActionReturn::Excute(Action { name: name.trim().to_string(), args: vec![] }));
+    }
+    Ok(rtn)
+}
+
+// The optional argument answers the unsaved buffers prompt
+pub fn quit(action: &Action) -> Result<Vec<ActionReturn>> {
+    Ok(match unsaved_answer(action.args.first()) {
+        Some(answer) => vec![ActionReturn::State(KeymapState::Normal), ActionReturn::Quit(answer)],
+        None => cancelled(),
+    })
 }
 
 pub fn find_mode(_: &Action) -> Result<Vec<ActionReturn>> {
-    Ok(vec![ActionReturn::State(KeymapState::Find)])
+    Ok(vec![
+        ActionReturn::State(KeymapState::Find),
+        ActionReturn::Notice("Find: ".to_string()),
+    ])
+}
+
+// The buffer keeps the match for FindAccept or goes back for FindCancel
+pub fn find_exit(_: &Action) -> Result<Vec<ActionReturn>> {
+    Ok(vec![
+        ActionReturn::State(KeymapState::Normal),
+        ActionReturn::Notice(String::new()),
+    ])
 }
 
 pub fn line_mode(_: &Action) -> Result<Vec<ActionReturn>> {
@@ -45,6 +143,123 @@ pub fn prev_tab(_: &Action) -> Result<Vec<ActionReturn>> {
     Ok(vec![ActionReturn::ChangeTab(-1)])
 }
 
+pub fn last_tab(_: &Action) -> Result<Vec<ActionReturn>> {
+    Ok(vec![ActionReturn::LastTab])
+}
+
+// Sent by the event handler with the new terminal size
+pub fn resize(action: &Action) -> Result<Vec<ActionReturn>> {
+    let arg = |i: usize| action.args.get(i).cloned().flatten().ok_or(anyhow::anyhow!("Resize(width,height)"))?.parse::<u16>().map_err(Error::from);
+    Ok(vec![ActionReturn::Resize(Size { width: arg(0)?, height: arg(1)? })])
+}
+
+// RenameTab(name) titles the focused tab, RenameTab() gives it back its name
+pub fn rename_tab(action: &Action) -> Result<Vec<ActionReturn>> {
+    let name = action.args.iter().flatten().cloned().collect::<Vec<_>>().join(",");
+    let name = name.trim();
+    Ok(vec![ActionReturn::RenameTab((!name.is_empty()).then(|| name.to_string()))])
+}
+
+// Sent at startup when the last run left its session behind
+pub fn restore_session(action: &Action) -> Result<Vec<ActionReturn>> {
+    match action.args.first() {
+        Some(Some(s)) => Ok(vec![
+            ActionReturn::State(KeymapState::Normal),
+            ActionReturn::RestoreSession(s.trim().to_lowercase().starts_with('y')),
+        ]),
+        _ => Ok(vec![
+            ActionReturn::State(KeymapState::LineInsert),
+            ActionReturn::Notice("Restore previous session? (y/n): ".to_string()),
+            ActionReturn::ExcuteLine("RestoreSession($line)".to_string()),
+        ]),
+    }
+}
+
+// Sent by the autosave timer and when the terminal loses focus
+pub fn autosave(_: &Action) -> Result<Vec<ActionReturn>> {
+    Ok(vec![ActionReturn::Autosave])
+}
+
+// Sent when the terminal regains focus; the argument answers the reload prompt
+pub fn check_disk(action: &Action) -> Result<Vec<ActionReturn>> {
+    match action.args.first() {
+        Some(Some(s)) => Ok(vec![
+            ActionReturn::State(KeymapState::Normal),
+            ActionReturn::CheckDisk(Some(s.trim().to_lowercase().starts_with('y'))),
+        ]),
+        _ => Ok(vec![ActionReturn::CheckDisk(None)]),
+    }
+}
+
+pub fn click_tab(action: &Action) -> Result<Vec<ActionReturn>> {
+    let col = action.args.first().cloned().flatten().unwrap_or_default().parse::<u16>()?;
+    Ok(vec![ActionReturn::ClickTab(col)])
+}
+
+// Tabs are numbered from 1 as shown in the tab bar
+pub fn goto_tab(action: &Action) -> Result<Vec<ActionReturn>> {
+    match action.args.first() {
+        Some(Some(s)) => {
+            let n: usize = s.trim().parse().map_err(|_| anyhow::anyhow!("GotoTab: invalid tab number {}", s))?;
+            Ok(vec![ActionReturn::GotoTab(n.max(1) - 1)])
+        }
+        _ => Err(anyhow::anyhow!("GotoTab: missing tab number")),
+    }
+}
+
+// DiffBuffers(a,b) compares tabs a and b, DiffBuffers(b) the focused tab and b
+pub fn diff_buffers(action: &Action) -> Result<Vec<ActionReturn>> {
+    // The prompt's answer comes as one argument, "2,3"
+    let tabs = action.args.iter().flatten().cloned().collect::<Vec<_>>().join(",");
+    if tabs.trim().is_empty() {
+        return Ok(vec![
+            ActionReturn::State(KeymapState::LineInsert),
+            ActionReturn::Notice("Diff with tab (or tabs a,b): ".to_string()),
+            ActionReturn::ExcuteLine("DiffBuffers($line)".to_string()),
+        ]);
+    }
+    let numbers = tabs.split(',')
+        .map(|s| s.trim().parse::<usize>().ok().filter(|n| *n > 0).map(|n| n - 1)
+            .ok_or(anyhow::anyhow!("DiffBuffers: invalid tab number {}", s.trim())))
+        .collect::<Result<Vec<_>>>()?;
+    let diff = match numbers[..] {
+        [b] => ActionReturn::DiffBuffers(None, b),
+        [a, b] => ActionReturn::DiffBuffers(Some(a), b),
+        _ => return Err(anyhow::anyhow!("DiffBuffers takes one or two tab numbers")),
+    };
+    Ok(vec![ActionReturn::State(KeymapState::Normal), diff])
+}
+
+// Split(right) puts another tab beside the focused one, Split(down) below it
+pub fn split(action: &Action) -> Result<Vec<ActionReturn>> {
+    match action.args.first().cloned().flatten().as_deref() {
+        Some("down") => Ok(vec![ActionReturn::Split(Direction::Vertical)]),
+        Some("right") | None => Ok(vec![ActionReturn::Split(Direction::Horizontal)]),
+        Some(s) => Err(anyhow::anyhow!("Split: {} is not right or down", s)),
+    }
+}
+
+pub fn next_pane(_: &Action) -> Result<Vec<ActionReturn>> {
+    Ok(vec![ActionReturn::ChangePane(true)])
+}
+
+pub fn prev_pane(_: &Action) -> Result<Vec<ActionReturn>> {
+    Ok(vec![ActionReturn::ChangePane(false)])
+}
+
+// The tab stays open, only its pane goes
+pub fn close_pane(_: &Action) -> Result<Vec<ActionReturn>> {
+    Ok(vec![ActionReturn::ClosePane])
+}
+
+pub fn move_tab_left(_: &Action) -> Result<Vec<ActionReturn>> {
+    Ok(vec![ActionReturn::MoveTab(-1)])
+}
+
+pub fn move_tab_right(_: &Action) -> Result<Vec<ActionReturn>> {
+    Ok(vec![ActionReturn::MoveTab(1)])
+}
+
 pub fn open(action: &Action) -> Result<Vec<ActionReturn>> {
     if action.args[0].is_none() {
         return Ok(vec![
@@ -53,7 +268,24 @@ pub fn open(action: &Action) -> Result<Vec<ActionReturn>> {
             ActionReturn::ExcuteLine("Open($line)".to_string()),
         ]);
     } else {
-        let path = Path::new(action.args[0].as_ref().unwrap());
+        // Pasted lines may have commas, which split the arguments
+        let arg = &action.args.iter().flatten().cloned().collect::<Vec<_>>().join(",");
+        if arg.starts_with(remote::SCHEME) {
+            return open_remote(arg);
+        }
+        let path = Path::new(arg);
+        if !path.exists() {
+            if let Some(location) = Location::find(arg).filter(|l| l.path.is_file()) {
+                return Ok(vec![
+                    ActionReturn::OpenAt(location),
+                    ActionReturn::State(KeymapState::Normal),
+                ]);
+            }
+        }
+        // NewBuffer asks for the passphrase, so the state stays LineInsert
+        if path.is_file() && Cipher::detect(path).is_some() {
+            return Ok(vec![ActionReturn::NewBuffer(Some(path.to_path_buf()))]);
+        }
         if path.is_file() {
             return Ok(vec![
                 ActionReturn::Notice(format!("Opened {}", path.display())),
@@ -62,8 +294,8 @@ pub fn open(action: &Action) -> Result<Vec<ActionReturn>> {
             ]);
         } else if path.is_dir() {
             return Ok(vec![
-                ActionReturn::Notice(format!("Opened {}", path.display())),
                 ActionReturn::NewDir(path.to_path_buf()),
+                ActionReturn::SetWorkspace(path.to_path_buf()),
                 ActionReturn::State(KeymapState::Normal),
             ]);
         } else {
@@ -75,11 +307,339 @@ pub fn open(action: &Action) -> Result<Vec<ActionReturn>> {
     }
 }
 
+// Fetches the file in the background; OpenRemote then creates the buffer
+fn open_remote(url: &str) -> Result<Vec<ActionReturn>> {
+    let remote = Remote::parse(url)?;
+    Ok(vec![
+        ActionReturn::State(KeymapState::Normal),
+        ActionReturn::Job(format!("open {}", remote.url()), Box::new(move |_| Box::pin(async move {
+            let text = remote.read().await?;
+            Ok(vec![Action {
+                name: "OpenRemote".to_string(),
+                args: vec![Some(remote.url()), Some(text)],
+            }])
+        }))),
+    ])
+}
+
+pub fn new_remote(action: &Action) -> Result<Vec<ActionReturn>> {
+    let url = action.args.first().cloned().flatten().unwrap_or_default();
+    let text = action.args.get(1).cloned().flatten().unwrap_or_default();
+    Ok(vec![
+        ActionReturn::NewRemote(Remote::parse(&url)?, text),
+        ActionReturn::Notice(format!("Opened {}", url)),
+    ])
+}
+
+// Decrypts in the background; OpenDecrypted then creates the buffer
+pub fn open_encrypted(action: &Action) -> Result<Vec<ActionReturn>> {
+    // The passphrase is the last arg; parse_action splits a path with commas
+    // into the ones before it, which are joined back
+    let (path, secret) = match action.args.split_last() {
+        Some((secret, path)) if !path.is_empty() => (path.iter().map(|a| a.clone().unwrap_or_default()).collect::<Vec<_>>().join(","), secret.clone()),
+        _ => (action.args.first().cloned().flatten().unwrap_or_default(), None),
+    };
+    let path = PathBuf::from(path);
+    let cipher = Cipher::detect(&path).ok_or(anyhow::anyhow!("{} is not an encrypted file", path.display()))?;
+    let Some(secret) = secret else {
+        return Ok(vec![
+            ActionReturn::Notice("Cancelled".to_string()),
+            ActionReturn::State(KeymapState::Normal),
+        ]);
+    };
+    Ok(vec![
+        ActionReturn::State(KeymapState::Normal),
+        ActionReturn::Job(format!("decrypt {}", path.display()), Box::new(move |_| Box::pin(async move {
+            let text = cipher.decrypt(&path, &secret).await?;
+            Ok(vec![Action {
+                name: "OpenDecrypted".to_string(),
+                args: vec![Some(path.to_string_lossy().to_string()), Some(secret), Some(text)],
+            }])
+        }))),
+    ])
+}
+
+pub fn new_encrypted(action: &Action) -> Result<Vec<ActionReturn>> {
+    let path = PathBuf::from(action.args.first().cloned().flatten().unwrap_or_default());
+    let cipher = Cipher::detect(&path).ok_or(anyhow::anyhow!("{} is not an encrypted file", path.display()))?;
+    let secret = action.args.get(1).cloned().flatten().unwrap_or_default();
+    let text = action.args.get(2).cloned().flatten().unwrap_or_default();
+    Ok(vec![
+        ActionReturn::Notice(format!("Opened {}", path.display())),
+        ActionReturn::NewEncrypted(path, Encryption { cipher, secret }, text),
+    ])
+}
+
+// CloseTab(idx) or, answering the unsaved buffer prompt, CloseTab(idx,answer)
 pub fn close_tab(action: &Action) -> Result<Vec<ActionReturn>> {
     let tab_idx = action.args[0].as_ref().unwrap().parse::<usize>().unwrap();
-    Ok(vec![ActionReturn::CloseTab(tab_idx)])
+    Ok(match unsaved_answer(action.args.get(1)) {
+        Some(answer) => vec![ActionReturn::State(KeymapState::Normal), ActionReturn::CloseTab(tab_idx, answer)],
+        None => cancelled(),
+    })
+}
+
+// The optional argument answers the unsaved buffers prompt
+fn close_others(action: &Action, right_only: bool) -> Result<Vec<ActionReturn>> {
+    match action.args.first() {
+        Some(Some(s)) if !s.trim().to_lowercase().starts_with('y') => Ok(cancelled()),
+        Some(Some(_)) => Ok(vec![
+            ActionReturn::State(KeymapState::Normal),
+            ActionReturn::CloseOthers(right_only, true),
+        ]),
+        _ => Ok(vec![ActionReturn::CloseOthers(right_only, false)]),
+    }
+}
+
+pub fn close_other_tabs(action: &Action) -> Result<Vec<ActionReturn>> {
+    close_others(action, false)
+}
+
+pub fn close_tabs_right(action: &Action) -> Result<Vec<ActionReturn>> {
+    close_others(action, true)
+}
+
+pub fn new_view(_: &Action) -> Result<Vec<ActionReturn>> {
+    Ok(vec![ActionReturn::NewView])
+}
+
+// Opens a tab of a kind registered in `tab::Registry`
+pub fn new_tab(action: &Action) -> Result<Vec<ActionReturn>> {
+    match action.args.first() {
+        Some(Some(kind)) => Ok(vec![ActionReturn::NewTab(kind.clone(), action.args[1..].to_vec())]),
+        _ => Err(anyhow::anyhow!("NewTab: missing tab kind")),
+    }
 }
 
 pub fn new_shell(_: &Action) -> Result<Vec<ActionReturn>> {
-    Ok(vec![ActionReturn::NewShell])
+    Ok(vec![ActionReturn::NewTab("shell".to_string(), vec![])])
+}
+
+pub fn help(action: &Action) -> Result<Vec<ActionReturn>> {
+    let topic = action.args.first().cloned().flatten().unwrap_or_default();
+    Ok(vec![
+        ActionReturn::NewTab("help".to_string(), vec![Some(topic)]),
+        ActionReturn::State(KeymapState::Normal),
+    ])
+}
+
+// Notices and alerts so far, on a help page
+pub fn messages(_: &Action) -> Result<Vec<ActionReturn>> {
+    Ok(vec![
+        ActionReturn::NewTab("help".to_string(), vec![Some("messages".to_string())]),
+        ActionReturn::State(KeymapState::Normal),
+    ])
+}
+
+pub fn tutor_progress(action: &Action) -> Result<Vec<ActionReturn>> {
+    let done = action.args.first().cloned().flatten().unwrap_or_default().parse::<usize>()?;
+    Ok(vec![ActionReturn::Notice(tutor::progress(done))])
+}
+
+pub fn toggle_profiler(_: &Action) -> Result<Vec<ActionReturn>> {
+    let notice = if profiler::toggle() { "Profiler on" } else { "Profiler off" };
+    Ok(vec![ActionReturn::Notice(notice.to_string())])
+}
+
+pub fn toggle_screen_reader(_: &Action) -> Result<Vec<ActionReturn>> {
+    let notice = if announce::toggle() { "Screen reader mode on" } else { "Screen reader mode off" };
+    Ok(vec![
+        ActionReturn::Notice(notice.to_string()),
+        ActionReturn::State(KeymapState::Normal),
+    ])
+}
+
+// parse_action splits on commas, so the expression is joined back
+fn calc_expr(action: &Action) -> Result<(String, String)> {
+    let expr = action.args.iter().flatten().cloned().collect::<Vec<_>>().join(",");
+    let value = calc::format(calc::eval(&expr)?);
+    Ok((expr, value))
+}
+
+pub fn calc(action: &Action) -> Result<Vec<ActionReturn>> {
+    let (expr, value) = calc_expr(action)?;
+    Ok(vec![
+        ActionReturn::Notice(format!("{} = {}", expr.trim(), value)),
+        ActionReturn::State(KeymapState::Normal),
+    ])
+}
+
+pub fn calc_insert(action: &Action) -> Result<Vec<ActionReturn>> {
+    let (_, value) = calc_expr(action)?;
+    Ok(vec![
+        ActionReturn::Excute(Action { name: "InsertStr".to_string(), args: vec![Some(value)] }),
+        ActionReturn::State(KeymapState::Normal),
+    ])
+}
+
+pub fn cancel_job(action: &Action) -> Result<Vec<ActionReturn>> {
+    let id = match action.args.first() {
+        Some(Some(s)) => Some(s.trim().parse::<usize>()?),
+        _ => None,
+    };
+    Ok(vec![ActionReturn::CancelJob(id)])
+}
+
+pub fn show_popup(action: &Action) -> Result<Vec<ActionReturn>> {
+    let title = action.args.first().cloned().flatten().unwrap_or_default();
+    let text = action.args.get(1).cloned().flatten().unwrap_or_default();
+    Ok(vec![ActionReturn::Popup(Some(Popup::new(&title, &text)))])
+}
+
+pub fn open_at(action: &Action) -> Result<Vec<ActionReturn>> {
+    match action.args.first() {
+        Some(Some(s)) => Ok(vec![ActionReturn::OpenAt(Location::parse(s)?)]),
+        _ => Err(anyhow::anyhow!("OpenAt: missing location")),
+    }
+}
+
+pub fn show_results(action: &Action) -> Result<Vec<ActionReturn>> {
+    let title = action.args.first().cloned().flatten().unwrap_or_default();
+    let items = action.args.iter().skip(1).flatten()
+        .map(|s| Location::parse(s))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(vec![ActionReturn::NewResults(title, items)])
+}
+
+pub fn jump_back(_: &Action) -> Result<Vec<ActionReturn>> {
+    Ok(vec![ActionReturn::JumpBack])
+}
+
+// Wraps an action so process_action dispatches it to the buffer with `id`
+// instead of the focused tab; used for results of background jobs
+pub fn targeted(id: usize, action: Action) -> Action {
+    let mut args = vec![Some(id.to_string()), Some(action.name)];
+    args.extend(action.args);
+    Action {
+        name: "OnBuffer".to_string(),
+        args,
+    }
+}
+
+pub fn untarget(action: Action) -> (Action, Option<usize>) {
+    if action.name != "OnBuffer" || action.args.len() < 2 {
+        return (action, None);
+    }
+    let mut args = action.args.into_iter();
+    let id = args.next().flatten().and_then(|s| s.parse::<usize>().ok());
+    let name = args.next().flatten().unwrap_or_default();
+    (Action { name, args: args.collect() }, id)
+}
+
+// Wraps an action typed after a line range, e.g. `10,20 SortLines`, as
+// Range(10,20,SortLines); the buffer selects the lines before running it
+pub fn ranged(start: String, end: String, action: Action) -> Action {
+    let mut args = vec![Some(start), Some(end), Some(action.name)];
+    args.extend(action.args);
+    Action {
+        name: "Range".to_string(),
+        args,
+    }
+}
+
+pub fn unrange(action: &Action) -> Option<(Action, String, String)> {
+    if action.name != "Range" || action.args.len() < 3 {
+        return None;
+    }
+    let mut args = action.args.iter().cloned();
+    let start = args.next().flatten()?;
+    let end = args.next().flatten()?;
+    let name = args.next().flatten()?;
+    Some((Action { name, args: args.collect() }, start, end))
+}
+
+pub fn build(action: &Action, default: &str) -> Result<Vec<ActionReturn>> {
+    let cmd = match action.args.first() {
+        Some(Some(cmd)) => cmd.clone(),
+        _ => default.to_string(),
+    };
+    if cmd.is_empty() {
+        return Err(anyhow::anyhow!("No build command configured"));
+    }
+    Ok(vec![ActionReturn::FileJob(format!("build: {}", cmd), Box::new(move |reporter| Box::pin(async move {
+        reporter.progress(0.0, "running");
+        let output = shell::run_command(&cmd, None).await?;
+        let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+        text.push_str(&String::from_utf8_lossy(&output.stderr));
+        let locations = results::parse_compiler_output(&text);
+        if locations.is_empty() {
+            if output.status.success() {
+                return Ok(vec![]);
+            }
+            // Nothing to jump to, so the whole output is shown instead
+            return Ok(vec![scratch_output("build", format!("$ {}\n{}\n{}\n", cmd, text.trim_end(), output.status))]);
+        }
+        Ok(vec![lsp::results_action("Build", &locations), lsp::open_action(&locations[0])])
+    })))])
+}
+
+// NewScratch(name) or, as an output target, NewScratch(name,text)
+pub fn new_scratch(action: &Action) -> Result<Vec<ActionReturn>> {
+    let name = action.args.first().cloned().flatten().unwrap_or_else(|| "scratch".to_string());
+    let text = action.args.get(1).cloned().flatten();
+    Ok(vec![
+        ActionReturn::NewScratch(name, text),
+        ActionReturn::State(KeymapState::Normal),
+    ])
+}
+
+// Output of a job shown in the scratch buffer `name`
+pub fn scratch_output(name: &str, text: String) -> Action {
+    Action {
+        name: "NewScratch".to_string(),
+        args: vec![Some(name.to_string()), Some(text)],
+    }
+}
+
+// RunCommand(cmd) runs cmd once in a job, without a terminal, and streams
+// what it prints into a read-only scratch tab; the title ends with the exit
+// status once it finishes
+pub fn run_command(action: &Action) -> Result<Vec<ActionReturn>> {
+    // parse_action splits on commas, so the command is joined back
+    let cmd = action.args.iter().flatten().cloned().collect::<Vec<_>>().join(",");
+    if cmd.trim().is_empty() {
+        return Ok(vec![
+            ActionReturn::State(KeymapState::LineInsert),
+            ActionReturn::Notice("Run: ".to_string()),
+            ActionReturn::ExcuteLine("RunCommand($line)".to_string()),
+        ]);
+    }
+    Ok(vec![
+        ActionReturn::State(KeymapState::Normal),
+        ActionReturn::Job(format!("run: {}", cmd), Box::new(move |reporter| Box::pin(async move {
+            // One tab per run, so output of an earlier run stays
+            let name = format!("run {}", reporter.id());
+            // Only the first output opens the tab; once it is closed the rest is dropped
+            let output = |text: String, title: Option<String>, open: bool| Action {
+                name: "CommandOutput".to_string(),
+                args: vec![Some(name.clone()), Some(text), title, open.then(|| "open".to_string())],
+            };
+            reporter.run(vec![output(format!("$ {}\n", cmd), Some(format!("$ {} (running)", cmd)), true)]);
+            let status = shell::stream_command(&cmd, |text| reporter.run(vec![output(text, None, false)])).await?;
+            let status = match status.code() {
+                Some(code) => format!("exit {}", code),
+                None => "killed".to_string(),
+            };
+            Ok(vec![output(String::new(), Some(format!("$ {} ({})", cmd, status)), false)])
+        })))
+    ])
+}
+
+pub fn command_output(action: &Action) -> Result<Vec<ActionReturn>> {
+    let arg = |i: usize| action.args.get(i).cloned().flatten();
+    let open = arg(3).as_deref() == Some("open");
+    Ok(vec![ActionReturn::CommandOutput(arg(0).unwrap_or_default(), arg(1).unwrap_or_default(), arg(2), open)])
+}
+
+pub fn bookmark_list(action: &Action) -> Result<Vec<ActionReturn>> {
+    let picked = action.args.first().cloned().flatten().map(|i| i.parse::<usize>()).transpose()?;
+    Ok(vec![ActionReturn::ListBookmarks(picked)])
+}
+
+pub fn git_status(_: &Action) -> Result<Vec<ActionReturn>> {
+    Ok(vec![ActionReturn::NewTab("git".to_string(), vec![])])
+}
+
+pub fn git_commit(_: &Action) -> Result<Vec<ActionReturn>> {
+    Ok(vec![ActionReturn::Job("git commit".to_string(), Box::new(|_| Box::pin(git::commit())))])
 }
\ No newline at end of file
@@ -0,0 +1,125 @@
+// Screen reader output: what changed on screen is appended as plain lines to
+// `announce_path`, which a screen reader or speech synthesizer can follow
+// instead of the redrawn terminal
+
+use std::{fs::{File, OpenOptions}, io::Write, sync::atomic::{AtomicBool, Ordering}};
+
+use log::error;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn set_enabled(on: bool) {
+    ENABLED.store(on, Ordering::Relaxed);
+}
+
+pub fn toggle() -> bool {
+    !ENABLED.fetch_xor(true, Ordering::Relaxed)
+}
+
+// The parts of a frame that are announced when they change
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    pub mode: String,
+    pub tab: String,
+    // Line number and text under the cursor
+    pub line: Option<(usize, String)>,
+    pub selected: usize,
+    pub notice: String,
+}
+
+// Only line moves are announced, not edits within the line: typed characters
+// are already echoed by the screen reader
+pub fn changes(old: &Snapshot, new: &Snapshot) -> Vec<String> {
+    let mut out = Vec::new();
+    if old.tab != new.tab {
+        out.push(format!("Tab {}", new.tab));
+    }
+    if old.mode != new.mode {
+        out.push(format!("{} mode", new.mode));
+    }
+    let row = |s: &Snapshot| s.line.as_ref().map(|(n, _)| *n);
+    if old.tab != new.tab || row(old) != row(new) {
+        if let Some((n, text)) = &new.line {
+            let text = if text.trim().is_empty() { "blank" } else { text.trim_end() };
+            out.push(format!("Line {}: {}", n + 1, text));
+        }
+    }
+    if old.selected != new.selected {
+        out.push(match new.selected {
+            0 => "Selection cleared".to_string(),
+            n => format!("{} selected", n),
+        });
+    }
+    if old.notice != new.notice && !new.notice.is_empty() {
+        out.push(new.notice.clone());
+    }
+    out
+}
+
+#[derive(Debug)]
+pub struct Announcer {
+    path: String,
+    file: Option<File>,
+    last: Snapshot,
+}
+
+impl Announcer {
+    pub fn new(path: String) -> Self {
+        Self {
+            path,
+            file: None,
+            last: Snapshot::default(),
+        }
+    }
+
+    pub fn update(&mut self, snapshot: Snapshot) {
+        for line in changes(&self.last, &snapshot) {
+            self.say(&line);
+        }
+        self.last = snapshot;
+    }
+
+    pub fn say(&mut self, text: &str) {
+        if self.file.is_none() {
+            match OpenOptions::new().create(true).append(true).open(&self.path) {
+                Ok(f) => self.file = Some(f),
+                Err(e) => {
+                    error!("{}: {}", self.path, e);
+                    set_enabled(false);
+                    return;
+                }
+            }
+        }
+        let file = self.file.as_mut().unwrap();
+        if let Err(e) = writeln!(file, "{}", text).and_then(|_| file.flush()) {
+            error!("{}: {}", self.path, e);
+            self.file = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn announces_changes() {
+        let old = Snapshot {
+            mode: "Normal".to_string(),
+            tab: "1 main.rs".to_string(),
+            line: Some((0, "fn main() {".to_string())),
+            ..Default::default()
+        };
+        assert!(changes(&old, &old).is_empty());
+        let edited = Snapshot { line: Some((0, "fn main() {}".to_string())), ..old.clone() };
+        assert!(changes(&old, &edited).is_empty());
+        let moved = Snapshot { line: Some((1, "    ".to_string())), mode: "Cmd".to_string(), selected: 3, ..old.clone() };
+        assert_eq!(changes(&old, &moved), ["Cmd mode", "Line 2: blank", "3 selected"]);
+        let noticed = Snapshot { notice: "Saved".to_string(), ..old.clone() };
+        assert_eq!(changes(&old, &noticed), ["Saved"]);
+    }
+}
@@ -0,0 +1,53 @@
+// Bookmarked lines follow the text: lines inserted or removed above a
+// bookmark move it, and a removed line takes its bookmark with it
+use std::collections::BTreeSet;
+
+// `added` line breaks went into `line`; from its start, the line itself moves too
+pub fn inserted(marks: &mut BTreeSet<usize>, line: usize, at_start: bool, added: usize) {
+    if added == 0 {
+        return;
+    }
+    let first = if at_start { line } else { line + 1 };
+    let moved: Vec<usize> = marks.range(first..).copied().collect();
+    for l in moved.iter().rev() {
+        marks.remove(l);
+        marks.insert(l + added);
+    }
+}
+
+// The text from `first` to `last` was removed; what is left of `last` joins
+// `first`, which keeps its bookmark unless it went from its start
+pub fn removed(marks: &mut BTreeSet<usize>, first: usize, last: usize, at_start: bool) {
+    if first == last {
+        return;
+    }
+    let gone = if at_start { first..last } else { first + 1..last + 1 };
+    marks.retain(|l| !gone.contains(l));
+    let moved: Vec<usize> = marks.range(last..).copied().collect();
+    for l in moved {
+        marks.remove(&l);
+        marks.insert(l - (last - first));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn follows_lines() {
+        let mut marks = BTreeSet::from([1, 3, 5]);
+        inserted(&mut marks, 3, false, 2);
+        assert_eq!(marks, BTreeSet::from([1, 3, 7]));
+        inserted(&mut marks, 3, true, 1);
+        assert_eq!(marks, BTreeSet::from([1, 4, 8]));
+        inserted(&mut marks, 0, false, 0);
+        assert_eq!(marks, BTreeSet::from([1, 4, 8]));
+        removed(&mut marks, 1, 4, true);
+        assert_eq!(marks, BTreeSet::from([1, 5]));
+        removed(&mut marks, 1, 2, false);
+        assert_eq!(marks, BTreeSet::from([1, 4]));
+        removed(&mut marks, 0, 1, false);
+        assert_eq!(marks, BTreeSet::from([3]));
+    }
+}
@@ -0,0 +1,94 @@
+// Matching brackets: the pair of the `()[]{}` at the cursor is highlighted and
+// GotoMatch jumps between them. Brackets inside strings and comments, going by
+// the syntax scopes of their line, are skipped
+
+use ropey::Rope;
+use syntect::parsing::{ParseState, Scope, ScopeStack, SyntaxReference, SyntaxSet};
+
+const PAIRS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+// Lines looked through for a match before giving up
+const MAX_LINES: usize = 5000;
+
+// Whether each char of a line is code rather than part of a string or comment.
+// Like highlighting, every line is parsed on its own
+pub fn code_chars(line: &str, syntax: &SyntaxReference, syntax_set: &SyntaxSet) -> Vec<bool> {
+    let ops = ParseState::new(syntax).parse_line(line, syntax_set).unwrap_or_default();
+    let (string, comment) = (Scope::new("string").unwrap(), Scope::new("comment").unwrap());
+    let mut stack = ScopeStack::new();
+    let mut ops = ops.into_iter().peekable();
+    line.char_indices().map(|(byte, _)| {
+        while let Some((_, op)) = ops.next_if(|(at, _)| *at <= byte) {
+            let _ = stack.apply(&op);
+        }
+        !stack.as_slice().iter().any(|s| string.is_prefix_of(*s) || comment.is_prefix_of(*s))
+    }).collect()
+}
+
+// The bracket at `idx`, or else the one right before it, and its match.
+// `code` gives code_chars of a line
+pub fn find(text: &Rope, idx: usize, code: &dyn Fn(usize) -> Vec<bool>) -> Option<(usize, usize)> {
+    let len = text.len_chars();
+    let is_code = |i: usize| {
+        let line = text.char_to_line(i);
+        code(line).get(i - text.line_to_char(line)).copied().unwrap_or(true)
+    };
+    let at = [Some(idx), idx.checked_sub(1)].into_iter().flatten()
+        .find(|i| *i < len && PAIRS.iter().any(|(o, c)| [*o, *c].contains(&text.char(*i))) && is_code(*i))?;
+    let c = text.char(at);
+    let (open, close) = *PAIRS.iter().find(|(o, cl)| *o == c || *cl == c)?;
+    let forward = c == open;
+    let first = text.char_to_line(at);
+    let lines: Box<dyn Iterator<Item = usize>> = match forward {
+        true => Box::new(first..text.len_lines()),
+        false => Box::new((0..=first).rev()),
+    };
+    // Brackets of the same kind opened, going away from `at`, and not closed yet
+    let mut depth = 0;
+    for line in lines.take(MAX_LINES) {
+        let start = text.line_to_char(line);
+        let chars: Vec<char> = text.line(line).chars().collect();
+        let mask = code(line);
+        let cols: Box<dyn Iterator<Item = usize>> = match forward {
+            true => Box::new(0..chars.len()),
+            false => Box::new((0..chars.len()).rev()),
+        };
+        for col in cols {
+            let i = start + col;
+            if (forward && i <= at) || (!forward && i >= at) || !mask.get(col).copied().unwrap_or(true) {
+                continue;
+            }
+            match chars[col] {
+                c if c == if forward { open } else { close } => depth += 1,
+                c if c == open || c == close => {
+                    if depth == 0 {
+                        return Some((at, i));
+                    }
+                    depth -= 1;
+                }
+                _ => (),
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn finds_match_outside_strings() {
+        let syntax_set = crate::syntax::syntax_set();
+        let syntax = syntax_set.find_syntax_by_extension("rs").unwrap();
+        let text = Rope::from_str("fn f(a: [u8; 2]) {\n    g(\")\"); // )\n}\n");
+        let code = |line: usize| code_chars(&text.line(line).to_string(), syntax, syntax_set);
+        assert_eq!((code(1)[5], code(1)[7], code(1)[15]), (true, false, false));
+        assert_eq!(find(&text, 4, &code), Some((4, 15)));
+        assert_eq!(find(&text, 16, &code), Some((15, 4)));
+        assert_eq!(find(&text, 17, &code), Some((17, 36)));
+        assert_eq!(find(&text, 36, &code), Some((36, 17)));
+        assert_eq!(find(&text, 24, &code), Some((24, 28)));
+        assert_eq!(find(&text, 35, &code), None);
+        assert_eq!(find(&text, 0, &code), None);
+    }
+}
@@ -0,0 +1,127 @@
+// Arithmetic for the `= expr` command line shorthand: numbers (decimal or 0x
+// hex), + - * / % ^, unary minus and parentheses
+
+use anyhow::Result;
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl Parser<'_> {
+    fn peek(&mut self) -> Option<char> {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+        self.chars.peek().copied()
+    }
+
+    fn expr(&mut self) -> Result<f64> {
+        let mut value = self.term()?;
+        while let Some(op @ ('+' | '-')) = self.peek() {
+            self.chars.next();
+            let rhs = self.term()?;
+            value = if op == '+' { value + rhs } else { value - rhs };
+        }
+        Ok(value)
+    }
+
+    fn term(&mut self) -> Result<f64> {
+        let mut value = self.power()?;
+        while let Some(op @ ('*' | '/' | '%')) = self.peek() {
+            self.chars.next();
+            let rhs = self.power()?;
+            value = match op {
+                '*' => value * rhs,
+                _ if rhs == 0.0 => return Err(anyhow::anyhow!("Division by zero")),
+                '/' => value / rhs,
+                _ => value % rhs,
+            };
+        }
+        Ok(value)
+    }
+
+    // Right associative, so 2^3^2 is 2^9
+    fn power(&mut self) -> Result<f64> {
+        let base = self.unary()?;
+        if self.peek() == Some('^') {
+            self.chars.next();
+            return Ok(base.powf(self.power()?));
+        }
+        Ok(base)
+    }
+
+    fn unary(&mut self) -> Result<f64> {
+        match self.peek() {
+            Some('-') => {
+                self.chars.next();
+                Ok(-self.unary()?)
+            }
+            Some('+') => {
+                self.chars.next();
+                self.unary()
+            }
+            _ => self.atom(),
+        }
+    }
+
+    fn atom(&mut self) -> Result<f64> {
+        match self.peek() {
+            Some('(') => {
+                self.chars.next();
+                let value = self.expr()?;
+                if self.peek() != Some(')') {
+                    return Err(anyhow::anyhow!("Missing ')'"));
+                }
+                self.chars.next();
+                Ok(value)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let mut number = String::new();
+                while let Some(c) = self.chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '.' || *c == '_') {
+                    if c != '_' {
+                        number.push(c);
+                    }
+                }
+                match number.strip_prefix("0x") {
+                    Some(hex) => Ok(i64::from_str_radix(hex, 16)? as f64),
+                    None => number.parse::<f64>().map_err(|_| anyhow::anyhow!("Invalid number {}", number)),
+                }
+            }
+            Some(c) => Err(anyhow::anyhow!("Unexpected '{}'", c)),
+            None => Err(anyhow::anyhow!("Unexpected end of expression")),
+        }
+    }
+}
+
+pub fn eval(s: &str) -> Result<f64> {
+    let mut parser = Parser { chars: s.chars().peekable() };
+    let value = parser.expr()?;
+    if let Some(c) = parser.peek() {
+        return Err(anyhow::anyhow!("Unexpected '{}'", c));
+    }
+    Ok(value)
+}
+
+// Whole numbers are shown without a fraction
+pub fn format(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        format!("{}", value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn evaluates() {
+        assert_eq!(eval("17*32+5").unwrap(), 549.0);
+        assert_eq!(eval("-(1 + 2) * 3").unwrap(), -9.0);
+        assert_eq!(eval("2^3^2").unwrap(), 512.0);
+        assert_eq!(eval("0x10 % 5").unwrap(), 1.0);
+        assert_eq!(format(eval("7 / 2").unwrap()), "3.5");
+        assert!(eval("1 / 0").is_err());
+        assert!(eval("(1 + 2").is_err());
+        assert!(eval("1 2").is_err());
+    }
+}
@@ -0,0 +1,85 @@
+// Copied and cut text, newest first, shared by every buffer, the line input
+// and the shell prompt; Paste takes the newest entry and PasteFromHistory
+// picks an older one
+
+use std::{collections::VecDeque, sync::Mutex};
+
+const RING_SIZE: usize = 30;
+
+static RING: Mutex<Ring> = Mutex::new(Ring::new());
+
+#[derive(Debug)]
+struct Ring {
+    items: VecDeque<String>,
+}
+
+impl Ring {
+    const fn new() -> Self {
+        Self { items: VecDeque::new() }
+    }
+
+    // Copying the same text again moves it to the front instead of repeating it
+    fn push(&mut self, text: String) {
+        if text.is_empty() {
+            return;
+        }
+        self.items.retain(|t| *t != text);
+        self.items.push_front(text);
+        self.items.truncate(RING_SIZE);
+    }
+
+    fn take(&mut self, i: usize) -> Option<String> {
+        let text = self.items.remove(i)?;
+        self.items.push_front(text.clone());
+        Some(text)
+    }
+}
+
+pub fn push(text: String) {
+    RING.lock().unwrap().push(text);
+}
+
+pub fn latest() -> Option<String> {
+    RING.lock().unwrap().items.front().cloned()
+}
+
+// Entry `i` of `entries`, which becomes the latest
+pub fn take(i: usize) -> Option<String> {
+    RING.lock().unwrap().take(i)
+}
+
+pub fn entries() -> Vec<String> {
+    RING.lock().unwrap().items.iter().cloned().collect()
+}
+
+// One line summary of an entry for the history picker
+pub fn preview(text: &str, width: usize) -> String {
+    let lines = text.lines().count();
+    let first = text.lines().find(|l| !l.trim().is_empty()).unwrap_or("").trim();
+    let first: String = first.chars().take(width).collect();
+    if lines > 1 {
+        format!("{} (+{} lines)", first, lines - 1)
+    } else {
+        first
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ring_keeps_newest_first() {
+        let mut ring = Ring::new();
+        for i in 0..RING_SIZE + 5 {
+            ring.push(i.to_string());
+        }
+        assert_eq!(ring.items.len(), RING_SIZE);
+        assert_eq!(ring.items[0], (RING_SIZE + 4).to_string());
+        ring.push("7".to_string());
+        assert_eq!(ring.items.iter().filter(|t| *t == "7").count(), 1);
+        assert_eq!(ring.take(3).unwrap(), ring.items[0]);
+        assert!(ring.take(RING_SIZE).is_none());
+        assert_eq!(preview("\n  fn main() {\n}\n", 40), "fn main() { (+2 lines)");
+    }
+}
@@ -0,0 +1,134 @@
+// Encrypted files (`.gpg`/`.asc` and `.age`) decrypted through the system gpg
+// or age into memory only; the plaintext is never written to disk
+
+use std::{path::{Path, PathBuf}, process::Stdio};
+
+use anyhow::Result;
+use tokio::{io::AsyncWriteExt, process::Command};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cipher {
+    Gpg,
+    Age,
+}
+
+// What was used to open the file, kept so saving can encrypt it again
+#[derive(Clone)]
+pub struct Encryption {
+    pub cipher: Cipher,
+    pub secret: String,
+}
+
+// Debug output must not show the passphrase
+impl std::fmt::Debug for Encryption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Encryption").field("cipher", &self.cipher).finish_non_exhaustive()
+    }
+}
+
+impl Cipher {
+    pub fn detect(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "gpg" | "asc" => Some(Self::Gpg),
+            "age" => Some(Self::Age),
+            _ => None,
+        }
+    }
+
+    pub fn prompt(&self, path: &Path) -> String {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        match self {
+            Self::Gpg => format!("Passphrase for {}: ", name),
+            Self::Age => format!("Identity file for {}: ", name),
+        }
+    }
+
+    // A gpg passphrase is typed in, an age identity is a path that can be shown
+    pub fn secret_input(&self) -> bool {
+        *self == Self::Gpg
+    }
+
+    // gpg reads the passphrase as the first line of stdin, followed by the data
+    fn command(&self, path: &Path, secret: &str, encrypt: bool) -> Command {
+        let mut cmd = match self {
+            Self::Gpg => {
+                let mut cmd = Command::new("gpg");
+                cmd.args(["--batch", "--yes", "--quiet", "--pinentry-mode", "loopback", "--passphrase-fd", "0"]);
+                if encrypt {
+                    cmd.arg("--symmetric");
+                    if path.extension().is_some_and(|e| e == "asc") {
+                        cmd.arg("--armor");
+                    }
+                    cmd.arg("--output").arg(path);
+                } else {
+                    cmd.arg("--decrypt").arg(path);
+                }
+                cmd
+            }
+            Self::Age => {
+                let mut cmd = Command::new("age");
+                let identity = expand_home(secret);
+                if encrypt {
+                    cmd.arg("--encrypt").arg("--identity").arg(identity).arg("--output").arg(path);
+                } else {
+                    cmd.arg("--decrypt").arg("--identity").arg(identity).arg(path);
+                }
+                cmd
+            }
+        };
+        cmd.stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        cmd
+    }
+
+    async fn run(&self, path: &Path, secret: &str, encrypt: bool, input: &str) -> Result<Vec<u8>> {
+        let mut child = self.command(path, secret, encrypt).spawn()
+            .map_err(|e| anyhow::anyhow!("{:?}: {}", self, e))?;
+        let mut stdin = child.stdin.take().unwrap();
+        if *self == Self::Gpg {
+            stdin.write_all(format!("{}\n", secret).as_bytes()).await?;
+        }
+        stdin.write_all(input.as_bytes()).await?;
+        drop(stdin);
+        let output = child.wait_with_output().await?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!("{}: {}", path.display(), String::from_utf8_lossy(&output.stderr).trim()));
+        }
+        Ok(output.stdout)
+    }
+
+    pub async fn decrypt(&self, path: &Path, secret: &str) -> Result<String> {
+        let plain = self.run(path, secret, false, "").await?;
+        String::from_utf8(plain).map_err(|_| anyhow::anyhow!("{}: decrypted text is not valid UTF-8", path.display()))
+    }
+
+    // gpg files are written back symmetrically with the passphrase they were opened with
+    pub async fn encrypt(&self, path: &Path, secret: &str, text: &str) -> Result<()> {
+        self.run(path, secret, true, text).await?;
+        Ok(())
+    }
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detect_cipher() {
+        assert_eq!(Cipher::detect(Path::new("notes.md.gpg")), Some(Cipher::Gpg));
+        assert_eq!(Cipher::detect(Path::new("key.asc")), Some(Cipher::Gpg));
+        assert_eq!(Cipher::detect(Path::new("secrets.age")), Some(Cipher::Age));
+        assert_eq!(Cipher::detect(Path::new("main.rs")), None);
+        let debug = format!("{:?}", Encryption { cipher: Cipher::Gpg, secret: "hunter2".to_string() });
+        assert!(!debug.contains("hunter2"));
+    }
+}
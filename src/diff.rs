@@ -0,0 +1,112 @@
+// Line by line comparison of two texts for DiffBuffers: rows pair the lines of
+// both sides, lines kept by both on one row and changed ones side by side,
+// with an empty side where a line was only removed or only added
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Same,
+    Changed,
+    // Only on the left
+    Removed,
+    // Only on the right
+    Added,
+}
+
+// Line numbers from 0 into each side
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Row {
+    pub left: Option<usize>,
+    pub right: Option<usize>,
+    pub kind: Kind,
+}
+
+// Above this many line pairs between the common start and end, changed lines
+// are paired in order instead of looking for the longest common subsequence
+const MAX_CELLS: usize = 4_000_000;
+
+pub fn align(a: &[&str], b: &[&str]) -> Vec<Row> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..].iter().rev().zip(b[prefix..].iter().rev()).take_while(|(x, y)| x == y).count();
+    let (mid_a, mid_b) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+    let mut rows: Vec<Row> = (0..prefix).map(|i| Row { left: Some(i), right: Some(i), kind: Kind::Same }).collect();
+    let mut pending = (Vec::new(), Vec::new());
+    for (i, j) in common(mid_a, mid_b) {
+        match (i, j) {
+            (Some(i), Some(j)) => {
+                flush(&mut rows, &mut pending);
+                rows.push(Row { left: Some(prefix + i), right: Some(prefix + j), kind: Kind::Same });
+            }
+            (Some(i), None) => pending.0.push(prefix + i),
+            (None, Some(j)) => pending.1.push(prefix + j),
+            (None, None) => (),
+        }
+    }
+    flush(&mut rows, &mut pending);
+    let (start_a, start_b) = (a.len() - suffix, b.len() - suffix);
+    rows.extend((0..suffix).map(|k| Row { left: Some(start_a + k), right: Some(start_b + k), kind: Kind::Same }));
+    rows
+}
+
+// Removed and added lines between two kept ones, paired up as changes
+fn flush(rows: &mut Vec<Row>, (removed, added): &mut (Vec<usize>, Vec<usize>)) {
+    for k in 0..removed.len().max(added.len()) {
+        let (left, right) = (removed.get(k).copied(), added.get(k).copied());
+        let kind = match (left, right) {
+            (Some(_), Some(_)) => Kind::Changed,
+            (Some(_), None) => Kind::Removed,
+            _ => Kind::Added,
+        };
+        rows.push(Row { left, right, kind });
+    }
+    removed.clear();
+    added.clear();
+}
+
+// Both sides walked in order: (i, j) for a line kept, (i, None) for one removed
+// and (None, j) for one added
+fn common(a: &[&str], b: &[&str]) -> Vec<(Option<usize>, Option<usize>)> {
+    let (n, m) = (a.len(), b.len());
+    if n * m > MAX_CELLS {
+        return (0..n.max(m)).map(|k| ((k < n).then_some(k), (k < m).then_some(k))).collect();
+    }
+    // lcs[i][j]: length of the longest common subsequence of a[i..] and b[j..]
+    let mut lcs = vec![vec![0u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if a[i] == b[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+        }
+    }
+    let (mut i, mut j, mut out) = (0, 0, Vec::new());
+    while i < n || j < m {
+        if i < n && j < m && a[i] == b[j] {
+            out.push((Some(i), Some(j)));
+            i += 1;
+            j += 1;
+        } else if j == m || (i < n && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            out.push((Some(i), None));
+            i += 1;
+        } else {
+            out.push((None, Some(j)));
+            j += 1;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn aligns_lines() {
+        let a = ["a", "b", "c", "d", "e"];
+        let b = ["a", "B", "c", "x", "d"];
+        let rows = align(&a, &b);
+        let kinds: Vec<Kind> = rows.iter().map(|r| r.kind).collect();
+        assert_eq!(kinds, [Kind::Same, Kind::Changed, Kind::Same, Kind::Added, Kind::Same, Kind::Removed]);
+        assert_eq!(rows[3], Row { left: None, right: Some(3), kind: Kind::Added });
+        assert_eq!(rows[5], Row { left: Some(4), right: None, kind: Kind::Removed });
+        assert!(align(&a, &a).iter().all(|r| r.kind == Kind::Same));
+        assert_eq!(align(&[], &["x"]), [Row { left: None, right: Some(0), kind: Kind::Added }]);
+    }
+}
@@ -0,0 +1,93 @@
+// Line endings of a file. Buffers keep `\n` alone, and a file read with
+// `\r\n` gets them back when it is saved. A file mixing the two is left as it
+// is, `\r` and all, until SetLineEnding makes it one or the other
+
+use std::{borrow::Cow, io::Write};
+
+use anyhow::Result;
+use ropey::Rope;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+    Mixed,
+}
+
+impl LineEnding {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "lf" => Ok(Self::Lf),
+            "crlf" => Ok(Self::Crlf),
+            s => Err(anyhow::anyhow!("SetLineEnding: {:?} is not lf or crlf", s)),
+        }
+    }
+
+    // Text without a line break counts as lf
+    pub fn detect(text: &str) -> Self {
+        let crlf = text.matches("\r\n").count();
+        let lf = text.matches('\n').count() - crlf;
+        match (lf, crlf) {
+            (_, 0) => Self::Lf,
+            (0, _) => Self::Crlf,
+            _ => Self::Mixed,
+        }
+    }
+
+    // The ending of `text` and the text as a buffer keeps it
+    pub fn read(text: &str) -> (Self, Cow<'_, str>) {
+        let ending = Self::detect(text);
+        match ending {
+            Self::Crlf => (ending, Cow::Owned(text.replace("\r\n", "\n"))),
+            _ => (ending, Cow::Borrowed(text)),
+        }
+    }
+
+    // Buffer text as it goes back to the file
+    pub fn emit<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        match self {
+            Self::Crlf => Cow::Owned(text.replace('\n', "\r\n")),
+            _ => Cow::Borrowed(text),
+        }
+    }
+
+    // `emit` a chunk at a time, for large files
+    pub fn write_to(&self, text: &Rope, mut writer: impl Write) -> Result<()> {
+        for chunk in text.chunks() {
+            writer.write_all(self.emit(chunk).as_bytes())?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn status(&self) -> Option<String> {
+        match self {
+            Self::Lf => None,
+            Self::Crlf => Some("CRLF".to_string()),
+            Self::Mixed => Some("mixed endings".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keeps_endings() {
+        assert_eq!(LineEnding::detect("a\nb\n"), LineEnding::Lf);
+        assert_eq!(LineEnding::detect("no break"), LineEnding::Lf);
+        assert_eq!(LineEnding::detect("a\r\nb\r\n"), LineEnding::Crlf);
+        assert_eq!(LineEnding::detect("a\r\nb\n"), LineEnding::Mixed);
+        let (ending, text) = LineEnding::read("a\r\nb\r\nc");
+        assert_eq!((ending, text.as_ref()), (LineEnding::Crlf, "a\nb\nc"));
+        let mut out = Vec::new();
+        ending.write_to(&Rope::from_str(&text), &mut out).unwrap();
+        assert_eq!(out, b"a\r\nb\r\nc");
+        let (ending, text) = LineEnding::read("a\r\nb\n");
+        assert_eq!(ending.emit(&text), "a\r\nb\n");
+        assert_eq!(LineEnding::parse("CRLF").unwrap(), LineEnding::Crlf);
+        assert!(LineEnding::parse("cr").is_err());
+    }
+}
@@ -0,0 +1,135 @@
+// Hangul syllable composition for terminals that send one compatibility jamo per key
+
+const BASE: u32 = 0xAC00;
+const CHO: [char; 19] = ['ㄱ', 'ㄲ', 'ㄴ', 'ㄷ', 'ㄸ', 'ㄹ', 'ㅁ', 'ㅂ', 'ㅃ', 'ㅅ', 'ㅆ', 'ㅇ', 'ㅈ', 'ㅉ', 'ㅊ', 'ㅋ', 'ㅌ', 'ㅍ', 'ㅎ'];
+const JUNG: [char; 21] = ['ㅏ', 'ㅐ', 'ㅑ', 'ㅒ', 'ㅓ', 'ㅔ', 'ㅕ', 'ㅖ', 'ㅗ', 'ㅘ', 'ㅙ', 'ㅚ', 'ㅛ', 'ㅜ', 'ㅝ', 'ㅞ', 'ㅟ', 'ㅠ', 'ㅡ', 'ㅢ', 'ㅣ'];
+// Index 0 is "no final consonant"
+const JONG: [char; 28] = ['\0', 'ㄱ', 'ㄲ', 'ㄳ', 'ㄴ', 'ㄵ', 'ㄶ', 'ㄷ', 'ㄹ', 'ㄺ', 'ㄻ', 'ㄼ', 'ㄽ', 'ㄾ', 'ㄿ', 'ㅀ', 'ㅁ', 'ㅂ', 'ㅄ', 'ㅅ', 'ㅆ', 'ㅇ', 'ㅈ', 'ㅊ', 'ㅋ', 'ㅌ', 'ㅍ', 'ㅎ'];
+const VOWEL_PAIRS: [(char, char, char); 7] = [
+    ('ㅗ', 'ㅏ', 'ㅘ'), ('ㅗ', 'ㅐ', 'ㅙ'), ('ㅗ', 'ㅣ', 'ㅚ'),
+    ('ㅜ', 'ㅓ', 'ㅝ'), ('ㅜ', 'ㅔ', 'ㅞ'), ('ㅜ', 'ㅣ', 'ㅟ'),
+    ('ㅡ', 'ㅣ', 'ㅢ'),
+];
+const FINAL_PAIRS: [(char, char, char); 11] = [
+    ('ㄱ', 'ㅅ', 'ㄳ'), ('ㄴ', 'ㅈ', 'ㄵ'), ('ㄴ', 'ㅎ', 'ㄶ'),
+    ('ㄹ', 'ㄱ', 'ㄺ'), ('ㄹ', 'ㅁ', 'ㄻ'), ('ㄹ', 'ㅂ', 'ㄼ'), ('ㄹ', 'ㅅ', 'ㄽ'),
+    ('ㄹ', 'ㅌ', 'ㄾ'), ('ㄹ', 'ㅍ', 'ㄿ'), ('ㄹ', 'ㅎ', 'ㅀ'), ('ㅂ', 'ㅅ', 'ㅄ'),
+];
+
+fn index(list: &[char], c: char) -> Option<usize> {
+    list.iter().position(|x| *x == c)
+}
+
+fn pair(pairs: &[(char, char, char)], a: char, b: char) -> Option<char> {
+    pairs.iter().find(|p| p.0 == a && p.1 == b).map(|p| p.2)
+}
+
+fn split(pairs: &[(char, char, char)], c: char) -> Option<(char, char)> {
+    pairs.iter().find(|p| p.2 == c).map(|p| (p.0, p.1))
+}
+
+fn syllable(cho: usize, jung: usize, jong: usize) -> char {
+    char::from_u32(BASE + ((cho * 21 + jung) * 28 + jong) as u32).unwrap()
+}
+
+// (cho, jung, jong) indices of a precomposed syllable
+fn parts(c: char) -> Option<(usize, usize, usize)> {
+    let code = (c as u32).checked_sub(BASE)?;
+    if code >= 19 * 21 * 28 {
+        return None;
+    }
+    let code = code as usize;
+    Some((code / (21 * 28), code / 28 % 21, code % 28))
+}
+
+pub fn is_jamo(c: char) -> bool {
+    ('\u{3131}'..='\u{3163}').contains(&c)
+}
+
+// Combines the character before the cursor with a newly typed jamo. Returns the
+// replacement for `prev` and, when a final consonant moved into a new syllable,
+// the character to insert after it; None when they don't combine
+pub fn compose(prev: char, next: char) -> Option<(char, Option<char>)> {
+    let next_vowel = index(&JUNG, next);
+    if let Some((cho, jung, jong)) = parts(prev) {
+        if jong == 0 {
+            if let Some(v) = next_vowel {
+                let compound = pair(&VOWEL_PAIRS, JUNG[jung], JUNG[v])?;
+                return Some((syllable(cho, index(&JUNG, compound)?, 0), None));
+            }
+            return Some((syllable(cho, jung, index(&JONG[1..], next)? + 1), None));
+        }
+        let v = match next_vowel {
+            Some(v) => v,
+            None => {
+                let compound = pair(&FINAL_PAIRS, JONG[jong], next)?;
+                return Some((syllable(cho, jung, index(&JONG, compound)?), None));
+            }
+        };
+        // The final consonant (or the second half of a double one) starts the next syllable
+        let (kept, moved) = match split(&FINAL_PAIRS, JONG[jong]) {
+            Some((a, b)) => (index(&JONG, a)?, b),
+            None => (0, JONG[jong]),
+        };
+        return Some((syllable(cho, jung, kept), Some(syllable(index(&CHO, moved)?, v, 0))));
+    }
+    if let Some(v) = next_vowel {
+        if let Some(cho) = index(&CHO, prev) {
+            return Some((syllable(cho, v, 0), None));
+        }
+        return Some((pair(&VOWEL_PAIRS, prev, next)?, None));
+    }
+    None
+}
+
+// Removes the last typed jamo; None when nothing is left
+pub fn decompose(c: char) -> Option<char> {
+    if let Some((cho, jung, jong)) = parts(c) {
+        if jong != 0 {
+            let rest = split(&FINAL_PAIRS, JONG[jong]).and_then(|(a, _)| index(&JONG, a)).unwrap_or(0);
+            return Some(syllable(cho, jung, rest));
+        }
+        if let Some((a, _)) = split(&VOWEL_PAIRS, JUNG[jung]) {
+            return Some(syllable(cho, index(&JUNG, a)?, 0));
+        }
+        return Some(CHO[cho]);
+    }
+    split(&VOWEL_PAIRS, c).map(|(a, _)| a)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn type_keys(keys: &str) -> String {
+        let mut out: Vec<char> = Vec::new();
+        for k in keys.chars() {
+            match out.last().and_then(|p| compose(*p, k)) {
+                Some((prev, next)) => {
+                    *out.last_mut().unwrap() = prev;
+                    out.extend(next);
+                }
+                None => out.push(k),
+            }
+        }
+        out.into_iter().collect()
+    }
+
+    #[test]
+    fn composes_syllables() {
+        assert_eq!(type_keys("ㅎㅏㄴㄱㅡㄹ"), "한글");
+        assert_eq!(type_keys("ㄷㅏㄹㄱㅇㅣ"), "닭이");
+        assert_eq!(type_keys("ㄱㅘㄴ"), "관");
+        assert_eq!(type_keys("ㄱㅗㅏ"), "과");
+        assert_eq!(type_keys("ㅇㅏㄴㅈㅏ"), "안자");
+    }
+
+    #[test]
+    fn backspace_decomposes() {
+        assert_eq!(decompose('닭'), Some('달'));
+        assert_eq!(decompose('달'), Some('다'));
+        assert_eq!(decompose('과'), Some('고'));
+        assert_eq!(decompose('고'), Some('ㄱ'));
+        assert_eq!(decompose('ㄱ'), None);
+    }
+}
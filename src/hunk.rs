@@ -0,0 +1,52 @@
+// Hunks of a `git diff -U0` of one file, to stage or unstage a single change
+// from the buffer
+
+// The diff's file header and the hunk covering `line` (from 1) of its new
+// side, as a patch for `git apply --unidiff-zero`
+pub fn patch_at(diff: &str, line: usize) -> Option<String> {
+    let mut header = String::new();
+    let mut hunks: Vec<(usize, usize, String)> = Vec::new();
+    for l in diff.split_inclusive('\n') {
+        if l.starts_with("@@") {
+            let (start, len) = new_range(l)?;
+            hunks.push((start, len, l.to_string()));
+        } else if let Some((_, _, hunk)) = hunks.last_mut() {
+            hunk.push_str(l);
+        } else {
+            header.push_str(l);
+        }
+    }
+    let (_, _, hunk) = hunks.into_iter().find(|(start, len, _)| covers(*start, *len, line))?;
+    Some(header + &hunk)
+}
+
+// Start and length of the `+c,d` part of `@@ -a,b +c,d @@`
+fn new_range(header: &str) -> Option<(usize, usize)> {
+    let range = header.split_whitespace().find(|w| w.starts_with('+'))?.trim_start_matches('+');
+    let (start, len) = range.split_once(',').unwrap_or((range, "1"));
+    Some((start.parse().ok()?, len.parse().ok()?))
+}
+
+// Lines only removed are after line `start`, so the lines on either side count
+fn covers(start: usize, len: usize, line: usize) -> bool {
+    match len {
+        0 => line == start || line == start + 1,
+        n => (start..start + n).contains(&line),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn finds_hunk_at_line() {
+        let diff = "diff --git a/f b/f\n--- a/f\n+++ b/f\n@@ -2 +2 @@\n-b\n+B\n@@ -5,2 +4,0 @@\n-e\n-f\n@@ -9,0 +8,2 @@\n+x\n+y\n";
+        let header = "diff --git a/f b/f\n--- a/f\n+++ b/f\n";
+        assert_eq!(patch_at(diff, 2), Some(format!("{}@@ -2 +2 @@\n-b\n+B\n", header)));
+        assert_eq!(patch_at(diff, 5), Some(format!("{}@@ -5,2 +4,0 @@\n-e\n-f\n", header)));
+        assert_eq!(patch_at(diff, 9).map(|p| p.ends_with("+x\n+y\n")), Some(true));
+        assert_eq!(patch_at(diff, 3), None);
+        assert_eq!(patch_at("", 1), None);
+    }
+}
@@ -0,0 +1,111 @@
+// Guesses the indentation of a file from its leading whitespace, so editing it
+// keeps its style instead of the configured one, and re-indents pasted text
+
+use crate::TabType;
+
+// Enough lines to decide without reading all of a large file
+const SAMPLE_LINES: usize = 1000;
+const WIDTHS: [usize; 4] = [2, 3, 4, 8];
+
+// Tab type and, for spaces, the indent width; None when nothing is indented
+pub fn detect<I, S>(lines: I) -> Option<(TabType, Option<usize>)>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut tabs = 0;
+    let mut spaces = 0;
+    // How often the indent grows by each width between consecutive lines
+    let mut steps = [0usize; 9];
+    let mut previous = 0;
+    for line in lines.into_iter().take(SAMPLE_LINES) {
+        let line = line.as_ref();
+        let rest = line.trim_start_matches([' ', '\t']);
+        // Blank lines and the ` * ` of block comments say nothing about the indent
+        if rest.trim().is_empty() || rest.starts_with('*') {
+            continue;
+        }
+        let indent = &line[..line.len() - rest.len()];
+        if indent.starts_with('\t') {
+            tabs += 1;
+            continue;
+        }
+        let width = indent.len();
+        if width > 0 {
+            spaces += 1;
+        }
+        if width > previous && width - previous < steps.len() {
+            steps[width - previous] += 1;
+        }
+        previous = width;
+    }
+    if tabs == 0 && spaces == 0 {
+        return None;
+    }
+    if tabs > spaces {
+        return Some((TabType::Tab, None));
+    }
+    // Ties go to the smaller width, e.g. 4 space files that nest by 8 too
+    let width = WIDTHS.iter().copied().max_by_key(|w| (steps[*w], std::cmp::Reverse(*w)))
+        .filter(|w| steps[*w] > 0);
+    Some((TabType::Space, width))
+}
+
+fn indent_len(line: &str) -> usize {
+    line.len() - line.trim_start_matches([' ', '\t']).len()
+}
+
+// Moves a pasted block to `indent`, keeping the relative indentation of its
+// lines. The first line lands after the cursor, so it only loses the block's
+// common indent; if it was copied from mid-line it has none, and is left out
+// when finding the common indent
+pub fn reindent(text: &str, indent: &str) -> String {
+    let lines: Vec<&str> = text.split_inclusive('\n').collect();
+    if lines.len() < 2 {
+        return text.to_string();
+    }
+    let blank = |l: &str| l.trim().is_empty();
+    let first = indent_len(lines[0]);
+    let common = lines[1..].iter()
+        .filter(|l| !blank(l))
+        .map(|l| indent_len(l))
+        .chain((first > 0 && !blank(lines[0])).then_some(first))
+        .min()
+        .unwrap_or(0);
+    let mut out = String::with_capacity(text.len());
+    out.push_str(&lines[0][first.min(common)..]);
+    for line in &lines[1..] {
+        if blank(line) {
+            out.push_str(line.trim_start_matches([' ', '\t']));
+        } else {
+            out.push_str(indent);
+            out.push_str(&line[common..]);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detects_indentation() {
+        let two = "fn main() {\n  if x {\n    y();\n  }\n  z();\n}\n";
+        assert!(matches!(detect(two.lines()), Some((TabType::Space, Some(2)))));
+        let four = "class A:\n    def f(self):\n        pass\n\n    def g(self):\n        pass\n";
+        assert!(matches!(detect(four.lines()), Some((TabType::Space, Some(4)))));
+        let tabs = "int main() {\n\tif (x) {\n\t\ty();\n\t}\n}\n/*\n * doc\n */\n";
+        assert!(matches!(detect(tabs.lines()), Some((TabType::Tab, None))));
+        assert!(detect("no\nindent\n".lines()).is_none());
+    }
+
+    #[test]
+    fn reindents_pasted_blocks() {
+        // Copied from mid-line: the first line has no indent of its own
+        assert_eq!(reindent("if x {\n        y();\n    }\n", "\t"), "if x {\n\t    y();\n\t}\n");
+        // Whole lines copied with their indent
+        assert_eq!(reindent("    a\n\n      b\n", "  "), "a\n\n    b\n");
+        assert_eq!(reindent("one line", "    "), "one line");
+    }
+}
@@ -0,0 +1,166 @@
+use std::{future::Future, pin::Pin};
+
+use anyhow::Result;
+use tokio::{sync::mpsc, task::JoinHandle};
+
+use crate::Action;
+
+pub type JobFuture = Pin<Box<dyn Future<Output = Result<Vec<Action>>> + Send>>;
+pub type JobTask = Box<dyn FnOnce(JobReporter) -> JobFuture + Send>;
+
+// Events sent from running jobs back to process_action
+#[derive(Debug)]
+pub enum JobEvent {
+    Progress(usize, f32, String),
+    // Actions to run while the job goes on, e.g. output streamed into a tab
+    Actions(usize, Vec<Action>),
+    Done(usize, Result<Vec<Action>>),
+}
+
+// Handed to a job so it can report how far it got
+#[derive(Debug, Clone)]
+pub struct JobReporter {
+    id: usize,
+    tx: mpsc::UnboundedSender<JobEvent>,
+}
+
+impl JobReporter {
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn progress(&self, ratio: f32, message: &str) {
+        let _ = self.tx.send(JobEvent::Progress(self.id, ratio.clamp(0.0, 1.0), message.to_string()));
+    }
+
+    pub fn run(&self, actions: Vec<Action>) {
+        let _ = self.tx.send(JobEvent::Actions(self.id, actions));
+    }
+}
+
+#[derive(Debug)]
+pub struct Job {
+    pub id: usize,
+    pub name: String,
+    pub progress: f32,
+    pub message: String,
+    handle: JoinHandle<()>,
+}
+
+#[derive(Debug)]
+pub struct Jobs {
+    pub list: Vec<Job>,
+    next_id: usize,
+    tx: mpsc::UnboundedSender<JobEvent>,
+}
+
+impl Jobs {
+    pub fn new() -> (Self, mpsc::UnboundedReceiver<JobEvent>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (Self {
+            list: Vec::new(),
+            next_id: 0,
+            tx,
+        }, rx)
+    }
+
+    pub fn spawn(&mut self, name: &str, task: JobTask) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        let reporter = JobReporter {
+            id,
+            tx: self.tx.clone(),
+        };
+        let tx = self.tx.clone();
+        let handle = tokio::spawn(async move {
+            let result = task(reporter).await;
+            let _ = tx.send(JobEvent::Done(id, result));
+        });
+        self.list.push(Job {
+            id,
+            name: name.to_string(),
+            progress: 0.0,
+            message: String::new(),
+            handle,
+        });
+        id
+    }
+
+    // Cancels the given job, or the most recently started one
+    pub fn cancel(&mut self, id: Option<usize>) -> Option<Job> {
+        let pos = match id {
+            Some(id) => self.list.iter().position(|j| j.id == id)?,
+            None => self.list.len().checked_sub(1)?,
+        };
+        let job = self.list.remove(pos);
+        job.handle.abort();
+        Some(job)
+    }
+
+    pub fn update(&mut self, id: usize, progress: f32, message: String) {
+        if let Some(job) = self.list.iter_mut().find(|j| j.id == id) {
+            job.progress = progress;
+            job.message = message;
+        }
+    }
+
+    pub fn finish(&mut self, id: usize) -> Option<Job> {
+        let pos = self.list.iter().position(|j| j.id == id)?;
+        Some(self.list.remove(pos))
+    }
+
+    pub fn status(&self) -> String {
+        self.list.iter().map(|j| {
+            if j.message.is_empty() {
+                format!("[{} {:.0}%]", j.name, j.progress * 100.0)
+            } else {
+                format!("[{} {:.0}% {}]", j.name, j.progress * 100.0, j.message)
+            }
+        }).collect::<Vec<_>>().join(" ")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn spawn_and_finish() {
+        let (mut jobs, mut rx) = Jobs::new();
+        let id = jobs.spawn("test", Box::new(|r| Box::pin(async move {
+            r.progress(0.5, "half");
+            Ok(vec![])
+        })));
+        match rx.recv().await.unwrap() {
+            JobEvent::Progress(i, p, m) => {
+                assert_eq!(i, id);
+                jobs.update(i, p, m);
+            }
+            _ => panic!("expected progress"),
+        }
+        assert_eq!(jobs.status(), "[test 50% half]");
+        match rx.recv().await.unwrap() {
+            JobEvent::Done(i, r) => {
+                assert!(r.unwrap().is_empty());
+                assert_eq!(jobs.finish(i).unwrap().name, "test");
+            }
+            _ => panic!("expected done"),
+        }
+        assert!(jobs.list.is_empty());
+    }
+
+    #[tokio::test]
+    async fn cancel_latest() {
+        let (mut jobs, _rx) = Jobs::new();
+        jobs.spawn("a", Box::new(|_| Box::pin(async move {
+            tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+            Ok(vec![])
+        })));
+        jobs.spawn("b", Box::new(|_| Box::pin(async move {
+            tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+            Ok(vec![])
+        })));
+        assert_eq!(jobs.cancel(None).unwrap().name, "b");
+        assert_eq!(jobs.list.len(), 1);
+    }
+}
@@ -0,0 +1,122 @@
+// `--journal <file>` writes every action the editor runs to a file, a line of
+// JSON each, and `--replay <file>` sends the actions of such a file again at
+// the pace they were recorded, to reproduce a bug or script a session
+
+use std::{fs::File, io::Write, path::Path, time::{Duration, Instant}};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{job::JobReporter, Action};
+
+// Actions whose args hold a passphrase or the text of a file that is not
+// written out in the clear; they are recorded without them
+const SECRET: [&str; 3] = ["OpenEncrypted", "OpenDecrypted", "OpenRemote"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    // Milliseconds since the editor started
+    pub ms: u64,
+    pub name: String,
+    #[serde(default)]
+    pub args: Vec<Option<String>>,
+    // Run by another action or a job rather than sent by a key, the mouse or
+    // the terminal; replaying what was sent brings these about again
+    #[serde(default)]
+    pub queued: bool,
+}
+
+#[derive(Debug)]
+pub struct Journal {
+    file: File,
+    started: Instant,
+}
+
+impl Journal {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("Journal {}", path.display()))?;
+        Ok(Self { file, started: Instant::now() })
+    }
+
+    // Written out at once, so the journal of a crash ends at what caused it.
+    // `secret` is set while a passphrase is typed, whose keys are left out too
+    pub fn record(&mut self, action: &Action, queued: bool, secret: bool) -> Result<()> {
+        let hidden = SECRET.contains(&action.name.as_str()) || (secret && action.name.starts_with("Line"));
+        let entry = Entry {
+            ms: self.started.elapsed().as_millis() as u64,
+            name: action.name.clone(),
+            args: if hidden { vec![] } else { action.args.clone() },
+            queued,
+        };
+        writeln!(self.file, "{}", serde_json::to_string(&entry)?)?;
+        Ok(())
+    }
+}
+
+pub fn read(path: &Path) -> Result<Vec<Entry>> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("Replay {}", path.display()))?;
+    text.lines().enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| serde_json::from_str(line).with_context(|| format!("Replay {} line {}", path.display(), i + 1)))
+        .collect()
+}
+
+// What a replay sends: queued actions follow from the others, and the
+// terminal of the replay keeps its own size
+fn replayed(entries: Vec<Entry>) -> Vec<Entry> {
+    entries.into_iter().filter(|e| !e.queued && e.name != "Resize").collect()
+}
+
+// Runs as a job, so CancelJob stops a replay
+pub async fn replay(entries: Vec<Entry>, reporter: JobReporter) -> Result<Vec<Action>> {
+    let entries = replayed(entries);
+    let first = entries.first().map_or(0, |e| e.ms);
+    let started = Instant::now();
+    for (i, entry) in entries.iter().enumerate() {
+        let at = Duration::from_millis(entry.ms.saturating_sub(first));
+        tokio::time::sleep(at.saturating_sub(started.elapsed())).await;
+        reporter.progress(i as f32 / entries.len() as f32, &entry.name);
+        reporter.run(vec![Action { name: entry.name.clone(), args: entry.args.clone() }]);
+    }
+    Ok(vec![])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn records_and_reads() {
+        let path = std::env::temp_dir().join(format!("zutto-journal-{}", std::process::id()));
+        let mut journal = Journal::create(&path).unwrap();
+        journal.record(&Action { name: "Insert".to_string(), args: vec![Some(",".to_string())] }, false, false).unwrap();
+        journal.record(&Action { name: "Resize".to_string(), args: vec![Some("80".to_string()), Some("24".to_string())] }, false, false).unwrap();
+        journal.record(&Action { name: "CheckDisk".to_string(), args: vec![None] }, true, false).unwrap();
+        let entries = read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!((entries[0].name.as_str(), &entries[0].args), ("Insert", &vec![Some(",".to_string())]));
+        assert_eq!(entries[2].args, [None]);
+        assert!(entries[2].queued);
+        let sent: Vec<String> = replayed(entries).into_iter().map(|e| e.name).collect();
+        assert_eq!(sent, ["Insert"]);
+        assert!(serde_json::from_str::<Entry>(r#"{"ms": 5, "name": "Save"}"#).is_ok());
+    }
+
+    #[test]
+    fn leaves_out_secrets() {
+        let path = std::env::temp_dir().join(format!("zutto-journal-secret-{}", std::process::id()));
+        let mut journal = Journal::create(&path).unwrap();
+        let action = |name: &str, args: &[&str]| Action { name: name.to_string(), args: args.iter().map(|a| Some(a.to_string())).collect() };
+        journal.record(&action("LineInsert", &["h"]), false, true).unwrap();
+        journal.record(&action("LinePaste", &["hunter2"]), false, true).unwrap();
+        journal.record(&action("OpenEncrypted", &["a.gpg", "hunter2"]), true, false).unwrap();
+        journal.record(&action("OpenDecrypted", &["a.gpg", "hunter2", "plain text"]), true, false).unwrap();
+        journal.record(&action("LineInsert", &["x"]), false, false).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(!text.contains("hunter2") && !text.contains("plain text") && !text.contains("\"h\""));
+        let last: Entry = serde_json::from_str(text.lines().last().unwrap()).unwrap();
+        assert_eq!(last.args, [Some("x".to_string())]);
+    }
+}
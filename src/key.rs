@@ -43,6 +43,17 @@ pub enum Key {
     Comma,
 }
 
+impl Key {
+    fn label(&self) -> String {
+        match self {
+            Key::CharAny => "<char>".to_string(),
+            Key::Char(c) => c.to_string(),
+            Key::F(n) => format!("F{}", n),
+            k => <&'static str>::from(*k).to_string(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(transparent)]
 struct Command {
@@ -115,6 +126,20 @@ impl Keymap {
         }
         None
     }
+    // Actions sorted by name with their key combinations, e.g. `Ctrl+S`
+    pub fn bindings(&self) -> Vec<(String, Vec<String>)> {
+        let mut bindings = self.keymap.iter()
+            .map(|(action, command)| {
+                let combos = command.key.iter()
+                    .map(|combo| combo.iter().map(Key::label).collect::<Vec<_>>().join("+"))
+                    .collect();
+                (action.clone(), combos)
+            })
+            .collect::<Vec<_>>();
+        bindings.sort();
+        bindings
+    }
+
     pub fn read(event: KeyEvent) -> Option<BTreeSet<Key>> {
         let mut rtn = BTreeSet::new();
 
@@ -173,6 +198,12 @@ impl Keymap {
 pub fn open_keymaps(path: &str) -> Result<HashMap<KeymapState, Keymap>> {
     let file = std::fs::File::open(path)?;
     let json: Value = serde_json::from_reader(file)?;
+    keymaps_from_value(json, false)
+}
+
+// `modal` is the modal setting: Normal gets the Modal section laid over it
+pub fn keymaps_from_value(mut json: Value, modal: bool) -> Result<HashMap<KeymapState, Keymap>> {
+    layer(&mut json, modal);
     let mut rtn: HashMap<KeymapState, Keymap> = serde_json::from_value(json)?;
     for (_, keymap) in &mut rtn {
         for (action, command) in &mut keymap.keymap {
@@ -182,6 +213,28 @@ pub fn open_keymaps(path: &str) -> Result<HashMap<KeymapState, Keymap>> {
     Ok(rtn)
 }
 
+// Insert and Modal only list what differs from Normal, so each is Normal with
+// its section merged over by action name; an action bound to [] is unbound.
+// Modal is not a mode of its own but what Normal becomes with modal editing
+fn layer(json: &mut Value, modal: bool) {
+    let Some(map) = json.as_object_mut() else { return };
+    let normal = map.get("Normal").cloned().unwrap_or_default();
+    let over = |section: Option<Value>| {
+        let mut out = normal.as_object().cloned().unwrap_or_default();
+        if let Some(Value::Object(section)) = section {
+            out.extend(section);
+        }
+        out.retain(|_, combos| combos.as_array().is_none_or(|c| !c.is_empty()));
+        Value::Object(out)
+    };
+    let insert = over(map.remove("Insert"));
+    let modal_section = map.remove("Modal");
+    if modal {
+        map.insert("Normal".to_string(), over(modal_section));
+    }
+    map.insert("Insert".to_string(), insert);
+}
+
 #[cfg(test)]
 mod test{
     use super::*;
@@ -226,4 +279,20 @@ mod test{
         let action = keymap.get_action(&BTreeSet::from_iter(vec![Key::Char('c')])).unwrap();
         assert_eq!(action, "Insert(C)");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn modal_layers() {
+        let json: Value = serde_json::from_reader(std::fs::File::open("settings/keymap.json").unwrap()).unwrap();
+        let key = |keys: Vec<Key>| BTreeSet::from_iter(keys);
+        let modal = keymaps_from_value(json.clone(), true).unwrap();
+        let normal = &modal[&KeymapState::Normal];
+        assert_eq!(normal.get_action(&key(vec![Key::Char('I')])).as_deref(), Some("InsertMode"));
+        assert_eq!(normal.get_action(&key(vec![Key::Char('Q')])), None);
+        assert_eq!(normal.get_action(&key(vec![Key::Ctrl, Key::Char('Z')])).as_deref(), Some("Undo"));
+        let insert = &modal[&KeymapState::Insert];
+        assert_eq!(insert.get_action(&key(vec![Key::Char('Q')])).as_deref(), Some("Insert(Q)"));
+        assert_eq!(insert.get_action(&key(vec![Key::Esc])).as_deref(), Some("NormalMode"));
+        let modeless = keymaps_from_value(json, false).unwrap();
+        assert_eq!(modeless[&KeymapState::Normal].get_action(&key(vec![Key::Char('I')])).as_deref(), Some("Insert(I)"));
+    }
+}
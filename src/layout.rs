@@ -0,0 +1,186 @@
+// Split panes: the tabs shown at once, as a tree of side by side or stacked
+// splits of the area between the tab bar and the status bar. Leaves are tab
+// indices, and the focused tab is always one of them
+
+use crate::tab::{Pos, Size};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    // Side by side with a column of │ between
+    Horizontal,
+    // One above the other with a row of ─ between
+    Vertical,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Layout {
+    Pane(usize),
+    Split(Direction, Box<Layout>, Box<Layout>),
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout::Pane(0)
+    }
+}
+
+// A pane's tab and the area it is drawn in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Placed {
+    pub tab: usize,
+    pub pos: Pos,
+    pub size: Size,
+}
+
+impl Layout {
+    pub fn is_split(&self) -> bool {
+        matches!(self, Layout::Split(..))
+    }
+
+    // Tabs of the panes, left to right and top to bottom
+    pub fn tabs(&self) -> Vec<usize> {
+        match self {
+            Layout::Pane(i) => vec![*i],
+            Layout::Split(_, a, b) => [a.tabs(), b.tabs()].concat(),
+        }
+    }
+
+    pub fn contains(&self, tab: usize) -> bool {
+        self.tabs().contains(&tab)
+    }
+
+    // Splits the pane of `tab`, `new` going right of or below it
+    pub fn split(&mut self, tab: usize, new: usize, direction: Direction) {
+        match self {
+            Layout::Pane(i) if *i == tab => {
+                *self = Layout::Split(direction, Box::new(Layout::Pane(tab)), Box::new(Layout::Pane(new)));
+            }
+            Layout::Pane(_) => (),
+            Layout::Split(_, a, b) => {
+                a.split(tab, new, direction);
+                b.split(tab, new, direction);
+            }
+        }
+    }
+
+    // Takes the pane of `tab` out, the other side of its split getting the room
+    pub fn remove(&mut self, tab: usize) {
+        let Layout::Split(_, a, b) = self else { return };
+        if **a == Layout::Pane(tab) {
+            *self = std::mem::take(b.as_mut());
+        } else if **b == Layout::Pane(tab) {
+            *self = std::mem::take(a.as_mut());
+        } else {
+            a.remove(tab);
+            b.remove(tab);
+        }
+    }
+
+    fn renumber(&mut self, f: &dyn Fn(usize) -> usize) {
+        match self {
+            Layout::Pane(i) => *i = f(*i),
+            Layout::Split(_, a, b) => {
+                a.renumber(f);
+                b.renumber(f);
+            }
+        }
+    }
+
+    // Tab `closed` was closed, so the tabs after it moved down one
+    pub fn close(&mut self, closed: usize) {
+        self.remove(closed);
+        self.renumber(&|i| if i > closed { i - 1 } else { i });
+    }
+
+    // Tabs `a` and `b` traded places in the tab bar
+    pub fn swap(&mut self, a: usize, b: usize) {
+        self.renumber(&|i| if i == a { b } else if i == b { a } else { i });
+    }
+
+    // Keeps the focused tab on screen: it takes the pane of the tab focused
+    // before, or the whole area when that one is gone
+    pub fn show(&mut self, tab: usize, before: usize) {
+        if self.contains(tab) {
+            return;
+        }
+        match self.contains(before) {
+            true => self.renumber(&|i| if i == before { tab } else { i }),
+            false => *self = Layout::Pane(tab),
+        }
+    }
+
+    // The pane after or before the one of `tab`, going around
+    pub fn next(&self, tab: usize, forward: bool) -> usize {
+        let tabs = self.tabs();
+        let n = tabs.len();
+        let i = tabs.iter().position(|t| *t == tab).unwrap_or(0);
+        tabs[if forward { (i + 1) % n } else { (i + n - 1) % n }]
+    }
+
+    // Every pane's area within `pos` and `size`, and the separators between them
+    pub fn place(&self, pos: Pos, size: Size) -> (Vec<Placed>, Vec<Placed>) {
+        let (mut panes, mut separators) = (Vec::new(), Vec::new());
+        self.place_into(pos, size, &mut panes, &mut separators);
+        (panes, separators)
+    }
+
+    fn place_into(&self, pos: Pos, size: Size, panes: &mut Vec<Placed>, separators: &mut Vec<Placed>) {
+        let (direction, a, b) = match self {
+            Layout::Pane(tab) => return panes.push(Placed { tab: *tab, pos, size }),
+            Layout::Split(direction, a, b) => (direction, a, b),
+        };
+        match direction {
+            Direction::Horizontal => {
+                let left = size.width.saturating_sub(1) / 2;
+                let right = size.width.saturating_sub(left + 1);
+                a.place_into(pos, Size { width: left, height: size.height }, panes, separators);
+                let col = pos.col + left;
+                separators.push(Placed { tab: 0, pos: Pos { row: pos.row, col }, size: Size { width: 1, height: size.height } });
+                b.place_into(Pos { row: pos.row, col: col + 1 }, Size { width: right, height: size.height }, panes, separators);
+            }
+            Direction::Vertical => {
+                let top = size.height.saturating_sub(1) / 2;
+                let bottom = size.height.saturating_sub(top + 1);
+                a.place_into(pos, Size { width: size.width, height: top }, panes, separators);
+                let row = pos.row + top;
+                separators.push(Placed { tab: 0, pos: Pos { row, col: pos.col }, size: Size { width: size.width, height: 1 } });
+                b.place_into(Pos { row: row + 1, col: pos.col }, Size { width: size.width, height: bottom }, panes, separators);
+            }
+        }
+    }
+
+    // The tab of the pane a screen cell is in
+    pub fn pane_at(&self, pos: Pos, size: Size, col: u16, row: u16) -> Option<usize> {
+        self.place(pos, size).0.into_iter()
+            .find(|p| (p.pos.col..p.pos.col + p.size.width).contains(&col) && (p.pos.row..p.pos.row + p.size.height).contains(&row))
+            .map(|p| p.tab)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn splits_and_closes() {
+        let mut layout = Layout::Pane(0);
+        layout.split(0, 2, Direction::Horizontal);
+        layout.split(2, 3, Direction::Vertical);
+        assert_eq!(layout.tabs(), [0, 2, 3]);
+        let (panes, separators) = layout.place(Pos { row: 1, col: 0 }, Size { width: 81, height: 21 });
+        assert_eq!(panes[0].size, Size { width: 40, height: 21 });
+        assert_eq!((panes[1].pos, panes[1].size), (Pos { row: 1, col: 41 }, Size { width: 40, height: 10 }));
+        assert_eq!(panes[2].pos, Pos { row: 12, col: 41 });
+        assert_eq!(separators.len(), 2);
+        assert_eq!(layout.pane_at(Pos { row: 1, col: 0 }, Size { width: 81, height: 21 }, 50, 15), Some(3));
+        assert_eq!(layout.next(3, true), 0);
+        layout.show(1, 2);
+        assert_eq!(layout.tabs(), [0, 1, 3]);
+        layout.close(1);
+        assert_eq!(layout.tabs(), [0, 2]);
+        layout.swap(0, 2);
+        assert_eq!(layout, Layout::Split(Direction::Horizontal, Box::new(Layout::Pane(2)), Box::new(Layout::Pane(0))));
+        layout.show(1, 5);
+        assert_eq!(layout, Layout::Pane(1));
+    }
+}
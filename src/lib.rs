@@ -1,14 +1,13 @@
-use std::{collections::HashMap, hash::Hash, io, path::PathBuf, sync::Arc};
+use std::{collections::{HashMap, HashSet, VecDeque}, hash::Hash, io::IsTerminal, path::{Path, PathBuf}, sync::Arc};
 
 use anyhow::{Result, Error};
-use key::{open_keymaps, Keymap};
-use crossterm::{event::{self, EventStream}, terminal};
-use log::debug;
+use key::Keymap;
+use crossterm::{event::{self, EventStream, MouseButton, MouseEventKind}, terminal};
+use log::{debug, error};
 use regex::Regex;
 use render::Renderer;
 use strum_macros::IntoStaticStr;
-use syntect::highlighting::ThemeSet;
-use tab::{buffer::Buffer, directory, Pos, Size, Tab};
+use tab::{buffer::Buffer, directory, results::Location, Pos, Size, Tab};
 use tokio::sync::{mpsc::{self, Receiver}, Mutex};
 use tokio_stream::StreamExt;
 use serde::{de, Deserialize, Serialize};
@@ -17,21 +16,173 @@ pub mod key;
 pub mod render;
 pub mod actions;
 pub mod tab;
+pub mod lineedit;
 pub mod lineinput;
+pub mod job;
+pub mod middleware;
+pub mod lsp;
+pub mod popup;
+pub mod workspace;
+pub mod hangul;
+pub mod calc;
+pub mod profiler;
+pub mod syntax;
+pub mod transform;
+pub mod unicode;
+pub mod remote;
+pub mod crypt;
+pub mod announce;
+pub mod tutor;
+pub mod clipboard;
+pub mod indent;
+pub mod abbrev;
+pub mod search;
+pub mod messages;
+pub mod views;
+pub mod templates;
+pub mod layout;
+pub mod hunk;
+pub mod session;
+pub mod range;
+pub mod brackets;
+pub mod diff;
+pub mod journal;
+pub mod eol;
+pub mod bookmarks;
+pub mod repeat;
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-enum TabType {
+pub enum TabType {
     Space,
     Tab,
 }
 
+// Whether saving makes a file end with a line break
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum FinalNewline {
+    Always,
+    Never,
+    #[default]
+    Preserve,
+}
+
+impl FinalNewline {
+    pub fn apply(&self, text: &mut ropey::Rope) {
+        let len = text.len_chars();
+        match self {
+            FinalNewline::Always if len > 0 && text.char(len - 1) != '\n' => text.insert_char(len, '\n'),
+            FinalNewline::Never => {
+                let mut end = len;
+                while end > 0 && matches!(text.char(end - 1), '\n' | '\r') {
+                    end -= 1;
+                }
+                text.remove(end..len);
+            }
+            _ => (),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Setting {
     line_numbers: bool,
     tab_size: usize,
     tab_type: TabType,
     show_spaces: bool,
+    // Soft wrap long lines, as prose mode does
+    #[serde(default)]
+    wrap: bool,
     theme: String,
+    #[serde(default)]
+    lsp_servers: HashMap<String, String>,
+    #[serde(default)]
+    formatters: HashMap<String, String>,
+    #[serde(default)]
+    format_on_save: bool,
+    #[serde(default)]
+    build_command: String,
+    // Show the word count of the current buffer in the status bar
+    #[serde(default)]
+    word_count: bool,
+    // Draw a swatch after `#RRGGBB` and `rgb(r, g, b)` values
+    #[serde(default)]
+    color_swatches: bool,
+    // Column prose mode breaks lines at while typing, 0 to not break them
+    #[serde(default)]
+    textwidth: usize,
+    // Reads text on stdin and prints misspelled words, one per line
+    #[serde(default)]
+    spell_command: String,
+    // Announce cursor lines, mode changes and notices to `announce_path`
+    #[serde(default)]
+    screen_reader: bool,
+    #[serde(default)]
+    announce_path: String,
+    // Use the indentation an opened file already has over tab_type and tab_size
+    #[serde(default)]
+    detect_indent: bool,
+    // Re-indent pasted lines to the indent at the cursor
+    #[serde(default)]
+    smart_paste: bool,
+    // Expand settings/abbreviations.json per file extension, "*" for the rest
+    #[serde(default)]
+    abbreviations: HashMap<String, bool>,
+    // Seconds a notice or alert stays in the status bar, 0 until the next one
+    #[serde(default)]
+    notice_timeout: u64,
+    // Opening a file from a directory tab closes it instead of keeping it open
+    #[serde(default)]
+    close_directory_on_open: bool,
+    #[serde(default)]
+    final_newline: FinalNewline,
+    // Chars other than letters and digits that word motions and double click
+    // take as part of a word, per file extension, "*" for the rest
+    #[serde(default)]
+    word_chars: HashMap<String, String>,
+    // Two chars drawn at the left and right edge where a line goes on out of
+    // view without soft wrap, e.g. "<>"; empty for none
+    #[serde(default)]
+    clip_markers: String,
+    // Normal mode keys move and edit and typing happens in Insert mode, see
+    // the Modal section of settings/keymap.json
+    #[serde(default)]
+    modal: bool,
+    // Seconds between saves of modified files, 0 for none
+    #[serde(default)]
+    autosave: u64,
+    // Modified files are also saved when the terminal loses focus
+    #[serde(default)]
+    autosave_on_focus_lost: bool,
+    // Copy a file to `<file>~` before a save writes over it
+    #[serde(default)]
+    backup: bool,
+    // Directory backups go to instead, named by the file's whole path with
+    // `%` for the slashes; empty to keep them next to the file
+    #[serde(default)]
+    backup_dir: String,
+    // SetLocal also writes the setting to the project's .zutto.json
+    #[serde(default)]
+    persist_settings: bool,
+}
+
+impl Setting {
+    pub fn autosaving(&self) -> bool {
+        self.autosave > 0 || self.autosave_on_focus_lost
+    }
+
+    // Where the backup of `path` goes, when backups are on
+    pub fn backup_path(&self, path: &Path) -> Option<PathBuf> {
+        if !self.backup {
+            return None;
+        }
+        if self.backup_dir.is_empty() {
+            let mut name = path.file_name()?.to_os_string();
+            name.push("~");
+            return Some(path.with_file_name(name));
+        }
+        let path = std::path::absolute(path).ok()?;
+        Some(Path::new(&self.backup_dir).join(path.to_string_lossy().replace(std::path::MAIN_SEPARATOR, "%")))
+    }
 }
 
 #[derive(Debug, IntoStaticStr, Clone, Copy, Hash, Serialize, Deserialize,PartialEq, Eq)]
@@ -40,14 +191,17 @@ pub enum KeymapState {
     Cmd,
     Find,
     LineInsert,
+    // Typing in modal editing, left with NormalMode
+    Insert,
 } 
 
 #[derive(Debug)]
 pub struct EventHandler {
     action_channel_tx: tokio::sync::mpsc::Sender<String>,
-    keymaps: HashMap<KeymapState, Keymap>,
     reader: EventStream,
     editor: EditorInfo,
+    // Time, cell and count of the last left click, for double and triple clicks
+    last_click: Option<(std::time::Instant, u16, u16, u8)>,
 }
 
 impl EventHandler {
@@ -55,12 +209,24 @@ impl EventHandler {
     {
         Self {
             action_channel_tx,
-            keymaps: open_keymaps("settings/keymap.json").unwrap(),
             reader: EventStream::new(),
             editor,
+            last_click: None,
         }
     }
 
+    // 1 for a single click, 2 and 3 for a double and triple one on the same cell
+    fn click_count(&mut self, col: u16, row: u16) -> u8 {
+        let now = std::time::Instant::now();
+        let count = match self.last_click {
+            Some((at, c, r, n)) if (c, r) == (col, row) && n < 3
+                && now.duration_since(at) < std::time::Duration::from_millis(400) => n + 1,
+            _ => 1,
+        };
+        self.last_click = Some((now, col, row, count));
+        count
+    }
+
     pub async fn run(&mut self) -> Result<()> {
         while let Some(event) = self.reader.next().await {
             {
@@ -69,24 +235,45 @@ impl EventHandler {
                     return Ok(());
                 }
             }
-            let state = self.editor.state.lock().await;
-            let keymap = self.keymaps.get(&state).unwrap();
+            let state = *self.editor.state.lock().await;
             if let Ok(event) = event {
                 match event {
                     event::Event::Key(event) => {
                         let key = Keymap::read(event);
                         if let Some(key) = key {
-                            if let Some(action) = keymap.get_action(&key) {
+                            let action = self.editor.keymaps.lock().await.get(&state).unwrap().get_action(&key);
+                            if let Some(action) = action {
                                 self.action_channel_tx.send(action).await?;
                             }
                         }
                     }
+                    event::Event::Mouse(event) => {
+                        let (col, row) = (event.column, event.row);
+                        let action = match event.kind {
+                            MouseEventKind::Down(MouseButton::Left) if row == 0 => format!("ClickTab({})", col),
+                            MouseEventKind::Down(MouseButton::Left) => format!("MouseDown({},{},{})", col, row, self.click_count(col, row)),
+                            MouseEventKind::Drag(MouseButton::Left) => format!("MouseDrag({},{})", col, row),
+                            MouseEventKind::ScrollUp => "ScrollUp".to_string(),
+                            MouseEventKind::ScrollDown => "ScrollDown".to_string(),
+                            _ => continue,
+                        };
+                        self.action_channel_tx.send(action).await?;
+                    }
+                    event::Event::Paste(text) => {
+                        let action = match state {
+                            KeymapState::LineInsert => "LinePaste",
+                            _ => "PasteText",
+                        };
+                        self.action_channel_tx.send(format!("{}({})", action, transform::url_encode(&text))).await?;
+                    }
+                    event::Event::FocusGained => {
+                        self.action_channel_tx.send("CheckDisk".to_string()).await?;
+                    }
+                    event::Event::FocusLost if self.editor.setting.lock().unwrap().autosave_on_focus_lost => {
+                        self.action_channel_tx.send("Autosave".to_string()).await?;
+                    }
                     event::Event::Resize(_, _) => {
                         let size = terminal::size().unwrap();
-                        self.editor.size = Size {
-                            width: size.0,
-                            height: size.1,
-                        };
                         self.action_channel_tx.send(
                             format!("Resize({},{})", size.0, size.1)
                         ).await?;
@@ -103,57 +290,249 @@ impl EventHandler {
 #[derive(Debug, Clone)]
 pub struct EditorInfo
 {
-    pub size: Size,
-    pub setting: Setting,
+    // The terminal's, updated on Resize
+    pub size: Arc<std::sync::Mutex<Size>>,
+    // Replaced by SetWorkspace and WriteSettings; read it through `setting()`
+    pub setting: Arc<std::sync::Mutex<Setting>>,
     pub state: Arc<Mutex<KeymapState>>,
     pub running: Arc<Mutex<bool>>,
     pub alart_tx: mpsc::Sender<Error>,
     pub tabs: Arc<Mutex<Vec<Tab>>>,
-    let 
+    pub tab_idx: Arc<Mutex<usize>>,
     pub line_input: Arc<Mutex<lineinput::LineInput>>,
+    pub jobs: Arc<Mutex<job::Jobs>>,
+    pub middleware: Arc<Mutex<middleware::Pipeline>>,
+    pub lsp: Arc<Mutex<lsp::Clients>>,
+    pub popup: Arc<Mutex<Option<popup::Popup>>>,
+    pub workspace: Arc<Mutex<workspace::Workspace>>,
+    pub keymaps: Arc<Mutex<HashMap<KeymapState, Keymap>>>,
+    pub tab_kinds: Arc<Mutex<tab::Registry>>,
+    // Panes shown at once, a single one of the focused tab until Split
+    pub layout: Arc<std::sync::Mutex<layout::Layout>>,
+}
+
+impl EditorInfo {
+    // What a tab gets between the tab bar and the status bar
+    pub fn tab_size(&self) -> Size {
+        let mut size = *self.size.lock().unwrap();
+        size.height = size.height.saturating_sub(2);
+        size
+    }
+
+    pub fn setting(&self) -> Setting {
+        self.setting.lock().unwrap().clone()
+    }
 }
 
 async fn process_action(
     mut action_rx: Receiver<String>, 
+    mut job_rx: mpsc::UnboundedReceiver<job::JobEvent>,
     editor: EditorInfo,
+    mut journal: Option<journal::Journal>,
 ) 
 {
-    type F = Box<dyn FnMut(&Action) -> Result<Vec<actions::ActionReturn>>>;
+    type F = Box<dyn FnMut(&Action) -> Result<Vec<actions::ActionReturn>> + Send>;
     let mut continued = false;
-    let mut pre_selected_action = None;
-    let mut tab_idx = 0;
-    let mut clear = false;
+    let mut pending_actions: VecDeque<Action> = VecDeque::new();
+    let mut jumps: Vec<Location> = Vec::new();
+    // Previously focused tab, for LastTab; None once it was closed
+    let mut last_tab: Option<usize> = None;
+    let mut last_edit = repeat::LastEdit::default();
+    // Where the panes were last put, so tabs are only moved when it changes
+    let mut placed: Vec<layout::Placed> = Vec::new();
+    // Running FileJobs; buffers stay locked until the last one finishes
+    let mut file_jobs: HashSet<usize> = HashSet::new();
     let mut action_map: HashMap<&str, F>
         = HashMap::new();
     action_map.insert("NormalMode", Box::new(actions::normal_mode));
     action_map.insert("CmdMode", Box::new(actions::cmd_mode));
+    action_map.insert("InsertMode", Box::new(actions::insert_mode));
     action_map.insert("Quit", Box::new(actions::quit));
     action_map.insert("FindMode", Box::new(actions::find_mode));
+    action_map.insert("FindAccept", Box::new(actions::find_exit));
+    action_map.insert("FindCancel", Box::new(actions::find_exit));
     action_map.insert("LineMode", Box::new(actions::line_mode));
     action_map.insert("NextTab", Box::new(actions::next_tab));
     action_map.insert("PrevTab", Box::new(actions::prev_tab));
+    action_map.insert("LastTab", Box::new(actions::last_tab));
+    action_map.insert("RenameTab", Box::new(actions::rename_tab));
+    action_map.insert("Resize", Box::new(actions::resize));
+    action_map.insert("CheckDisk", Box::new(actions::check_disk));
+    action_map.insert("RestoreSession", Box::new(actions::restore_session));
+    action_map.insert("Autosave", Box::new(actions::autosave));
+    action_map.insert("ClickTab", Box::new(actions::click_tab));
+    action_map.insert("GotoTab", Box::new(actions::goto_tab));
+    action_map.insert("DiffBuffers", Box::new(actions::diff_buffers));
+    action_map.insert("Split", Box::new(actions::split));
+    action_map.insert("NextPane", Box::new(actions::next_pane));
+    action_map.insert("PrevPane", Box::new(actions::prev_pane));
+    action_map.insert("ClosePane", Box::new(actions::close_pane));
+    action_map.insert("MoveTabLeft", Box::new(actions::move_tab_left));
+    action_map.insert("MoveTabRight", Box::new(actions::move_tab_right));
     action_map.insert("Open", Box::new(actions::open));
+    action_map.insert("OpenRemote", Box::new(actions::new_remote));
+    action_map.insert("OpenEncrypted", Box::new(actions::open_encrypted));
+    action_map.insert("OpenDecrypted", Box::new(actions::new_encrypted));
     action_map.insert("CloseTab", Box::new(actions::close_tab));
+    action_map.insert("CloseOtherTabs", Box::new(actions::close_other_tabs));
+    action_map.insert("CloseTabsRight", Box::new(actions::close_tabs_right));
+    action_map.insert("NewView", Box::new(actions::new_view));
     action_map.insert("Shell", Box::new(actions::new_shell));
+    action_map.insert("NewTab", Box::new(actions::new_tab));
+    action_map.insert("NewScratch", Box::new(actions::new_scratch));
+    action_map.insert("RunCommand", Box::new(actions::run_command));
+    action_map.insert("CommandOutput", Box::new(actions::command_output));
+    action_map.insert("BookmarkList", Box::new(actions::bookmark_list));
+    action_map.insert("CancelJob", Box::new(actions::cancel_job));
+    action_map.insert("ShowPopup", Box::new(actions::show_popup));
+    action_map.insert("OpenAt", Box::new(actions::open_at));
+    action_map.insert("ShowResults", Box::new(actions::show_results));
+    action_map.insert("JumpBack", Box::new(actions::jump_back));
+    action_map.insert("GitStatus", Box::new(actions::git_status));
+    action_map.insert("GitCommit", Box::new(actions::git_commit));
+    action_map.insert("Help", Box::new(actions::help));
+    action_map.insert("Messages", Box::new(actions::messages));
+    action_map.insert("TutorProgress", Box::new(actions::tutor_progress));
+    action_map.insert("ToggleProfiler", Box::new(actions::toggle_profiler));
+    action_map.insert("ToggleScreenReader", Box::new(actions::toggle_screen_reader));
+    action_map.insert("Calc", Box::new(actions::calc));
+    action_map.insert("CalcInsert", Box::new(actions::calc_insert));
+    let setting = editor.setting.clone();
+    action_map.insert("Build", Box::new(move |a: &Action| actions::build(a, &setting.lock().unwrap().build_command)));
     
     loop {
-        let mut line_input = editor.line_input.lock().await;
         if continued {
             continued = false;
         }
-        if clear {
-            clear = false;
-        }
-        let action = if let Some(a) = pre_selected_action {
-            pre_selected_action = None;
-            a
+        // Queued: run by an earlier action or a job; replayed: a copy queued by RepeatLast
+        let mut replayed = false;
+        let (action, queued) = if let Some(a) = pending_actions.pop_front() {
+            replayed = last_edit.take_replayed();
+            (a, true)
         } else {
-            let action = action_rx.recv().await.unwrap();
-            parse_action(&action, &line_input.text, tab_idx).unwrap()
+            (tokio::select! {
+                action = action_rx.recv() => {
+                    let Some(action) = action else { return; };
+                    let tab_idx = *editor.tab_idx.lock().await;
+                    let line_input = editor.line_input.lock().await;
+                    match parse_action(&action, line_input.text(), tab_idx) {
+                        Ok(a) => a,
+                        Err(e) => {
+                            editor.alart_tx.send(e).await.unwrap();
+                            continue;
+                        }
+                    }
+                }
+                event = job_rx.recv() => {
+                    let Some(event) = event else { continue; };
+                    let mut jobs = editor.jobs.lock().await;
+                    match event {
+                        job::JobEvent::Progress(id, progress, message) => {
+                            jobs.update(id, progress, message);
+                        }
+                        // Left over from a cancelled job otherwise
+                        job::JobEvent::Actions(id, actions) => {
+                            if jobs.list.iter().any(|j| j.id == id) {
+                                pending_actions.extend(actions);
+                            }
+                        }
+                        job::JobEvent::Done(id, result) => {
+                            let name = match jobs.finish(id) {
+                                Some(job) => job.name,
+                                None => continue,
+                            };
+                            drop(jobs);
+                            if file_jobs.remove(&id) && file_jobs.is_empty() {
+                                unlock_buffers(&mut editor.tabs.lock().await, &editor, &mut pending_actions).await;
+                            }
+                            match result {
+                                Ok(actions) => {
+                                    editor.line_input.lock().await.notice = format!("{} finished", name);
+                                    pending_actions.extend(actions);
+                                }
+                                Err(e) => {
+                                    editor.alart_tx.send(e.context(name)).await.unwrap();
+                                }
+                            }
+                        }
+                    }
+                    continue;
+                }
+            }, false)
+        };
+        // Writing stops at the first failure, which is alerted
+        let secret = journal.is_some() && editor.line_input.lock().await.secret;
+        if let Some(Err(e)) = journal.as_mut().map(|j| j.record(&action, queued, secret)) {
+            journal = None;
+            editor.alart_tx.send(e.context("Journal")).await.unwrap();
+        }
+        let started = std::time::Instant::now();
+        let (action, target) = actions::untarget(action);
+        let ctx = middleware::Context {
+            state: *editor.state.lock().await,
+            tab_idx: *editor.tab_idx.lock().await,
         };
+        let action = match editor.middleware.lock().await.run(action, &ctx) {
+            middleware::Flow::Continue(a) => a,
+            middleware::Flow::Veto(reason) => {
+                if let Some(reason) = reason {
+                    editor.line_input.lock().await.notice = reason;
+                }
+                continue;
+            }
+        };
+        let waiting = std::time::Instant::now();
         let mut state = editor.state.lock().await;
         let mut running = editor.running.lock().await;
         let mut tabs = editor.tabs.lock().await;
+        let mut tab_idx = editor.tab_idx.lock().await;
+        let mut line_input = editor.line_input.lock().await;
+        profiler::record(profiler::Metric::LockWait, waiting.elapsed());
+        // A click in another pane focuses it first
+        if action.name == "MouseDown" && target.is_none() {
+            let arg = |i: usize| action.args.get(i).cloned().flatten().and_then(|s| s.parse::<u16>().ok()).unwrap_or(0);
+            let clicked = editor.layout.lock().unwrap().pane_at(Pos { row: 1, col: 0 }, editor.tab_size(), arg(0), arg(1));
+            if let Some(i) = clicked.filter(|i| *i < tabs.len()) {
+                *tab_idx = i;
+            }
+        }
+        let target_idx = match target {
+            Some(id) => match tabs.iter().position(|t| matches!(t, Tab::Buffer(b) if b.id == id)) {
+                Some(i) => i,
+                None => {
+                    editor.alart_tx.send(anyhow::anyhow!("{}: buffer was closed", action.name)).await.unwrap();
+                    continue;
+                }
+            },
+            None => *tab_idx,
+        };
+        // A picker popup takes the cursor keys and Enter while it is shown
+        {
+            let mut popup = editor.popup.lock().await;
+            match popup.as_mut().map(|p| p.navigate(&action.name)) {
+                Some(popup::Pick::Moved) => continue,
+                Some(popup::Pick::Picked(a)) => {
+                    *popup = None;
+                    pending_actions.push_back(a);
+                    continue;
+                }
+                _ => *popup = None,
+            }
+        }
+        if action.name == "RepeatLast" {
+            let times = action.args.first().cloned().flatten().and_then(|n| n.parse().ok()).unwrap_or(1);
+            if last_edit.is_empty() {
+                line_input.notice = "Nothing to repeat".to_string();
+            }
+            for a in last_edit.replay(times).into_iter().rev() {
+                pending_actions.push_front(a);
+            }
+            continue;
+        }
+        let edits_before = match &tabs[target_idx] {
+            Tab::Buffer(b) => Some(b.edits()),
+            _ => None,
+        };
         let func = action_map.get_mut(action.name.as_str());
         let mut return_queue = Vec::new();
         if let Some(f) = func {
@@ -163,39 +542,59 @@ async fn process_action(
             };
             return_queue.extend(returns);
         };
-        return_queue.extend(match tabs[tab_idx] {
-            Tab::Buffer(ref mut buffer) => {
-                buffer.process_action(&action).await.unwrap()
-            }
-            Tab::Directory(ref mut directory) => {
-                directory.process_action(&action).await.unwrap()
-            }
-            Tab::Shell(ref mut shell) => {
-                shell.process_action(&action).await.unwrap()
-        }
+        return_queue.extend(match tabs[target_idx].view_mut().process_action(&action).await {
+            Ok(r) => r,
+            Err(e) => vec![actions::ActionReturn::Err(e)],
         });
-        return_queue.extend(renderer.line_input.process_action(&action, tab_idx).unwrap());
+        return_queue.extend(line_input.process_action(&action, *tab_idx).unwrap());
+        // Edits made by jobs, e.g. formatting, are not something the user typed
+        let edited = target.is_none() && match &tabs[target_idx] {
+            Tab::Buffer(b) => edits_before.is_some_and(|e| e != b.edits()),
+            _ => false,
+        };
+        if !replayed {
+            last_edit.record(&action, edited);
+        }
+        let focused = *tab_idx;
+        // Set when tabs were closed or moved, so `focused` is no longer the
+        // index of the tab it was
+        let mut reindexed = false;
         for r in return_queue {
             match r {
-                actions::ActionReturn::Stop => {
-                    *running = false;
-                    return ();
-                }
                 actions::ActionReturn::Continue => {
                     continued = true;
                 }
                 actions::ActionReturn::Excute(a) => {
-                    pre_selected_action = Some(a);
+                    pending_actions.push_back(a);
                 }
                 actions::ActionReturn::Err(e) => {
                     editor.alart_tx.send(e).await.unwrap();
                 }
                 actions::ActionReturn::NewBuffer(path) => {
-                    let mut size = editor.size;
-                    size.height -= 2;
+                    let size = editor.tab_size();
                     match path {
                         Some(path) => {
-                            let new_buffer = match Buffer::from_file(size, Pos{row: 1, col: 0}, &path, editor.setting.clone(), tabs.len()) {
+                            // A file that is already open gets another view of the same text
+                            let canonical = path.canonicalize().ok();
+                            let open = tabs.iter().find_map(|t| match t {
+                                Tab::Buffer(b) if canonical.is_some() && b.path().and_then(|p| p.canonicalize().ok()) == canonical => Some(b),
+                                _ => None,
+                            });
+                            if let Some(buffer) = open {
+                                let view = buffer.new_view(tabs.len());
+                                tabs.push(Tab::Buffer(view));
+                                *tab_idx = tabs.len() - 1;
+                                continue;
+                            }
+                            // The plaintext only ever lives in the buffer, see OpenEncrypted
+                            if let Some(cipher) = crypt::Cipher::detect(&path) {
+                                *state = KeymapState::LineInsert;
+                                line_input.notice = cipher.prompt(&path);
+                                line_input.action = Some(format!("OpenEncrypted({},$line)", path.display()));
+                                line_input.secret = cipher.secret_input();
+                                continue;
+                            }
+                            let new_buffer = match Buffer::from_file(size, Pos{row: 1, col: 0}, &path, editor.setting(), tabs.len()) {
                                 Ok(b) => b,
                                 Err(e) => {
                                     editor.alart_tx.send(e).await.unwrap();
@@ -205,31 +604,88 @@ async fn process_action(
                             tabs.push(Tab::Buffer(new_buffer));
                         }
                         None => {
-                            let new_buffer = Buffer::new(size, Pos{row: 1, col: 0}, editor.setting.clone(), tabs.len());
+                            let new_buffer = Buffer::new(size, Pos{row: 1, col: 0}, editor.setting(), tabs.len());
                             tabs.push(Tab::Buffer(new_buffer));
                         }
                     }
                     
-                    tab_idx = tabs.len() - 1;
+                    *tab_idx = tabs.len() - 1;
                 }
                 actions::ActionReturn::State(s) => {
                     *state = s;
                 }
                 actions::ActionReturn::Notice(s) => {
-                    renderer.line_input.notice = s;
+                    line_input.notice = s;
                 }
+                actions::ActionReturn::Matches(counts) if *state == KeymapState::Find => {
+                    line_input.notice = search::prompt(&counts, line_input.text());
+                }
+                actions::ActionReturn::Matches(_) => {}
                 actions::ActionReturn::ExcuteLine(s) => {
-                    renderer.line_input.action = Some(s);
+                    line_input.action = Some(s);
+                    line_input.secret = false;
                 }
                 actions::ActionReturn::ChangeTab(i) => {
                     let len = tabs.len() as isize;
-                    tab_idx = ((tab_idx as isize + i + len) % len) as usize;
-                    clear = true;
+                    *tab_idx = ((*tab_idx as isize + i + len) % len) as usize;
+                }
+                actions::ActionReturn::NewRemote(remote, text) => {
+                    let size = editor.tab_size();
+                    let mut buffer = Buffer::from_text(size, Pos{row: 1, col: 0}, &text, editor.setting(), tabs.len());
+                    buffer.set_remote(remote);
+                    tabs.push(Tab::Buffer(buffer));
+                    *tab_idx = tabs.len() - 1;
+                }
+                actions::ActionReturn::NewEncrypted(path, encryption, text) => {
+                    let size = editor.tab_size();
+                    let mut buffer = Buffer::from_text(size, Pos{row: 1, col: 0}, &text, editor.setting(), tabs.len());
+                    buffer.set_encryption(path, encryption);
+                    tabs.push(Tab::Buffer(buffer));
+                    *tab_idx = tabs.len() - 1;
+                }
+                actions::ActionReturn::NewScratch(name, text) => {
+                    let open = tabs.iter().position(|t| matches!(t, Tab::Buffer(b) if b.scratch() == Some(name.as_str())));
+                    let i = match open {
+                        Some(i) => i,
+                        None => {
+                            let size = editor.tab_size();
+                            let mut buffer = Buffer::new(size, Pos{row: 1, col: 0}, editor.setting(), tabs.len());
+                            buffer.set_scratch(name);
+                            tabs.push(Tab::Buffer(buffer));
+                            tabs.len() - 1
+                        }
+                    };
+                    if let (Tab::Buffer(buffer), Some(text)) = (&mut tabs[i], text) {
+                        buffer.set_text(&text);
+                    }
+                    *tab_idx = i;
+                }
+                actions::ActionReturn::CommandOutput(name, text, title, first) => {
+                    let open = tabs.iter().position(|t| matches!(t, Tab::Buffer(b) if b.scratch() == Some(name.as_str())));
+                    let i = match open {
+                        Some(i) => i,
+                        // The tab was closed while the command ran
+                        None if !first => continue,
+                        None => {
+                            let size = editor.tab_size();
+                            let mut buffer = Buffer::new(size, Pos{row: 1, col: 0}, editor.setting(), tabs.len());
+                            buffer.set_scratch(name);
+                            buffer.set_read_only();
+                            tabs.push(Tab::Buffer(buffer));
+                            *tab_idx = tabs.len() - 1;
+                            tabs.len() - 1
+                        }
+                    };
+                    if let Tab::Buffer(buffer) = &mut tabs[i] {
+                        buffer.append_text(&text);
+                    }
+                    if title.is_some() {
+                        tabs[i].view_mut().rename(title);
+                    }
                 }
                 actions::ActionReturn::NewDir(path) => {
-                    let mut size = editor.size;
-                    size.height -= 2;
-                    let new_dir = match directory::Directory::new(path, Pos{row: 1, col: 0}, size, tabs.len()) {
+                    let size = editor.tab_size();
+                    let new_dir = match directory::Directory::new(path, Pos{row: 1, col: 0}, size, tabs.len(), editor.setting().close_directory_on_open) {
                         Ok(d) => d,
                         Err(e) => {
                             editor.alart_tx.send(e).await.unwrap();
@@ -237,48 +693,552 @@ async fn process_action(
                         }
                     };
                     tabs.push(Tab::Directory(new_dir));
-                    tab_idx = tabs.len() - 1;
+                    *tab_idx = tabs.len() - 1;
+                }
+                actions::ActionReturn::RevealFile(path) => {
+                    let Some(file) = path.canonicalize().ok() else { continue };
+                    let dir = file.parent().unwrap_or(&file).to_path_buf();
+                    let open = tabs.iter().position(|t| matches!(t, Tab::Directory(d) if d.path().canonicalize().ok().as_ref() == Some(&dir)));
+                    let i = match open {
+                        Some(i) => i,
+                        None => {
+                            let size = editor.tab_size();
+                            match directory::Directory::new(dir, Pos{row: 1, col: 0}, size, tabs.len(), editor.setting().close_directory_on_open) {
+                                Ok(d) => tabs.push(Tab::Directory(d)),
+                                Err(e) => {
+                                    editor.alart_tx.send(e).await.unwrap();
+                                    continue;
+                                }
+                            }
+                            tabs.len() - 1
+                        }
+                    };
+                    if let Tab::Directory(d) = &mut tabs[i] {
+                        d.select(&file);
+                    }
+                    *tab_idx = i;
+                }
+                actions::ActionReturn::Quit(answer) => {
+                    let unsaved: Vec<usize> = (0..tabs.len()).filter(|i| tabs[*i].is_unsaved()).collect();
+                    if !unsaved.is_empty() && answer != actions::Unsaved::Discard {
+                        let quit = Action { name: "Quit".to_string(), args: vec![] };
+                        if answer == actions::Unsaved::Save {
+                            save_unsaved(&tabs, &unsaved, &mut tab_idx, &mut pending_actions, quit);
+                            continue;
+                        }
+                        let names: Vec<String> = unsaved.iter().map(|i| tabs[*i].view().label()).collect();
+                        *state = KeymapState::LineInsert;
+                        line_input.notice = format!("{} unsaved: save, discard or cancel? (s/d/c): ", names.join(", "));
+                        line_input.action = Some("Quit($line)".to_string());
+                        continue;
+                    }
+                    *running = false;
+                    return;
                 }
-                actions::ActionReturn::CloseTab(i) => {
+                actions::ActionReturn::CloseTab(i, answer) => {
+                    if i >= tabs.len() {
+                        continue;
+                    }
+                    if tabs[i].is_unsaved() && answer != actions::Unsaved::Discard {
+                        let close = Action { name: "CloseTab".to_string(), args: vec![Some(i.to_string())] };
+                        if answer == actions::Unsaved::Save {
+                            save_unsaved(&tabs, &[i], &mut tab_idx, &mut pending_actions, close);
+                            continue;
+                        }
+                        *state = KeymapState::LineInsert;
+                        line_input.notice = format!("{} is unsaved: save, discard or cancel? (s/d/c): ", tabs[i].view().label());
+                        line_input.action = Some(format!("CloseTab({},$line)", i));
+                        continue;
+                    }
                     tabs.remove(i);
-                    if tab_idx >= i {
-                        tab_idx -= 1;
+                    editor.layout.lock().unwrap().close(i);
+                    if *tab_idx >= i && *tab_idx > 0 {
+                        *tab_idx -= 1;
                     }
-                    if tab_idx == i {
-                        clear = true;
+                    last_tab = last_tab.and_then(|t| match t.cmp(&i) {
+                        std::cmp::Ordering::Less => Some(t),
+                        std::cmp::Ordering::Equal => None,
+                        std::cmp::Ordering::Greater => Some(t - 1),
+                    });
+                    reindexed = true;
+                    tab::reindex(&mut tabs);
+                    if tabs.len() == 0 {
+                        *running = false;
+                        return ();
                     }
-                    for i in 0..tabs.len() {
-                        match &mut tabs[i] {
-                            Tab::Buffer(b) => {
-                                b.tab_idx = i;
+                }
+                actions::ActionReturn::CloseOthers(right_only, force) => {
+                    let current = *tab_idx;
+                    let keep = |i: usize| if right_only { i <= current } else { i == current };
+                    let unsaved = tabs.iter().enumerate().filter(|(i, t)| !keep(*i) && t.is_unsaved()).count();
+                    if unsaved > 0 && !force {
+                        let name = if right_only { "CloseTabsRight" } else { "CloseOtherTabs" };
+                        *state = KeymapState::LineInsert;
+                        line_input.notice = format!("{} unsaved buffer(s) will be closed, continue? (y/n): ", unsaved);
+                        line_input.action = Some(format!("{}($line)", name));
+                        continue;
+                    }
+                    let mut i = 0;
+                    tabs.retain(|_| {
+                        i += 1;
+                        keep(i - 1)
+                    });
+                    *tab_idx = if right_only { current } else { 0 };
+                    last_tab = last_tab.filter(|t| right_only && *t <= current);
+                    reindexed = true;
+                    tab::reindex(&mut tabs);
+                    *editor.layout.lock().unwrap() = layout::Layout::Pane(*tab_idx);
+                }
+                actions::ActionReturn::CheckDisk(answer) => {
+                    let changed: Vec<&mut Buffer> = tabs.iter_mut().filter_map(|t| match t {
+                        Tab::Buffer(b) if b.changed_on_disk() => Some(b),
+                        _ => None,
+                    }).collect();
+                    match answer {
+                        _ if changed.is_empty() => {}
+                        None => {
+                            // Reloading drops unsaved edits, so those buffers say so
+                            let names: Vec<String> = changed.iter().map(|b| match b.is_saved() {
+                                true => b.name(),
+                                false => format!("{} (unsaved, reloading drops the edits)", b.name()),
+                            }).collect();
+                            *state = KeymapState::LineInsert;
+                            line_input.notice = format!("{} changed on disk, reload? (y/n): ", names.join(", "));
+                            line_input.action = Some("CheckDisk($line)".to_string());
+                        }
+                        Some(true) => {
+                            for buffer in changed {
+                                if let Err(e) = buffer.reload() {
+                                    editor.alart_tx.send(e).await.unwrap();
+                                }
                             }
-                            Tab::Directory(d) => {
-                                d.tab_idx = i;
+                            line_input.notice = "Reloaded".to_string();
+                        }
+                        Some(false) => {
+                            for buffer in changed {
+                                buffer.ignore_disk_change();
                             }
-                            Tab::Shell(s) => {
-                                s.tab_idx = i;
+                        }
+                    }
+                }
+                actions::ActionReturn::ListBookmarks(picked) => {
+                    let bookmarks: Vec<(usize, usize)> = tabs.iter().enumerate()
+                        .flat_map(|(i, t)| match t {
+                            Tab::Buffer(b) => b.bookmarks().into_iter().map(|l| (i, l)).collect(),
+                            _ => Vec::new(),
+                        })
+                        .collect();
+                    match picked {
+                        Some(n) => {
+                            if let Some(&(i, line)) = bookmarks.get(n) {
+                                if let Tab::Buffer(b) = &mut tabs[i] {
+                                    b.goto(line, 0);
+                                }
+                                *tab_idx = i;
                             }
                         }
+                        None if bookmarks.is_empty() => line_input.notice = "No bookmarks".to_string(),
+                        None => {
+                            let lines = bookmarks.iter().map(|&(i, line)| match &tabs[i] {
+                                Tab::Buffer(b) => format!("{}:{}  {}", b.name(), line + 1, b.line_text(line).trim()),
+                                _ => String::new(),
+                            }).collect();
+                            *editor.popup.lock().await = Some(popup::Popup::picker("Bookmarks", lines, "BookmarkList"));
+                        }
                     }
-                    if tabs.len() == 0 {
-                        *running = false;
-                        return ();
+                }
+                actions::ActionReturn::ClickTab(col) => {
+                    if let Some(n) = render::tab_at(editor.size.lock().unwrap().width, tabs.len(), col) {
+                        *tab_idx = n;
+                    }
+                }
+                actions::ActionReturn::GotoTab(n) => {
+                    if n < tabs.len() {
+                        *tab_idx = n;
+                    }
+                }
+                actions::ActionReturn::MoveTab(offset) => {
+                    let target = *tab_idx as isize + offset;
+                    if target >= 0 && (target as usize) < tabs.len() {
+                        tabs.swap(*tab_idx, target as usize);
+                        editor.layout.lock().unwrap().swap(*tab_idx, target as usize);
+                        if last_tab == Some(target as usize) {
+                            last_tab = Some(*tab_idx);
+                        }
+                        reindexed = true;
+                        *tab_idx = target as usize;
+                        tab::reindex(&mut tabs);
+                    }
+                }
+                actions::ActionReturn::SetWorkspace(root) => {
+                    let loaded = workspace::Workspace::open(&root).and_then(|w| {
+                        let setting = w.setting()?;
+                        let keymaps = w.keymaps(setting.modal)?;
+                        Ok((w, setting, keymaps))
+                    });
+                    match loaded {
+                        Ok((w, setting, keymaps)) => {
+                            line_input.notice = format!("Workspace {} ({})", w.name(), w.root.display());
+                            *editor.setting.lock().unwrap() = setting;
+                            *editor.keymaps.lock().await = keymaps;
+                            *editor.workspace.lock().await = w;
+                        }
+                        Err(e) => {
+                            editor.alart_tx.send(e).await.unwrap();
+                        }
+                    }
+                }
+                actions::ActionReturn::WriteSettings(settings) => {
+                    let keys = settings.keys().cloned().collect::<Vec<_>>().join(", ");
+                    let mut workspace = editor.workspace.lock().await;
+                    match workspace.write_settings(&settings).and_then(|file| Ok((file, workspace.setting()?))) {
+                        Ok((file, setting)) => {
+                            line_input.notice = format!("Wrote {} to {}", keys, file.display());
+                            *editor.setting.lock().unwrap() = setting;
+                        }
+                        Err(e) => {
+                            editor.alart_tx.send(e).await.unwrap();
+                        }
+                    }
+                }
+                actions::ActionReturn::NewView => {
+                    if let Tab::Buffer(buffer) = &tabs[*tab_idx] {
+                        let view = buffer.new_view(tabs.len());
+                        tabs.push(Tab::Buffer(view));
+                        *tab_idx = tabs.len() - 1;
                     }
                 }
-                actions::ActionReturn::NewShell => {
-                    let mut size = editor.size;
-                    size.height -= 2;
-                    let shell = tab::shell::Shell::new(Pos{row: 1, col: 0}, size, tabs.len());
-                    tabs.push(Tab::Shell(shell));
-                    tab_idx = tabs.len() - 1;
+                actions::ActionReturn::DiffBuffers(a, b) => {
+                    let buffer = |i: usize| match tabs.get(i) {
+                        Some(Tab::Buffer(buffer)) => Ok((buffer.name(), buffer.contents())),
+                        Some(_) => Err(anyhow::anyhow!("DiffBuffers: tab {} is not a buffer", i + 1)),
+                        None => Err(anyhow::anyhow!("DiffBuffers: there is no tab {}", i + 1)),
+                    };
+                    match buffer(a.unwrap_or(*tab_idx)).and_then(|left| Ok((left, buffer(b)?))) {
+                        Ok(((left_name, left), (right_name, right))) => {
+                            let diff = tab::diff::Diff::new((left_name, right_name), &left, &right, Pos{row: 1, col: 0}, editor.tab_size(), tabs.len());
+                            tabs.push(Tab::Custom(Box::new(diff)));
+                            *tab_idx = tabs.len() - 1;
+                        }
+                        Err(e) => editor.alart_tx.send(e).await.unwrap(),
+                    }
+                }
+                actions::ActionReturn::NewTab(kind, args) => {
+                    let size = editor.tab_size();
+                    match editor.tab_kinds.lock().await.create(&kind, &args, Pos{row: 1, col: 0}, size, tabs.len()).await {
+                        Ok(tab) => {
+                            tabs.push(tab);
+                            *tab_idx = tabs.len() - 1;
+                        }
+                        Err(e) => editor.alart_tx.send(e).await.unwrap(),
+                    }
                 }
+                actions::ActionReturn::Job(name, task) => {
+                    editor.jobs.lock().await.spawn(&name, task);
+                }
+                actions::ActionReturn::FileJob(name, task) => {
+                    for tab in tabs.iter_mut() {
+                        if let Tab::Buffer(b) = tab {
+                            b.lock(&name);
+                        }
+                    }
+                    file_jobs.insert(editor.jobs.lock().await.spawn(&name, task));
+                }
+                actions::ActionReturn::Lsp(request) => {
+                    let clients = Arc::clone(&editor.lsp);
+                    editor.jobs.lock().await.spawn("lsp", Box::new(move |_| Box::pin(lsp::run(clients, request))));
+                }
+                actions::ActionReturn::Popup(popup) => {
+                    *editor.popup.lock().await = popup;
+                }
+                actions::ActionReturn::OpenAt(location) => {
+                    if let Tab::Buffer(ref buffer) = tabs[*tab_idx] {
+                        jumps.extend(buffer.location());
+                    }
+                    if let Err(e) = open_location(&mut tabs, &mut tab_idx, &location, &editor) {
+                        editor.alart_tx.send(e).await.unwrap();
+                    }
+                }
+                actions::ActionReturn::JumpBack => {
+                    match jumps.pop() {
+                        Some(location) => {
+                            if let Err(e) = open_location(&mut tabs, &mut tab_idx, &location, &editor) {
+                                editor.alart_tx.send(e).await.unwrap();
+                            }
+                        }
+                        None => line_input.notice = "Jump list is empty".to_string(),
+                    }
+                }
+                actions::ActionReturn::NewResults(title, items) => {
+                    let size = editor.tab_size();
+                    let results = tab::results::Results::new(title, items, Pos{row: 1, col: 0}, size, tabs.len());
+                    tabs.push(Tab::Results(results));
+                    *tab_idx = tabs.len() - 1;
+                }
+                actions::ActionReturn::CancelJob(id) => {
+                    let cancelled = editor.jobs.lock().await.cancel(id);
+                    match cancelled {
+                        // An aborted job sends no Done, which would unlock its files
+                        Some(job) => {
+                            if file_jobs.remove(&job.id) && file_jobs.is_empty() {
+                                unlock_buffers(&mut tabs, &editor, &mut pending_actions).await;
+                            }
+                            line_input.notice = format!("{} cancelled", job.name);
+                        }
+                        None => line_input.notice = "No running job".to_string(),
+                    }
+                }
+                actions::ActionReturn::Resize(size) => {
+                    *editor.size.lock().unwrap() = size;
+                    line_input.len = size.width as usize;
+                    let tab_size = editor.tab_size();
+                    for tab in tabs.iter_mut() {
+                        tab.view_mut().resize(tab_size);
+                    }
+                    placed.clear();
+                }
+                actions::ActionReturn::RenameTab(name) => {
+                    tabs[*tab_idx].view_mut().rename(name);
+                }
+                actions::ActionReturn::Split(direction) => {
+                    let mut layout = editor.layout.lock().unwrap();
+                    let shown = layout.tabs();
+                    // The tab focused last, or else the first one not on screen
+                    let other = last_tab.into_iter().chain(0..tabs.len())
+                        .find(|i| *i < tabs.len() && !shown.contains(i));
+                    match other {
+                        Some(i) => layout.split(*tab_idx, i, direction),
+                        None => line_input.notice = "Every tab is shown, open another one to split".to_string(),
+                    }
+                }
+                actions::ActionReturn::ChangePane(forward) => {
+                    *tab_idx = editor.layout.lock().unwrap().next(*tab_idx, forward);
+                }
+                actions::ActionReturn::ClosePane => {
+                    let mut layout = editor.layout.lock().unwrap();
+                    if layout.is_split() {
+                        let next = layout.next(*tab_idx, true);
+                        layout.remove(*tab_idx);
+                        *tab_idx = next;
+                    }
+                }
+                actions::ActionReturn::Autosave => {
+                    for tab in tabs.iter() {
+                        if let Tab::Buffer(b) = tab {
+                            if b.autosaves() {
+                                pending_actions.push_back(actions::targeted(b.id, Action {
+                                    name: "Save".to_string(),
+                                    args: vec![Some("autosave".to_string())],
+                                }));
+                            }
+                        }
+                    }
+                }
+                actions::ActionReturn::RestoreSession(restore) => {
+                    let root = editor.workspace.lock().await.root.clone();
+                    let Some(session) = session::take_crashed(&root).filter(|_| restore) else { continue };
+                    // The empty buffer the editor started with makes way for the session
+                    if tabs.len() == 1 && matches!(&tabs[0], Tab::Buffer(b) if b.is_blank()) {
+                        tabs.clear();
+                    }
+                    let first = tabs.len();
+                    for saved in &session.tabs {
+                        let opened = match saved.path.is_dir() {
+                            true => directory::Directory::new(saved.path.clone(), Pos{row: 1, col: 0}, editor.tab_size(), tabs.len(), editor.setting().close_directory_on_open)
+                                .map(|d| tabs.push(Tab::Directory(d))),
+                            false => {
+                                let location = Location { path: saved.path.clone(), line: saved.line, col: saved.col, text: String::new() };
+                                open_location(&mut tabs, &mut tab_idx, &location, &editor)
+                            }
+                        };
+                        if let Err(e) = opened {
+                            editor.alart_tx.send(e).await.unwrap();
+                        }
+                    }
+                    if tabs.is_empty() {
+                        tabs.push(Tab::Buffer(Buffer::new(editor.tab_size(), Pos{row: 1, col: 0}, editor.setting(), 0)));
+                    }
+                    tab::reindex(&mut tabs);
+                    *tab_idx = (first + session.focused).min(tabs.len() - 1);
+                    *editor.layout.lock().unwrap() = layout::Layout::Pane(*tab_idx);
+                    line_input.edit.set_history(session.history);
+                    line_input.notice = format!("Restored {} tab(s)", session.tabs.len());
+                }
+                actions::ActionReturn::LastTab => {
+                    if let Some(t) = last_tab.filter(|t| *t < tabs.len()) {
+                        *tab_idx = t;
+                    }
+                }
+            }
+        }
+        start_loading(&mut tabs, &editor).await;
+        for tab in tabs.iter_mut() {
+            if let Tab::Buffer(b) = tab {
+                b.clamp_to_text();
             }
         }
+        // Find mode searches the focused buffer for the line input as it is typed
+        match &mut tabs[*tab_idx] {
+            Tab::Buffer(b) if *state == KeymapState::Find && b.search_query() != Some(line_input.text()) => {
+                if let actions::ActionReturn::Matches(counts) = b.search(line_input.text()) {
+                    line_input.notice = search::prompt(&counts, line_input.text());
+                }
+            }
+            _ => {}
+        }
+        if *tab_idx != focused && !reindexed {
+            last_tab = Some(focused);
+        }
+        // The focused tab is always on screen, in the pane of the one before
+        // unless it already has one
+        {
+            let mut layout = editor.layout.lock().unwrap();
+            layout.show(*tab_idx, focused);
+            let (panes, _) = layout.place(Pos { row: 1, col: 0 }, editor.tab_size());
+            if panes != placed {
+                for pane in &panes {
+                    if let Some(tab) = tabs.get_mut(pane.tab) {
+                        tab.view_mut().set_pos(pane.pos);
+                        tab.view_mut().resize(pane.size);
+                    }
+                }
+                placed = panes;
+            }
+        }
+        profiler::record(profiler::Metric::Action, started.elapsed());
     }
 }
 
-pub async fn run(path: Option<PathBuf>) -> Result<()> {
+// Saves the buffers of the tabs `unsaved` and then runs `then`, which asks
+// again about any that did not get saved. A buffer without a file is focused
+// and asked for a file name instead, leaving `then` to be run again
+fn save_unsaved(tabs: &[Tab], unsaved: &[usize], tab_idx: &mut usize, pending_actions: &mut VecDeque<Action>, then: Action) {
+    let save = |args| Action { name: "Save".to_string(), args };
+    let mut unnamed = None;
+    for i in unsaved {
+        match &tabs[*i] {
+            Tab::Buffer(b) if b.needs_name() => { unnamed.get_or_insert(*i); }
+            Tab::Buffer(b) => pending_actions.push_back(actions::targeted(b.id, save(vec![Some("formatted".to_string())]))),
+            _ => (),
+        }
+    }
+    match unnamed {
+        Some(i) => {
+            *tab_idx = i;
+            pending_actions.push_back(save(vec![]));
+        }
+        None => pending_actions.push_back(then),
+    }
+}
+
+// Once the last FileJob is over, picks up what it wrote
+async fn unlock_buffers(tabs: &mut [Tab], editor: &EditorInfo, pending_actions: &mut VecDeque<Action>) {
+    for tab in tabs.iter_mut() {
+        if let Tab::Buffer(b) = tab {
+            if let Err(e) = b.unlock() {
+                editor.alart_tx.send(e).await.unwrap();
+            }
+        }
+    }
+    // Buffers with unsaved edits are asked about instead
+    pending_actions.push_back(Action { name: "CheckDisk".to_string(), args: vec![] });
+}
+
+// Starts the jobs reading the large files just opened
+async fn start_loading(tabs: &mut [Tab], editor: &EditorInfo) {
+    for tab in tabs.iter_mut() {
+        if let Tab::Buffer(b) = tab {
+            if let Some((name, task)) = b.take_loader() {
+                editor.jobs.lock().await.spawn(&name, task);
+            }
+        }
+    }
+}
+
+// Focuses the buffer showing the location's file, opening it if needed, and moves its cursor there
+fn open_location(tabs: &mut Vec<Tab>, tab_idx: &mut usize, location: &Location, editor: &EditorInfo) -> Result<()> {
+    let target = location.path.canonicalize()?;
+    let found = tabs.iter().position(|t| match t {
+        Tab::Buffer(b) => b.path().and_then(|p| p.canonicalize().ok()).as_ref() == Some(&target),
+        _ => false,
+    });
+    let idx = match found {
+        Some(i) => i,
+        None => {
+            let size = editor.tab_size();
+            let buffer = Buffer::from_file(size, Pos{row: 1, col: 0}, &location.path, editor.setting(), tabs.len())?;
+            tabs.push(Tab::Buffer(buffer));
+            tabs.len() - 1
+        }
+    };
+    *tab_idx = idx;
+    if let Tab::Buffer(ref mut buffer) = tabs[idx] {
+        buffer.goto(location.line, location.col);
+    }
+    Ok(())
+}
+
+// The open tabs for session.rs, files and directories only
+async fn snapshot(editor: &EditorInfo) -> session::Session {
+    let tabs = editor.tabs.lock().await;
+    let focused = *editor.tab_idx.lock().await;
+    let mut session = session::Session::default();
+    for (i, tab) in tabs.iter().enumerate() {
+        let saved = match tab {
+            Tab::Buffer(b) => b.session_location().map(|l| session::SavedTab { path: l.path, line: l.line, col: l.col }),
+            Tab::Directory(d) => Some(session::SavedTab { path: d.path().to_path_buf(), line: 0, col: 0 }),
+            _ => None,
+        };
+        let Some(mut saved) = saved else { continue };
+        if let Ok(path) = saved.path.canonicalize() {
+            saved.path = path;
+        }
+        if i == focused {
+            session.focused = session.tabs.len();
+        }
+        session.tabs.push(saved);
+    }
+    session.history = editor.line_input.lock().await.edit.history().to_vec();
+    session
+}
+
+// Sends Autosave every `autosave` seconds of the settings, read again each
+// time so SetWorkspace and WriteSettings change it; 0 turns it off
+async fn autosave(editor: EditorInfo, action_tx: mpsc::Sender<String>) {
+    loop {
+        let secs = editor.setting.lock().unwrap().autosave;
+        tokio::time::sleep(std::time::Duration::from_secs(secs.max(1))).await;
+        if !*editor.running.lock().await {
+            break;
+        }
+        let on = secs > 0 && editor.setting.lock().unwrap().autosave > 0;
+        if on && action_tx.send("Autosave".to_string()).await.is_err() {
+            break;
+        }
+    }
+}
+
+async fn autosave_session(editor: EditorInfo) {
+    let mut interval = tokio::time::interval(session::SAVE_INTERVAL);
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        if !*editor.running.lock().await {
+            break;
+        }
+        let root = editor.workspace.lock().await.root.clone();
+        if let Err(e) = session::save(&root, &snapshot(&editor).await) {
+            error!("{}: {}", session::SESSION_FILE, e);
+        }
+    }
+}
+
+// `tutor` opens the lesson buffer first and checks the exercises
+pub async fn run(paths: Vec<Location>, tutor: bool, journal: Option<PathBuf>, replay: Option<PathBuf>) -> Result<()> {
     log4rs::init_file("log4rs.yaml", Default::default())?;
+    // Read before the journal is created, which may be the same file
+    let replay = replay.map(|path| journal::read(&path)).transpose()?;
+    let journal = journal.map(|path| journal::Journal::create(&path)).transpose()?;
     let stdout = std::io::stdout();
     let (action_channel_tx, action_channel_rx) = tokio::sync::mpsc::channel(100);
     let (alart_channel_tx, alart_channel_rx) = tokio::sync::mpsc::channel(100);
@@ -287,59 +1247,152 @@ pub async fn run(path: Option<PathBuf>) -> Result<()> {
         width: rawsize.0,
         height: rawsize.1,
     };
-    let setting: Setting = serde_json::from_reader(std::fs::File::open("settings/default.json")?)?;
+    syntax::preload();
+    let workspace = workspace::Workspace::detect(paths.first().map(|l| l.path.as_path()))?;
+    let setting = workspace.setting()?;
+    let keymaps = workspace.keymaps(setting.modal)?;
+    announce::set_enabled(setting.screen_reader);
+    // Asked about once the editor is up; files on the command line come first
+    if session::stash_crashed(&workspace.root) && paths.is_empty() && !tutor {
+        action_channel_tx.try_send("RestoreSession".to_string())?;
+    }
     let mut buffer_size = size;
     buffer_size.height -= 2;
-    let tabs: Vec<Tab> = match path {
-        Some(p) => {
-            if p.is_dir() {
-                vec![Tab::Directory(directory::Directory::new(p, Pos{row: 1, col: 0}, size, 0)?)]
-            } else {
-                vec![Tab::Buffer(Buffer::from_file(buffer_size, Pos{row: 1, col: 0}, &p, setting.clone(), 0)?)]
-            }
+    let mut tabs: Vec<Tab> = Vec::new();
+    if tutor {
+        tabs.push(Tab::Buffer(Buffer::from_text(buffer_size, Pos{row: 1, col: 0}, &tutor::text(), setting.clone(), 0)));
+    }
+    for location in paths {
+        // `-` reads a pipe into an unnamed buffer; crossterm takes keys from /dev/tty then
+        if location.path.as_os_str() == "-" {
+            let stdin = std::io::stdin();
+            let text = if stdin.is_terminal() { String::new() } else { std::io::read_to_string(stdin)? };
+            tabs.push(Tab::Buffer(Buffer::from_text(buffer_size, Pos{row: 1, col: 0}, &text, setting.clone(), tabs.len())));
+            continue;
         }
-        None => vec![Tab::Buffer(Buffer::new(buffer_size, Pos{row: 1, col: 0}, setting.clone(), 0))]
-    };
+        if let Some(url) = location.path.to_str().filter(|p| p.starts_with(remote::SCHEME)) {
+            let remote = remote::Remote::parse(url)?;
+            let mut buffer = Buffer::from_text(buffer_size, Pos{row: 1, col: 0}, &remote.read().await?, setting.clone(), tabs.len());
+            buffer.set_remote(remote);
+            tabs.push(Tab::Buffer(buffer));
+            continue;
+        }
+        // Asks for the passphrase once the editor is up
+        if crypt::Cipher::detect(&location.path).is_some() {
+            action_channel_tx.try_send(format!("Open({})", location.path.display()))?;
+            continue;
+        }
+        if location.path.is_dir() {
+            tabs.push(Tab::Directory(directory::Directory::new(location.path, Pos{row: 1, col: 0}, buffer_size, tabs.len(), setting.close_directory_on_open)?));
+        } else {
+            let mut buffer = Buffer::from_file(buffer_size, Pos{row: 1, col: 0}, &location.path, setting.clone(), tabs.len())?;
+            buffer.goto(location.line, location.col);
+            tabs.push(Tab::Buffer(buffer));
+        }
+    }
+    if tabs.is_empty() {
+        tabs.push(Tab::Buffer(Buffer::new(buffer_size, Pos{row: 1, col: 0}, setting.clone(), 0)));
+    }
     let tabs = Arc::new(Mutex::new(tabs));
     let state = Arc::new(Mutex::new(KeymapState::Normal));
     let running = Arc::new(Mutex::new(true));
-    let line_input = Arc::new(Mutex::new(lineinput::LineInput::new(size.width as usize)));
+    let mut line_input = lineinput::LineInput::new(size.width as usize);
+    let mut middleware = middleware::Pipeline::new();
+    if tutor {
+        line_input.notice = tutor::intro();
+        middleware.register(Box::new(tutor::Tutor::new(action_channel_tx.clone())));
+    }
+    let line_input = Arc::new(Mutex::new(line_input));
+    let (jobs, job_rx) = job::Jobs::new();
+    let lsp = lsp::Clients::new(setting.lsp_servers.clone());
     let editor= EditorInfo {
-        size,
-        setting,
+        size: Arc::new(std::sync::Mutex::new(size)),
+        setting: Arc::new(std::sync::Mutex::new(setting)),
         state,
         running,
         alart_tx: alart_channel_tx,
         tabs,
+        tab_idx: Arc::new(Mutex::new(0)),
         line_input,
+        jobs: Arc::new(Mutex::new(jobs)),
+        middleware: Arc::new(Mutex::new(middleware)),
+        lsp: Arc::new(Mutex::new(lsp)),
+        popup: Arc::new(Mutex::new(None)),
+        workspace: Arc::new(Mutex::new(workspace)),
+        keymaps: Arc::new(Mutex::new(keymaps)),
+        tab_kinds: Arc::new(Mutex::new(tab::Registry::new())),
+        layout: Arc::new(std::sync::Mutex::new(layout::Layout::default())),
     };
 
-    let mut event_handler = EventHandler::new(action_channel_tx, editor.clone());
+    start_loading(&mut editor.tabs.lock().await, &editor).await;
+    if let Some(entries) = replay {
+        editor.jobs.lock().await.spawn("replay", Box::new(move |reporter| Box::pin(journal::replay(entries, reporter))));
+    }
+
+    let mut event_handler = EventHandler::new(action_channel_tx.clone(), editor.clone());
     let mut renderer = Renderer::new(editor.clone(), Box::new(stdout), alart_channel_rx);
 
     renderer.init().unwrap();
+    let signals = tokio::spawn(on_signal(editor.clone()));
     
     tokio::spawn(async move {
         event_handler.run().await.unwrap();
     });
 
+    tokio::spawn(autosave_session(editor.clone()));
+    tokio::spawn(autosave(editor.clone(), action_channel_tx.clone()));
+
+    let process_editor = editor.clone();
     tokio::spawn(async move {
-        process_action(action_channel_rx, editor.clone(), tabs).await;
+        process_action(action_channel_rx, job_rx, process_editor, journal).await;
     });
 
+    let mut frame = tokio::time::interval(std::time::Duration::from_millis(16));
     loop {
-        let running = editor.running.lock().await;
-        if *running == false {
+        frame.tick().await;
+        if !*editor.running.lock().await {
             break;
         }
-        renderer.render::<io::Stdout>(0, false).await.unwrap();
+        renderer.render().await.unwrap();
     }
     
 
     renderer.close().unwrap();
+    if signals.is_finished() {
+        for path in signals.await? {
+            eprintln!("Unsaved text recovered to {}", path.display());
+        }
+    } else {
+        session::remove(&editor.workspace.lock().await.root);
+    }
     Ok(())
 }
 
+// On SIGTERM or SIGHUP writes unsaved buffers to .zutto/recover and stops the
+// editor, so the terminal is restored as on Quit; returns the files written
+async fn on_signal(editor: EditorInfo) -> Vec<PathBuf> {
+    use tokio::signal::unix::{signal, SignalKind};
+    let (Ok(mut term), Ok(mut hup)) = (signal(SignalKind::terminate()), signal(SignalKind::hangup())) else {
+        return Vec::new();
+    };
+    tokio::select! {
+        _ = term.recv() => {}
+        _ = hup.recv() => {}
+    }
+    let dir = editor.workspace.lock().await.root.join(".zutto/recover");
+    let mut recovered = Vec::new();
+    for tab in editor.tabs.lock().await.iter() {
+        if let Tab::Buffer(b) = tab {
+            match b.recover(&dir) {
+                Ok(path) => recovered.extend(path),
+                Err(e) => log::error!("{}: {}", b.name(), e),
+            }
+        }
+    }
+    *editor.running.lock().await = false;
+    recovered
+}
+
 #[derive(Debug, Clone)]
 pub struct Action {
     pub name: String,
@@ -347,6 +1400,9 @@ pub struct Action {
 }
 
 pub fn parse_action(action: &str, line: &str, idx: usize) -> Result<Action> {
+    if let Some((start, end, command)) = range::split(action) {
+        return Ok(actions::ranged(start, end, parse_action(&command, line, idx)?));
+    }
     let r = Regex::new(r"^(\w+)(\((.+)\))?$").unwrap();
     let name = String::from(match r.captures(&action) {
         Some(c) => match c.get(1) {
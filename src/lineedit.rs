@@ -0,0 +1,213 @@
+// Line editing shared by the line input and the shell prompt: cursor and
+// word movement, kill to end and yank through the clipboard ring, and a
+// history with reverse search. Break starts another line, for prompts that
+// take more than one
+
+use crate::clipboard;
+
+#[derive(Debug, Default)]
+pub struct LineEdit {
+    pub text: String,
+    // Cursor as a char index into `text`
+    pub cur: usize,
+    history: Vec<String>,
+    history_idx: usize,
+    // Query of a running history search, the text typed before the first SearchHistory
+    search: Option<String>,
+}
+
+impl LineEdit {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Runs an editing action, its name without the `Line` prefix of the line
+    // input's; false when it is not one
+    pub fn process(&mut self, name: &str, arg: Option<&str>) -> bool {
+        let c = arg.and_then(|s| s.chars().next());
+        match name {
+            "Insert" => self.insert(c.map_or(' ', |c| c.to_lowercase().next().unwrap())),
+            "InsertUpper" => self.insert(c.unwrap_or(' ')),
+            "InsertSpace" => self.insert(' '),
+            "InsertComma" => self.insert(','),
+            "Break" => self.insert('\n'),
+            "Delete" => self.delete(),
+            "DeleteBackward" => self.delete_forward(),
+            "CursorForward" => self.cur = (self.cur + 1).min(self.len()),
+            "CursorBackward" => self.cur = self.cur.saturating_sub(1),
+            "CursorForwardWord" => self.cur = self.word_end(),
+            "CursorBackwardWord" => self.cur = self.word_start(),
+            "Start" => self.cur = 0,
+            "End" => self.cur = self.len(),
+            "KillEnd" => self.kill_end(),
+            "Yank" => self.insert_str(&clipboard::latest().unwrap_or_default().lines().collect::<Vec<_>>().join(" ")),
+            "Previous" => self.previous(),
+            "Next" => self.next(),
+            "SearchHistory" => {
+                self.search_history();
+                return true;
+            }
+            _ => return false,
+        }
+        self.search = None;
+        true
+    }
+
+    fn len(&self) -> usize {
+        self.text.chars().count()
+    }
+
+    fn byte(&self, idx: usize) -> usize {
+        self.text.char_indices().nth(idx).map_or(self.text.len(), |(i, _)| i)
+    }
+
+    pub fn insert(&mut self, c: char) {
+        let i = self.byte(self.cur);
+        self.text.insert(i, c);
+        self.cur += 1;
+    }
+
+    pub fn insert_str(&mut self, s: &str) {
+        let i = self.byte(self.cur);
+        self.text.insert_str(i, s);
+        self.cur += s.chars().count();
+    }
+
+    fn delete(&mut self) {
+        if self.cur > 0 {
+            self.cur -= 1;
+            let i = self.byte(self.cur);
+            self.text.remove(i);
+        }
+    }
+
+    fn delete_forward(&mut self) {
+        if self.cur < self.len() {
+            let i = self.byte(self.cur);
+            self.text.remove(i);
+        }
+    }
+
+    fn kill_end(&mut self) {
+        let i = self.byte(self.cur);
+        if i < self.text.len() {
+            clipboard::push(self.text.split_off(i));
+        }
+    }
+
+    fn chars(&self) -> Vec<char> {
+        self.text.chars().collect()
+    }
+
+    // End of the word at or after the cursor
+    fn word_end(&self) -> usize {
+        let chars = self.chars();
+        let mut i = self.cur;
+        while i < chars.len() && chars[i].is_whitespace() {
+            i += 1;
+        }
+        while i < chars.len() && !chars[i].is_whitespace() {
+            i += 1;
+        }
+        i
+    }
+
+    // Start of the word before the cursor
+    fn word_start(&self) -> usize {
+        let chars = self.chars();
+        let mut i = self.cur;
+        while i > 0 && chars[i - 1].is_whitespace() {
+            i -= 1;
+        }
+        while i > 0 && !chars[i - 1].is_whitespace() {
+            i -= 1;
+        }
+        i
+    }
+
+    fn load(&mut self, idx: usize) {
+        self.history_idx = idx;
+        self.text = self.history.get(idx).cloned().unwrap_or_default();
+        self.cur = self.len();
+    }
+
+    fn previous(&mut self) {
+        if self.history_idx > 0 {
+            self.load(self.history_idx - 1);
+        }
+    }
+
+    fn next(&mut self) {
+        if self.history_idx < self.history.len() {
+            self.load(self.history_idx + 1);
+        }
+    }
+
+    // Loads the next older entry holding what was typed, like Ctrl+R in a shell
+    fn search_history(&mut self) {
+        let query = self.search.get_or_insert_with(|| self.text.clone()).clone();
+        let found = self.history[..self.history_idx.min(self.history.len())].iter()
+            .rposition(|h| h.contains(&query));
+        if let Some(i) = found {
+            self.load(i);
+        }
+    }
+
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    pub fn set_history(&mut self, history: Vec<String>) {
+        self.history = history;
+        self.history_idx = self.history.len();
+    }
+
+    // Whether a history search is running, and its query
+    pub fn searching(&self) -> Option<&str> {
+        self.search.as_deref()
+    }
+
+    // Empties the line, keeping it in the history unless `record` is false
+    pub fn clear(&mut self, record: bool) -> String {
+        let text = std::mem::take(&mut self.text);
+        if record && !text.is_empty() && self.history.last() != Some(&text) {
+            self.history.push(text.clone());
+        }
+        self.cur = 0;
+        self.search = None;
+        self.history_idx = self.history.len();
+        text
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn edits_and_history() {
+        let mut edit = LineEdit::new();
+        edit.insert_str("git 커밋 -m");
+        edit.process("CursorBackwardWord", None);
+        edit.process("CursorBackwardWord", None);
+        assert_eq!(edit.cur, 4);
+        edit.process("KillEnd", None);
+        assert_eq!(edit.text, "git ");
+        edit.process("Yank", None);
+        edit.process("Yank", None);
+        assert_eq!(edit.text, "git 커밋 -m커밋 -m");
+        edit.clear(true);
+        edit.insert_str("ls");
+        edit.clear(true);
+        edit.insert_str("cargo");
+        edit.clear(true);
+        edit.insert_str("g");
+        edit.process("SearchHistory", None);
+        assert_eq!(edit.text, "cargo");
+        edit.process("SearchHistory", None);
+        assert_eq!(edit.text, "git 커밋 -m커밋 -m");
+        edit.process("Next", None);
+        assert_eq!(edit.text, "ls");
+        assert_eq!(edit.searching(), None);
+    }
+}
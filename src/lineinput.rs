@@ -1,152 +1,120 @@
 use anyhow::Result;
 
-use crate::{actions::ActionReturn, parse_action, Action};
+use crate::{actions::ActionReturn, lineedit::LineEdit, parse_action, transform, Action};
 
 #[derive(Debug)]
 pub struct LineInput{
-    pub text: String,
-    pub scroll: usize,
-    pub cur: usize,
+    pub edit: LineEdit,
     pub len: usize,
     pub action: Option<String>,
     pub notice: String,
-    log : Vec<String>,
-    log_idx: usize,
+    // Typed text is masked and kept out of the history, e.g. for passphrases
+    pub secret: bool,
 }
 
 impl LineInput {
     pub fn new(len: usize) -> Self {
         Self {
-            text: String::new(),
-            scroll: 0,
-            cur: 0,
+            edit: LineEdit::new(),
             len,
             action: None,
             notice: String::new(),
-            log: Vec::new(),
-            log_idx: 0,
+            secret: false,
         }
     }
 
-    pub fn cursor_forward(&mut self) {
-        if self.cur < self.text.len() {
-            self.cur += 1;
-        }
-        if self.cur > self.scroll + self.len {
-            self.scroll += 1;
-        }
-    }
-
-    pub fn cursor_backward(&mut self) {
-        if self.cur > 0 {
-            self.cur -= 1;
-        }
-        if self.cur < self.scroll {
-            self.scroll -= 1;
-        }
-    }
-
-    pub fn insert_char(&mut self, c: char, upper: bool) {
-        let c = if upper { c } else { c.to_lowercase().next().unwrap() };
-        self.text.insert(self.cur, c);
-        self.cursor_forward();
-    }
-
-    pub fn cursor_start(&mut self) {
-        self.cur = 0;
-    }
-
-    pub fn cursor_end(&mut self) {
-        self.cur = self.text.len();
+    pub fn text(&self) -> &str {
+        &self.edit.text
     }
 
-    pub fn delete(&mut self) {
-        if self.text.len() > 0 && self.cur > 0 {
-            self.text.remove(self.cur - 1);
-            self.cursor_backward();
-        }
+    pub fn clear(&mut self) {
+        self.edit.clear(!self.secret);
+        self.secret = false;
+        self.action = None;
     }
 
-    pub fn delete_back(&mut self) {
-        if self.text.len() > 0 && self.cur < self.text.len() {
-            self.text.remove(self.cur);
-        }
+    // What the status bar shows of the typed text, a line each
+    pub fn shown(&self) -> Vec<String> {
+        let text = match self.secret {
+            true => self.text().chars().map(|c| if c == '\n' { c } else { '*' }).collect(),
+            false => format!("{}{}", self.search_prefix(), self.text()),
+        };
+        text.split('\n').map(String::from).collect()
     }
 
-    pub fn load_prev(&mut self) {
-        if self.log_idx > 0 {
-            self.log_idx -= 1;
-            self.text = self.log[self.log_idx].clone();
-            self.cur = self.text.len();
-            self.scroll = self.cur.saturating_sub(self.len);
+    // Line and column of the cursor in what is shown
+    pub fn cursor(&self) -> (usize, usize) {
+        let before: Vec<char> = self.text().chars().take(self.edit.cur).collect();
+        match before.iter().rposition(|c| *c == '\n') {
+            Some(i) => (before.iter().filter(|c| **c == '\n').count(), before.len() - i - 1),
+            None => (0, self.search_prefix().chars().count() + before.len()),
         }
     }
 
-    pub fn load_next(&mut self) {
-        if self.log_idx < self.log.len() - 1 {
-            self.log_idx += 1;
-            self.text = self.log[self.log_idx].clone();
-            self.cur = self.text.len();
-            self.scroll = self.cur.saturating_sub(self.len);
+    fn search_prefix(&self) -> String {
+        match self.edit.searching() {
+            Some(query) if !self.secret => format!("(history `{}`) ", query),
+            _ => String::new(),
         }
     }
 
-    pub fn clear(&mut self) {
-        self.log.push(self.text.clone());
-        self.text.clear();
-        self.action = None;
-        self.cur = 0;
-        self.scroll = 0;
-        self.log_idx = self.log.len();
-    }
-
     pub fn process_action(&mut self, action: &Action, idx: usize) -> Result<Vec<ActionReturn>> {
         let action_name = &action.name;
-        let mut action_args = action.args.clone();
-        match action_name.as_str() {
-            "LineInsert" => {
-                let c = action_args[0].as_mut().unwrap().chars().next().unwrap();
-                self.insert_char(c, false);
-            }
-            "LineInsertUpper" => {
-                let c = action_args[0].as_mut().unwrap().chars().next().unwrap();
-                self.insert_char(c, true);
-            }
-            "LineInsertSpace" => {
-                self.insert_char(' ', false);
+        let action_args = action.args.clone();
+        let arg = action_args.first().cloned().flatten();
+        // A passphrase is not put in the clipboard
+        if let Some(name) = action_name.strip_prefix("Line").filter(|n| !(self.secret && *n == "KillEnd")) {
+            if self.edit.process(name, arg.as_deref()) {
+                return Ok(vec![]);
             }
-            "LineCursorForward" => {
-                self.cursor_forward();
-            }
-            "LineCursorBackward" => {
-                self.cursor_backward();
-            }
-            "LineStart" => {
-                self.cursor_start();
-            }
-            "LineEnd" => {
-                self.cursor_end();
+        }
+        match action_name.as_str() {
+            "LinePaste" => {
+                let encoded = action_args.iter().flatten().cloned().collect::<Vec<_>>().join(",");
+                let text = String::from_utf8(transform::url_decode(&encoded)?)?;
+                self.edit.insert_str(text.replace("\r\n", "\n").replace('\r', "\n").trim_end_matches('\n'));
             }
-            "LineDelete" => {
-                self.delete();
+            "FindAccept" | "FindCancel" => {
+                self.clear();
             }
-            "LineDeleteBackward" => {
-                self.delete_back();
+            // A half typed passphrase is dropped when the prompt is left
+            "NormalMode" | "CmdMode" if self.secret => {
+                self.clear();
             }
             "LineExecute" => {
                 let action = self.action.clone();
                 match action {
                     Some(a) => {
                         self.action = None;
-                        let action = parse_action(&a, &self.text, idx);
+                        let action = parse_action(&a, self.text(), idx);
                         self.clear();
                         match action{
                             Ok(a) => return Ok(vec![ActionReturn::Excute(a)]),
                             Err(e) => return Ok(vec![ActionReturn::Err(e)]),
                         };
                     }
+                    // `help topic` as a shorthand for Help(topic)
+                    None if self.text() == "help" || self.text().starts_with("help ") => {
+                        let topic = self.text()["help".len()..].trim().to_string();
+                        self.clear();
+                        return Ok(vec![ActionReturn::Excute(Action { name: "Help".to_string(), args: vec![Some(topic)] })]);
+                    }
+                    None if self.text().trim() == "messages" => {
+                        self.clear();
+                        return Ok(vec![ActionReturn::Excute(Action { name: "Messages".to_string(), args: vec![] })]);
+                    }
+                    // `= expr` shows the value of expr and `=> expr` inserts it
+                    None if self.text().starts_with('=') => {
+                        let (name, expr) = match self.text().strip_prefix("=>") {
+                            Some(expr) => ("CalcInsert", expr),
+                            None => ("Calc", &self.text()[1..]),
+                        };
+                        let action = Action { name: name.to_string(), args: vec![Some(expr.to_string())] };
+                        self.clear();
+                        return Ok(vec![ActionReturn::Excute(action)]);
+                    }
                     None => {
-                        let action = parse_action(&self.text, &self.text, idx);
+                        let action = parse_action(self.text(), self.text(), idx);
                         self.clear();
                         match action {
                             Ok(a) => return Ok(vec![ActionReturn::Excute(a)]),
@@ -0,0 +1,510 @@
+use std::{collections::HashMap, path::{Path, PathBuf}, process::Stdio, sync::{atomic::{AtomicU64, Ordering}, Arc}, time::Duration};
+
+use anyhow::{anyhow, Result};
+use log::debug;
+use serde_json::{json, Value};
+use tokio::{io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader}, process::{ChildStdin, ChildStdout, Command}, sync::{oneshot, Mutex, OnceCell}};
+
+use crate::{actions::targeted, tab::results::Location, Action};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// Snapshot of a buffer taken when a request is made
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub buffer: usize,
+    pub path: PathBuf,
+    pub text: String,
+    pub line: usize,
+    pub character: usize,
+}
+
+#[derive(Debug, Clone)]
+pub enum Request {
+    Hover(Document),
+    Definition(Document),
+    References(Document),
+    // tab size, insert spaces, save afterwards
+    Format(Document, usize, bool, bool),
+    // Symbols of the whole project matching the query, from the server of the document's file type
+    WorkspaceSymbols(Document, String),
+}
+
+#[derive(Debug)]
+pub struct Client {
+    stdin: Mutex<ChildStdin>,
+    pending: Arc<Mutex<HashMap<u64, oneshot::Sender<Value>>>>,
+    versions: Mutex<HashMap<String, i64>>,
+    next_id: AtomicU64,
+}
+
+#[derive(Debug)]
+pub struct Clients {
+    servers: HashMap<String, String>,
+    // Filled once the server has started; empty again after a failed start
+    clients: HashMap<String, Arc<OnceCell<Arc<Client>>>>,
+}
+
+pub fn path_to_uri(path: &Path) -> String {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    format!("file://{}", path.display()).replace(' ', "%20")
+}
+
+pub fn uri_to_path(uri: &str) -> PathBuf {
+    PathBuf::from(uri.trim_start_matches("file://").replace("%20", " "))
+}
+
+fn language_id(ext: &str) -> &str {
+    match ext {
+        "rs" => "rust",
+        "py" => "python",
+        "js" => "javascript",
+        "ts" => "typescript",
+        "c" | "h" => "c",
+        "cpp" | "hpp" => "cpp",
+        "go" => "go",
+        "java" => "java",
+        "md" => "markdown",
+        _ => ext,
+    }
+}
+
+impl Client {
+    pub async fn start(command: &str, root: &Path) -> Result<Self> {
+        let mut args = command.split_whitespace();
+        let program = args.next().ok_or(anyhow!("lsp: empty server command"))?;
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        tokio::spawn(async move {
+            let status = child.wait().await;
+            debug!("Language server exited with: {:?}", status);
+        });
+        let client = Self {
+            stdin: Mutex::new(stdin),
+            pending: Arc::new(Mutex::new(HashMap::new())),
+            versions: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+        };
+        client.spawn_reader(stdout);
+        client.request("initialize", json!({
+            "processId": std::process::id(),
+            "rootUri": path_to_uri(root),
+            "capabilities": {},
+        })).await?;
+        client.notify("initialized", json!({})).await?;
+        Ok(client)
+    }
+
+    fn spawn_reader(&self, stdout: ChildStdout) {
+        let pending = Arc::clone(&self.pending);
+        tokio::spawn(async move {
+            let mut reader = BufReader::new(stdout);
+            loop {
+                let message = match read_message(&mut reader).await {
+                    Ok(m) => m,
+                    Err(e) => {
+                        debug!("lsp: reader stopped: {}", e);
+                        break;
+                    }
+                };
+                match (message.get("id").and_then(|i| i.as_u64()), message.get("method")) {
+                    (Some(id), None) => {
+                        if let Some(tx) = pending.lock().await.remove(&id) {
+                            let _ = tx.send(message);
+                        }
+                    }
+                    (_, Some(method)) => {
+                        debug!("lsp: ignored server message {}", method);
+                    }
+                    _ => {}
+                }
+            }
+        });
+    }
+
+    async fn send(&self, message: Value) -> Result<()> {
+        let body = message.to_string();
+        let mut stdin = self.stdin.lock().await;
+        stdin.write_all(format!("Content-Length: {}\r\n\r\n{}", body.len(), body).as_bytes()).await?;
+        stdin.flush().await?;
+        Ok(())
+    }
+
+    pub async fn notify(&self, method: &str, params: Value) -> Result<()> {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params })).await
+    }
+
+    pub async fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().await.insert(id, tx);
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })).await?;
+        let response = match tokio::time::timeout(TIMEOUT, rx).await {
+            Ok(r) => r?,
+            Err(_) => {
+                self.pending.lock().await.remove(&id);
+                return Err(anyhow!("lsp: {} timed out", method));
+            }
+        };
+        if let Some(error) = response.get("error") {
+            return Err(anyhow!("lsp: {}", error["message"].as_str().unwrap_or("unknown error")));
+        }
+        Ok(response["result"].clone())
+    }
+
+    // Sends the whole text, opening the document the first time it is seen
+    pub async fn sync(&self, doc: &Document) -> Result<()> {
+        let uri = path_to_uri(&doc.path);
+        let mut versions = self.versions.lock().await;
+        match versions.get_mut(&uri) {
+            Some(version) => {
+                *version += 1;
+                self.notify("textDocument/didChange", json!({
+                    "textDocument": { "uri": uri, "version": *version },
+                    "contentChanges": [{ "text": doc.text }],
+                })).await
+            }
+            None => {
+                let ext = doc.path.extension().and_then(|e| e.to_str()).unwrap_or("");
+                versions.insert(uri.clone(), 0);
+                self.notify("textDocument/didOpen", json!({
+                    "textDocument": { "uri": uri, "languageId": language_id(ext), "version": 0, "text": doc.text },
+                })).await
+            }
+        }
+    }
+}
+
+async fn read_message(reader: &mut BufReader<ChildStdout>) -> Result<Value> {
+    let mut len = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 {
+            return Err(anyhow!("lsp: server closed the connection"));
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(l) = header.strip_prefix("Content-Length:") {
+            len = Some(l.trim().parse::<usize>()?);
+        }
+    }
+    let mut body = vec![0; len.ok_or(anyhow!("lsp: missing Content-Length"))?];
+    reader.read_exact(&mut body).await?;
+    Ok(serde_json::from_slice(&body)?)
+}
+
+impl Clients {
+    pub fn new(servers: HashMap<String, String>) -> Self {
+        Self {
+            servers,
+            clients: HashMap::new(),
+        }
+    }
+
+    // Returns the running server for this file type, starting it if needed.
+    // The lock is only held to find its slot, so requests for other servers
+    // go on while one starts, and requests for the same one wait for it
+    pub async fn get(clients: &Mutex<Self>, path: &Path) -> Result<Arc<Client>> {
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_string();
+        let (slot, command) = {
+            let mut clients = clients.lock().await;
+            let command = clients.servers.get(&ext).cloned()
+                .ok_or(anyhow!("No language server configured for .{}", ext))?;
+            (Arc::clone(clients.clients.entry(ext).or_default()), command)
+        };
+        let root = std::env::current_dir()?;
+        let client = slot.get_or_try_init(|| async { Client::start(&command, &root).await.map(Arc::new) }).await?;
+        Ok(Arc::clone(client))
+    }
+}
+
+fn position(doc: &Document) -> Value {
+    json!({
+        "textDocument": { "uri": path_to_uri(&doc.path) },
+        "position": { "line": doc.line, "character": doc.character },
+    })
+}
+
+fn hover_text(contents: &Value) -> String {
+    match contents {
+        Value::String(s) => s.clone(),
+        Value::Array(a) => a.iter().map(hover_text).collect::<Vec<_>>().join("\n"),
+        Value::Object(o) => o.get("value").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+        _ => String::new(),
+    }
+}
+
+fn line_text(path: &Path, line: usize) -> String {
+    std::fs::read_to_string(path).ok()
+        .and_then(|s| s.lines().nth(line).map(|l| l.trim().to_string()))
+        .unwrap_or_default()
+}
+
+// Accepts Location, Location[] and LocationLink[] results
+fn locations(result: &Value) -> Vec<Location> {
+    let items = match result {
+        Value::Array(a) => a.clone(),
+        Value::Null => vec![],
+        v => vec![v.clone()],
+    };
+    items.iter().filter_map(|item| {
+        let uri = item.get("uri").or(item.get("targetUri"))?.as_str()?;
+        let start = &item.get("range").or(item.get("targetSelectionRange"))?["start"];
+        let path = uri_to_path(uri);
+        let line = start["line"].as_u64()? as usize;
+        Some(Location {
+            text: line_text(&path, line),
+            path,
+            line,
+            col: start["character"].as_u64()? as usize,
+        })
+    }).collect()
+}
+
+// SymbolKind names for the kinds worth telling apart in a list
+fn symbol_kind(kind: u64) -> &'static str {
+    match kind {
+        2 => "module",
+        5 => "class",
+        6 => "method",
+        8 => "field",
+        10 => "enum",
+        11 => "interface",
+        12 => "function",
+        13 => "variable",
+        14 => "constant",
+        22 => "enum member",
+        23 => "struct",
+        26 => "type parameter",
+        _ => "symbol",
+    }
+}
+
+// How well `name` matches `query`: its chars in order, case ignored, with
+// runs and word starts counting for more. None when they are not all there
+fn fuzzy_score(query: &str, name: &str) -> Option<i64> {
+    let mut query = query.chars().flat_map(char::to_lowercase).filter(|c| !c.is_whitespace()).peekable();
+    let (mut score, mut prev, mut matched_prev) = (0, None::<char>, false);
+    for c in name.chars() {
+        let Some(&q) = query.peek() else { break };
+        let matched = c.to_lowercase().eq(std::iter::once(q));
+        if matched {
+            query.next();
+            let word_start = match prev {
+                None => true,
+                Some(p) => !p.is_alphanumeric() || (p.is_lowercase() && c.is_uppercase()),
+            };
+            score += 1 + if matched_prev { 2 } else { 0 } + if word_start { 3 } else { 0 };
+        }
+        matched_prev = matched;
+        prev = Some(c);
+    }
+    query.peek().is_none().then_some(score)
+}
+
+// Accepts SymbolInformation[] and WorkspaceSymbol[] results, best matches of
+// `query` first and shorter names before longer ones that match as well
+fn symbols(result: &Value, query: &str) -> Vec<Location> {
+    let mut found: Vec<(i64, usize, Location)> = result.as_array().map(|a| a.as_slice()).unwrap_or(&[]).iter().filter_map(|item| {
+        let name = item["name"].as_str()?;
+        let score = fuzzy_score(query, name)?;
+        let location = &item["location"];
+        let path = uri_to_path(location["uri"].as_str()?);
+        let start = &location["range"]["start"];
+        let container = item["containerName"].as_str().filter(|c| !c.is_empty());
+        let kind = symbol_kind(item["kind"].as_u64().unwrap_or(0));
+        let text = match container {
+            Some(c) => format!("{} {} in {}", kind, name, c),
+            None => format!("{} {}", kind, name),
+        };
+        let location = Location {
+            path,
+            line: start["line"].as_u64().unwrap_or(0) as usize,
+            col: start["character"].as_u64().unwrap_or(0) as usize,
+            text,
+        };
+        Some((score, name.len(), location))
+    }).collect();
+    found.sort_by_key(|(score, len, _)| (std::cmp::Reverse(*score), *len));
+    found.into_iter().map(|(_, _, location)| location).collect()
+}
+
+fn offset(text: &str, pos: &Value) -> Result<usize> {
+    let line = pos["line"].as_u64().ok_or(anyhow!("lsp: bad position"))? as usize;
+    let character = pos["character"].as_u64().ok_or(anyhow!("lsp: bad position"))? as usize;
+    let mut start = 0;
+    for _ in 0..line {
+        match text[start..].find('\n') {
+            Some(i) => start += i + 1,
+            None => return Ok(text.len()),
+        }
+    }
+    let mut units = 0;
+    for (i, c) in text[start..].char_indices() {
+        if units >= character || c == '\n' {
+            return Ok(start + i);
+        }
+        units += c.len_utf16();
+    }
+    Ok(text.len())
+}
+
+// Applies TextEdit[] to the text, last edit first so earlier offsets stay valid
+pub fn apply_edits(text: &str, edits: &Value) -> Result<String> {
+    let mut ranges = Vec::new();
+    for edit in edits.as_array().map(|a| a.as_slice()).unwrap_or(&[]) {
+        let start = offset(text, &edit["range"]["start"])?;
+        let end = offset(text, &edit["range"]["end"])?;
+        ranges.push((start, end.max(start), edit["newText"].as_str().unwrap_or("")));
+    }
+    ranges.sort_by_key(|r| std::cmp::Reverse(r.0));
+    let mut text = text.to_string();
+    for (start, end, new_text) in ranges {
+        text.replace_range(start..end, new_text);
+    }
+    Ok(text)
+}
+
+// Actions replacing a buffer's text with formatted output, optionally saving it
+pub fn format_actions(buffer: usize, text: String, save: bool) -> Vec<Action> {
+    let mut actions = vec![targeted(buffer, Action {
+        name: "ReplaceText".to_string(),
+        args: vec![Some(text)],
+    })];
+    if save {
+        actions.push(targeted(buffer, Action {
+            name: "Save".to_string(),
+            args: vec![Some("formatted".to_string())],
+        }));
+    }
+    actions
+}
+
+pub fn open_action(location: &Location) -> Action {
+    Action {
+        name: "OpenAt".to_string(),
+        args: vec![Some(location.to_string())],
+    }
+}
+
+pub fn results_action(title: &str, locations: &[Location]) -> Action {
+    Action {
+        name: "ShowResults".to_string(),
+        args: std::iter::once(Some(title.to_string()))
+            .chain(locations.iter().map(|l| Some(l.to_string())))
+            .collect(),
+    }
+}
+
+pub async fn run(clients: Arc<Mutex<Clients>>, request: Request) -> Result<Vec<Action>> {
+    match request {
+        Request::Hover(doc) => {
+            let client = Clients::get(&clients, &doc.path).await?;
+            client.sync(&doc).await?;
+            let result = client.request("textDocument/hover", position(&doc)).await?;
+            let text = hover_text(&result["contents"]);
+            if text.trim().is_empty() {
+                return Err(anyhow!("No hover information"));
+            }
+            Ok(vec![Action {
+                name: "ShowPopup".to_string(),
+                args: vec![Some("Hover".to_string()), Some(text)],
+            }])
+        }
+        Request::Definition(doc) => {
+            let client = Clients::get(&clients, &doc.path).await?;
+            client.sync(&doc).await?;
+            let result = client.request("textDocument/definition", position(&doc)).await?;
+            match locations(&result).as_slice() {
+                [] => Err(anyhow!("No definition found")),
+                [location] => Ok(vec![open_action(location)]),
+                all => Ok(vec![results_action("Definitions", all)]),
+            }
+        }
+        Request::References(doc) => {
+            let client = Clients::get(&clients, &doc.path).await?;
+            client.sync(&doc).await?;
+            let mut params = position(&doc);
+            params["context"] = json!({ "includeDeclaration": true });
+            let result = client.request("textDocument/references", params).await?;
+            let all = locations(&result);
+            if all.is_empty() {
+                return Err(anyhow!("No references found"));
+            }
+            Ok(vec![results_action("References", &all)])
+        }
+        Request::Format(doc, tab_size, insert_spaces, save) => {
+            let client = Clients::get(&clients, &doc.path).await?;
+            client.sync(&doc).await?;
+            let result = client.request("textDocument/formatting", json!({
+                "textDocument": { "uri": path_to_uri(&doc.path) },
+                "options": { "tabSize": tab_size, "insertSpaces": insert_spaces },
+            })).await?;
+            let text = apply_edits(&doc.text, &result)?;
+            Ok(format_actions(doc.buffer, text, save))
+        }
+        Request::WorkspaceSymbols(doc, query) => {
+            let client = Clients::get(&clients, &doc.path).await?;
+            client.sync(&doc).await?;
+            let result = client.request("workspace/symbol", json!({ "query": query })).await?;
+            match symbols(&result, &query).as_slice() {
+                [] => Err(anyhow!("No symbols matching {}", query)),
+                [location] => Ok(vec![open_action(location)]),
+                all => Ok(vec![results_action(&format!("Symbols: {}", query), all)]),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hover_contents() {
+        assert_eq!(hover_text(&json!("plain")), "plain");
+        assert_eq!(hover_text(&json!({ "kind": "markdown", "value": "fn main()" })), "fn main()");
+        assert_eq!(hover_text(&json!(["a", { "language": "rust", "value": "b" }])), "a\nb");
+    }
+
+    #[test]
+    fn edits() {
+        let text = "fn  main(){\n\tx\n}\n";
+        let edits = json!([
+            { "range": { "start": { "line": 0, "character": 2 }, "end": { "line": 0, "character": 4 } }, "newText": " " },
+            { "range": { "start": { "line": 0, "character": 10 }, "end": { "line": 0, "character": 10 } }, "newText": " " },
+            { "range": { "start": { "line": 1, "character": 0 }, "end": { "line": 1, "character": 1 } }, "newText": "    " },
+        ]);
+        assert_eq!(apply_edits(text, &edits).unwrap(), "fn main() {\n    x\n}\n");
+    }
+
+    #[test]
+    fn workspace_symbols() {
+        assert!(fuzzy_score("hb", "HelpBuffer").unwrap() > fuzzy_score("hb", "hashbrown").unwrap());
+        assert_eq!(fuzzy_score("bh", "HelpBuffer"), None);
+        let result = json!([
+            { "name": "parse_args", "kind": 12, "location": { "uri": "file:///src/cli.rs", "range": { "start": { "line": 3, "character": 0 } } } },
+            { "name": "Parser", "kind": 23, "containerName": "syntax", "location": { "uri": "file:///src/syntax.rs", "range": { "start": { "line": 9, "character": 4 } } } },
+            { "name": "render", "kind": 12, "location": { "uri": "file:///src/render.rs" } },
+        ]);
+        let found = symbols(&result, "par");
+        assert_eq!(found.iter().map(|l| l.text.as_str()).collect::<Vec<_>>(), ["struct Parser in syntax", "function parse_args"]);
+        assert_eq!((found[0].path.as_path(), found[0].line, found[0].col), (Path::new("/src/syntax.rs"), 9, 4));
+        assert_eq!(symbols(&result, "").len(), 3);
+    }
+
+    #[test]
+    fn uri_round_trip() {
+        let path = PathBuf::from("/tmp/some file.rs");
+        assert_eq!(uri_to_path(&path_to_uri(&path)), path);
+    }
+}
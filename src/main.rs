@@ -1,16 +1,49 @@
-use editor::run;
+use editor::{run, tab::results::Location};
 use std::{env, panic, path::PathBuf};
 
+// `file`, `file:line[:col]` and vim style `+line file`
+fn parse_args(args: &[String]) -> Vec<Location> {
+    let mut locations = Vec::new();
+    let mut line = None;
+    for arg in args {
+        if let Some(n) = arg.strip_prefix('+').and_then(|n| n.parse::<usize>().ok()) {
+            line = Some(n.max(1) - 1);
+            continue;
+        }
+        // Remote urls have colons of their own
+        let mut location = if PathBuf::from(arg).exists() || arg.starts_with("ssh://") {
+            Location { path: PathBuf::from(arg), line: 0, col: 0, text: String::new() }
+        } else {
+            match Location::parse(arg) {
+                Ok(l) => l,
+                Err(_) => continue,
+            }
+        };
+        if let Some(n) = line.take() {
+            location.line = n;
+        }
+        locations.push(location);
+    }
+    locations
+}
+
+// Takes `flag <value>` out of the arguments
+fn take_option(args: &mut Vec<String>, flag: &str) -> Option<PathBuf> {
+    let i = args.iter().position(|a| a == flag)?;
+    args.remove(i);
+    (i < args.len()).then(|| PathBuf::from(args.remove(i)))
+}
+
 #[tokio::main]
 async fn main() {
     log_panics::init();
     log_panics::Config::new()
         .backtrace_mode(log_panics::BacktraceMode::Off)
         .install_panic_hook();
-    let args: Vec<String> = env::args().collect();
-    let mut path = None;
-    if args.len() > 1 {
-        path = Some(PathBuf::from(&args[1]));
-    }
-    run(path).await.unwrap();
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let tutor = args.iter().any(|a| a == "--tutor");
+    args.retain(|a| a != "--tutor");
+    let journal = take_option(&mut args, "--journal");
+    let replay = take_option(&mut args, "--replay");
+    run(parse_args(&args), tutor, journal, replay).await.unwrap();
 }
\ No newline at end of file
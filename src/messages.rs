@@ -0,0 +1,63 @@
+// Notices and alerts shown in the status bar, kept for `messages` after they
+// expire from it
+
+use std::{collections::VecDeque, sync::Mutex, time::{Duration, Instant}};
+
+const HISTORY_SIZE: usize = 200;
+
+static HISTORY: Mutex<VecDeque<Message>> = Mutex::new(VecDeque::new());
+
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub text: String,
+    pub alert: bool,
+    pub at: Instant,
+}
+
+impl Message {
+    pub fn new(text: String, alert: bool) -> Self {
+        Self { text, alert, at: Instant::now() }
+    }
+
+    // Whether it is still shown with notice_timeout seconds, 0 never expiring
+    pub fn live(&self, timeout: u64) -> bool {
+        timeout == 0 || self.at.elapsed() < Duration::from_secs(timeout)
+    }
+}
+
+pub fn push(message: Message) {
+    let mut history = HISTORY.lock().unwrap();
+    history.push_back(message);
+    if history.len() > HISTORY_SIZE {
+        history.pop_front();
+    }
+}
+
+// Oldest first, each line prefixed with how long ago it was shown
+pub fn lines() -> Vec<String> {
+    HISTORY.lock().unwrap().iter()
+        .map(|m| format!("{:>6} {}{}", ago(m.at.elapsed()), if m.alert { "Alert: " } else { "" }, m.text))
+        .collect()
+}
+
+fn ago(d: Duration) -> String {
+    match d.as_secs() {
+        s if s < 60 => format!("{}s", s),
+        s if s < 3600 => format!("{}m", s / 60),
+        s => format!("{}h", s / 3600),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn messages_expire() {
+        let message = Message::new("Saved".to_string(), false);
+        assert!(message.live(0) && message.live(5));
+        let old = Message { at: Instant::now() - Duration::from_secs(6), ..message };
+        assert!(!old.live(5) && old.live(0));
+        assert_eq!(ago(Duration::from_secs(125)), "2m");
+    }
+}
@@ -0,0 +1,123 @@
+use std::fmt::Debug;
+
+use crate::{Action, KeymapState};
+
+// What the pipeline decided to do with an action
+#[derive(Debug)]
+pub enum Flow {
+    Continue(Action),
+    Veto(Option<String>),
+}
+
+// Editor state visible to middleware while an action passes through
+#[derive(Debug, Clone, Copy)]
+pub struct Context {
+    pub state: KeymapState,
+    pub tab_idx: usize,
+}
+
+pub trait Middleware: Debug + Send {
+    fn name(&self) -> &str;
+    // Return Flow::Continue with the (possibly rewritten) action, or Flow::Veto to drop it
+    fn handle(&mut self, action: Action, ctx: &Context) -> Flow;
+}
+
+#[derive(Debug, Default)]
+pub struct Pipeline {
+    stages: Vec<Box<dyn Middleware>>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self {
+            stages: Vec::new(),
+        }
+    }
+
+    pub fn register(&mut self, middleware: Box<dyn Middleware>) {
+        self.stages.push(middleware);
+    }
+
+    pub fn unregister(&mut self, name: &str) -> Option<Box<dyn Middleware>> {
+        let pos = self.stages.iter().position(|m| m.name() == name)?;
+        Some(self.stages.remove(pos))
+    }
+
+    // Stages run in registration order; the first veto stops the action
+    pub fn run(&mut self, mut action: Action, ctx: &Context) -> Flow {
+        for stage in self.stages.iter_mut() {
+            match stage.handle(action, ctx) {
+                Flow::Continue(a) => action = a,
+                veto => return veto,
+            }
+        }
+        Flow::Continue(action)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Rename;
+
+    impl Middleware for Rename {
+        fn name(&self) -> &str {
+            "rename"
+        }
+        fn handle(&mut self, mut action: Action, _: &Context) -> Flow {
+            if action.name == "Delete" {
+                action.name = "DeleteBack".to_string();
+            }
+            Flow::Continue(action)
+        }
+    }
+
+    #[derive(Debug)]
+    struct Block(usize);
+
+    impl Middleware for Block {
+        fn name(&self) -> &str {
+            "block"
+        }
+        fn handle(&mut self, action: Action, _: &Context) -> Flow {
+            self.0 += 1;
+            if action.name == "DeleteBack" {
+                Flow::Veto(Some("blocked".to_string()))
+            } else {
+                Flow::Continue(action)
+            }
+        }
+    }
+
+    fn ctx() -> Context {
+        Context {
+            state: KeymapState::Normal,
+            tab_idx: 0,
+        }
+    }
+
+    #[test]
+    fn rewrite_then_veto() {
+        let mut pipeline = Pipeline::new();
+        pipeline.register(Box::new(Rename));
+        pipeline.register(Box::new(Block(0)));
+        let action = Action { name: "Delete".to_string(), args: vec![] };
+        match pipeline.run(action, &ctx()) {
+            Flow::Veto(Some(s)) => assert_eq!(s, "blocked"),
+            _ => panic!("expected veto"),
+        }
+        let action = Action { name: "Insert".to_string(), args: vec![Some("a".to_string())] };
+        match pipeline.run(action, &ctx()) {
+            Flow::Continue(a) => assert_eq!(a.name, "Insert"),
+            _ => panic!("expected continue"),
+        }
+        assert!(pipeline.unregister("block").is_some());
+        let action = Action { name: "Delete".to_string(), args: vec![] };
+        match pipeline.run(action, &ctx()) {
+            Flow::Continue(a) => assert_eq!(a.name, "DeleteBack"),
+            _ => panic!("expected continue"),
+        }
+    }
+}
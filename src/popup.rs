@@ -0,0 +1,112 @@
+use std::io::Write;
+
+use anyhow::Result;
+use crossterm::{cursor, queue, style::{Print, Stylize}};
+
+use crate::{tab::{Cursor, Size}, Action};
+
+const MAX_HEIGHT: usize = 12;
+
+// What a picker popup did with an action
+pub enum Pick {
+    Moved,
+    Picked(Action),
+    Ignored,
+}
+
+// Floating text box drawn over the current tab, next to the cursor
+#[derive(Debug, Clone)]
+pub struct Popup {
+    pub title: String,
+    pub lines: Vec<String>,
+    // Pickers highlight a line and run `action` with its index on Enter
+    selected: Option<usize>,
+    action: String,
+}
+
+impl Popup {
+    pub fn new(title: &str, text: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            lines: text.lines().map(|l| l.replace('\t', "    ")).collect(),
+            selected: None,
+            action: String::new(),
+        }
+    }
+
+    pub fn picker(title: &str, lines: Vec<String>, action: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            selected: Some(0),
+            lines,
+            action: action.to_string(),
+        }
+    }
+
+    // Up and Down move a picker's selection and Enter picks; anything else closes it
+    pub fn navigate(&mut self, action: &str) -> Pick {
+        let (Some(selected), len) = (self.selected, self.lines.len()) else { return Pick::Ignored };
+        if len == 0 {
+            return Pick::Ignored;
+        }
+        match action {
+            "CursorUp" => self.selected = Some((selected + len - 1) % len),
+            "CursorDown" => self.selected = Some((selected + 1) % len),
+            "InsertNewline" => return Pick::Picked(Action {
+                name: self.action.clone(),
+                args: vec![Some(selected.to_string())],
+            }),
+            _ => return Pick::Ignored,
+        }
+        Pick::Moved
+    }
+
+    pub fn render<W>(&self, write: &mut W, anchor: Cursor, screen: Size) -> Result<()>
+    where W: Write
+    {
+        let screen_width = screen.width as usize;
+        let width = self.lines.iter()
+            .map(|l| l.chars().count())
+            .chain(std::iter::once(self.title.chars().count()))
+            .max()
+            .unwrap_or(0)
+            .saturating_add(2)
+            .min(screen_width);
+        // Keep one row free for the tab bar and one for the status bar
+        let room_below = (screen.height as usize).saturating_sub(anchor.row as usize + 2);
+        let room_above = (anchor.row as usize).saturating_sub(1);
+        let height = (self.lines.len() + 1).min(MAX_HEIGHT).min(room_below.max(room_above));
+        if height == 0 || width == 0 {
+            return Ok(());
+        }
+        let top = if room_below >= height {
+            anchor.row as usize + 1
+        } else {
+            anchor.row as usize - height
+        };
+        let left = (anchor.col as usize).min(screen_width - width);
+        let fit = |s: &str| {
+            let s: String = s.chars().take(width - 1).collect();
+            format!(" {:<w$}", s, w = width - 1)
+        };
+        queue!(
+            write,
+            cursor::MoveTo(left as u16, top as u16),
+            Print(fit(&self.title).bold().reverse()),
+        )?;
+        // Scrolled so a picker's selection stays visible
+        let skip = match self.selected {
+            Some(selected) if selected + 1 >= height => selected + 2 - height,
+            _ => 0,
+        };
+        for (i, line) in self.lines.iter().enumerate().skip(skip).take(height - 1) {
+            let line = if Some(i) == self.selected { fit(line).black().on_white() } else { fit(line).white().on_dark_grey() };
+            queue!(
+                write,
+                cursor::MoveTo(left as u16, (top + i - skip + 1) as u16),
+                Print(line),
+            )?;
+        }
+        Ok(())
+    }
+}
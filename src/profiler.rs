@@ -0,0 +1,138 @@
+use std::{collections::VecDeque, io::Write, sync::{atomic::{AtomicBool, Ordering}, Mutex}, time::Duration};
+
+use anyhow::Result;
+use crossterm::{cursor, queue, style::{Print, Stylize}};
+
+use crate::tab::Size;
+
+const SAMPLES: usize = 120;
+
+// Timings are only collected while the overlay is shown
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static PROFILER: Mutex<Profiler> = Mutex::new(Profiler::new());
+
+#[derive(Debug, Clone, Copy)]
+pub enum Metric {
+    Frame,
+    Action,
+    Highlight,
+    LockWait,
+}
+
+const METRICS: [(Metric, &str); 4] = [
+    (Metric::Frame, "frame"),
+    (Metric::Action, "action"),
+    (Metric::Highlight, "highlight"),
+    (Metric::LockWait, "lock wait"),
+];
+
+#[derive(Debug)]
+struct Samples {
+    // Time added since the last flush, e.g. highlighting of every line in a frame
+    pending: Duration,
+    list: VecDeque<Duration>,
+}
+
+impl Samples {
+    const fn new() -> Self {
+        Self {
+            pending: Duration::ZERO,
+            list: VecDeque::new(),
+        }
+    }
+
+    fn push(&mut self, d: Duration) {
+        if self.list.len() == SAMPLES {
+            self.list.pop_front();
+        }
+        self.list.push_back(d);
+    }
+}
+
+// Rolling timings of the last frames and actions, shown by ToggleProfiler
+#[derive(Debug)]
+pub struct Profiler {
+    samples: [Samples; 4],
+}
+
+impl Profiler {
+    const fn new() -> Self {
+        Self {
+            samples: [Samples::new(), Samples::new(), Samples::new(), Samples::new()],
+        }
+    }
+}
+
+fn with<T>(f: impl FnOnce(&mut Profiler) -> T) -> T {
+    let mut profiler = PROFILER.lock().unwrap_or_else(|e| e.into_inner());
+    f(&mut profiler)
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn toggle() -> bool {
+    let on = !ENABLED.fetch_xor(true, Ordering::Relaxed);
+    if !on {
+        with(|p| *p = Profiler::new());
+    }
+    on
+}
+
+// Stores one sample
+pub fn record(metric: Metric, d: Duration) {
+    if enabled() {
+        with(|p| p.samples[metric as usize].push(d));
+    }
+}
+
+// Adds to the sample stored by the next `flush`
+pub fn add(metric: Metric, d: Duration) {
+    if enabled() {
+        with(|p| p.samples[metric as usize].pending += d);
+    }
+}
+
+pub fn flush(metric: Metric) {
+    if enabled() {
+        with(|p| {
+            let samples = &mut p.samples[metric as usize];
+            let d = std::mem::take(&mut samples.pending);
+            samples.push(d);
+        });
+    }
+}
+
+fn ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+pub fn summary() -> Vec<String> {
+    with(|p| METRICS.iter().map(|(metric, name)| {
+        let list = &p.samples[*metric as usize].list;
+        let last = list.back().copied().unwrap_or_default();
+        let max = list.iter().max().copied().unwrap_or_default();
+        let avg = if list.is_empty() { Duration::ZERO } else { list.iter().sum::<Duration>() / list.len() as u32 };
+        format!("{:<9} {:>7.2} {:>7.2} {:>7.2}", name, ms(last), ms(avg), ms(max))
+    }).collect())
+}
+
+// Drawn in the top right corner, under the tab bar
+pub fn render<W>(write: &mut W, screen: Size) -> Result<()>
+where W: Write
+{
+    let mut lines = vec![format!("{:<9} {:>7} {:>7} {:>7}", "ms", "last", "avg", "max")];
+    lines.extend(summary());
+    let width = lines.iter().map(|l| l.len()).max().unwrap_or(0) + 2;
+    if width > screen.width as usize || lines.len() + 2 > screen.height as usize {
+        return Ok(());
+    }
+    let left = screen.width as usize - width;
+    for (i, line) in lines.iter().enumerate() {
+        let line = format!(" {:<w$}", line, w = width - 1);
+        let line = if i == 0 { line.bold().reverse() } else { line.white().on_dark_grey() };
+        queue!(write, cursor::MoveTo(left as u16, i as u16 + 1), Print(line))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,92 @@
+// Line ranges typed before a command in the line input, as in `10,20 SortLines`,
+// `% Filter(sort -u)` or `%s/foo/bar/`. Lines count from 1, `.` is the cursor
+// line, `$` the last one and `%` all of them. The range selects its lines and
+// the action then works on the selection
+
+use std::sync::LazyLock;
+
+use anyhow::Result;
+use regex::Regex;
+
+use crate::transform;
+
+// Actions that take a range, besides the transforms
+pub const ACTIONS: [&str; 4] = ["Filter", "Replace", "Indent", "Dedent"];
+
+static RANGED: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^:?\s*(?:(%)|(\d+|\.|\$)(?:\s*,\s*(\d+|\.|\$))?)?\s*(s/.*|\w.*)$").unwrap()
+});
+
+pub fn takes_range(action: &str) -> bool {
+    ACTIONS.contains(&action) || transform::ACTIONS.contains(&action)
+}
+
+// The start, end and command of a ranged command line. A substitute without a
+// range works on the cursor line; anything else without one is left alone
+pub fn split(line: &str) -> Option<(String, String, String)> {
+    let c = RANGED.captures(line.trim())?;
+    let command = c.get(4)?.as_str();
+    let (command, substituted) = match command.strip_prefix("s/") {
+        Some(s) => (substitute(s), true),
+        None => (command.to_string(), false),
+    };
+    let (start, end) = match (c.get(1), c.get(2), c.get(3)) {
+        (Some(_), _, _) => ("1", "$"),
+        (_, Some(start), end) => (start.as_str(), end.unwrap_or(start).as_str()),
+        _ if substituted => (".", "."),
+        _ => return None,
+    };
+    Some((start.to_string(), end.to_string(), command))
+}
+
+// `foo/bar/` as Replace(foo,bar); `\/` stands for a slash in either part
+fn substitute(s: &str) -> String {
+    let mut parts = vec![String::new()];
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.clone().next() == Some('/') => {
+                chars.next();
+                parts.last_mut().unwrap().push('/');
+            }
+            '/' => parts.push(String::new()),
+            c => parts.last_mut().unwrap().push(c),
+        }
+    }
+    let replacement = parts.get(1).cloned().unwrap_or_default();
+    format!("Replace({},{})", parts[0], replacement)
+}
+
+// A line of the range, from 0, for a buffer of `lines` lines with the cursor on `cursor`
+pub fn resolve(addr: &str, cursor: usize, lines: usize) -> Result<usize> {
+    match addr {
+        "." => Ok(cursor),
+        "$" => Ok(lines.saturating_sub(1)),
+        n => match n.parse::<usize>() {
+            Ok(n) if (1..=lines).contains(&n) => Ok(n - 1),
+            _ => Err(anyhow::anyhow!("Range: line {} is out of range, the buffer has {} lines", n, lines)),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn splits_ranges() {
+        let split = |s| split(s).map(|(a, b, c)| format!("{} {} {}", a, b, c));
+        assert_eq!(split("10,20 SortLines").as_deref(), Some("10 20 SortLines"));
+        assert_eq!(split(":% Filter(sort -u)").as_deref(), Some("1 $ Filter(sort -u)"));
+        assert_eq!(split(".,$SortLines").as_deref(), Some(". $ SortLines"));
+        assert_eq!(split("%s/a\\/b/c/").as_deref(), Some("1 $ Replace(a/b,c)"));
+        assert_eq!(split("s/foo/bar").as_deref(), Some(". . Replace(foo,bar)"));
+        assert_eq!(split("Quit"), None);
+        assert_eq!(split("Open(a.txt)"), None);
+        assert_eq!(resolve("3", 0, 5).unwrap(), 2);
+        assert_eq!(resolve("$", 0, 5).unwrap(), 4);
+        assert_eq!(resolve(".", 1, 5).unwrap(), 1);
+        assert!(resolve("6", 0, 5).is_err());
+        assert!(resolve("0", 0, 5).is_err());
+    }
+}
@@ -0,0 +1,140 @@
+// Files opened as `ssh://[user@]host[:port]/path`, read and written through the
+// system ssh client so keys, agents and ~/.ssh/config keep working
+
+use std::{process::Stdio, time::Duration};
+
+use anyhow::Result;
+use tokio::{io::AsyncWriteExt, process::Command};
+
+pub const SCHEME: &str = "ssh://";
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remote {
+    pub user: Option<String>,
+    pub host: String,
+    pub port: Option<u16>,
+    pub path: String,
+}
+
+impl Remote {
+    pub fn parse(url: &str) -> Result<Self> {
+        let rest = url.strip_prefix(SCHEME).ok_or(anyhow::anyhow!("{} is not an ssh:// url", url))?;
+        let (authority, path) = rest.split_once('/').ok_or(anyhow::anyhow!("{}: missing path", url))?;
+        let (user, host) = match authority.rsplit_once('@') {
+            Some((user, host)) => (Some(user.to_string()), host),
+            None => (None, authority),
+        };
+        let (host, port) = match host.split_once(':') {
+            Some((host, port)) => (host, Some(port.parse::<u16>().map_err(|_| anyhow::anyhow!("{}: invalid port", url))?)),
+            None => (host, None),
+        };
+        if host.is_empty() || path.is_empty() {
+            return Err(anyhow::anyhow!("{}: missing host or path", url));
+        }
+        // ssh would take `-oProxyCommand=...` as an option
+        if host.starts_with('-') || user.as_deref().is_some_and(|u| u.starts_with('-')) {
+            return Err(anyhow::anyhow!("{}: invalid host", url));
+        }
+        // `ssh://host/~/notes.md` is relative to the home directory, anything else is absolute
+        let path = if path.starts_with('~') { path.to_string() } else { format!("/{}", path) };
+        Ok(Self { user, host: host.to_string(), port, path })
+    }
+
+    pub fn url(&self) -> String {
+        let user = self.user.as_ref().map(|u| format!("{}@", u)).unwrap_or_default();
+        let port = self.port.map(|p| format!(":{}", p)).unwrap_or_default();
+        format!("{}{}{}{}/{}", SCHEME, user, self.host, port, self.path.trim_start_matches('/'))
+    }
+
+    pub fn file_name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or(&self.path)
+    }
+
+    pub fn destination(&self) -> String {
+        match &self.user {
+            Some(user) => format!("{}@{}", user, self.host),
+            None => self.host.clone(),
+        }
+    }
+
+    // One master connection per host is kept open for a while, so saves don't
+    // reconnect; BatchMode fails instead of prompting on the raw mode terminal
+    fn command(&self, remote_cmd: &str) -> Command {
+        let mut cmd = Command::new("ssh");
+        cmd.args(["-o", "BatchMode=yes", "-o", "ConnectTimeout=10"])
+            .args(["-o", "ControlMaster=auto", "-o", "ControlPersist=300"])
+            .arg("-o").arg(format!("ControlPath={}/zutto-ssh-%C", std::env::temp_dir().display()));
+        if let Some(port) = self.port {
+            cmd.arg("-p").arg(port.to_string());
+        }
+        cmd.arg("--")
+            .arg(self.destination())
+            .arg(remote_cmd)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        cmd
+    }
+
+    fn quoted_path(&self) -> String {
+        match self.path.strip_prefix("~/") {
+            Some(rest) => format!("~/{}", quote(rest)),
+            None => quote(&self.path),
+        }
+    }
+
+    pub async fn read(&self) -> Result<String> {
+        let mut cmd = self.command(&format!("cat {}", self.quoted_path()));
+        cmd.stdin(Stdio::null());
+        let output = tokio::time::timeout(TIMEOUT, cmd.output()).await
+            .map_err(|_| anyhow::anyhow!("{}: timed out", self.url()))??;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!("{}: {}", self.url(), String::from_utf8_lossy(&output.stderr).trim()));
+        }
+        Ok(String::from_utf8(output.stdout)?)
+    }
+
+    pub async fn write(&self, text: String) -> Result<()> {
+        let mut child = self.command(&format!("cat > {}", self.quoted_path()))
+            .stdin(Stdio::piped())
+            .spawn()?;
+        let mut stdin = child.stdin.take().unwrap();
+        let write = async move {
+            stdin.write_all(text.as_bytes()).await?;
+            drop(stdin);
+            anyhow::Ok(child.wait_with_output().await?)
+        };
+        let output = tokio::time::timeout(TIMEOUT, write).await
+            .map_err(|_| anyhow::anyhow!("{}: timed out", self.url()))??;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!("{}: {}", self.url(), String::from_utf8_lossy(&output.stderr).trim()));
+        }
+        Ok(())
+    }
+}
+
+// Single quotes for the remote shell, or any sh -c command line
+pub fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_url() {
+        let remote = Remote::parse("ssh://me@example.com:2222/etc/hosts").unwrap();
+        assert_eq!(remote.user.as_deref(), Some("me"));
+        assert_eq!(remote.host, "example.com");
+        assert_eq!(remote.port, Some(2222));
+        assert_eq!(remote.path, "/etc/hosts");
+        assert_eq!(remote.url(), "ssh://me@example.com:2222/etc/hosts");
+        assert_eq!(Remote::parse("ssh://box/~/notes.md").unwrap().path, "~/notes.md");
+        assert!(Remote::parse("ssh://box").is_err());
+        assert!(Remote::parse("ssh://-oProxyCommand=touch%20x/etc/hosts").is_err());
+        assert!(Remote::parse("ssh://-oProxyCommand=x@box/etc/hosts").is_err());
+        assert_eq!(quote("it's"), r"'it'\''s'");
+    }
+}
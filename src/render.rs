@@ -1,11 +1,11 @@
 use std::{io::Write, sync::Arc};
 
 use anyhow::{Error, Result};
-use crossterm::{cursor, execute, queue, style::{self, Colors, Print, StyledContent, Stylize}, terminal::{self, EnterAlternateScreen, LeaveAlternateScreen}};
+use crossterm::{cursor, event::{DisableBracketedPaste, DisableFocusChange, DisableMouseCapture, EnableBracketedPaste, EnableFocusChange, EnableMouseCapture}, execute, queue, style::{self, Colors, Print, StyledContent, Stylize}, terminal::{self, EnterAlternateScreen, LeaveAlternateScreen}};
 use log::error;
 use tokio::sync::{mpsc, Mutex};
 
-use crate::{lineinput::LineInput, syncol_to_crosscol, tab::Tab, EditorInfo, KeymapState};
+use crate::{announce::{self, Announcer}, layout::Layout, lineinput::LineInput, messages::{self, Message}, profiler, syncol_to_crosscol, tab::{Pos, Tab}, EditorInfo, KeymapState};
 
 #[derive(Debug)]
 pub struct Renderer<W>
@@ -15,6 +15,12 @@ where
     editor: EditorInfo,
     write: W,
     alart_rx: mpsc::Receiver<Error>,
+    // Panes, focused tab, tab count, terminal size and rows of the line input
+    // of the last frame
+    last_layout: (Layout, usize, usize, u16, u16, usize),
+    announcer: Announcer,
+    // Latest notice or alert, shown until notice_timeout passes
+    message: Option<Message>,
 }
 
 impl<W> Renderer<W>
@@ -24,23 +30,41 @@ where
     pub fn new(editor: EditorInfo, w: W, alart_rx: mpsc::Receiver<Error>) -> Self 
     {
         Self {
-            editor,
             write: w,
             alart_rx,
+            last_layout: (Layout::default(), 0, 0, 0, 0, 1),
+            announcer: Announcer::new(editor.setting().announce_path),
+            message: None,
+            editor,
         }
     }
 
-    pub async fn render<T>(&mut self, idx: usize, clear: bool) -> Result<()> 
-    where
+    pub async fn render(&mut self) -> Result<()> 
     {
+        let started = std::time::Instant::now();
         let state = self.editor.state.lock().await;
-        let line_input = self.editor.line_input.lock().await;
         let tabs = self.editor.tabs.lock().await;
-        let cursor = match tabs[idx] {
-            Tab::Buffer(ref buffer) => buffer.get_cursor(),
-            Tab::Directory(ref directory) => directory.get_cursor(),
-            Tab::Shell(ref shell) => shell.get_cursor(),
-        };
+        let idx = *self.editor.tab_idx.lock().await;
+        let mut line_input = self.editor.line_input.lock().await;
+        profiler::record(profiler::Metric::LockWait, started.elapsed());
+        let size = *self.editor.size.lock().unwrap();
+        let layout = self.editor.layout.lock().unwrap().clone();
+        // A notice outside a prompt becomes a message; a prompt's notice is its
+        // label and stays with the line input
+        let prompting = matches!(*state, KeymapState::LineInsert | KeymapState::Find) || line_input.action.is_some();
+        let typing = prompting || !line_input.text().is_empty();
+        // Input of more than one line grows the status bar upward, as far as
+        // the tab bar
+        let input_lines = if typing { line_input.shown() } else { Vec::new() };
+        let input_rows = input_lines.len().clamp(1, (size.height as usize).saturating_sub(1).max(1));
+        // Everything is drawn again after a tab change, a split, a resize or
+        // when the line input takes more or fewer rows
+        let frame = (layout, idx, tabs.len(), size.width, size.height, input_rows);
+        let clear = self.last_layout != frame;
+        self.last_layout = frame;
+        let layout = &self.last_layout.0;
+        let cursor = tabs[idx].view().get_cursor();
+        let tab_status = tabs[idx].view().status();
         if clear {
             queue!(self.write, terminal::Clear(terminal::ClearType::All))?;
         }
@@ -49,33 +73,36 @@ where
             cursor::Hide,
             cursor::MoveTo(0, 0),
         )?;
-        match tabs[idx]{ 
-            Tab::Buffer(ref mut buffer) => {
-                buffer.render(&mut self.write)?;
+        let (panes, separators) = layout.place(Pos { row: 1, col: 0 }, self.editor.tab_size());
+        for pane in &panes {
+            if let Some(tab) = tabs.get(pane.tab) {
+                tab.view().render(&mut self.write)?;
             }
-            Tab::Directory(ref mut directory) => {
-                directory.render(&mut self.write)?;
-            }
-            Tab::Shell(ref mut shell) => {
-                shell.render(&mut self.write).await?;
+        }
+        // After the panes, as some clear to the end of the row
+        for line in separators {
+            let bar = if line.size.width == 1 { "│" } else { "─" };
+            for i in 0..line.size.width.max(line.size.height) {
+                let (col, row) = match line.size.width {
+                    1 => (line.pos.col, line.pos.row + i),
+                    _ => (line.pos.col + i, line.pos.row),
+                };
+                queue!(self.write, cursor::MoveTo(col, row), Print(bar.dark_grey()))?;
             }
         }
         // Render the tab bar
-        let mut tab_bar = Bar::new(self.editor.size.width as usize, 0);
-        let tab_ratio = if 1.0 / tabs.len() as f32 > 0.3 {
-            1.0 / tabs.len() as f32
-        } else {
-            0.3
-        };
+        let mut tab_bar = Bar::new(size.width as usize, 0);
+        let tab_ratio = tab_ratio(tabs.len());
         for (i, tab) in tabs.iter().enumerate() {
-            let name = match tab {
-                Tab::Buffer(buffer) => buffer.name(),
-                Tab::Directory(directory) => directory.name(),
-                Tab::Shell(shell) => shell.name(),
-            };
+            let name = tab.view().label();
+            let name = format!("{} {}", i + 1, name);
+            // The active tab is marked by more than its color for screen readers
+            let name = if announce::enabled() && i == idx { format!("[{}]", name) } else { name };
             let s = name.clone();
             let s = if i == idx {
                 s.bold().reverse()
+            } else if layout.is_split() && layout.contains(i) {
+                s.bold().underlined()
             } else {
                 s.bold()
             };
@@ -83,24 +110,101 @@ where
         }
         tab_bar.render(&mut self.write)?;
 
+        // Render the popup over the tab, next to the cursor
+        if let Some(popup) = self.editor.popup.lock().await.as_ref() {
+            if let Some(cursor) = cursor {
+                popup.render(&mut self.write, cursor, size)?;
+            }
+        }
+
+        if !prompting && !line_input.notice.is_empty() {
+            let message = Message::new(std::mem::take(&mut line_input.notice), false);
+            messages::push(message.clone());
+            self.message = Some(message);
+        }
+        if let Ok(e) = self.alart_rx.try_recv() {
+            error!("Alart: {}", e);
+            let message = Message::new(e.to_string(), true);
+            messages::push(message.clone());
+            if announce::enabled() {
+                self.announcer.say(&format!("Alart: {}", e));
+            }
+            self.message = Some(message);
+        }
+        let timeout = self.editor.setting.lock().unwrap().notice_timeout;
+        if self.message.as_ref().is_some_and(|m| !m.live(timeout)) {
+            self.message = None;
+        }
+
         // Render the status bar
-        let mut status_bar = Bar::new(self.editor.size.width as usize, self.editor.size.height as usize - 1);
+        let width = size.width as usize;
+        let mut status_bar = Bar::new(width, size.height as usize - 1);
         let mut lineinput_cur= 0;
         let mut lineinput_pos= 0;
-        if let Ok(e) = self.alart_rx.try_recv() {
-            let s = format!("Alart: {}", e.to_string());
-            status_bar.add(s.clone().red(), 0.0, s.len());
-            error!("Alart: {}", e.to_string());
-        } else {
-            let keystate_str: &'static str = (*state).into();
-            let keystate_str = format!("State: {}", keystate_str);
-            let line = format!("{}{}",line_input.notice, line_input.text);
-            lineinput_cur = line_input.cur + line_input.notice.len();
-            status_bar.background = " ".reverse();
-            status_bar.add(keystate_str.clone().reverse(), 0.0, keystate_str.len());
-            lineinput_pos = status_bar.add(line.clone().white(), 0.2, line.len());
+        let mut lineinput_row = size.height as usize - 1;
+        let keystate_str: &'static str = (*state).into();
+        let keystate_str = format!("State: {}", keystate_str);
+        status_bar.background = " ".reverse();
+        status_bar.add(keystate_str.clone().reverse(), 0.0, keystate_str.len());
+        let jobs = self.editor.jobs.lock().await.status();
+        let right = if !jobs.is_empty() { Some(jobs) } else { tab_status };
+        if typing {
+            // The lines around the cursor when they do not all fit; those after
+            // the first line up under its text, past the prompt
+            let (cursor_line, cursor_col) = line_input.cursor();
+            let start = (cursor_line + 1).saturating_sub(input_rows);
+            let indent = line_input.notice.chars().count();
+            let top = size.height as usize - input_rows;
+            for (i, line) in input_lines[start..start + input_rows].iter().enumerate() {
+                let line = match i + start {
+                    0 => format!("{}{}", line_input.notice, line),
+                    _ => format!("{}{}", " ".repeat(indent), line),
+                };
+                let len = line.chars().count();
+                if top + i == status_bar.row {
+                    lineinput_pos = status_bar.add(line.white(), 0.2, len);
+                } else {
+                    let mut row = Bar::new(width, top + i);
+                    row.background = " ".reverse();
+                    lineinput_pos = row.add(line.white(), 0.2, len);
+                    row.render(&mut self.write)?;
+                }
+            }
+            lineinput_cur = indent + cursor_col;
+            lineinput_row = top + cursor_line - start;
+        } else if let Some(message) = &self.message {
+            // Up to the right segment, which would otherwise draw over it
+            let room = if right.is_some() { width * 7 / 10 } else { width }.saturating_sub(width / 5 + 1);
+            let text = match message.alert {
+                true => format!("Alart: {}", message.text),
+                false => message.text.clone(),
+            };
+            let text: String = text.chars().take(room).collect();
+            let len = text.chars().count();
+            let styled = if message.alert { text.red() } else { text.white() };
+            status_bar.add(styled, 0.2, len);
+        }
+        if let Some(s) = right {
+            status_bar.add(s.clone().reverse(), 0.7, s.len());
         }
         status_bar.render(&mut self.write)?;
+        if announce::enabled() {
+            let view = tabs[idx].view();
+            self.announcer.update(announce::Snapshot {
+                mode: <&'static str>::from(*state).to_string(),
+                tab: format!("{} {}", idx + 1, view.label()),
+                line: view.cursor_text(),
+                selected: view.selected(),
+                notice: match prompting {
+                    true => line_input.notice.clone(),
+                    false => self.message.as_ref().map(|m| m.text.clone()).unwrap_or_default(),
+                },
+            });
+        }
+        if profiler::enabled() {
+            profiler::flush(profiler::Metric::Highlight);
+            profiler::render(&mut self.write, size)?;
+        }
         // End of rendering
         if *state == KeymapState::LineInsert {
             execute!(
@@ -108,7 +212,7 @@ where
                 cursor::Show,
                 cursor::MoveTo(
                     lineinput_pos as u16 + lineinput_cur as u16,
-                    self.editor.size.height - 1,
+                    lineinput_row as u16,
                 ),
             )?;
         } else {
@@ -131,6 +235,7 @@ where
                 }
             }
         }
+        profiler::record(profiler::Metric::Frame, started.elapsed());
         Ok(())
     }
 
@@ -139,6 +244,9 @@ where
         execute!(
             self.write,
             EnterAlternateScreen,
+            EnableMouseCapture,
+            EnableFocusChange,
+            EnableBracketedPaste,
         )?;
         terminal::enable_raw_mode()?;
         execute!(
@@ -171,12 +279,31 @@ where
         terminal::disable_raw_mode()?;
         execute!(
             self.write,
+            DisableMouseCapture,
+            DisableFocusChange,
+            DisableBracketedPaste,
             LeaveAlternateScreen,
         )?;
         Ok(())
     }
 }
 
+// Share of the width each tab title gets in the tab bar
+fn tab_ratio(count: usize) -> f32 {
+    if 1.0 / count as f32 > 0.3 {
+        1.0 / count as f32
+    } else {
+        0.3
+    }
+}
+
+// Tab whose title covers column `col` of the tab bar
+pub fn tab_at(width: u16, count: usize, col: u16) -> Option<usize> {
+    let slot = width as f32 * tab_ratio(count);
+    let i = (col as f32 / slot) as usize;
+    (i < count).then_some(i)
+}
+
 struct Bar {
     len: usize,
     row: usize,
@@ -0,0 +1,81 @@
+// The last run of consecutive actions that edited a buffer, for RepeatLast.
+// Replayed copies are not recorded again, so only edits the user makes
+// directly change what RepeatLast repeats
+use crate::Action;
+
+#[derive(Debug, Default)]
+pub struct LastEdit {
+    actions: Vec<Action>,
+    // Whether the previous action was part of `actions`
+    editing: bool,
+    // Replayed actions still at the front of the queue
+    replaying: usize,
+}
+
+impl LastEdit {
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    // `times` copies of the edit, to run before anything else queued
+    pub fn replay(&mut self, times: usize) -> Vec<Action> {
+        let actions: Vec<Action> = (0..times).flat_map(|_| self.actions.clone()).collect();
+        self.replaying += actions.len();
+        self.editing = false;
+        actions
+    }
+
+    // Called for each action taken from the queue; whether it is a replayed copy
+    pub fn take_replayed(&mut self) -> bool {
+        let replayed = self.replaying > 0;
+        self.replaying = self.replaying.saturating_sub(1);
+        replayed
+    }
+
+    pub fn record(&mut self, action: &Action, edited: bool) {
+        if edited && !self.editing {
+            self.actions.clear();
+        }
+        if edited {
+            self.actions.push(action.clone());
+        }
+        self.editing = edited;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn action(name: &str) -> Action {
+        Action { name: name.to_string(), args: vec![] }
+    }
+
+    fn names(actions: &[Action]) -> Vec<&str> {
+        actions.iter().map(|a| a.name.as_str()).collect()
+    }
+
+    // As the action loop runs them: replayed copies are not recorded
+    fn run(last: &mut LastEdit, actions: &[Action]) {
+        for a in actions {
+            if !last.take_replayed() {
+                last.record(a, true);
+            }
+        }
+    }
+
+    #[test]
+    fn repeats_only_direct_edits() {
+        let mut last = LastEdit::default();
+        last.record(&action("Insert"), true);
+        last.record(&action("Delete"), true);
+        let replayed = last.replay(3);
+        assert_eq!(names(&replayed), ["Insert", "Delete", "Insert", "Delete", "Insert", "Delete"]);
+        run(&mut last, &replayed);
+        let replayed = last.replay(1);
+        assert_eq!(names(&replayed), ["Insert", "Delete"]);
+        run(&mut last, &replayed);
+        run(&mut last, &[action("Backspace")]);
+        assert_eq!(names(&last.replay(1)), ["Backspace"]);
+    }
+}
@@ -0,0 +1,245 @@
+// Find mode: the query typed in the line input is searched for in the
+// focused buffer after every keystroke
+
+use regex::{Regex, RegexBuilder};
+use ropey::Rope;
+
+// Toggled from the search prompt and kept for the next search
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Flags {
+    pub case_sensitive: bool,
+    // Only matches that are not part of a longer word
+    pub whole_word: bool,
+    // The query is a regular expression instead of literal text
+    pub regex: bool,
+}
+
+impl Flags {
+    pub fn toggle(&mut self, flag: &str) {
+        match flag {
+            "case" => self.case_sensitive = !self.case_sensitive,
+            "word" => self.whole_word = !self.whole_word,
+            "regex" => self.regex = !self.regex,
+            _ => (),
+        }
+    }
+
+    fn label(&self) -> String {
+        let mut out = vec![if self.regex { "regex" } else { "literal" }];
+        if self.case_sensitive {
+            out.push("case");
+        }
+        if self.whole_word {
+            out.push("word");
+        }
+        out.join(" ")
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Search {
+    pub query: String,
+    pub flags: Flags,
+    // Char ranges of every match, in order
+    pub matches: Vec<(usize, usize)>,
+    // Index into `matches` of the one the cursor is on
+    pub current: Option<usize>,
+    // Whether the last FindNext or FindPrevious went around the end of the text
+    pub wrapped: bool,
+    // Why a regex query could not be used
+    pub error: Option<String>,
+}
+
+// A Replace stepping through the matches of a search, asking about each
+#[derive(Debug, Clone, Default)]
+pub struct Replace {
+    pub replacement: String,
+    // Matches not answered yet, counting from the current one around the end
+    pub remaining: usize,
+    pub total: usize,
+    pub replaced: usize,
+}
+
+// Where the cursor is among the matches, for the Find prompt
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Counts {
+    pub current: Option<usize>,
+    pub total: usize,
+    pub wrapped: bool,
+    pub error: Option<String>,
+}
+
+impl Search {
+    pub fn update(&mut self, text: &Rope, query: &str, flags: Flags) {
+        self.query = query.to_string();
+        self.flags = flags;
+        let found = find_all(&text.to_string(), query, flags);
+        self.error = found.as_ref().err().map(error_message);
+        self.matches = found.unwrap_or_default()
+            .into_iter()
+            .map(|(s, e)| (text.byte_to_char(s), text.byte_to_char(e)))
+            .collect();
+        self.current = None;
+        self.wrapped = false;
+    }
+
+    // First match at or after `idx`, wrapping around to the first one
+    pub fn nearest(&mut self, idx: usize) -> Option<(usize, usize)> {
+        let i = self.matches.iter().position(|(s, _)| *s >= idx)
+            .or((!self.matches.is_empty()).then_some(0))?;
+        self.current = Some(i);
+        Some(self.matches[i])
+    }
+
+    // The match after or before the current one, wrapping around
+    pub fn step(&mut self, forward: bool) -> Option<(usize, usize)> {
+        let n = self.matches.len();
+        let (i, wrapped) = match (self.current, forward) {
+            _ if n == 0 => return None,
+            (None, _) => (0, false),
+            (Some(i), true) => ((i + 1) % n, i + 1 == n),
+            (Some(i), false) => ((i + n - 1) % n, i == 0),
+        };
+        self.current = Some(i);
+        self.wrapped = wrapped;
+        Some(self.matches[i])
+    }
+
+    pub fn counts(&self) -> Counts {
+        Counts {
+            current: self.current,
+            total: self.matches.len(),
+            wrapped: self.wrapped,
+            error: self.error.clone(),
+        }
+    }
+
+    // What the match at byte `at` of `text` becomes; $1 and ${name} expand in
+    // regex mode, from the groups of the match where it was found, so
+    // anchors and \b see the text around it
+    pub fn expand(&self, text: &str, at: usize, replacement: &str) -> String {
+        let found = pattern(&self.query, self.flags).ok()
+            .filter(|_| self.flags.regex)
+            .and_then(|re| re.captures_at(text, at))
+            .filter(|caps| caps.get(0).is_some_and(|m| m.start() == at));
+        let Some(caps) = found else { return replacement.to_string() };
+        let mut out = String::new();
+        caps.expand(replacement, &mut out);
+        out
+    }
+
+    // The flags and where the cursor is among the matches, shown in the
+    // status bar while searching
+    pub fn status(&self) -> String {
+        let at = match self.current {
+            Some(i) if self.wrapped => format!(" {}/{} search wrapped", i + 1, self.matches.len()),
+            Some(i) => format!(" {}/{}", i + 1, self.matches.len()),
+            None => String::new(),
+        };
+        format!("({}){}", self.flags.label(), at)
+    }
+}
+
+// The Find prompt with how many matches there are, e.g. "Find 3/17: "
+pub fn prompt(counts: &Counts, query: &str) -> String {
+    match (counts.current, counts.total) {
+        _ if query.is_empty() => "Find: ".to_string(),
+        _ if counts.error.is_some() => format!("Find ({}): ", counts.error.as_deref().unwrap_or_default()),
+        (_, 0) => "Find (no matches): ".to_string(),
+        (Some(i), n) if counts.wrapped => format!("Find {}/{} wrapped: ", i + 1, n),
+        (Some(i), n) => format!("Find {}/{}: ", i + 1, n),
+        (None, n) => format!("Find ({} matches): ", n),
+    }
+}
+
+fn pattern(query: &str, flags: Flags) -> Result<Regex, regex::Error> {
+    if flags.regex {
+        let pattern = match flags.whole_word {
+            true => format!(r"\b(?:{})\b", query),
+            false => query.to_string(),
+        };
+        return RegexBuilder::new(&pattern).case_insensitive(!flags.case_sensitive).build();
+    }
+    let mut pattern = regex::escape(query);
+    // \b only holds next to word chars, so `+=` still matches as a whole word
+    let word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    if flags.whole_word && word(query.chars().next()) {
+        pattern.insert_str(0, r"\b");
+    }
+    if flags.whole_word && word(query.chars().last()) {
+        pattern.push_str(r"\b");
+    }
+    RegexBuilder::new(&pattern)
+        .case_insensitive(!flags.case_sensitive)
+        .build()
+}
+
+// The last line of a parse error, e.g. "unclosed group", for the prompt
+fn error_message(e: &regex::Error) -> String {
+    let message = e.to_string();
+    let last = message.lines().last().unwrap_or_default();
+    format!("bad regex: {}", last.trim_start_matches("error: "))
+}
+
+// Byte ranges of the non overlapping matches of `query`; empty matches, like
+// those of `a*`, are left out
+fn find_all(text: &str, query: &str, flags: Flags) -> Result<Vec<(usize, usize)>, regex::Error> {
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+    Ok(pattern(query, flags)?.find_iter(text)
+        .filter(|m| !m.is_empty())
+        .map(|m| (m.start(), m.end()))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn finds_nearest_match() {
+        let text = Rope::from_str("한 ab\nab ab\n");
+        let mut search = Search::default();
+        let flags = Flags { case_sensitive: true, ..Default::default() };
+        search.update(&text, "ab", flags);
+        assert_eq!(search.matches, [(2, 4), (5, 7), (8, 10)]);
+        assert_eq!(search.nearest(3), Some((5, 7)));
+        assert_eq!(prompt(&search.counts(), "ab"), "Find 2/3: ");
+        assert_eq!(search.nearest(9), Some((2, 4)));
+        assert_eq!(search.step(false), Some((8, 10)));
+        assert_eq!(prompt(&search.counts(), "ab"), "Find 3/3 wrapped: ");
+        assert_eq!(search.status(), "(literal case) 3/3 search wrapped");
+        assert_eq!(search.step(true), Some((2, 4)));
+        assert_eq!(search.step(true), Some((5, 7)));
+        assert!(!search.wrapped);
+        search.update(&text, "x", flags);
+        assert_eq!(search.nearest(0), None);
+        assert_eq!(prompt(&search.counts(), "x"), "Find (no matches): ");
+        assert_eq!(search.status(), "(literal case)");
+    }
+
+    #[test]
+    fn applies_flags() {
+        let text = "Foo foo food a.b axb x+=1";
+        let found = |q, case_sensitive, whole_word| find_all(text, q, Flags { case_sensitive, whole_word, regex: false }).unwrap().len();
+        assert_eq!(found("foo", false, false), 3);
+        assert_eq!(found("foo", true, false), 2);
+        assert_eq!(found("foo", false, true), 2);
+        assert_eq!(found("a.b", false, false), 1);
+        assert_eq!(found("+=", false, true), 1);
+        let regex = Flags { regex: true, ..Default::default() };
+        assert_eq!(find_all(text, "a.b", regex).unwrap().len(), 2);
+        assert_eq!(find_all(text, "fo+d?", Flags { whole_word: true, ..regex }).unwrap().len(), 3);
+        assert_eq!(find_all(text, "z*", regex).unwrap().len(), 0);
+        let mut search = Search::default();
+        search.update(&Rope::from_str(text), r"(\w)\.(\w)", regex);
+        assert_eq!(search.expand(text, 13, "$2-$1"), "b-a");
+        // \B holds where the match was found, not in the match alone
+        search.update(&Rope::from_str("ab"), r"\B(\w)", regex);
+        assert_eq!(search.matches, [(1, 2)]);
+        assert_eq!(search.expand("ab", 1, "<$1>"), "<b>");
+        search.update(&Rope::from_str(text), "(foo", regex);
+        assert_eq!(prompt(&search.counts(), "(foo"), "Find (bad regex: unclosed group): ");
+    }
+}
@@ -0,0 +1,78 @@
+// The open tabs, where their cursors were and the command line history,
+// written to the project's .zutto/session.json every SAVE_INTERVAL while the
+// editor runs and removed when it quits. One left behind means the last run
+// did not quit, and the next launch without files offers to restore it
+
+use std::{fs, path::{Path, PathBuf}, time::Duration};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+pub const SESSION_FILE: &str = ".zutto/session.json";
+// Where the session of a run that did not quit waits for RestoreSession
+const CRASHED_FILE: &str = ".zutto/session.crashed.json";
+pub const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    pub tabs: Vec<SavedTab>,
+    // Index into `tabs`
+    pub focused: usize,
+    pub history: Vec<String>,
+}
+
+// A file with its cursor, or a directory
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedTab {
+    pub path: PathBuf,
+    pub line: usize,
+    pub col: usize,
+}
+
+pub fn save(root: &Path, session: &Session) -> Result<()> {
+    let path = root.join(SESSION_FILE);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(session)?)?;
+    Ok(())
+}
+
+pub fn remove(root: &Path) {
+    let _ = fs::remove_file(root.join(SESSION_FILE));
+}
+
+// Moves the session a run left behind out of the way of this run's, true
+// when there was one
+pub fn stash_crashed(root: &Path) -> bool {
+    fs::rename(root.join(SESSION_FILE), root.join(CRASHED_FILE)).is_ok()
+}
+
+// The stashed session, gone from disk whether or not it is restored
+pub fn take_crashed(root: &Path) -> Option<Session> {
+    let path = root.join(CRASHED_FILE);
+    let text = fs::read_to_string(&path).ok();
+    let _ = fs::remove_file(&path);
+    serde_json::from_str(&text?).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stashes_and_takes_crashed() {
+        let root = std::env::temp_dir().join(format!("zutto-session-{}", std::process::id()));
+        let session = Session {
+            tabs: vec![SavedTab { path: PathBuf::from("src/main.rs"), line: 12, col: 4 }],
+            focused: 0,
+            history: vec!["Goto(3)".to_string()],
+        };
+        save(&root, &session).unwrap();
+        assert!(stash_crashed(&root));
+        assert!(!stash_crashed(&root));
+        assert_eq!(take_crashed(&root), Some(session));
+        assert_eq!(take_crashed(&root), None);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,85 @@
+use std::{str::FromStr, sync::OnceLock};
+
+use log::error;
+use syntect::{dumps, highlighting::{Color, FontStyle, ScopeSelectors, StyleModifier, Theme, ThemeItem, ThemeSet, ThemeSettings}, parsing::SyntaxSet};
+
+// Precompiled syntaxes, e.g. written with `syntect::dumps::dump_to_file`; the
+// set bundled with syntect is used when it is missing
+const SYNTAX_DUMP: &str = "settings/syntaxes.packdump";
+const THEME_DUMP: &str = "settings/themes.themedump";
+const DEFAULT_THEME: &str = "base16-ocean.dark";
+pub const HIGH_CONTRAST: &str = "high-contrast";
+
+static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+static THEMES: OnceLock<ThemeSet> = OnceLock::new();
+
+// Shared by every buffer; loaded on first use
+pub fn syntax_set() -> &'static SyntaxSet {
+    SYNTAXES.get_or_init(|| match dumps::from_dump_file(SYNTAX_DUMP) {
+        Ok(set) => set,
+        Err(e) => {
+            if std::path::Path::new(SYNTAX_DUMP).exists() {
+                error!("{}: {}", SYNTAX_DUMP, e);
+            }
+            SyntaxSet::load_defaults_newlines()
+        }
+    })
+}
+
+// Themes are only needed once something is drawn
+pub fn theme(name: &str) -> &'static Theme {
+    let themes = THEMES.get_or_init(|| {
+        let mut set = match dumps::from_dump_file(THEME_DUMP) {
+            Ok(set) => set,
+            Err(e) => {
+                if std::path::Path::new(THEME_DUMP).exists() {
+                    error!("{}: {}", THEME_DUMP, e);
+                }
+                ThemeSet::load_defaults()
+            }
+        };
+        set.themes.insert(HIGH_CONTRAST.to_string(), high_contrast());
+        set
+    });
+    themes.themes.get(name)
+        .or_else(|| themes.themes.get(DEFAULT_THEME))
+        .or_else(|| themes.themes.values().next())
+        .expect("no themes loaded")
+}
+
+// Built in: white on black with a few saturated colors, kept apart by
+// brightness as well as hue
+fn high_contrast() -> Theme {
+    let rgb = |r, g, b| Color { r, g, b, a: 0xff };
+    let item = |scope: &str, color: Color, font_style: FontStyle| ThemeItem {
+        scope: ScopeSelectors::from_str(scope).unwrap(),
+        style: StyleModifier { foreground: Some(color), background: None, font_style: Some(font_style) },
+    };
+    Theme {
+        name: Some("High Contrast".to_string()),
+        author: None,
+        settings: ThemeSettings {
+            foreground: Some(rgb(0xff, 0xff, 0xff)),
+            background: Some(rgb(0x00, 0x00, 0x00)),
+            caret: Some(rgb(0xff, 0xff, 0x00)),
+            selection: Some(rgb(0x00, 0x00, 0xaa)),
+            ..Default::default()
+        },
+        scopes: vec![
+            item("comment", rgb(0x7f, 0xff, 0x7f), FontStyle::ITALIC),
+            item("keyword, storage", rgb(0xff, 0xff, 0x00), FontStyle::BOLD),
+            item("string", rgb(0x00, 0xff, 0xff), FontStyle::empty()),
+            item("constant, support.constant", rgb(0xff, 0x9f, 0xff), FontStyle::empty()),
+            item("entity.name, support.function", rgb(0xff, 0xff, 0xff), FontStyle::BOLD),
+            item("invalid", rgb(0xff, 0x5f, 0x5f), FontStyle::UNDERLINE),
+        ],
+    }
+}
+
+// Starts loading the syntaxes in the background so the first frame doesn't wait
+pub fn preload() {
+    std::thread::spawn(|| {
+        syntax_set();
+        theme(DEFAULT_THEME);
+    });
+}
@@ -1,16 +1,20 @@
 use core::sync;
-use std::{cmp::min, io::Write, path::{self, PathBuf}};
+use std::{cmp::min, collections::{BTreeSet, HashMap, HashSet, VecDeque}, io::Write, path::{self, Path, PathBuf}, sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, Arc, Mutex, LazyLock, RwLock, RwLockReadGuard, RwLockWriteGuard}, time::SystemTime};
 
 use anyhow::Result;
 use async_trait::async_trait;
 use crossterm::{cursor, queue, style::{Color, Print, StyledContent, Stylize}, terminal::{self, Clear}};
-use log::debug;
-use ropey::Rope;
-use syntect::{easy::HighlightLines, highlighting::{self, Theme, ThemeSet}, parsing::{SyntaxReference, SyntaxSet}};
+use log::{debug, error};
+use regex::Regex;
+use ropey::{Rope, RopeSlice};
+use syntect::{easy::HighlightLines, highlighting::{self, Theme}, parsing::{SyntaxReference, SyntaxSet}};
+use tokio::io::AsyncReadExt;
 
-use crate::{actions::ActionReturn, syncol_to_crosscol, Action, KeymapState, Setting};
+use crate::{abbrev, bookmarks, actions::{ActionReturn, Unsaved}, brackets, eol::LineEnding, job::{JobReporter, JobTask}, range, clipboard, crypt::Encryption, hangul, indent, lsp, popup::Popup, profiler, remote::Remote, search::{self, Search}, syntax, templates, transform, unicode, views, syncol_to_crosscol, Action, KeymapState, Setting, TabType};
 
-use super::{numlen, Cursor, Pos, Size, Tab};
+use super::{git, results::Location, shell};
+
+use super::{numlen, Cursor, Pos, Size, Tab, TabView, Title};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Camera {
@@ -18,49 +22,214 @@ pub struct Camera {
     pub col: u16,
 }
 
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+// Actions run at every cursor while there are more than one
+const MULTI_CURSOR_ACTIONS: &[&str] = &[
+    "Insert", "InsertUpper", "InsertStr", "InsertSpace", "InsertComma", "InsertTab", "InsertNewline",
+    "Delete", "DeleteBackward", "CursorUp", "CursorDown", "CursorForward", "CursorBackward",
+    "CursorForwardWord", "CursorBackwardWord", "CursorStart", "CursorEnd",
+    "DeleteWordForward", "DeleteWordBackward", "DeleteToEnd",
+];
+
+// Files git opens the editor on; they get commit message mode
+const COMMIT_MESSAGE_FILES: [&str; 3] = ["COMMIT_EDITMSG", "MERGE_MSG", "TAG_EDITMSG"];
+// Widths of a commit message's summary line and of its body
+const SUMMARY_WIDTH: usize = 50;
+const BODY_WIDTH: usize = 72;
+
+// Files larger than this are shown at once and read in by a job, in chunks
+// of LOAD_CHUNK bytes
+const LAZY_LOAD_BYTES: u64 = 64 << 20;
+const LOAD_CHUNK: usize = 4 << 20;
+
+// How the selection was made: SelectStart or a drag, a double click, or a
+// triple click, the line numbers or a line range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SelectMode {
+    Char,
+    Word,
+    Line,
+}
+
+// Text drawn after the end of a line without being part of the rope
+// (diagnostics, blame, test results); `source` lets each producer replace its own
+#[derive(Debug, Clone)]
+pub struct VirtualText {
+    pub source: String,
+    pub line: usize,
+    pub text: String,
+    pub color: Color,
+}
+
+// Text read so far by the job loading a large file, taken by LoadChunk
+#[derive(Debug, Default)]
+struct Loading {
+    started: bool,
+    chunks: VecDeque<String>,
+    // Line ending of the first chunk, which the whole file is taken to have
+    ending: Option<LineEnding>,
+    done: bool,
+    // The job failed; the part read is not the file, so it is never saved
+    // and the buffer stays read-only
+    failed: bool,
+}
+
+fn virtual_text_color(items: &[VirtualText], line: usize) -> Color {
+    items.iter().find(|v| v.line == line).map(|v| v.color).unwrap_or(Color::DarkGrey)
+}
+
 #[derive(Debug)]
 pub struct Buffer {
+    pub id: usize,
     pub tab_idx: usize,
-    text: Rope,
+    // Set by RenameTab and shown in the tab bar instead of the name
+    renamed: Title,
+    // Shared between every view of the same file, see `new_view`
+    text: Arc<RwLock<Rope>>,
     cursor_idx: usize,
     camera: Camera,
     size: Size,
     pos: Pos,
     path: Option<PathBuf>, //None if it is a new buffer
-    syntax_set: SyntaxSet,
     area_start: Option<usize>,
+    // Cursors besides `cursor_idx`, added by AddCursorBelow and AddCursorAtNextMatch
+    cursors: Vec<usize>,
+    // Line a click on the line numbers started on, while dragging from it
+    gutter_anchor: Option<usize>,
+    select_mode: SelectMode,
+    virtual_text: Vec<VirtualText>,
     setting: Setting,
-    saved: bool,
-    theme_set: ThemeSet,
+    saved: Arc<AtomicBool>,
+    // Modification time of the file when it was last read or written
+    mtime: Option<SystemTime>,
+    // Bumped on every change to the shared text, invalidating `line_cache`
+    edits: Arc<AtomicUsize>,
+    // (edits, cursor_idx, line) of the last cursor line lookup
+    line_cache: Mutex<Option<(usize, usize, usize)>>,
+    // Hangul jamo typed so far are combined into the syllable before the cursor
+    composing: bool,
+    // (edits, stats) of the whole text for the status bar
+    stats_cache: Mutex<Option<(usize, Stats)>>,
+    // (edits, cursor_idx, brackets) of the last matching bracket lookup
+    bracket_cache: Mutex<Option<(usize, usize, Option<Brackets>)>>,
+    // (key, columns) of the last `layout`
+    layout_cache: Mutex<Option<(LayoutKey, Vec<usize>)>>,
+    // (edits, swatches by line) of the lines drawn since the last edit
+    swatch_cache: Mutex<(usize, HashMap<usize, Swatches>)>,
+    // Soft wrap, reflow and spell check for prose; holds `line_numbers` from before
+    prose: Option<bool>,
+    misspelled: HashSet<String>,
+    // Read and written over ssh instead of `path`
+    remote: Option<Remote>,
+    encryption: Option<Encryption>,
+    // Active in Find mode, with the cursor and camera to go back to on FindCancel
+    search: Option<Search>,
+    search_origin: (usize, Camera),
+    search_flags: search::Flags,
+    // A Replace waiting for the answer about the current match of `search`
+    replacing: Option<search::Replace>,
+    // Named throwaway buffer, never unsaved; SaveAs turns it into a file
+    scratch: Option<String>,
+    // Bookmarked lines, shared with other views like the text
+    bookmarks: Arc<Mutex<BTreeSet<usize>>>,
+    // Settings changed by SetLocal, saved with the bookmarks in views.json
+    local_settings: serde_json::Map<String, serde_json::Value>,
+    // Name of the job that may be writing the file; edits are refused until
+    // it finishes
+    locked_by: Option<String>,
+    // The last Save(autosave) failed; cleared by the next save that works
+    autosave_failed: bool,
+    // Output of RunCommand, which takes no edits
+    read_only: bool,
+    // How the file ends its lines, shared with other views like the text
+    line_ending: Arc<Mutex<LineEnding>>,
+    // Set while a large file is still being read; it is read-only until then
+    loading: Option<Arc<Mutex<Loading>>>,
+}
+
+// Lines, words and chars
+type Stats = (usize, usize, usize);
+// A bracket and the one matching it, as char indices
+type Brackets = (usize, usize);
+// Edits, line, tab_size and color_swatches, which the columns of a line depend on
+type LayoutKey = (usize, usize, usize, bool);
+// (line relative char index, color) of the swatches on a line
+type Swatches = Vec<(usize, Color)>;
+
+// A word is a run of non whitespace chars
+fn text_stats(text: RopeSlice) -> Stats {
+    let mut words = 0;
+    let mut in_word = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            in_word = false;
+        } else if !in_word {
+            in_word = true;
+            words += 1;
+        }
+    }
+    let chars = text.len_chars();
+    let lines = match text.chars().last() {
+        None => 0,
+        Some('\n') => text.len_lines() - 1,
+        Some(_) => text.len_lines(),
+    };
+    (lines, words, chars)
+}
+
+fn format_stats((lines, words, chars): Stats) -> String {
+    format!("{} lines, {} words, {} chars", lines, words, chars)
+}
+
+// Where `p` goes when `n` chars of indent are added at or taken from
+// `start`; the line start itself stays, so whole line selections stay whole
+fn shifted(p: usize, start: usize, n: usize, indent: bool) -> usize {
+    match indent {
+        true if p > start => p + n,
+        false if p >= start + n => p - n,
+        false if p > start => start,
+        _ => p,
+    }
+}
+
+// Word chars, blanks and everything else, for word motions and double click
+// selection. Letters, digits and `word_chars` make up words
+fn char_class(c: char, word_chars: &str) -> u8 {
+    if c.is_whitespace() {
+        0
+    } else if c.is_alphanumeric() || word_chars.contains(c) {
+        2
+    } else {
+        1
+    }
 }
 
 fn is_hangul(c: char) -> bool {
     (0xAC00 < c as u32 && 0xD7AF > c as u32) || (0x3130 < c as u32 && 0x318E > c as u32)
 }
 
+// By extension, then by file name for the likes of Makefile, then by a first
+// line such as a shebang
 fn get_syntex_ref<'a>(text: &Rope, path: &Option<PathBuf>, syntax_set: &'a SyntaxSet) -> &'a SyntaxReference {
-    match path {
-        Some(p) => {
-            if let Some(extension) = p.extension() {
-                syntax_set.find_syntax_by_extension(extension.to_str().unwrap())
-            } else {
-                for line in text.lines() {
-                    if let Some(syntax) = syntax_set.find_syntax_by_first_line(line.as_str().unwrap_or("")) {
-                        return syntax;
-                    }
-                }
-                None
-            }
-        }
-        None => {
-            for line in text.lines() {
-                if let Some(syntax) = syntax_set.find_syntax_by_first_line(line.as_str().unwrap_or("")) {
-                    return syntax;
-                }
-            }
-            None
-        }
-    }.unwrap_or_else(|| syntax_set.find_syntax_plain_text())
+    let by_path = path.as_ref().and_then(|p| {
+        let ext = p.extension().and_then(|e| e.to_str());
+        let name = p.file_name().and_then(|n| n.to_str());
+        ext.and_then(|e| syntax_set.find_syntax_by_extension(e))
+            .or_else(|| name.and_then(|n| syntax_set.find_syntax_by_extension(n)))
+    });
+    by_path.or_else(|| {
+        text.lines().find_map(|line| syntax_set.find_syntax_by_first_line(line.as_str().unwrap_or("")))
+    }).unwrap_or_else(|| syntax_set.find_syntax_plain_text())
+}
+
+// Cells a char takes when drawn at display column `col`
+fn char_width(c: char, col: usize, tab_size: usize) -> usize {
+    match c {
+        '\t' => tab_size - col % tab_size,
+        c if is_hangul(c) => 2,
+        _ => 1,
+    }
 }
 
 fn highlight_line<'a>(line: &'a str, syntax: &SyntaxReference, syntax_set: &SyntaxSet, theme: &Theme) -> Vec<(highlighting::Style, &'a str)> {
@@ -70,12 +239,12 @@ fn highlight_line<'a>(line: &'a str, syntax: &SyntaxReference, syntax_set: &Synt
 // Text buffer
 impl Buffer {
     pub fn new(size: Size, pos: Pos, setting: Setting, tab_idx: usize) -> Self {
-        let syntax_set = SyntaxSet::load_defaults_newlines();
-        let theme_set = ThemeSet::load_defaults();
         Self {
+            id: NEXT_ID.fetch_add(1, Ordering::SeqCst),
             tab_idx,
+            renamed: Title::default(),
             pos,
-            text: Rope::new(),
+            text: Arc::new(RwLock::new(Rope::new())),
             cursor_idx: 0,
             camera: Camera {
                 row: 0,
@@ -83,136 +252,711 @@ impl Buffer {
             },
             size,
             path: None,
-            syntax_set,
-            theme_set,
             area_start: None,
+            gutter_anchor: None,
+            select_mode: SelectMode::Char,
+            cursors: Vec::new(),
+            virtual_text: Vec::new(),
             setting,
-            saved: false,
+            saved: Arc::new(AtomicBool::new(false)),
+            mtime: None,
+            composing: false,
+            edits: Arc::new(AtomicUsize::new(0)),
+            line_cache: Mutex::new(None),
+            stats_cache: Mutex::new(None),
+            bracket_cache: Mutex::new(None),
+            layout_cache: Mutex::new(None),
+            swatch_cache: Mutex::new((0, HashMap::new())),
+            prose: None,
+            misspelled: HashSet::new(),
+            bookmarks: Arc::new(Mutex::new(BTreeSet::new())),
+            remote: None,
+            encryption: None,
+            search: None,
+            search_origin: (0, Camera { row: 0, col: 0 }),
+            search_flags: search::Flags::default(),
+            replacing: None,
+            scratch: None,
+            local_settings: serde_json::Map::new(),
+            locked_by: None,
+            autosave_failed: false,
+            read_only: false,
+            line_ending: Arc::new(Mutex::new(LineEnding::Lf)),
+            loading: None,
+        }
+    }
+
+    // Unnamed buffer holding `text`, e.g. read from a pipe
+    pub fn from_text(size: Size, pos: Pos, text: &str, setting: Setting, tab_idx: usize) -> Self {
+        let mut buffer = Self::new(size, pos, setting, tab_idx);
+        let (ending, text) = LineEnding::read(text);
+        *buffer.text_mut() = Rope::from_str(&text);
+        *buffer.line_ending.lock().unwrap() = ending;
+        buffer.detect_indent();
+        buffer
+    }
+
+    // Applied to this buffer's copy of the settings only
+    fn detect_indent(&mut self) {
+        if !self.setting.detect_indent {
+            return;
+        }
+        let detected = indent::detect(self.text().lines().map(|l| l.to_string()));
+        match detected {
+            Some((TabType::Tab, _)) => self.setting.tab_type = TabType::Tab,
+            Some((TabType::Space, width)) => {
+                self.setting.tab_type = TabType::Space;
+                if let Some(width) = width {
+                    self.setting.tab_size = width;
+                }
+            }
+            None => {}
+        }
+    }
+
+    // Another view of the same text with its own cursor and camera;
+    // edits made through either view show up in both
+    pub fn new_view(&self, tab_idx: usize) -> Self {
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::SeqCst),
+            tab_idx,
+            renamed: Title::default(),
+            text: self.text.clone(),
+            cursor_idx: self.cursor_idx,
+            camera: self.camera,
+            size: self.size,
+            pos: self.pos,
+            path: self.path.clone(),
+            area_start: None,
+            gutter_anchor: None,
+            select_mode: SelectMode::Char,
+            cursors: Vec::new(),
+            virtual_text: Vec::new(),
+            setting: self.setting.clone(),
+            saved: self.saved.clone(),
+            mtime: self.mtime,
+            composing: false,
+            edits: self.edits.clone(),
+            line_cache: Mutex::new(None),
+            stats_cache: Mutex::new(None),
+            bracket_cache: Mutex::new(None),
+            layout_cache: Mutex::new(None),
+            swatch_cache: Mutex::new((0, HashMap::new())),
+            prose: self.prose,
+            misspelled: self.misspelled.clone(),
+            bookmarks: self.bookmarks.clone(),
+            remote: self.remote.clone(),
+            encryption: self.encryption.clone(),
+            search: None,
+            search_origin: (0, Camera { row: 0, col: 0 }),
+            search_flags: self.search_flags,
+            replacing: None,
+            scratch: self.scratch.clone(),
+            local_settings: self.local_settings.clone(),
+            locked_by: self.locked_by.clone(),
+            autosave_failed: false,
+            read_only: self.read_only,
+            line_ending: self.line_ending.clone(),
+            loading: self.loading.clone(),
+        }
+    }
+
+    fn char_at(&self, idx: usize) -> char {
+        self.text().char(idx)
+    }
+
+    fn text(&self) -> RwLockReadGuard<'_, Rope> {
+        self.text.read().unwrap()
+    }
+
+    fn text_mut(&self) -> RwLockWriteGuard<'_, Rope> {
+        self.edits.fetch_add(1, Ordering::SeqCst);
+        self.text.write().unwrap()
+    }
+
+    // Edits at a char index go through these, moving the bookmarks below
+    fn insert_text(&self, idx: usize, s: &str) {
+        let mut text = self.text_mut();
+        let line = text.char_to_line(idx);
+        let at_start = text.line_to_char(line) == idx;
+        text.insert(idx, s);
+        bookmarks::inserted(&mut self.bookmarks.lock().unwrap(), line, at_start, s.matches('\n').count());
+    }
+
+    fn remove_text(&self, range: std::ops::Range<usize>) {
+        let mut text = self.text_mut();
+        let (first, last) = (text.char_to_line(range.start), text.char_to_line(range.end));
+        let at_start = text.line_to_char(first) == range.start;
+        text.remove(range);
+        bookmarks::removed(&mut self.bookmarks.lock().unwrap(), first, last, at_start);
+    }
+
+    pub fn set_remote(&mut self, remote: Remote) {
+        self.remote = Some(remote);
+        self.set_saved(true);
+    }
+
+    pub fn set_encryption(&mut self, path: PathBuf, encryption: Encryption) {
+        self.path = Some(path);
+        self.encryption = Some(encryption);
+        self.mtime = self.disk_mtime();
+        self.set_saved(true);
+    }
+
+    // Moves the cursor to the match of `query` nearest to where the search
+    // started, or back there when nothing matches
+    pub fn search(&mut self, query: &str) -> ActionReturn {
+        let search = self.search.get_or_insert_with(|| {
+            self.search_origin = (self.cursor_idx, self.camera);
+            Search::default()
+        });
+        let text = self.text.read().unwrap().clone();
+        search.update(&text, query, self.search_flags);
+        let nearest = search.nearest(self.search_origin.0);
+        let counts = search.counts();
+        match nearest {
+            Some((start, _)) => {
+                self.cursor_idx = start;
+                self.adj_camera();
+            }
+            None => (self.cursor_idx, self.camera) = self.search_origin,
         }
+        ActionReturn::Matches(counts)
+    }
+
+    pub fn search_query(&self) -> Option<&str> {
+        self.search.as_ref().map(|s| s.query.as_str())
+    }
+
+    // Counts every change to the text, from any view
+    pub fn edits(&self) -> usize {
+        self.edits.load(Ordering::SeqCst)
+    }
+
+    pub fn is_saved(&self) -> bool {
+        self.saved.load(Ordering::SeqCst)
+    }
+
+    // An untitled buffer nothing was typed in, like the one the editor starts with
+    pub fn is_blank(&self) -> bool {
+        self.path.is_none() && self.text().len_chars() == 0
+    }
+
+    fn set_saved(&self, saved: bool) {
+        self.saved.store(saved, Ordering::SeqCst);
     }
 
     pub fn resize(&mut self, size: Size) {
         self.size = size;
+        self.adj_camera();
     }
 
     pub fn from_file(size: Size, pos: Pos, path: &PathBuf, setting: Setting, tab_idx: usize) -> Result<Self> {
-        let text = Self::open(path)?;
-        let syntax_set = SyntaxSet::load_defaults_newlines();
-        let theme_set = ThemeSet::load_defaults();
-        Ok(Self {
+        let large = std::fs::metadata(path)?.len() > LAZY_LOAD_BYTES;
+        let (ending, text) = match large {
+            true => (LineEnding::Lf, Rope::new()),
+            false => Self::open(path)?,
+        };
+        let mut buffer = Self {
+            id: NEXT_ID.fetch_add(1, Ordering::SeqCst),
             tab_idx,
-            text,
+            renamed: Title::default(),
+            text: Arc::new(RwLock::new(text)),
             cursor_idx: 0,
             camera: Camera { row: 0, col: 0 },
             size,
             pos,
             path: Some(path.clone()), 
-            syntax_set,
-            theme_set,
             area_start: None,
+            gutter_anchor: None,
+            select_mode: SelectMode::Char,
+            cursors: Vec::new(),
+            virtual_text: Vec::new(),
             setting,
-            saved: true,
-        })
+            saved: Arc::new(AtomicBool::new(true)),
+            mtime: std::fs::metadata(path).and_then(|m| m.modified()).ok(),
+            composing: false,
+            edits: Arc::new(AtomicUsize::new(0)),
+            line_cache: Mutex::new(None),
+            stats_cache: Mutex::new(None),
+            bracket_cache: Mutex::new(None),
+            layout_cache: Mutex::new(None),
+            swatch_cache: Mutex::new((0, HashMap::new())),
+            prose: None,
+            misspelled: HashSet::new(),
+            bookmarks: Arc::new(Mutex::new(BTreeSet::new())),
+            remote: None,
+            encryption: None,
+            search: None,
+            search_origin: (0, Camera { row: 0, col: 0 }),
+            search_flags: search::Flags::default(),
+            replacing: None,
+            scratch: None,
+            local_settings: serde_json::Map::new(),
+            locked_by: None,
+            autosave_failed: false,
+            read_only: false,
+            line_ending: Arc::new(Mutex::new(ending)),
+            loading: large.then(|| Arc::new(Mutex::new(Loading::default()))),
+        };
+        if !large {
+            buffer.detect_indent();
+            buffer.restore_view();
+        }
+        Ok(buffer)
     }
 
+    // After SaveAs gives the buffer its path: what was made out from the old
+    // one, or from text it did not have yet, is made out again. Syntax and
+    // the per extension settings follow the path as it is
+    fn named(&mut self, path: PathBuf) {
+        self.path = Some(path);
+        self.scratch = None;
+        self.read_only = false;
+        *self.bracket_cache.lock().unwrap() = None;
+        self.detect_indent();
+        // Settings set on the buffer itself still win over detection
+        for (key, value) in self.local_settings.clone() {
+            if let Err(e) = self.set_local(&key, value) {
+                error!("{}", e);
+            }
+        }
+    }
 
-    // file I/O
-    // 0x01: hangul padding
-    // 0x02: tab padding
-
-    fn open(path: &PathBuf) -> Result<Rope> {
-        let mut rope = Rope::from_reader(std::fs::File::open(path)?)?;
-        let chars = rope.chars().collect::<Vec<_>>();
-        for (i, c) in chars.iter().enumerate() {
-            if *c == '\t' {
-                rope.insert_char(i, '\t');
-                for _ in 0..3 {
-                    rope.insert_char(i + 1, '\x02');
-                }
-            } else if is_hangul(*c) {
-                rope.insert_char(i, *c);
-                rope.insert_char(i + 1, '\x01');
-            } else {
-                rope.insert_char(i, *c);
+    fn restore_view(&mut self) {
+        let Some(view) = self.path.as_deref().and_then(views::load) else { return };
+        // The file may have been shortened since, by another program
+        let lines = self.text().len_lines();
+        *self.bookmarks.lock().unwrap() = view.bookmarks.into_iter().filter(|l| *l < lines).collect();
+        for (key, value) in view.settings {
+            if let Err(e) = self.set_local(&key, value) {
+                error!("{}: {}", views::VIEWS_FILE, e);
             }
         }
-        Ok(rope)
+    }
+
+    fn save_view(&self) -> Option<ActionReturn> {
+        let path = self.path.as_ref().filter(|_| self.remote.is_none())?;
+        let view = views::View {
+            bookmarks: self.bookmarks.lock().unwrap().iter().copied().collect(),
+            settings: self.local_settings.clone(),
+        };
+        views::save(path, &view).err().map(ActionReturn::Err)
+    }
+
+    fn set_local(&mut self, key: &str, value: serde_json::Value) -> Result<()> {
+        let mut json = serde_json::to_value(&self.setting)?;
+        let Some(current) = json.get_mut(key) else {
+            return Err(anyhow::anyhow!("Unknown setting: {}", key));
+        };
+        *current = value.clone();
+        let setting: Setting = serde_json::from_value(json).map_err(|e| anyhow::anyhow!("{}: {}", key, e))?;
+        if setting.tab_size == 0 {
+            return Err(anyhow::anyhow!("tab_size: must be at least 1"));
+        }
+        self.setting = setting;
+        self.local_settings.insert(key.to_string(), value);
+        self.adj_camera();
+        Ok(())
+    }
+
+
+    // file I/O
+
+    fn open(path: &PathBuf) -> Result<(LineEnding, Rope)> {
+        let text = std::fs::read_to_string(path)?;
+        let (ending, text) = LineEnding::read(&text);
+        Ok((ending, Rope::from_str(&text)))
     }
 
     fn save(&mut self, p: Option<&str>) -> Result<()> {
-        let mut byte: Vec<u8> = Vec::new();
-        for b in self.text.bytes() {
-            if b != 0 || b != 1 || b != 2 {
-                byte.push(b);
-            }
-        }
-        if let Some(path) = p {
-            let mut file = std::fs::File::create(path)?; 
-            file.write_all(&byte)?;
-        } else if let Some(path) = &self.path {
-            let mut file = std::fs::File::create(path)?;
-            file.write_all(&byte)?;
+        let path = match p {
+            Some(path) => PathBuf::from(path),
+            None => self.path.clone().ok_or(anyhow::anyhow!("No path to save, use save_as(Cmd: Ctrl+S)"))?,
+        };
+        if let Some(backup) = self.setting.backup_path(&path).filter(|_| path.is_file()) {
+            if let Some(dir) = backup.parent().filter(|d| !d.as_os_str().is_empty()) {
+                std::fs::create_dir_all(dir)?;
+            }
+            std::fs::copy(&path, &backup)
+                .map_err(|e| anyhow::anyhow!("Not saved, could not back up {} to {}: {}", path.display(), backup.display(), e))?;
+        }
+        self.setting.final_newline.apply(&mut self.text_mut());
+        let len = self.text().len_chars();
+        self.cursor_idx = min(self.cursor_idx, len);
+        let file = std::fs::File::create(path)?;
+        self.line_ending().write_to(&self.text(), std::io::BufWriter::new(file))?;
+        self.set_saved(true);
+        self.mtime = self.disk_mtime();
+        Ok(())
+    }
+
+    // The job reading a large file from_file left empty, the first time it is asked for
+    pub fn take_loader(&mut self) -> Option<(String, JobTask)> {
+        let loading = self.loading.clone()?;
+        if std::mem::replace(&mut loading.lock().unwrap().started, true) {
+            return None;
+        }
+        let (buffer, path) = (self.id, self.path.clone()?);
+        Some((format!("load {}", path.display()), Box::new(move |reporter| Box::pin(load(path, buffer, loading, reporter)))))
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.loading.as_ref().is_some_and(|l| {
+            let loading = l.lock().unwrap();
+            !loading.done || !loading.chunks.is_empty() || loading.failed
+        })
+    }
+
+    // Appends what the loading job has read; the rest of opening a file
+    // waits for the last chunk
+    fn load_chunks(&mut self) {
+        let Some(loading) = self.loading.clone() else { return };
+        let mut loading = loading.lock().unwrap();
+        if let Some(ending) = loading.ending.take() {
+            *self.line_ending.lock().unwrap() = ending;
+        }
+        for chunk in loading.chunks.drain(..) {
+            let len = self.text().len_chars();
+            self.insert_text(len, &chunk);
+        }
+        if !loading.done || loading.failed {
+            return;
+        }
+        drop(loading);
+        self.loading = None;
+        self.mtime = self.disk_mtime();
+        self.detect_indent();
+        self.restore_view();
+        self.adj_camera();
+    }
+
+    // The job loading the file failed; what it read stays on show, read-only
+    fn load_failed(&self) -> bool {
+        self.loading.as_ref().is_some_and(|l| l.lock().unwrap().failed)
+    }
+
+    // Writes unsaved text into `dir` when the editor is killed, leaving the
+    // file itself alone; encrypted text is not written out in the clear
+    pub fn recover(&self, dir: &Path) -> Result<Option<PathBuf>> {
+        if self.is_saved() || self.scratch.is_some() || self.encryption.is_some() {
+            return Ok(None);
+        }
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(format!("{}-{}-{}", std::process::id(), self.id, self.name()));
+        let file = std::fs::File::create(&path)?;
+        self.text().write_to(std::io::BufWriter::new(file))?;
+        Ok(Some(path))
+    }
+
+    fn disk_mtime(&self) -> Option<SystemTime> {
+        std::fs::metadata(self.path.as_ref()?).and_then(|m| m.modified()).ok()
+    }
+
+    // True when the file was modified by something else since it was read or saved
+    // Encrypted files are written by a job, and reloading would read the ciphertext
+    pub fn changed_on_disk(&self) -> bool {
+        if self.encryption.is_some() {
+            return false;
+        }
+        match (self.mtime, self.disk_mtime()) {
+            (Some(known), Some(current)) => current != known,
+            _ => false,
+        }
+    }
+
+    // Whether Autosave writes this buffer: modified local files, not encrypted
+    // ones, which are written by a job, or ones a job is writing
+    pub fn autosaves(&self) -> bool {
+        !self.is_saved() && self.path.is_some() && self.remote.is_none() && self.encryption.is_none()
+            && self.scratch.is_none() && self.locked_by.is_none()
+    }
+
+    // Whether Save asks for a file name before writing anything
+    pub fn needs_name(&self) -> bool {
+        self.path.is_none() && self.remote.is_none()
+    }
+
+    // Makes a file buffer read-only while `job` runs
+    pub fn lock(&mut self, job: &str) {
+        if self.path.is_some() && self.remote.is_none() && self.encryption.is_none() && self.scratch.is_none() {
+            self.locked_by = Some(job.to_string());
+        }
+    }
+
+    // Ends the lock and picks up what the job wrote, unless there are unsaved
+    // edits to ask about
+    pub fn unlock(&mut self) -> Result<()> {
+        if self.locked_by.take().is_some() && self.is_saved() && self.changed_on_disk() {
+            self.reload()?;
+        }
+        Ok(())
+    }
+
+    // Runs the action and undoes any edit it made to a locked or read-only buffer
+    async fn locked_action(&mut self, action: &Action, reason: String) -> Result<Vec<ActionReturn>> {
+        let (text, saved, edits) = (self.text().clone(), self.is_saved(), self.edits());
+        let cursor = (self.cursor_idx, self.area_start, self.cursors.clone(), self.gutter_anchor, self.select_mode);
+        let marks = self.bookmarks.lock().unwrap().clone();
+        let returns = self.process_action(action).await;
+        if self.edits() == edits {
+            return returns;
+        }
+        // Through text_mut, so what was cached about the refused edit is dropped
+        *self.text_mut() = text;
+        *self.bookmarks.lock().unwrap() = marks;
+        (self.cursor_idx, self.area_start, self.cursors, self.gutter_anchor, self.select_mode) = cursor;
+        self.set_saved(saved);
+        self.adj_camera();
+        Err(anyhow::anyhow!(reason))
+    }
+
+    // Another view of the text may have shortened it under this one's cursor
+    // and selection; called after every action, before they are drawn or used
+    pub fn clamp_to_text(&mut self) {
+        let text = self.text();
+        let (len, lines) = (text.len_chars(), text.len_lines());
+        drop(text);
+        let within = |i: &usize| *i <= len;
+        if within(&self.cursor_idx) && self.area_start.as_ref().is_none_or(within) && self.cursors.iter().all(within)
+            && self.gutter_anchor.is_none_or(|l| l < lines) {
+            return;
+        }
+        self.cursor_idx = min(self.cursor_idx, len);
+        self.area_start = self.area_start.map(|i| min(i, len));
+        self.gutter_anchor = self.gutter_anchor.map(|l| min(l, lines - 1));
+        for c in self.cursors.iter_mut() {
+            *c = min(*c, len);
+        }
+        let cursor_idx = self.cursor_idx;
+        self.cursors.retain(|c| *c != cursor_idx);
+        self.cursors.dedup();
+        self.adj_camera();
+    }
+
+    // Stops reporting the current external change without reloading
+    pub fn ignore_disk_change(&mut self) {
+        self.mtime = self.disk_mtime();
+    }
+
+    pub fn set_scratch(&mut self, name: String) {
+        self.scratch = Some(name);
+    }
+
+    pub fn scratch(&self) -> Option<&str> {
+        self.scratch.as_deref()
+    }
+
+    pub fn set_read_only(&mut self) {
+        self.read_only = true;
+    }
+
+    // Adds output at the end; a cursor at the end stays there, following it
+    pub fn append_text(&mut self, text: &str) {
+        let len = self.text().len_chars();
+        self.insert_text(len, text);
+        if self.cursor_idx == len {
+            self.cursor_idx = len + text.chars().count();
+            self.adj_camera();
+        }
+    }
+
+    // Replaces the whole text, e.g. with new output for a scratch buffer
+    pub fn set_text(&mut self, text: &str) {
+        *self.text_mut() = Rope::from_str(text);
+        self.cursor_idx = 0;
+        self.camera = Camera { row: 0, col: 0 };
+        self.search = None;
+    }
+
+    // Bookmarked lines that still exist, in order
+    pub fn bookmarks(&self) -> Vec<usize> {
+        let lines = self.text().len_lines();
+        self.bookmarks.lock().unwrap().iter().copied().filter(|l| *l < lines).collect()
+    }
+
+    pub fn line_text(&self, line: usize) -> String {
+        self.text().line(line).to_string().trim_end_matches(['\n', '\r']).to_string()
+    }
+
+    fn toggle_bookmark(&mut self) -> ActionReturn {
+        let line = self.cursor_line();
+        let mut bookmarks = self.bookmarks.lock().unwrap();
+        let notice = if bookmarks.remove(&line) {
+            format!("Bookmark removed from line {}", line + 1)
         } else {
-            return Err(anyhow::anyhow!("No path to save, use save_as(Cmd: Ctrl+S)"));
+            bookmarks.insert(line);
+            format!("Bookmarked line {}", line + 1)
+        };
+        ActionReturn::Notice(notice)
+    }
+
+    // Next bookmark after the cursor line, or the one before it going back, wrapping around
+    fn goto_bookmark(&mut self, forward: bool) -> Option<ActionReturn> {
+        let bookmarks = self.bookmarks();
+        let line = self.cursor_line();
+        let next = match forward {
+            true => bookmarks.iter().find(|l| **l > line).or(bookmarks.first()),
+            false => bookmarks.iter().rev().find(|l| **l < line).or(bookmarks.last()),
+        };
+        match next {
+            Some(&next) => {
+                self.goto(next, 0);
+                None
+            }
+            None => Some(ActionReturn::Notice("No bookmarks".to_string())),
         }
-        self.saved = true;
+    }
+
+    // Line number, or blank on continued rows, with a mark on bookmarked lines
+    fn gutter(&self, line: Option<usize>, width: usize, marked: &BTreeSet<usize>) -> [StyledContent<String>; 2] {
+        let mark = match line {
+            Some(l) if marked.contains(&l) => "*".to_string().yellow().bold(),
+            _ => " ".to_string().dark_grey(),
+        };
+        let num = line.map(|l| (l + 1).to_string()).unwrap_or_default();
+        let num = format!("{:width$} ", num, width = width.saturating_sub(1));
+        let num = if line == Some(self.get_row() as usize) { num.white() } else { num.dark_grey() };
+        [mark, num]
+    }
+
+    pub fn reload(&mut self) -> Result<()> {
+        let path = self.path.clone().ok_or(anyhow::anyhow!("No file to reload"))?;
+        let (ending, text) = Self::open(&path)?;
+        let (line, col) = (self.get_row() as usize, self.logical_col());
+        *self.text_mut() = text;
+        *self.line_ending.lock().unwrap() = ending;
+        self.goto(line, col);
+        self.set_saved(true);
+        self.mtime = self.disk_mtime();
         Ok(())
     }
 
 
     // cursor movement & row, col calculation
 
-    fn get_row(&self) -> u16 {
-        let i = self.cursor_idx;
-        let mut row = 0;
-        let chars = self.text.chars().take(i);
-        for c in chars {
-            if c == '\n' {
-                row += 1;
+    // Line of the cursor; remembered until the cursor moves or any view edits the text
+    fn cursor_line(&self) -> usize {
+        let version = self.edits.load(Ordering::SeqCst);
+        let mut cache = self.line_cache.lock().unwrap();
+        match *cache {
+            Some((v, idx, line)) if v == version && idx == self.cursor_idx => line,
+            _ => {
+                let line = self.text().char_to_line(self.cursor_idx);
+                *cache = Some((version, self.cursor_idx, line));
+                line
             }
         }
-        row
     }
+
+    fn get_row(&self) -> u16 {
+        self.cursor_line() as u16
+    }
+
     fn get_row_start(&self) -> usize {
-        let i = self.cursor_idx;
-        let chars  = self.text.chars().take(i);
-        let mut start = 0;
-        for (j, c) in chars.enumerate() {
-            if c == '\n' {
-                start = j + 1;
-            }
-        }
-        start
+        self.text().line_to_char(self.cursor_line())
     }
+
     fn get_row_end(&self) -> usize {
-        let i = self.cursor_idx;
-        let chars = self.text.chars().skip(i);
-        for (j, c) in chars.enumerate() {
-            if c == '\n' {
-                return i + j;
-            }
-        }
-        self.text.len_chars()
+        let row = self.cursor_line();
+        let text = self.text();
+        let line = text.line(row);
+        let newline = line.len_chars() > 0 && line.char(line.len_chars() - 1) == '\n';
+        text.line_to_char(row) + line.len_chars() - newline as usize
     }
 
-    fn get_row_len(&self) -> usize {
-        let i = self.cursor_idx;
-        let chars = self.text.chars().skip(i);
-        for (j, c) in chars.enumerate() {
-            if c == '\n' {
-                return j;
+    // Display column each char of `line` starts at, and the line end last; tabs
+    // and wide chars take several cells and color swatches one more
+    fn layout(&self, line: usize) -> Vec<usize> {
+        let key = (self.edits(), line, self.setting.tab_size, self.setting.color_swatches);
+        if let Some((k, cols)) = self.layout_cache.lock().unwrap().as_ref() {
+            if *k == key {
+                return cols.clone();
             }
         }
-        self.text.len_chars() - i
+        let swatches = self.swatches(line);
+        let swatch_at = |i: usize| swatches.iter().filter(|(end, _)| *end == i).count();
+        let mut cols = Vec::new();
+        let mut col = 0;
+        for (i, c) in self.text().line(line).chars().take_while(|c| *c != '\n').enumerate() {
+            col += swatch_at(i);
+            cols.push(col);
+            col += char_width(c, col, self.setting.tab_size);
+        }
+        cols.push(col + swatch_at(cols.len()));
+        *self.layout_cache.lock().unwrap() = Some((key, cols.clone()));
+        cols
     }
+
     fn get_col(&self) -> u16 {
-        let i = self.get_row_start();
-        (self.cursor_idx - i) as u16
+        self.layout(self.get_row() as usize)[self.cursor_idx - self.get_row_start()] as u16
+    }
+
+    // Char index on `line` drawn at display column `col`, or the line end
+    fn idx_at_col(&self, line: usize, col: usize) -> usize {
+        let cols = self.layout(line);
+        let len = cols.len() - 1;
+        self.text().line_to_char(line) + (0..len).find(|i| cols[i + 1] > col).unwrap_or(len)
+    }
+
+    // (line relative char index the swatch is drawn before, color) for every
+    // `#RRGGBB` and `rgb(r, g, b)` on `line`
+    fn swatches(&self, line: usize) -> Swatches {
+        static COLOR: LazyLock<Regex> = LazyLock::new(|| {
+            Regex::new(r"#([0-9a-fA-F]{6})\b|rgb\(\s*(\d{1,3})\s*,\s*(\d{1,3})\s*,\s*(\d{1,3})\s*\)").unwrap()
+        });
+        if !self.setting.color_swatches {
+            return Vec::new();
+        }
+        let edits = self.edits();
+        let mut cache = self.swatch_cache.lock().unwrap();
+        if cache.0 != edits {
+            *cache = (edits, HashMap::new());
+        }
+        cache.1.entry(line).or_insert_with(|| {
+            let s = self.text().line(line).to_string();
+            COLOR.captures_iter(&s).filter_map(|c| {
+                let color = match c.get(1) {
+                    Some(hex) => {
+                        let n = u32::from_str_radix(hex.as_str(), 16).ok()?;
+                        Color::Rgb { r: (n >> 16) as u8, g: (n >> 8) as u8, b: n as u8 }
+                    }
+                    None => {
+                        let part = |i: usize| c.get(i)?.as_str().parse::<u8>().ok();
+                        Color::Rgb { r: part(2)?, g: part(3)?, b: part(4)? }
+                    }
+                };
+                let end = c.get(0)?.end();
+                Some((s[..end].chars().count(), color))
+            }).collect()
+        }).clone()
+    }
+
+    // Columns left for text once the line number gutter is drawn
+    fn text_width(&self) -> u16 {
+        let gutter = if self.setting.line_numbers {
+            numlen(self.text().len_lines()) + 2
+        } else {
+            1
+        };
+        self.size.width.saturating_sub(gutter as u16).max(1)
+    }
+
+    fn soft_wrap(&self) -> bool {
+        self.prose.is_some() || self.setting.wrap
     }
 
     fn adj_camera(&mut self) {
         let row = self.get_row();
         let col = self.get_col();
+        if self.soft_wrap() {
+            self.camera.col = 0;
+            self.camera.row = min(self.camera.row, row);
+            while self.camera.row < row && self.wrapped_cursor().0 >= self.size.height {
+                self.camera.row += 1;
+            }
+            return;
+        }
         while row < self.camera.row {
             self.camera.row -= 1;
         }
@@ -222,34 +966,66 @@ impl Buffer {
         while col < self.camera.col {
             self.camera.col -= 1;
         }
-        while col >= self.camera.col + self.size.width {
+        while col >= self.camera.col + self.text_width() {
             self.camera.col += 1;
         }
     }
 
+    // Moves the view sideways by half its width, leaving the cursor where it
+    // is; the next cursor motion brings it back into view
+    fn scroll_sideways(&mut self, right: bool) {
+        let step = (self.text_width() / 2).max(1);
+        self.camera.col = match right {
+            true => {
+                let text = self.text();
+                let rows = self.camera.row as usize..min(self.camera.row as usize + self.size.height as usize, text.len_lines());
+                let longest = rows.map(|l| text.line(l).len_chars()).max().unwrap_or(0) as u16;
+                min(self.camera.col + step, longest.saturating_sub(1))
+            }
+            false => self.camera.col.saturating_sub(step),
+        };
+    }
+
+    // Moves the view three lines for the mouse wheel. The cursor stays where
+    // it is unless it would leave the view, then it goes to the nearest line shown
+    fn scroll(&mut self, down: bool) {
+        let last = self.text().len_lines().saturating_sub(1) as u16;
+        self.camera.row = match down {
+            true => min(self.camera.row.saturating_add(3), last),
+            false => self.camera.row.saturating_sub(3),
+        };
+        let row = self.get_row();
+        let bottom = self.camera.row + self.size.height.saturating_sub(1);
+        let target = row.clamp(self.camera.row, bottom);
+        if target != row {
+            let col = self.get_col() as usize;
+            self.cursor_idx = self.idx_at_col(target as usize, col);
+            self.adj_camera();
+        }
+    }
+
     fn cursor_up(&mut self) {
-        if self.get_row() == 0 {
+        let row = self.get_row() as usize;
+        if row == 0 {
             return;
         }
-        let col = self.get_col();
-        self.cursor_idx = self.get_row_start() - 1;
-        self.cursor_idx = self.get_row_start();
-        self.cursor_idx += min(col as usize, self.get_row_len());
+        let col = self.get_col() as usize;
+        self.cursor_idx = self.idx_at_col(row - 1, col);
         self.adj_camera();
     }
 
     fn cursor_down(&mut self) {
-        if self.get_row() == (self.text.len_lines() - 1) as u16 {
+        let row = self.get_row() as usize;
+        if row == self.text().len_lines() - 1 {
             return;
         }
-        let col = self.get_col();
-        self.cursor_idx = self.get_row_end() + 1;
-        self.cursor_idx += min(col as usize, self.get_row_len());
+        let col = self.get_col() as usize;
+        self.cursor_idx = self.idx_at_col(row + 1, col);
         self.adj_camera();
     }
 
     fn cursor_forward(&mut self) {
-        if self.cursor_idx < self.text.len_chars() {
+        if self.cursor_idx < self.text().len_chars() {
             self.cursor_idx += 1;
         }
         self.adj_camera();
@@ -257,21 +1033,10 @@ impl Buffer {
 
     fn cursor_forward_action(&mut self) {
         self.cursor_forward();
-        let chars = self.text.chars().collect::<Vec<_>>();
-        if self.cursor_idx > 0 {
-            if is_hangul(chars[self.cursor_idx - 1]) {
-                self.cursor_forward();
-            } else if chars[self.cursor_idx - 1] == '\t' {
+        let tab_size = self.setting.tab_size;
+        if self.cursor_idx > 0 && self.char_at(self.cursor_idx - 1) == ' ' && tab_size > 0 && (self.get_col() as usize - 1).is_multiple_of(tab_size) {
+            while self.cursor_idx < self.text().len_chars() && self.char_at(self.cursor_idx) == ' ' && !(self.get_col() as usize).is_multiple_of(tab_size) {
                 self.cursor_forward();
-                while self.cursor_idx < self.text.len_chars() && chars[self.cursor_idx] == '\x02' {
-                    self.cursor_forward();
-                }
-            } else if chars[self.cursor_idx - 1] == ' ' {
-                if (self.get_col() as usize - 1) % self.setting.tab_size == 0 {
-                    while self.cursor_idx < self.text.len_chars() && chars[self.cursor_idx] == ' ' && self.get_col() as usize % self.setting.tab_size != 0 {
-                        self.cursor_forward();
-                    }
-                }
             }
         }
     }
@@ -285,96 +1050,141 @@ impl Buffer {
 
     fn cursor_backward_action(&mut self) {
         self.cursor_backward();
-        let chars = self.text.chars().collect::<Vec<_>>();
-        if self.cursor_idx > 0{ 
-            if is_hangul(chars[self.cursor_idx - 1]) {
-                self.cursor_backward();
-            } else if chars[self.cursor_idx - 1] == '\x02' {
-                while self.cursor_idx > 0 && chars[self.cursor_idx - 1] == '\x02' {
-                    self.cursor_backward();
-                }
+        let tab_size = self.setting.tab_size;
+        if self.cursor_idx > 0 && self.char_at(self.cursor_idx - 1) == ' ' && tab_size > 0 && (self.get_col() as usize + 1).is_multiple_of(tab_size) {
+            while self.cursor_idx > 0 && self.char_at(self.cursor_idx - 1) == ' ' && !(self.get_col() as usize).is_multiple_of(tab_size) {
                 self.cursor_backward();
-            } else if chars[self.cursor_idx - 1] == ' ' {
-                if (self.get_col() as usize + 1) % self.setting.tab_size == 0 {
-                    while self.cursor_idx > 0 && chars[self.cursor_idx - 1] == ' ' && self.get_col() as usize % self.setting.tab_size != 0 {
-                        self.cursor_backward();
-                    }
-                }
             }
         }
     }
 
     fn cursor_start(&mut self) {
-        let i = self.cursor_idx;
-        for j in (0..i).rev() {
-            if self.text.char(j) == '\n' {
-                self.cursor_idx = j + 1;
-                return;
-            }
-        }
+        self.cursor_idx = self.get_row_start();
         self.adj_camera();
     }
 
     fn cursor_end(&mut self) {
-        let i = self.cursor_idx;
-        for j in i..self.text.len_chars() {
-            if self.text.char(j) == '\n' {
-                self.cursor_idx = j;
-                return;
-            }
-        }
+        self.cursor_idx = self.get_row_end();
         self.adj_camera();
     }
 
     fn cursor_forward_word(&mut self) {
-        let i = self.cursor_idx;
-        let mut j = i;
-        while j < self.text.len_chars() && self.text.char(j).is_whitespace() {
-            j += 1;
-        }
-        while j < self.text.len_chars() && !self.text.char(j).is_whitespace() {
-            j += 1;
-        }
-        self.cursor_idx = j;
+        self.cursor_idx = self.word_end(self.cursor_idx);
         self.adj_camera();
     }
 
     fn cursor_backward_word(&mut self) {
-        let i = self.cursor_idx;
-        let mut j = i;
-        while j > 0 && self.text.char(j - 1).is_whitespace() {
-            j -= 1;
-        }
-        while j > 0 && !self.text.char(j - 1).is_whitespace() {
-            j -= 1;
-        }
-        self.cursor_idx = j;
+        self.cursor_idx = self.word_start(self.cursor_idx);
         self.adj_camera();
     }
 
-    // text manipulation
+    // `word_chars` of the settings for this file's extension, "*" for the rest
+    fn word_chars(&self) -> &str {
+        let ext = self.path.as_ref().and_then(|p| p.extension()).and_then(|e| e.to_str()).unwrap_or("");
+        let chars = &self.setting.word_chars;
+        chars.get(ext).or_else(|| chars.get("*")).map_or("_", String::as_str)
+    }
+
+    // Past the blanks after `idx` and the run of word chars, or of other non
+    // blank chars, that follows, so `foo.bar` stops at the dot
+    fn word_end(&self, idx: usize) -> usize {
+        let text = self.text();
+        let class = |i: usize| char_class(text.char(i), self.word_chars());
+        let len = text.len_chars();
+        let mut j = idx;
+        while j < len && class(j) == 0 {
+            j += 1;
+        }
+        let run = if j < len { class(j) } else { 0 };
+        while j < len && class(j) == run && run != 0 {
+            j += 1;
+        }
+        j
+    }
+
+    // The same run before `idx`, going back to its start
+    fn word_start(&self, idx: usize) -> usize {
+        let text = self.text();
+        let class = |i: usize| char_class(text.char(i), self.word_chars());
+        let mut j = idx;
+        while j > 0 && class(j - 1) == 0 {
+            j -= 1;
+        }
+        let run = if j > 0 { class(j - 1) } else { 0 };
+        while j > 0 && class(j - 1) == run && run != 0 {
+            j -= 1;
+        }
+        j
+    }
+
+    // text manipulation
+
+    // Hangul syllable right before the cursor, if it is still being composed
+    fn composing_char(&self) -> Option<char> {
+        if !self.composing || self.cursor_idx < 1 {
+            return None;
+        }
+        Some(self.text().char(self.cursor_idx - 1))
+    }
+
+    fn replace_composing(&mut self, c: char) {
+        let idx = self.cursor_idx - 1;
+        let mut text = self.text_mut();
+        text.remove(idx..idx + 1);
+        text.insert_char(idx, c);
+    }
 
     fn insert_char(&mut self, c: char, upper: bool) {
         let c = if upper { c } else { c.to_lowercase().next().unwrap() };
-        self.text.insert_char(self.cursor_idx, c);
+        if let Some((prev, next)) = self.composing_char().and_then(|p| hangul::compose(p, c)) {
+            self.replace_composing(prev);
+            if let Some(next) = next {
+                self.insert_text(self.cursor_idx, &next.to_string());
+                self.cursor_forward();
+            }
+            self.set_saved(false);
+            return;
+        }
+        self.composing = hangul::is_jamo(c);
+        self.insert_text(self.cursor_idx, &c.to_string());
         self.cursor_forward();
-        if is_hangul(c) {
-            self.text.insert_char(self.cursor_idx, '\x01');
+        self.set_saved(false);
+    }
+
+    fn insert_str(&mut self, s: &str) {
+        self.insert_text(self.cursor_idx, s);
+        for _ in s.chars() {
             self.cursor_forward();
         }
-        self.saved = false;
+        self.set_saved(false);
     }
 
-    fn insert_str(&mut self, s: &str) {
-        self.text.insert(self.cursor_idx, s);
-        self.cursor_forward();
-        self.saved = false;
+    fn abbreviations_on(&self) -> bool {
+        let ext = self.path.as_ref().and_then(|p| p.extension()).and_then(|e| e.to_str()).unwrap_or("");
+        let on = &self.setting.abbreviations;
+        on.get(ext).or_else(|| on.get("*")).copied().unwrap_or(false)
+    }
+
+    // Called before a word boundary is inserted
+    fn expand_abbreviation(&mut self) {
+        if !self.abbreviations_on() {
+            return;
+        }
+        let ext = self.path.as_ref().and_then(|p| p.extension()).and_then(|e| e.to_str()).unwrap_or("");
+        let before = self.slice_string(self.get_row_start(), self.cursor_idx);
+        if let Some((len, text)) = abbrev::lookup(ext, &before) {
+            let start = self.cursor_idx - len;
+            self.remove_text(start..self.cursor_idx);
+            self.cursor_idx = start;
+            self.composing = false;
+            self.insert_str(&text);
+        }
     }
 
     fn insert_newline(&mut self) {
         self.insert_char('\n', true);
         self.cursor_forward();
-        self.saved = false;
+        self.set_saved(false);
     }
 
     fn insert_tab(&mut self) {
@@ -382,107 +1192,883 @@ impl Buffer {
         match self.setting.tab_type {
             crate::TabType::Space => {
                 for _ in 0..tab_size {
-                    self.text.insert_char(self.cursor_idx, ' ');
+                    self.insert_text(self.cursor_idx, " ");
                     self.cursor_forward();
                 }
             }
             crate::TabType::Tab => {
-                self.text.insert_char(self.cursor_idx, '\t');
+                self.insert_text(self.cursor_idx, "\t");
                 self.cursor_forward();
-                for _ in 0..tab_size - 1 {
-                    self.text.insert_char(self.cursor_idx, '\x02');
-                    self.cursor_forward();
-                }
             }
         }
-        self.saved = false;
+        self.set_saved(false);
     }
 
     fn insert_newline_above(&mut self) {
         let idx = self.get_row_start();
-        self.text.insert_char(idx, '\n');
-        self.saved = false;
+        self.insert_text(idx, "\n");
+        self.set_saved(false);
     }
 
     fn insert_newline_below(&mut self) {
         let idx = self.get_row_end();
-        self.text.insert_char(idx, '\n');
-        self.saved = false;
+        self.insert_text(idx, "\n");
+        self.set_saved(false);
     }
 
     fn delete(&mut self) {
-        if self.text.len_chars() > 0 && self.cursor_idx > 0 {
-            self.text.remove(self.cursor_idx - 1..self.cursor_idx);
+        if self.text().len_chars() > 0 && self.cursor_idx > 0 {
+            self.remove_text(self.cursor_idx - 1..self.cursor_idx);
             self.cursor_backward();
         }
     }
 
     fn delete_action(&mut self) {
-        let chars = self.text.chars().collect::<Vec<_>>();
+        if let Some(c) = self.composing_char() {
+            match hangul::decompose(c) {
+                Some(rest) => self.replace_composing(rest),
+                None => self.composing = false,
+            }
+            if self.composing {
+                self.set_saved(false);
+                return;
+            }
+        }
+        let tab_size = self.setting.tab_size;
         if self.cursor_idx > 0 {
-            if chars[self.cursor_idx - 1] == ' ' && self.get_col() as usize % self.setting.tab_size == 0 {
+            if self.char_at(self.cursor_idx - 1) == ' ' && tab_size > 0 && (self.get_col() as usize).is_multiple_of(tab_size) {
                 self.delete();
-                while self.cursor_idx > 0 && chars[self.cursor_idx - 1] == ' ' && self.get_col() as usize % self.setting.tab_size != 0 {
-                    self.text.remove(self.cursor_idx - 1..self.cursor_idx);
+                while self.cursor_idx > 0 && self.char_at(self.cursor_idx - 1) == ' ' && !(self.get_col() as usize).is_multiple_of(tab_size) {
+                    self.remove_text(self.cursor_idx - 1..self.cursor_idx);
                     self.cursor_backward();
                 }
-            }
-            else if chars[self.cursor_idx - 1] == '\x01' && self.cursor_idx > 1 && is_hangul(chars[self.cursor_idx - 2]) {
-                self.text.remove(self.cursor_idx - 2..self.cursor_idx);
-                self.cursor_backward();
-                self.cursor_backward();
-            } else if chars[self.cursor_idx - 1] == '\x02' {
-                while chars[self.cursor_idx - 1] == '\x02' {
-                    self.delete();
-                }
-                self.delete();
             } else {
                 self.delete();
             }
         }
-        self.saved = false;
+        self.set_saved(false);
     }
 
     fn delete_back(&mut self) {
-        if self.text.len_chars() > 0 && self.cursor_idx < self.text.len_chars() {
-            self.text.remove(self.cursor_idx..self.cursor_idx + 1);
+        if self.text().len_chars() > 0 && self.cursor_idx < self.text().len_chars() {
+            self.remove_text(self.cursor_idx..self.cursor_idx + 1);
         }
-        self.saved = false;
+        self.set_saved(false);
     }
 
-    // visualization
+    // selection
 
-    fn visualize(&self, line: usize, theme: &Theme, numpad: usize) -> Vec<StyledContent<String>> {
-        let bg = syncol_to_crosscol(theme.settings.background.unwrap());
-        let line_slice = match self.text.get_line(line) {
-            Some(l) => l,
-            None => return vec![" ".repeat(self.size.width as usize).on(bg)],
+    fn select_start(&mut self) {
+        self.select_mode = SelectMode::Char;
+        self.area_start = match self.area_start {
+            Some(_) => None,
+            None => Some(self.cursor_idx),
+        };
+    }
+
+    // Size and mode of the selection for the status bar, e.g. "2 lines, 31 chars (word)"
+    fn selection_status(&self) -> Option<String> {
+        let (start, end) = self.selection()?;
+        let (first, last) = self.selected_lines();
+        let plural = |n: usize, what: &str| format!("{} {}{}", n, what, if n == 1 { "" } else { "s" });
+        let mode = match self.select_mode {
+            SelectMode::Char => "char",
+            SelectMode::Word => "word",
+            SelectMode::Line => "line",
+        };
+        Some(format!("{}, {} ({})", plural(last - first + 1, "line"), plural(end - start, "char"), mode))
+    }
+
+    // multiple cursors
+
+    // The new cursor becomes the main one and the old one stays behind
+    fn add_cursor(&mut self, idx: usize) {
+        if idx != self.cursor_idx && !self.cursors.contains(&idx) {
+            self.cursors.push(self.cursor_idx);
+            self.cursor_idx = idx;
+            self.adj_camera();
+        }
+    }
+
+    // At the same column on the line below the lowest cursor
+    fn add_cursor_below(&mut self) {
+        let last = self.cursors.iter().copied().chain([self.cursor_idx]).max().unwrap();
+        let (line, line_start, lines) = {
+            let text = self.text();
+            let line = text.char_to_line(last);
+            (line, text.line_to_char(line), text.len_lines())
         };
+        if line + 1 >= lines {
+            return;
+        }
+        let col = self.layout(line).get(last - line_start).copied().unwrap_or(0);
+        let idx = self.idx_at_col(line + 1, col);
+        self.add_cursor(idx);
+    }
 
-        let mut s = line_slice.to_string();
-        
-        if line_slice.len_chars() == 0 {
-            return vec![" ".repeat(self.size.width as usize - numpad - 1).on(bg)];
+    // At the next whole word occurrence of the word under the main cursor,
+    // after the last cursor and going around the end
+    fn add_cursor_at_next_match(&mut self) -> Option<ActionReturn> {
+        let text = self.text().clone();
+        let len = text.len_chars();
+        let is_word = |i: usize| i < len && char_class(text.char(i), self.word_chars()) == 2;
+        let (mut start, mut end) = (self.cursor_idx, self.cursor_idx);
+        while start > 0 && is_word(start - 1) {
+            start -= 1;
         }
-        if line_slice.char(line_slice.len_chars() - 1) == '\n' {
-            s.pop().unwrap();
+        while is_word(end) {
+            end += 1;
         }
-        if self.camera.col as usize > s.chars().count() {
-            return vec![" ".repeat(self.size.width as usize - numpad - 1).on(bg)];
+        if start == end {
+            return Some(ActionReturn::Notice("No word under the cursor".to_string()));
         }
-        let syntax = get_syntex_ref(&self.text, &self.path, &self.syntax_set);
-        let h = highlight_line(&s, syntax, &self.syntax_set, theme);
-        let mut styled = Vec::new();
-        let mut len = 0;
+        let mut search = Search::default();
+        let flags = search::Flags { case_sensitive: true, whole_word: true, regex: false };
+        search.update(&text, &text.slice(start..end).to_string(), flags);
+        let last = self.cursors.iter().copied().chain([self.cursor_idx]).max().unwrap();
+        let offset = self.cursor_idx - start;
+        let taken = |s: usize| s + offset == self.cursor_idx || self.cursors.contains(&(s + offset));
+        let found = search.matches.iter().map(|(s, _)| *s)
+            .filter(|s| !taken(*s))
+            .min_by_key(|s| (*s + offset <= last, *s));
+        match found {
+            Some(s) => self.add_cursor(s + offset),
+            None => return Some(ActionReturn::Notice("No more matches".to_string())),
+        }
+        None
+    }
+
+    // Runs the action at each cursor from the last one back, so an edit only
+    // moves the cursors after it
+    async fn at_every_cursor(&mut self, action: &Action) -> Result<Vec<ActionReturn>> {
+        let main = self.cursor_idx;
+        let composing = self.composing;
+        // A selection belongs to the main cursor only; typing at every cursor
+        // would otherwise delete it from the wrong places
+        self.area_start = None;
+        let mut cursors = std::mem::take(&mut self.cursors);
+        cursors.push(main);
+        cursors.sort_unstable();
+        cursors.dedup();
+        let mut moved: Vec<(usize, bool)> = Vec::with_capacity(cursors.len());
+        let mut out = Vec::new();
+        for &at in cursors.iter().rev() {
+            let len = self.text().len_chars();
+            self.cursor_idx = at;
+            self.composing = composing;
+            out = Buffer::process_action(self, action).await?;
+            let delta = self.text().len_chars() as isize - len as isize;
+            for (idx, _) in moved.iter_mut() {
+                *idx = idx.saturating_add_signed(delta);
+            }
+            moved.push((self.cursor_idx, at == main));
+        }
+        let len = self.text().len_chars();
+        for (idx, is_main) in moved {
+            match is_main {
+                true => self.cursor_idx = min(idx, len),
+                false => self.cursors.push(min(idx, len)),
+            }
+        }
+        self.cursors.sort_unstable();
+        self.cursors.dedup();
+        self.cursors.retain(|c| *c != self.cursor_idx);
+        self.adj_camera();
+        Ok(out)
+    }
+
+    // Double click: the run of word chars, or of the other non blank chars,
+    // around the cursor
+    fn select_word(&mut self) {
+        let text = self.text();
+        let len = text.len_chars();
+        let word_chars = self.word_chars();
+        let Some(class) = (self.cursor_idx < len).then(|| char_class(text.char(self.cursor_idx), word_chars)) else {
+            return;
+        };
+        let (mut start, mut end) = (self.cursor_idx, self.cursor_idx);
+        while start > 0 && char_class(text.char(start - 1), word_chars) == class {
+            start -= 1;
+        }
+        while end < len && char_class(text.char(end), word_chars) == class {
+            end += 1;
+        }
+        drop(text);
+        self.area_start = Some(start);
+        self.select_mode = SelectMode::Word;
+        self.cursor_idx = end;
+        self.adj_camera();
+    }
+
+    // Triple click: the whole line with its line break
+    fn select_line(&mut self) {
+        let start = self.get_row_start();
+        let len = self.text().line(self.cursor_line()).len_chars();
+        self.area_start = Some(start);
+        self.select_mode = SelectMode::Line;
+        self.cursor_idx = start + len;
+        self.adj_camera();
+    }
+
+    fn word_count(&self) -> String {
+        let text = self.text();
+        let mut count = format_stats(text_stats(text.slice(..)));
+        if let Some((start, end)) = self.selection() {
+            count.push_str(&format!("; selection: {}", format_stats(text_stats(text.slice(start..end)))));
+        }
+        count
+    }
+
+    // Words in the selection, or the whole text, for the status bar
+    fn word_status(&self) -> String {
+        if let Some((start, end)) = self.selection() {
+            let (_, words, chars) = text_stats(self.text().slice(start..end));
+            return format!("{}w {}c selected", words, chars);
+        }
+        let edits = self.edits.load(Ordering::SeqCst);
+        let mut cache = self.stats_cache.lock().unwrap();
+        let (_, words, chars) = match *cache {
+            Some((e, stats)) if e == edits => stats,
+            _ => {
+                let stats = text_stats(self.text().slice(..));
+                *cache = Some((edits, stats));
+                stats
+            }
+        };
+        format!("{}w {}c", words, chars)
+    }
+
+    // Selected char range, if a selection is active
+    fn selection(&self) -> Option<(usize, usize)> {
+        let start = min(self.area_start?, self.text().len_chars());
+        Some((min(start, self.cursor_idx), start.max(self.cursor_idx)))
+    }
+
+    // Typing and pasting over a selection replace it: the selected text goes
+    // first, in the same edit as what is inserted
+    fn delete_selection(&mut self) {
+        if let Some((start, end)) = self.selection().filter(|(start, end)| end > start) {
+            self.remove_text(start..end);
+            self.cursor_idx = start;
+            self.set_saved(false);
+        }
+        self.area_start = None;
+    }
+
+    // With smart_paste, lines after the first take the indent of the cursor's line
+    fn paste(&mut self, text: &str) {
+        if !self.setting.smart_paste || !text.contains('\n') {
+            return self.insert_str(text);
+        }
+        let start = self.get_row_start();
+        let before = self.slice_string(start, self.cursor_idx);
+        let indent = &before[..before.len() - before.trim_start_matches([' ', '\t']).len()];
+        let text = indent::reindent(text, indent);
+        self.insert_str(&text);
+    }
+
+    // What Copy and Cut take: the selection, or the whole line without one
+    fn yank_range(&self) -> (usize, usize) {
+        self.selection().unwrap_or_else(|| {
+            let start = self.get_row_start();
+            (start, start + self.text().line(self.cursor_line()).len_chars())
+        })
+    }
+
+    fn slice_string(&self, start: usize, end: usize) -> String {
+        self.text().slice(start..end).to_string()
+    }
+
+    // Replace(pattern,replacement) asks about each match from the cursor on,
+    // going around the end once; the pattern follows the Find flags
+    fn replace_start(&mut self, pattern: &str, replacement: &str) -> Result<Vec<ActionReturn>> {
+        let mut search = Search::default();
+        let text = self.text.read().unwrap().clone();
+        search.update(&text, pattern, self.search_flags);
+        if let Some(error) = search.error {
+            return Err(anyhow::anyhow!("Replace: {}", error));
+        }
+        // Only within the selection, as a line range leaves it
+        let mut from = self.cursor_idx;
+        if let Some((start, end)) = self.selection() {
+            search.matches.retain(|(s, e)| *s >= start && *e <= end);
+            self.area_start = None;
+            from = start;
+        }
+        if search.nearest(from).is_none() {
+            return Ok(vec![ActionReturn::Notice(format!("No matches for {}", pattern))]);
+        }
+        self.replacing = Some(search::Replace {
+            replacement: replacement.to_string(),
+            remaining: search.matches.len(),
+            total: search.matches.len(),
+            replaced: 0,
+        });
+        self.search = Some(search);
+        Ok(self.replace_prompt())
+    }
+
+    fn replace_prompt(&mut self) -> Vec<ActionReturn> {
+        let (Some(search), Some(replace)) = (&self.search, &self.replacing) else { return vec![] };
+        let Some(i) = search.current.filter(|_| replace.remaining > 0) else {
+            return self.replace_end();
+        };
+        let notice = format!("Replace {}/{}? (y)es (n)o (a)ll (q)uit: ", replace.total - replace.remaining + 1, replace.total);
+        self.cursor_idx = search.matches[i].0;
+        self.adj_camera();
+        vec![
+            ActionReturn::State(KeymapState::LineInsert),
+            ActionReturn::Notice(notice),
+            ActionReturn::ExcuteLine("ReplaceAnswer($line)".to_string()),
+        ]
+    }
+
+    fn replace_answer(&mut self, answer: &str) -> Vec<ActionReturn> {
+        match answer.trim().to_lowercase().chars().next() {
+            Some('y') => self.replace_current(),
+            Some('n') => {
+                if let (Some(search), Some(replace)) = (self.search.as_mut(), self.replacing.as_mut()) {
+                    replace.remaining -= 1;
+                    search.step(true);
+                }
+            }
+            Some('a') => self.replace_remaining(),
+            _ => return self.replace_end(),
+        }
+        self.replace_prompt()
+    }
+
+    // Replaces the current match and moves to the first one after it
+    fn replace_current(&mut self) {
+        let (Some(search), Some(replace)) = (self.search.as_ref(), self.replacing.as_ref()) else { return };
+        let Some((start, end)) = search.current.map(|i| search.matches[i]) else { return };
+        let text = self.text();
+        let with = search.expand(&text.to_string(), text.char_to_byte(start), &replace.replacement);
+        drop(text);
+        self.replace_range(start, end, &with);
+        let text = self.text.read().unwrap().clone();
+        let (Some(search), Some(replace)) = (self.search.as_mut(), self.replacing.as_mut()) else { return };
+        replace.replaced += 1;
+        replace.remaining -= 1;
+        let (query, flags) = (search.query.clone(), search.flags);
+        search.update(&text, &query, flags);
+        search.nearest(start + with.chars().count());
+    }
+
+    // Replaces every match not answered yet, the last first so the char
+    // indices of the rest stay put
+    fn replace_remaining(&mut self) {
+        let (Some(search), Some(replace)) = (self.search.as_ref(), self.replacing.as_ref()) else { return };
+        let (Some(i), n) = (search.current, search.matches.len()) else { return };
+        let mut ranges: Vec<(usize, usize)> = (0..replace.remaining.min(n)).map(|k| search.matches[(i + k) % n]).collect();
+        ranges.sort_by(|a, b| b.cmp(a));
+        let text = self.text();
+        let haystack = text.to_string();
+        let edits: Vec<(usize, usize, String)> = ranges.iter()
+            .map(|&(start, end)| (start, end, search.expand(&haystack, text.char_to_byte(start), &replace.replacement)))
+            .collect();
+        drop(text);
+        for (start, end, with) in &edits {
+            self.remove_text(*start..*end);
+            self.insert_text(*start, with);
+        }
+        self.set_saved(false);
+        if let Some(replace) = self.replacing.as_mut() {
+            replace.replaced += edits.len();
+            replace.remaining = 0;
+        }
+    }
+
+    fn replace_end(&mut self) -> Vec<ActionReturn> {
+        let replaced = self.replacing.take().map_or(0, |r| r.replaced);
+        self.search = None;
+        self.adj_camera();
+        vec![
+            ActionReturn::State(KeymapState::Normal),
+            ActionReturn::Notice(format!("Replaced {} match{}", replaced, if replaced == 1 { "" } else { "es" })),
+        ]
+    }
+
+    fn replace_range(&mut self, start: usize, end: usize, s: &str) {
+        let end = min(end, self.text().len_chars());
+        let start = min(start, end);
+        self.remove_text(start..end);
+        self.insert_text(start, s);
+        self.cursor_idx = start;
+        self.area_start = None;
+        self.adj_camera();
+        self.set_saved(false);
+    }
+
+    // First and last line of the selection, or the cursor line twice; a
+    // selection ending at the start of a line leaves that line out
+    fn selected_lines(&self) -> (usize, usize) {
+        let text = self.text();
+        match self.selection() {
+            Some((start, end)) if end > start => (text.char_to_line(start), text.char_to_line(end - 1)),
+            _ => (text.char_to_line(self.cursor_idx), text.char_to_line(self.cursor_idx)),
+        }
+    }
+
+    // Swaps the selected lines with the line above or below them; the cursor
+    // and selection move along with the text
+    fn move_lines(&mut self, up: bool) {
+        let (first, last) = self.selected_lines();
+        let lines = self.text().len_lines();
+        let (lo, hi) = match up {
+            true if first > 0 => (first - 1, last),
+            false if last + 1 < lines => (first, last + 1),
+            _ => return,
+        };
+        let text = self.text();
+        let (start, end) = (text.line_to_char(lo), text.line_to_char(hi) + text.line(hi).len_chars());
+        let block_start = text.line_to_char(first);
+        let region = text.slice(start..end).to_string();
+        drop(text);
+        let body = region.strip_suffix('\n').unwrap_or(&region);
+        let mut parts: Vec<&str> = body.split('\n').collect();
+        let moved_start = match up {
+            true => {
+                parts.rotate_left(1);
+                start
+            }
+            false => {
+                parts.rotate_right(1);
+                start + parts[0].chars().count() + 1
+            }
+        };
+        let mut moved = parts.join("\n");
+        if region.ends_with('\n') {
+            moved.push('\n');
+        }
+        let mut text = self.text_mut();
+        text.remove(start..end);
+        text.insert(start, &moved);
+        drop(text);
+        // The line count stays, so the bookmarks rotate along with the lines
+        let mut marks = self.bookmarks.lock().unwrap();
+        let swapped: Vec<usize> = marks.range(lo..=hi).copied().collect();
+        for l in &swapped {
+            marks.remove(l);
+        }
+        for l in swapped {
+            marks.insert(match up {
+                true if l == lo => hi,
+                true => l - 1,
+                false if l == hi => lo,
+                false => l + 1,
+            });
+        }
+        drop(marks);
+        self.cursor_idx = self.cursor_idx + moved_start - block_start;
+        self.area_start = self.area_start.map(|i| i + moved_start - block_start);
+        self.adj_camera();
+        self.set_saved(false);
+    }
+
+    // Puts a copy of the selected lines below them, the cursor and selection
+    // going to the copy
+    fn duplicate_lines(&mut self) {
+        let (first, last) = self.selected_lines();
+        let text = self.text();
+        let (start, end) = (text.line_to_char(first), text.line_to_char(last) + text.line(last).len_chars());
+        let mut copy = text.slice(start..end).to_string();
+        drop(text);
+        let at = match copy.ends_with('\n') {
+            true => end,
+            false => {
+                copy.insert(0, '\n');
+                end + 1
+            }
+        };
+        self.insert_text(end, &copy);
+        self.cursor_idx += at - start;
+        self.area_start = self.area_start.map(|i| i + at - start);
+        self.adj_camera();
+        self.set_saved(false);
+    }
+
+    // Shifts the selected lines, or the cursor line, one tab stop right or
+    // left. Blank lines are not indented; dedenting takes a tab or up to
+    // tab_size spaces, whatever the line starts with
+    fn shift_lines(&mut self, indent: bool) {
+        let (first, last) = self.selected_lines();
+        let unit = match self.setting.tab_type {
+            TabType::Space => " ".repeat(self.setting.tab_size),
+            TabType::Tab => "\t".to_string(),
+        };
+        let mut moved = false;
+        // From the last line up, so the starts of the lines above stay put
+        for line in (first..=last).rev() {
+            let text = self.text();
+            let start = text.line_to_char(line);
+            let content = text.line(line).to_string();
+            drop(text);
+            let n = match indent {
+                true if content.trim().is_empty() => continue,
+                true => {
+                    self.insert_text(start, &unit);
+                    unit.chars().count()
+                }
+                false => {
+                    let n = match content.starts_with('\t') {
+                        true => 1,
+                        false => content.chars().take(self.setting.tab_size).take_while(|c| *c == ' ').count(),
+                    };
+                    if n == 0 {
+                        continue;
+                    }
+                    self.remove_text(start..start + n);
+                    n
+                }
+            };
+            self.cursor_idx = shifted(self.cursor_idx, start, n, indent);
+            self.area_start = self.area_start.map(|p| shifted(p, start, n, indent));
+            moved = true;
+        }
+        if moved {
+            self.adj_camera();
+            self.set_saved(false);
+        }
+    }
+
+    // To where CursorForwardWord or CursorBackwardWord would go
+    fn delete_word(&mut self, forward: bool) {
+        let (start, end) = match forward {
+            true => (self.cursor_idx, self.word_end(self.cursor_idx)),
+            false => (self.word_start(self.cursor_idx), self.cursor_idx),
+        };
+        self.delete_span(start, end);
+    }
+
+    // To the end of the line, or the line break when already there
+    fn delete_to_end(&mut self) {
+        let end = self.get_row_end();
+        let end = match end == self.cursor_idx {
+            true => (end + 1).min(self.text().len_chars()),
+            false => end,
+        };
+        self.delete_span(self.cursor_idx, end);
+    }
+
+    // The selected lines, or the cursor line, with their line break
+    fn delete_lines(&mut self) {
+        let (first, last) = self.selected_lines();
+        let text = self.text();
+        let (mut start, end) = (text.line_to_char(first), text.line_to_char(last) + text.line(last).len_chars());
+        // The last line has no break of its own, so the one before it goes
+        if start > 0 && end == text.len_chars() && (end == start || text.char(end - 1) != '\n') {
+            start -= 1;
+        }
+        drop(text);
+        self.delete_span(start, end);
+        let text = self.text();
+        let line_start = text.line_to_char(text.char_to_line(self.cursor_idx));
+        drop(text);
+        self.cursor_idx = line_start;
+        self.adj_camera();
+    }
+
+    fn delete_span(&mut self, start: usize, end: usize) {
+        self.area_start = None;
+        if start < end {
+            self.remove_text(start..end);
+            self.set_saved(false);
+        }
+        self.cursor_idx = start;
+        self.adj_camera();
+    }
+
+    fn filter(&self, cmd: &str) -> ActionReturn {
+        let (start, end) = self.selection().unwrap_or((0, self.text().len_chars()));
+        let input = self.slice_string(start, end);
+        let (buffer, cmd, edits) = (self.id, cmd.to_string(), self.edits());
+        ActionReturn::Job(format!("filter: {}", cmd), Box::new(move |_| Box::pin(async move {
+            let output = shell::run_command(&cmd, Some(input)).await?;
+            if !output.status.success() {
+                return Err(anyhow::anyhow!("{} exited with {}: {}", cmd, output.status, String::from_utf8_lossy(&output.stderr).trim()));
+            }
+            Ok(vec![crate::actions::targeted(buffer, Action {
+                name: "ReplaceRange".to_string(),
+                args: vec![Some(start.to_string()), Some(end.to_string()), Some(String::from_utf8(output.stdout)?), Some(edits.to_string())],
+            })])
+        })))
+    }
+
+    // Marks the buffer saved only once the remote side has the text, and only
+    // if it was not edited meanwhile
+    fn save_remote(&self) -> ActionReturn {
+        let (buffer, remote, text) = (self.id, self.remote.clone().unwrap(), self.saved_contents());
+        let edits = self.edits();
+        ActionReturn::Job(format!("save {}", remote.url()), Box::new(move |_| Box::pin(async move {
+            remote.write(text).await?;
+            Ok(vec![crate::actions::targeted(buffer, Action {
+                name: "MarkSaved".to_string(),
+                args: vec![Some(edits.to_string())],
+            })])
+        })))
+    }
+
+    fn save_encrypted(&self) -> ActionReturn {
+        let (buffer, path, text) = (self.id, self.path.clone().unwrap(), self.saved_contents());
+        let Encryption { cipher, secret } = self.encryption.clone().unwrap();
+        let edits = self.edits();
+        ActionReturn::Job(format!("encrypt {}", path.display()), Box::new(move |_| Box::pin(async move {
+            cipher.encrypt(&path, &secret, &text).await?;
+            Ok(vec![crate::actions::targeted(buffer, Action {
+                name: "MarkSaved".to_string(),
+                args: vec![Some(edits.to_string())],
+            })])
+        })))
+    }
+
+    // Inserts the stdout of `cmd` at the cursor once it exits
+    fn read_command(&self, cmd: String) -> ActionReturn {
+        let buffer = self.id;
+        ActionReturn::Job(format!("read: {}", cmd), Box::new(move |_| Box::pin(async move {
+            let output = shell::run_command(&cmd, None).await?;
+            if !output.status.success() {
+                return Err(anyhow::anyhow!("{} exited with {}: {}", cmd, output.status, String::from_utf8_lossy(&output.stderr).trim()));
+            }
+            Ok(vec![crate::actions::targeted(buffer, Action {
+                name: "InsertStr".to_string(),
+                args: vec![Some(String::from_utf8(output.stdout)?)],
+            })])
+        })))
+    }
+
+    // Background ranges (line relative, in chars) drawn over syntax colors
+    fn overlays(&self, line: usize, theme: &Theme) -> Vec<(usize, usize, Color)> {
+        let mut overlays = Vec::new();
+        let line_start = self.text().line_to_char(line);
+        let line_end = line_start + self.text().line(line).len_chars();
+        if let Some((start, end)) = self.selection() {
+            if start < line_end && end > line_start {
+                let color = theme.settings.selection.map(syncol_to_crosscol).unwrap_or(Color::DarkGrey);
+                overlays.push((start.saturating_sub(line_start), min(end, line_end) - line_start, color));
+            }
+        }
+        if let Some((at, other)) = self.matching_bracket() {
+            let color = theme.settings.brackets_background.or(theme.settings.line_highlight).map(syncol_to_crosscol).unwrap_or(Color::DarkGrey);
+            for i in [at, other].into_iter().filter(|i| (line_start..line_end).contains(i)) {
+                overlays.push((i - line_start, i - line_start + 1, color));
+            }
+        }
+        if !self.cursors.is_empty() {
+            let color = theme.settings.caret.map(syncol_to_crosscol).unwrap_or(Color::Grey);
+            for c in self.cursors.iter().filter(|c| (line_start..line_end).contains(*c)) {
+                overlays.push((c - line_start, c - line_start + 1, color));
+            }
+        }
+        if let Some(search) = &self.search {
+            let color = theme.settings.find_highlight.map(syncol_to_crosscol).unwrap_or(Color::DarkYellow);
+            let first = search.matches.partition_point(|(_, end)| *end <= line_start);
+            for (start, end) in search.matches[first..].iter().take_while(|(start, _)| *start < line_end) {
+                overlays.push((start.saturating_sub(line_start), min(*end, line_end) - line_start, color));
+            }
+        }
+        if let Some(width) = self.commit_width(line) {
+            let len = self.line_len(line);
+            if len > width {
+                overlays.push((width, len, Color::DarkRed));
+            }
+        }
+        if !self.misspelled.is_empty() {
+            let text = self.text();
+            let mut word = String::new();
+            for (i, c) in text.line(line).chars().chain(std::iter::once('\n')).enumerate() {
+                if c.is_alphabetic() || (c == '\'' && !word.is_empty()) {
+                    word.push(c);
+                    continue;
+                }
+                if self.misspelled.contains(&word) {
+                    overlays.push((i - word.chars().count(), i, Color::DarkRed));
+                }
+                word.clear();
+            }
+        }
+        overlays
+    }
+
+    // Commit message mode, for the files git opens the editor on
+    fn is_commit_message(&self) -> bool {
+        let name = self.path.as_ref().and_then(|p| p.file_name()).and_then(|n| n.to_str());
+        name.is_some_and(|n| COMMIT_MESSAGE_FILES.contains(&n))
+    }
+
+    // How long a line of a commit message should be; git's comment lines
+    // are left alone
+    fn commit_width(&self, line: usize) -> Option<usize> {
+        if !self.is_commit_message() || self.text().get_line(line)?.chars().next() == Some('#') {
+            return None;
+        }
+        Some(if line == 0 { SUMMARY_WIDTH } else { BODY_WIDTH })
+    }
+
+    fn line_len(&self, line: usize) -> usize {
+        self.text().line(line).chars().take_while(|c| *c != '\n').count()
+    }
+
+    // The summary line's length and, on the body, the cursor line's
+    fn commit_status(&self) -> Option<String> {
+        self.is_commit_message().then_some(())?;
+        let mut status = format!("summary {}/{}", self.line_len(0), SUMMARY_WIDTH);
+        let line = self.cursor_line();
+        if let Some(width) = self.commit_width(line).filter(|_| line > 0) {
+            status.push_str(&format!("  line {}/{}", self.line_len(line), width));
+        }
+        Some(status)
+    }
+
+    // The bracket at the cursor and the one matching it
+    fn matching_bracket(&self) -> Option<Brackets> {
+        let edits = self.edits();
+        let mut cache = self.bracket_cache.lock().unwrap();
+        if let Some((e, idx, found)) = *cache {
+            if e == edits && idx == self.cursor_idx {
+                return found;
+            }
+        }
+        let text = self.text();
+        let syntax_set = syntax::syntax_set();
+        let syntax_ref = get_syntex_ref(&text, &self.path, syntax_set);
+        let code = |line: usize| brackets::code_chars(&text.line(line).to_string(), syntax_ref, syntax_set);
+        let found = brackets::find(&text, self.cursor_idx, &code);
+        *cache = Some((edits, self.cursor_idx, found));
+        found
+    }
+
+    // virtual text
+
+    pub fn set_virtual_text(&mut self, source: &str, items: Vec<VirtualText>) {
+        self.virtual_text.retain(|v| v.source != source);
+        self.virtual_text.extend(items);
+    }
+
+    fn virtual_text_for(&self, line: usize) -> Option<String> {
+        let texts = self.virtual_text.iter()
+            .filter(|v| v.line == line)
+            .map(|v| v.text.as_str())
+            .collect::<Vec<_>>();
+        if texts.is_empty() {
+            None
+        } else {
+            Some(texts.join("  "))
+        }
+    }
+
+    // visualization
+
+    // Display cells of a line with overlays applied over the syntax colors; tabs
+    // become spaces and a wide char is followed by a '\x01' cell that prints nothing
+    fn cells(&self, line: usize, theme: &Theme) -> Vec<(char, Color, Color)> {
+        let mut s = match self.text().get_line(line) {
+            Some(l) => l.to_string(),
+            None => return Vec::new(),
+        };
+        if s.ends_with('\n') {
+            s.pop();
+        }
+        let highlighting = std::time::Instant::now();
+        let syntax_ref = get_syntex_ref(&self.text(), &self.path, syntax::syntax_set());
+        let h = highlight_line(&s, syntax_ref, syntax::syntax_set(), theme);
+        profiler::add(profiler::Metric::Highlight, highlighting.elapsed());
+        let overlays = self.overlays(line, theme);
+        let swatches = self.swatches(line);
+        let mut cells = Vec::with_capacity(s.len());
+        let mut i = 0;
         for (style, s) in h {
             let fg = syncol_to_crosscol(style.foreground);
-            let bg = syncol_to_crosscol(style.background);
-            len += s.chars().count();
-            let s = s.to_string().on(bg).with(fg);
-            styled.push(s);
+            let style_bg = syncol_to_crosscol(style.background);
+            for c in s.chars() {
+                cells.extend(swatches.iter().filter(|(end, _)| *end == i).map(|(_, color)| (' ', fg, *color)));
+                let c_bg = overlays.iter()
+                    .find(|(start, end, _)| (*start..*end).contains(&i))
+                    .map(|(_, _, color)| *color)
+                    .unwrap_or(style_bg);
+                i += 1;
+                match char_width(c, cells.len(), self.setting.tab_size) {
+                    w if c == '\t' => cells.extend(std::iter::repeat_n((' ', fg, c_bg), w)),
+                    2 => cells.extend([(c, fg, c_bg), ('\x01', fg, c_bg)]),
+                    _ => cells.push((c, fg, c_bg)),
+                }
+            }
+        }
+        cells.extend(swatches.iter().filter(|(end, _)| *end == i).map(|(_, color)| (' ', Color::Reset, *color)));
+        // A cursor past the last char still gets a cell to show on
+        if let Some((_, _, color)) = overlays.iter().find(|(start, _, _)| *start == i).filter(|_| !self.cursors.is_empty()) {
+            cells.push((' ', Color::Reset, *color));
         }
-        if len + numpad < self.size.width as usize {
-            styled.push(" ".repeat(self.size.width as usize - len - numpad - 1).on(bg));
+        // A guide at the column a shorter commit message line may go up to
+        if let Some(width) = self.commit_width(line).filter(|w| cells.len() < *w) {
+            let bg = syncol_to_crosscol(theme.settings.background.unwrap());
+            let guide = theme.settings.guide.or(theme.settings.line_highlight).map(syncol_to_crosscol).unwrap_or(Color::DarkGrey);
+            cells.resize(width, (' ', Color::Reset, bg));
+            cells.push((' ', Color::Reset, guide));
+        }
+        cells
+    }
+
+    fn visualize(&self, line: usize, theme: &Theme, numpad: usize) -> Vec<StyledContent<String>> {
+        let bg = syncol_to_crosscol(theme.settings.background.unwrap());
+        let width = (self.size.width as usize).saturating_sub(numpad + 1);
+        if line >= self.text().len_lines() {
+            return vec![" ".repeat(width).on(bg)];
+        }
+        let cells = self.cells(line, theme);
+        let first = self.camera.col as usize;
+        let mut visible = cells.iter().skip(first).take(width).copied().collect::<Vec<_>>();
+        // Markers where the line goes on past either edge of the view
+        if let [left, right] = self.setting.clip_markers.chars().collect::<Vec<_>>()[..] {
+            if first > 0 && !cells.is_empty() {
+                match visible.first_mut() {
+                    Some(cell) => *cell = (left, Color::DarkGrey, cell.2),
+                    None => visible.push((left, Color::DarkGrey, bg)),
+                }
+            }
+            if cells.len() > first + width {
+                if let Some(cell) = visible.last_mut() {
+                    *cell = (right, Color::DarkGrey, cell.2);
+                }
+            }
+        }
+        let virtual_text = self.virtual_text_for(line).filter(|_| first <= cells.len());
+        self.styled(&visible.iter().collect::<Vec<_>>(), width, bg, virtual_text.map(|t| (t, line)))
+    }
+
+    // Clips cells to a screen row; a wide char cut by either edge is drawn as a space
+    fn styled(&self, visible: &[&(char, Color, Color)], width: usize, bg: Color, virtual_text: Option<(String, usize)>) -> Vec<StyledContent<String>> {
+        let mut styled: Vec<StyledContent<String>> = Vec::new();
+        let mut run = String::new();
+        let mut run_style = None;
+        for (i, (c, fg, c_bg)) in visible.iter().enumerate() {
+            let c = match c {
+                '\x01' if i == 0 => ' ',
+                '\x01' => continue,
+                c if is_hangul(*c) && i + 1 == visible.len() => ' ',
+                c => *c,
+            };
+            if run_style != Some((*fg, *c_bg)) && !run.is_empty() {
+                let (fg, c_bg) = run_style.unwrap();
+                styled.push(std::mem::take(&mut run).on(c_bg).with(fg));
+            }
+            run_style = Some((*fg, *c_bg));
+            run.push(c);
+        }
+        if let Some((fg, c_bg)) = run_style {
+            styled.push(run.on(c_bg).with(fg));
+        }
+        let mut len = visible.len();
+        // Virtual text goes after the end of the line when there is room left
+        if let Some((virtual_text, line)) = virtual_text {
+            if len + 2 < width {
+                let text: String = format!(" {}", virtual_text).chars().take(width - len).collect();
+                len += text.chars().count();
+                styled.push(text.on(bg).with(virtual_text_color(&self.virtual_text, line)).italic());
+            }
+        }
+        if len < width {
+            styled.push(" ".repeat(width - len).on(bg));
         }
         styled
     }
@@ -492,31 +2078,26 @@ impl Buffer {
         W: Write,
     {
         let camera = self.camera;
-        let line_len = self.text.len_lines();
+        let line_len = self.text().len_lines();
         let line_num_padding = if self.setting.line_numbers {
             numlen(line_len) + 1
         } else {
             0
         };
+        if self.soft_wrap() {
+            return self.render_wrapped(write, line_num_padding);
+        }
+        let marked = self.bookmarks.lock().unwrap().clone();
         for i in 0..self.size.height as usize {
-            let line = self.visualize(i + camera.row as usize, &self.theme_set.themes["base16-ocean.dark"], line_num_padding);
+            let line = self.visualize(i + camera.row as usize, syntax::theme(&self.setting.theme), line_num_padding);
             queue!(
                 write,
                 cursor::MoveTo(self.pos.col, i as u16 + self.pos.row),
                 //Clear(terminal::ClearType::UntilNewLine),
             )?;
             if self.setting.line_numbers {
-                let line_num = format!("{:width$} ", i + 1 + camera.row as usize, width = line_num_padding);
-                if i + camera.row as usize == self.get_row() as usize {
-                    queue!(
-                        write,
-                        Print(line_num.white()),
-                    )?;
-                } else {
-                    queue!(
-                        write,
-                        Print(line_num.dark_grey()),
-                    )?;
+                for s in self.gutter(Some(i + camera.row as usize), line_num_padding, &marked) {
+                    queue!(write, Print(s))?;
                 }
             }
             for s in line {
@@ -526,31 +2107,359 @@ impl Buffer {
         Ok(())
     }
 
-    fn get_pos(&self) -> Pos {
-        self.pos
+    // Long lines continue on the next screen rows instead of scrolling sideways
+    fn render_wrapped<W>(&self, write: &mut W, numpad: usize) -> Result<()>
+    where
+        W: Write,
+    {
+        let theme = syntax::theme(&self.setting.theme);
+        let bg = syncol_to_crosscol(theme.settings.background.unwrap());
+        let width = self.text_width() as usize;
+        let height = self.size.height as usize;
+        let marked = self.bookmarks.lock().unwrap().clone();
+        let mut row = 0;
+        let mut line = self.camera.row as usize;
+        while row < height {
+            let cells = if line < self.text().len_lines() { self.cells(line, theme) } else { Vec::new() };
+            let cells = cells.iter().collect::<Vec<_>>();
+            let chunks = cells.len() / width + 1;
+            for chunk in 0..chunks {
+                if row == height {
+                    break;
+                }
+                queue!(write, cursor::MoveTo(self.pos.col, row as u16 + self.pos.row))?;
+                if self.setting.line_numbers {
+                    let numbered = (chunk == 0 && line < self.text().len_lines()).then_some(line);
+                    for s in self.gutter(numbered, numpad, &marked) {
+                        queue!(write, Print(s))?;
+                    }
+                }
+                let visible = &cells[min(chunk * width, cells.len())..min((chunk + 1) * width, cells.len())];
+                let virtual_text = self.virtual_text_for(line).filter(|_| chunk + 1 == chunks);
+                for s in self.styled(visible, width, bg, virtual_text.map(|t| (t, line))) {
+                    queue!(write, Print(s))?;
+                }
+                row += 1;
+            }
+            line += 1;
+        }
+        Ok(())
+    }
+
+    // Screen rows `line` takes when wrapped
+    fn wrapped_rows(&self, line: usize) -> usize {
+        self.layout(line).last().unwrap() / self.text_width() as usize + 1
+    }
+
+    // (row, col) of the cursor relative to the camera when lines are wrapped
+    fn wrapped_cursor(&self) -> (u16, u16) {
+        let width = self.text_width();
+        let rows: usize = (self.camera.row as usize..self.get_row() as usize)
+            .map(|line| self.wrapped_rows(line))
+            .sum();
+        let col = self.get_col();
+        (rows as u16 + col / width, col % width)
+    }
+
+    pub fn contents(&self) -> String {
+        self.text().to_string()
+    }
+
+    // The text with the line endings of the file, as a save writes it
+    fn saved_contents(&self) -> String {
+        self.line_ending().emit(&self.contents()).into_owned()
+    }
+
+    fn line_ending(&self) -> LineEnding {
+        *self.line_ending.lock().unwrap()
+    }
+
+    // Mixed endings are made one or the other; the text keeps `\n` alone either way
+    fn set_line_ending(&mut self, ending: LineEnding) {
+        if self.line_ending() == ending {
+            return;
+        }
+        if self.line_ending() == LineEnding::Mixed {
+            let text = self.contents().replace("\r\n", "\n");
+            self.set_contents(&text);
+        }
+        *self.line_ending.lock().unwrap() = ending;
+        self.set_saved(false);
+    }
+
+    pub fn path(&self) -> Option<&PathBuf> {
+        self.path.as_ref()
+    }
+
+    // Column of the cursor counted in characters
+    fn logical_col(&self) -> usize {
+        self.cursor_idx - self.get_row_start()
+    }
+
+    pub fn location(&self) -> Option<Location> {
+        Some(Location {
+            path: self.path.clone()?,
+            line: self.get_row() as usize,
+            col: self.logical_col(),
+            text: String::new(),
+        })
+    }
+
+    // Where to reopen it in a restored session; remote, encrypted and scratch
+    // buffers are left out
+    pub fn session_location(&self) -> Option<Location> {
+        if self.remote.is_some() || self.encryption.is_some() || self.scratch.is_some() {
+            return None;
+        }
+        self.location()
+    }
+
+    // Replaces the whole text, keeping the cursor on the same line and column
+    fn set_contents(&mut self, s: &str) {
+        let (line, col) = (self.get_row() as usize, self.logical_col());
+        *self.text_mut() = Rope::from_str(s);
+        self.goto(line, col);
+        self.set_saved(false);
+    }
+
+    // Formats with the configured external command, falling back to the language server
+    fn format(&self, save: bool) -> Result<ActionReturn> {
+        let doc = self.document().ok_or(anyhow::anyhow!("Format needs a saved file"))?;
+        let ext = doc.path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        match self.setting.formatters.get(ext) {
+            Some(cmd) => {
+                let cmd = cmd.replace("{file}", &crate::remote::quote(&doc.path.display().to_string()));
+                let (buffer, text) = (self.id, doc.text);
+                Ok(ActionReturn::Job("format".to_string(), Box::new(move |_| Box::pin(async move {
+                    let output = shell::run_command(&cmd, Some(text)).await?;
+                    if !output.status.success() {
+                        return Err(anyhow::anyhow!("{} failed: {}", cmd, String::from_utf8_lossy(&output.stderr).trim()));
+                    }
+                    Ok(lsp::format_actions(buffer, String::from_utf8(output.stdout)?, save))
+                }))))
+            }
+            None => {
+                let insert_spaces = matches!(self.setting.tab_type, TabType::Space);
+                Ok(ActionReturn::Lsp(lsp::Request::Format(doc, self.setting.tab_size, insert_spaces, save)))
+            }
+        }
+    }
+
+    // Soft wrap, reflow at `textwidth` and spell check on, line numbers off; or back
+    fn toggle_prose(&mut self) -> Vec<ActionReturn> {
+        match self.prose.take() {
+            Some(line_numbers) => {
+                self.setting.line_numbers = line_numbers;
+                self.misspelled.clear();
+                self.adj_camera();
+                vec![ActionReturn::Notice("Prose mode off".to_string())]
+            }
+            None => {
+                self.prose = Some(self.setting.line_numbers);
+                self.setting.line_numbers = false;
+                self.adj_camera();
+                let mut returns = vec![ActionReturn::Notice("Prose mode on".to_string())];
+                if !self.setting.spell_command.is_empty() {
+                    returns.push(self.spell_check());
+                }
+                returns
+            }
+        }
+    }
+
+    // Breaks the cursor line at the last space that keeps it within `textwidth`
+    fn reflow(&mut self) {
+        let width = self.setting.textwidth;
+        if self.prose.is_none() || width == 0 || (self.get_col() as usize) <= width {
+            return;
+        }
+        let start = self.get_row_start();
+        let mut col = 0;
+        let mut brk = None;
+        for (i, c) in self.text().slice(start..self.cursor_idx).chars().enumerate() {
+            if c == ' ' && col <= width && i > 0 {
+                brk = Some(start + i);
+            }
+            col += char_width(c, col, self.setting.tab_size);
+        }
+        if let Some(idx) = brk {
+            self.remove_text(idx..idx + 1);
+            self.insert_text(idx, "\n");
+            self.adj_camera();
+        }
+    }
+
+    // Runs `spell_command` over the text; it prints the misspelled words, one per line
+    fn spell_check(&self) -> ActionReturn {
+        let (buffer, cmd, text, edits) = (self.id, self.setting.spell_command.clone(), self.contents(), self.edits());
+        ActionReturn::Job(format!("spell: {}", cmd), Box::new(move |_| Box::pin(async move {
+            let output = shell::run_command(&cmd, Some(text)).await?;
+            if !output.status.success() {
+                return Err(anyhow::anyhow!("{} exited with {}: {}", cmd, output.status, String::from_utf8_lossy(&output.stderr).trim()));
+            }
+            let words = std::iter::once(Some(edits.to_string()))
+                .chain(String::from_utf8_lossy(&output.stdout).lines().map(|w| Some(w.trim().to_string())))
+                .collect();
+            Ok(vec![crate::actions::targeted(buffer, Action {
+                name: "SetMisspelled".to_string(),
+                args: words,
+            })])
+        })))
+    }
+
+    fn can_format(&self) -> bool {
+        let ext = self.path.as_ref().and_then(|p| p.extension()).and_then(|e| e.to_str()).unwrap_or("");
+        self.setting.formatters.contains_key(ext) || self.setting.lsp_servers.contains_key(ext)
+    }
+
+    // Moves the cursor to a zero based line and column counted in chars
+    pub fn goto(&mut self, line: usize, col: usize) {
+        let line = min(line, self.text().len_lines() - 1);
+        let start = self.text().line_to_char(line);
+        let len = self.text().line(line).chars().take_while(|c| *c != '\n').count();
+        self.cursor_idx = start + min(col, len);
+        self.adj_camera();
+    }
+
+    // Goto(line[,col]) from the line input, both counted from 1; a column past
+    // the end of the line stops at its end
+    fn goto_checked(&mut self, line: &str, col: Option<&str>) -> Result<()> {
+        let parse = |what: &str, s: &str| s.trim().parse::<usize>().ok().filter(|n| *n > 0)
+            .ok_or(anyhow::anyhow!("Goto: {} must be a number from 1, not \"{}\"", what, s.trim()));
+        let line = parse("line", line)?;
+        let lines = self.text().len_lines();
+        if line > lines {
+            return Err(anyhow::anyhow!("Goto: line {} is out of range, {} has {} lines", line, self.name(), lines));
+        }
+        let col = col.map(|c| parse("column", c)).transpose()?.unwrap_or(1);
+        self.goto(line - 1, col - 1);
+        self.recenter();
+        Ok(())
+    }
+
+    // Scrolls the cursor line to the middle of the view
+    fn recenter(&mut self) {
+        self.camera.row = self.get_row().saturating_sub(self.size.height / 2);
+        self.adj_camera();
+    }
+
+    // Decrypted text is not sent to language servers
+    pub fn document(&self) -> Option<lsp::Document> {
+        if self.encryption.is_some() {
+            return None;
+        }
+        let path = self.path.clone()?;
+        let character = self.text().slice(self.get_row_start()..self.cursor_idx).chars()
+            .map(|c| c.len_utf16())
+            .sum();
+        Some(lsp::Document {
+            buffer: self.id,
+            path,
+            text: self.contents(),
+            line: self.get_row() as usize,
+            character,
+        })
     }
 
     pub fn name(&self) -> String {
+        if let Some(remote) = &self.remote {
+            return remote.file_name().to_string();
+        }
+        if let Some(name) = &self.scratch {
+            return format!("[{}]", name);
+        }
         match &self.path {
             Some(p) => p.file_name().unwrap().to_string_lossy().to_string(),
             None => "Untitled".to_string(),
         }
     }
 
-    fn get_size(&self) -> Size {
-        self.size
+    // Whether a screen column is on the line numbers
+    fn in_gutter(&self, col: u16) -> bool {
+        self.setting.line_numbers && col >= self.pos.col
+            && ((col - self.pos.col) as usize) < numlen(self.text().len_lines()) + 2
+    }
+
+    // Whole lines from `anchor` to the cursor's, the selection running the
+    // way the mouse went
+    fn select_lines(&mut self, anchor: usize) {
+        let line = self.cursor_line();
+        let text = self.text();
+        let line_end = |l: usize| text.line_to_char(l) + text.line(l).len_chars();
+        let (start, end) = match line >= anchor {
+            true => (text.line_to_char(anchor), line_end(line)),
+            false => (line_end(anchor), text.line_to_char(line)),
+        };
+        drop(text);
+        self.area_start = Some(start);
+        self.select_mode = SelectMode::Line;
+        self.cursor_idx = end;
+        self.adj_camera();
+    }
+
+    // Selects the whole lines of a command line range like `10,20` or `.,$`
+    fn select_range(&mut self, start: &str, end: &str) -> Result<()> {
+        let (cursor, lines) = (self.cursor_line(), self.text().len_lines());
+        let start = range::resolve(start, cursor, lines)?;
+        let end = range::resolve(end, cursor, lines)?;
+        let (start, end) = (min(start, end), start.max(end));
+        let text = self.text();
+        let (from, to) = (text.line_to_char(start), text.line_to_char(end) + text.line(end).len_chars());
+        drop(text);
+        self.area_start = Some(from);
+        self.select_mode = SelectMode::Line;
+        self.cursor_idx = to;
+        self.adj_camera();
+        Ok(())
+    }
+
+    // Moves the cursor to the character drawn at a screen cell
+    fn click(&mut self, col: u16, row: u16) {
+        if row < self.pos.row || col < self.pos.col {
+            return;
+        }
+        let gutter = if self.setting.line_numbers {
+            numlen(self.text().len_lines()) + 2
+        } else {
+            0
+        };
+        let mut line = min((row - self.pos.row + self.camera.row) as usize, self.text().len_lines() - 1);
+        let mut cell = ((col - self.pos.col) as usize).saturating_sub(gutter) + self.camera.col as usize;
+        if self.soft_wrap() {
+            // Walk the wrapped rows down to the clicked one
+            let mut rows = (row - self.pos.row) as usize;
+            line = self.camera.row as usize;
+            while line + 1 < self.text().len_lines() && rows >= self.wrapped_rows(line) {
+                rows -= self.wrapped_rows(line);
+                line += 1;
+            }
+            cell += rows * self.text_width() as usize;
+        }
+        self.cursor_idx = self.idx_at_col(line, cell);
+        self.adj_camera();
     }
 
     pub fn get_cursor(&self) -> Option<Cursor> {
         let line_num_padding = if self.setting.line_numbers {
-            numlen(self.text.len_lines()) + 2
+            numlen(self.text().len_lines()) + 2
         } else {
             0
         };
+        if self.soft_wrap() {
+            let (row, col) = self.wrapped_cursor();
+            return Some(Cursor {
+                row: row + self.pos.row,
+                col: col + line_num_padding as u16 + self.pos.col,
+            });
+        }
         let mut cursor = Cursor {
             row: self.get_row(),
             col: self.get_col(),
         };
+        // ScrollLeft and ScrollRight can leave the cursor out of view
+        if cursor.col < self.camera.col || cursor.col >= self.camera.col + self.text_width() {
+            return None;
+        }
         cursor.col += line_num_padding as u16;
         cursor.col -= self.camera.col;
         cursor.col += self.pos.col;
@@ -559,10 +2468,51 @@ impl Buffer {
         Some(cursor)
     }
     pub async fn process_action(&mut self, action: &Action) -> Result<Vec<ActionReturn>> {
+        // Another view may have shortened the shared text
+        let len = self.text().len_chars();
+        self.cursor_idx = min(self.cursor_idx, len);
+        let unranged = crate::actions::unrange(action);
+        let action = match &unranged {
+            Some((action, start, end)) => {
+                if !range::takes_range(&action.name) {
+                    return Err(anyhow::anyhow!("{} does not take a line range", action.name));
+                }
+                self.select_range(start, end)?;
+                action
+            }
+            None => action,
+        };
+        if !matches!(action.name.as_str(), "Insert" | "InsertUpper" | "Delete") {
+            self.composing = false;
+        }
         let action_name = &action.name;
         let mut action_args = action.args.clone();
         match action_name.as_str() {
             "CursorUp" => { self.cursor_up(); }
+            "MouseDown" | "MouseDrag" => {
+                let arg = |i: usize| action_args.get(i).cloned().flatten().and_then(|s| s.parse::<u16>().ok()).unwrap_or(0);
+                if action_name == "MouseDown" {
+                    self.area_start = None;
+                    self.gutter_anchor = None;
+                    self.cursors.clear();
+                } else if self.area_start.is_none() {
+                    self.area_start = Some(self.cursor_idx);
+                    self.select_mode = SelectMode::Char;
+                }
+                let gutter = action_name == "MouseDown" && self.in_gutter(arg(0));
+                self.click(arg(0), arg(1));
+                if gutter {
+                    self.gutter_anchor = Some(self.cursor_line());
+                }
+                match (self.gutter_anchor, arg(2)) {
+                    (Some(anchor), _) => self.select_lines(anchor),
+                    (None, 2) => self.select_word(),
+                    (None, 3) => self.select_line(),
+                    _ => (),
+                }
+            }
+            "ScrollUp" => { self.scroll(false); }
+            "ScrollDown" => { self.scroll(true); }
             "CursorDown" => { self.cursor_down(); }
             "CursorForward" => { self.cursor_forward_action(); }
             "CursorBackward" => { self.cursor_backward_action(); }
@@ -572,37 +2522,418 @@ impl Buffer {
             "CursorEnd" => { self.cursor_end(); }
             "Insert" => {
                 let c = action_args[0].as_mut().unwrap().chars().next().unwrap();
+                self.delete_selection();
+                if abbrev::is_boundary(c) {
+                    self.expand_abbreviation();
+                }
                 self.insert_char(c, false);
+                self.reflow();
             }
             "InsertUpper" => {
                 let c = action_args[0].as_mut().unwrap().chars().next().unwrap();
+                self.delete_selection();
+                if abbrev::is_boundary(c) {
+                    self.expand_abbreviation();
+                }
                 self.insert_char(c, true);
+                self.reflow();
             }
             "InsertStr" => {
                 let s = action_args[0].as_ref().unwrap();
+                self.delete_selection();
                 self.insert_str(s);
             }
-            "InsertNewline" => { self.insert_newline(); }
+            "InsertNewline" => {
+                self.delete_selection();
+                self.expand_abbreviation();
+                self.insert_newline();
+            }
             "InsertNewlineAbove" => { self.insert_newline_above(); }
             "InsertNewlineBelow" => { self.insert_newline_below(); }
-            "InsertSpace" => { self.insert_str(" "); }
-            "InsertComma" => { self.insert_str(","); }
-            "InsertTab" => { self.insert_tab(); }
+            "InsertSpace" => {
+                self.delete_selection();
+                self.expand_abbreviation();
+                self.insert_str(" ");
+                self.reflow();
+            }
+            "InsertComma" => {
+                self.delete_selection();
+                self.expand_abbreviation();
+                self.insert_str(",");
+            }
+            // For this buffer only; `abbreviations` in the settings sets the default
+            "ToggleAbbreviations" => {
+                let ext = self.path.as_ref().and_then(|p| p.extension()).and_then(|e| e.to_str()).unwrap_or("*").to_string();
+                let on = !self.abbreviations_on();
+                self.setting.abbreviations.insert(ext, on);
+                let notice = if on { "Abbreviations on" } else { "Abbreviations off" };
+                return Ok(vec![ActionReturn::Notice(notice.to_string())]);
+            }
+            "InsertTab" => {
+                self.delete_selection();
+                self.insert_tab();
+            }
+            "AddCursorBelow" => { self.add_cursor_below(); }
+            "AddCursorAtNextMatch" => {
+                return Ok(self.add_cursor_at_next_match().into_iter().collect());
+            }
+            "ClearCursors" => { self.cursors.clear(); }
+            "FindAccept" => { self.search = None; }
+            "Replace" => {
+                let pattern = action_args.first().cloned().flatten().ok_or(anyhow::anyhow!("Replace(pattern,replacement)"))?;
+                let replacement = action_args[1..].iter().flatten().cloned().collect::<Vec<_>>().join(",");
+                return self.replace_start(&pattern, &replacement);
+            }
+            "ReplaceAnswer" if self.replacing.is_some() => {
+                let answer = action_args.first().cloned().flatten().unwrap_or_default();
+                return Ok(self.replace_answer(&answer));
+            }
+            "NormalMode" if self.replacing.is_some() => {
+                return Ok(self.replace_end());
+            }
+            "FindNext" | "FindPrevious" => {
+                let Some(search) = self.search.as_mut() else { return Ok(vec![]) };
+                let found = search.step(action_name == "FindNext");
+                let counts = search.counts();
+                if let Some((start, _)) = found {
+                    self.cursor_idx = start;
+                    self.adj_camera();
+                }
+                return Ok(vec![ActionReturn::Matches(counts)]);
+            }
+            // FindToggle(case) or FindToggle(word), kept for later searches
+            "FindToggle" => {
+                let flag = action_args.first().cloned().flatten().unwrap_or_default();
+                self.search_flags.toggle(&flag);
+                if let Some(query) = self.search_query().map(str::to_string) {
+                    return Ok(vec![self.search(&query)]);
+                }
+            }
+            "StageHunk" | "UnstageHunk" => {
+                let Some(path) = self.path.clone().filter(|_| self.remote.is_none()) else {
+                    return Ok(vec![ActionReturn::Notice("Not a local file".to_string())]);
+                };
+                if !self.is_saved() {
+                    return Ok(vec![ActionReturn::Notice("Save first, git only sees the file on disk".to_string())]);
+                }
+                let staged = git::stage_hunk(&path, self.cursor_line() + 1, action_name == "UnstageHunk").await;
+                return Ok(vec![
+                    staged.map_or_else(ActionReturn::Err, ActionReturn::Notice),
+                    ActionReturn::State(KeymapState::Normal),
+                ]);
+            }
+            "RevealInDirectory" => {
+                return Ok(vec![match &self.path {
+                    Some(path) if self.remote.is_none() => ActionReturn::RevealFile(path.clone()),
+                    _ => ActionReturn::Notice("Not a local file".to_string()),
+                }]);
+            }
+            "BookmarkToggle" => {
+                return Ok([self.toggle_bookmark()].into_iter().chain(self.save_view()).collect());
+            }
+            "BookmarkNext" | "BookmarkPrev" => {
+                return Ok(self.goto_bookmark(action_name == "BookmarkNext").into_iter().collect());
+            }
+            // Overrides a setting for this tab only, the value read as JSON or else as a string
+            "SetLocal" => {
+                let key = action_args.first().cloned().flatten().ok_or(anyhow::anyhow!("SetLocal(key,value)"))?;
+                let value = action_args[1..].iter().flatten().cloned().collect::<Vec<_>>().join(",");
+                if value.is_empty() {
+                    let json = serde_json::to_value(&self.setting)?;
+                    let current = json.get(&key).ok_or(anyhow::anyhow!("Unknown setting: {}", key))?;
+                    return Ok(vec![ActionReturn::Notice(format!("{} = {}", key, current))]);
+                }
+                let value = serde_json::from_str(&value).unwrap_or(serde_json::Value::String(value));
+                let notice = format!("{} = {} for this tab", key, value);
+                self.set_local(&key, value.clone())?;
+                let persist = self.setting.persist_settings.then(|| ActionReturn::WriteSettings([(key, value)].into_iter().collect()));
+                return Ok([ActionReturn::Notice(notice)].into_iter().chain(self.save_view()).chain(persist).collect());
+            }
+            "WriteSettings" => {
+                let settings: serde_json::Map<_, _> = match action_args.first().cloned().flatten() {
+                    Some(key) => self.local_settings.get(&key).map(|v| (key.clone(), v.clone())).into_iter().collect(),
+                    None => self.local_settings.clone(),
+                };
+                if settings.is_empty() {
+                    return Err(anyhow::anyhow!("WriteSettings: nothing set with SetLocal in this tab"));
+                }
+                return Ok(vec![ActionReturn::WriteSettings(settings)]);
+            }
+            "SetLineEnding" => {
+                let ending = action_args.first().cloned().flatten().ok_or(anyhow::anyhow!("SetLineEnding(lf|crlf)"))?;
+                self.set_line_ending(LineEnding::parse(&ending)?);
+            }
+            "FindCancel" => {
+                let searched = self.search.take().is_some();
+                if searched {
+                    (self.cursor_idx, self.camera) = self.search_origin;
+                }
+            }
             "Delete" => { self.delete_action(); }
             "DeleteBack" => { self.delete_back(); }
+            "Hover" | "GotoDefinition" | "FindReferences" => {
+                let doc = match self.document() {
+                    Some(doc) => doc,
+                    None => return Ok(vec![ActionReturn::Err(anyhow::anyhow!("{} needs a saved file", action_name))]),
+                };
+                let request = match action_name.as_str() {
+                    "Hover" => lsp::Request::Hover(doc),
+                    "GotoDefinition" => lsp::Request::Definition(doc),
+                    _ => lsp::Request::References(doc),
+                };
+                return Ok(vec![ActionReturn::Lsp(request)]);
+            }
+            // WorkspaceSymbols(query) asks the language server of this file's type
+            "WorkspaceSymbols" => {
+                let query = action_args.iter().flatten().cloned().collect::<Vec<_>>().join(",");
+                if query.trim().is_empty() {
+                    return Ok(vec![
+                        ActionReturn::State(KeymapState::LineInsert),
+                        ActionReturn::Notice("Symbol: ".to_string()),
+                        ActionReturn::ExcuteLine("WorkspaceSymbols($line)".to_string()),
+                    ]);
+                }
+                let doc = match self.document() {
+                    Some(doc) => doc,
+                    None => return Ok(vec![ActionReturn::State(KeymapState::Normal), ActionReturn::Err(anyhow::anyhow!("WorkspaceSymbols needs a saved file"))]),
+                };
+                return Ok(vec![ActionReturn::State(KeymapState::Normal), ActionReturn::Lsp(lsp::Request::WorkspaceSymbols(doc, query.trim().to_string()))]);
+            }
+            "SelectStart" => { self.select_start(); }
+            "Copy" => {
+                let (start, end) = self.yank_range();
+                clipboard::push(self.slice_string(start, end));
+                self.area_start = None;
+            }
+            "Cut" => {
+                let (start, end) = self.yank_range();
+                clipboard::push(self.slice_string(start, end));
+                self.replace_range(start, end, "");
+            }
+            "Paste" => {
+                match clipboard::latest() {
+                    Some(text) => {
+                        self.delete_selection();
+                        self.paste(&text);
+                    }
+                    None => return Ok(vec![ActionReturn::Notice("Nothing to paste".to_string())]),
+                }
+            }
+            // Bracketed paste from the terminal, url encoded to survive parse_action
+            "PasteText" => {
+                let encoded = action_args.iter().flatten().cloned().collect::<Vec<_>>().join(",");
+                let text = String::from_utf8(transform::url_decode(&encoded)?)?;
+                self.delete_selection();
+                self.paste(&text.replace("\r\n", "\n").replace('\r', "\n"));
+            }
+            "PasteFromHistory" => {
+                if let Some(i) = action_args.first().cloned().flatten() {
+                    if let Some(text) = clipboard::take(i.parse::<usize>()?) {
+                        self.delete_selection();
+                        self.paste(&text);
+                    }
+                    return Ok(vec![]);
+                }
+                let entries = clipboard::entries();
+                if entries.is_empty() {
+                    return Ok(vec![ActionReturn::Notice("Nothing to paste".to_string())]);
+                }
+                let width = (self.size.width as usize / 2).max(20);
+                let lines = entries.iter().map(|t| clipboard::preview(t, width)).collect();
+                return Ok(vec![ActionReturn::Popup(Some(Popup::picker("Paste from history", lines, "PasteFromHistory")))]);
+            }
+            "ProseMode" => {
+                return Ok(self.toggle_prose());
+            }
+            "SpellCheck" => {
+                return Ok(vec![self.spell_check()]);
+            }
+            // The edits count the text was checked at, then the words
+            "SetMisspelled" => {
+                if action_args.first().cloned().flatten().and_then(|e| e.parse::<usize>().ok()) != Some(self.edits()) {
+                    return Ok(vec![ActionReturn::Notice(format!("{} was edited during the spell check, run SpellCheck again", self.name()))]);
+                }
+                self.misspelled = action_args.iter().skip(1).flatten().cloned().collect();
+            }
+            name if transform::ACTIONS.contains(&name) => {
+                // The whole text when nothing is selected, as with Filter
+                let (start, end) = self.selection().unwrap_or((0, self.text().len_chars()));
+                let output = transform::apply(name, &self.slice_string(start, end))?;
+                self.replace_range(start, end, &output);
+            }
+            // Saves and closes the editor in one go, as when git is waiting on it
+            "SaveQuit" => {
+                // Those are written by a background job that quitting would cut short
+                if self.remote.is_some() || self.encryption.is_some() {
+                    return Err(anyhow::anyhow!("SaveQuit: use Save for {}, then Quit", self.name()));
+                }
+                self.save(None)?;
+                return Ok(vec![ActionReturn::Quit(Unsaved::Ask)]);
+            }
+            "ScrollLeft" if !self.soft_wrap() => { self.scroll_sideways(false); }
+            "ScrollRight" if !self.soft_wrap() => { self.scroll_sideways(true); }
+            "MoveLineUp" => { self.move_lines(true); }
+            "MoveLineDown" => { self.move_lines(false); }
+            "Indent" => { self.shift_lines(true); }
+            "Dedent" => { self.shift_lines(false); }
+            "DeleteWordForward" => { self.delete_word(true); }
+            "DeleteWordBackward" => { self.delete_word(false); }
+            "DeleteToEnd" => { self.delete_to_end(); }
+            "DeleteLine" => {
+                self.delete_lines();
+                return Ok(vec![ActionReturn::State(KeymapState::Normal)]);
+            }
+            "DuplicateLine" => {
+                self.duplicate_lines();
+                return Ok(vec![ActionReturn::State(KeymapState::Normal)]);
+            }
+            "GotoMatch" => {
+                let Some((_, other)) = self.matching_bracket() else {
+                    return Ok(vec![ActionReturn::Notice("No matching bracket".to_string())]);
+                };
+                self.cursor_idx = other;
+                self.adj_camera();
+            }
+            "Goto" => {
+                match action_args.first() {
+                    Some(Some(_)) => {
+                        // The prompt's answer comes as one argument, "120,8"
+                        let target = action_args.iter().flatten().cloned().collect::<Vec<_>>().join(",");
+                        let (line, col) = target.split_once(',').map_or((target.as_str(), None), |(l, c)| (l, Some(c)));
+                        let mut out = vec![ActionReturn::State(KeymapState::Normal)];
+                        out.extend(self.goto_checked(line, col).err().map(ActionReturn::Err));
+                        return Ok(out);
+                    }
+                    _ => return Ok(vec![
+                        ActionReturn::State(KeymapState::LineInsert),
+                        ActionReturn::Notice("Go to line[,column]: ".to_string()),
+                        ActionReturn::ExcuteLine("Goto($line)".to_string()),
+                    ]),
+                }
+            }
+            "InsertUnicode" => {
+                match action_args.first() {
+                    Some(Some(s)) => {
+                        let c = unicode::parse(s)?;
+                        self.insert_str(&c.to_string());
+                        return Ok(vec![ActionReturn::State(KeymapState::Normal)]);
+                    }
+                    _ => return Ok(vec![
+                        ActionReturn::State(KeymapState::LineInsert),
+                        ActionReturn::Notice("Insert character (U+XXXX or digraph): ".to_string()),
+                        ActionReturn::ExcuteLine("InsertUnicode($line)".to_string()),
+                    ]),
+                }
+            }
+            "DescribeChar" => {
+                if self.cursor_idx == self.text().len_chars() {
+                    return Ok(vec![ActionReturn::Notice("End of text".to_string())]);
+                }
+                let c = self.char_at(self.cursor_idx);
+                let width = char_width(c, self.get_col() as usize, self.setting.tab_size);
+                return Ok(vec![ActionReturn::Notice(unicode::describe(c, width))]);
+            }
+            "WordCount" => {
+                return Ok(vec![ActionReturn::Notice(self.word_count())]);
+            }
+            "Filter" => {
+                match action_args.first() {
+                    Some(Some(cmd)) => return Ok(vec![self.filter(cmd), ActionReturn::State(KeymapState::Normal)]),
+                    _ => return Ok(vec![
+                        ActionReturn::State(KeymapState::LineInsert),
+                        ActionReturn::Notice("Filter through: ".to_string()),
+                        ActionReturn::ExcuteLine("Filter($line)".to_string()),
+                    ]),
+                }
+            }
+            "ReadCommand" => {
+                if action_args.iter().all(|a| a.is_none()) {
+                    return Ok(vec![
+                        ActionReturn::State(KeymapState::LineInsert),
+                        ActionReturn::Notice("Insert output of: ".to_string()),
+                        ActionReturn::ExcuteLine("ReadCommand($line)".to_string()),
+                    ]);
+                }
+                // parse_action splits on commas, so the command is joined back
+                let cmd = action_args.iter().flatten().cloned().collect::<Vec<_>>().join(",");
+                return Ok(vec![self.read_command(cmd), ActionReturn::State(KeymapState::Normal)]);
+            }
+            "SetVirtualText" => {
+                // SetVirtualText(source, line:text, ...) with one based lines
+                let source = action_args.first().cloned().flatten().unwrap_or_default();
+                let items = action_args.iter().skip(1).flatten().filter_map(|item| {
+                    let (line, text) = item.split_once(':')?;
+                    Some(VirtualText {
+                        source: source.clone(),
+                        line: line.trim().parse::<usize>().ok()?.max(1) - 1,
+                        text: text.trim().to_string(),
+                        color: Color::DarkGrey,
+                    })
+                }).collect();
+                self.set_virtual_text(&source, items);
+            }
+            "ClearVirtualText" => {
+                let source = action_args.first().cloned().flatten().unwrap_or_default();
+                self.set_virtual_text(&source, vec![]);
+            }
+            // From a job, with the edit count the range was taken at; the
+            // offsets mean nothing once the text changed
+            "ReplaceRange" => {
+                let arg = |i: usize| action_args.get(i).cloned().flatten();
+                let offset = |i: usize| arg(i).and_then(|a| a.parse::<usize>().ok()).ok_or(anyhow::anyhow!("ReplaceRange(start,end,text)"));
+                let (start, end) = (offset(0)?, offset(1)?);
+                if arg(3).and_then(|e| e.parse::<usize>().ok()).is_some_and(|e| e != self.edits()) {
+                    return Err(anyhow::anyhow!("{} was edited meanwhile, the output is dropped", self.name()));
+                }
+                if start > end || end > self.text().len_chars() {
+                    return Err(anyhow::anyhow!("ReplaceRange: {}..{} is out of range", start, end));
+                }
+                self.replace_range(start, end, &arg(2).unwrap_or_default());
+            }
+            "Format" => {
+                return Ok(vec![self.format(false)?]);
+            }
+            "ReplaceText" => {
+                let s = action_args.first().cloned().flatten().ok_or(anyhow::anyhow!("ReplaceText(text)"))?;
+                self.set_contents(&s);
+                return Ok(vec![ActionReturn::Notice("Formatted".to_string())]);
+            }
+            "Save" if self.remote.is_some() => {
+                return Ok(vec![self.save_remote(), ActionReturn::State(KeymapState::Normal)]);
+            }
+            "Save" if self.encryption.is_some() => {
+                return Ok(vec![self.save_encrypted(), ActionReturn::State(KeymapState::Normal)]);
+            }
+            // With the edit count the saved text had, when it was written by a job
+            "MarkSaved" => {
+                let saved = action_args.first().cloned().flatten().and_then(|e| e.parse::<usize>().ok());
+                if saved.is_none_or(|e| e == self.edits()) {
+                    self.set_saved(true);
+                }
+            }
             "Save" => {
                 if self.path.is_none() {
                     return Ok(vec![
                         ActionReturn::State(KeymapState::LineInsert),
                         ActionReturn::Notice("Enter file name: ".to_string()),
-                        ActionReturn::Excute(Action {
-                            name: "SaveAs".to_string(),
-                            args: vec![],
-                        }),
+                        ActionReturn::ExcuteLine("SaveAs($line)".to_string()),
                     ]);
                 }
+                let formatted = matches!(action_args.first(), Some(Some(s)) if s == "formatted");
+                // From Autosave: no formatting under the cursor, and no leaving a prompt
+                // A failure is alerted once, then only shown in the status bar
+                if matches!(action_args.first(), Some(Some(s)) if s == "autosave") {
+                    let failed_before = self.autosave_failed;
+                    let saved = self.save(None);
+                    self.autosave_failed = saved.is_err();
+                    return Ok(saved.err().filter(|_| !failed_before).map(ActionReturn::Err).into_iter().collect());
+                }
+                if self.setting.format_on_save && !formatted && self.can_format() {
+                    return Ok(vec![self.format(true)?]);
+                }
                 match self.save(None) {
                     Ok(_) => {
+                        self.autosave_failed = false;
                         return Ok(vec![
                             ActionReturn::Notice("Saved".to_string()),
                             ActionReturn::State(KeymapState::Normal),
@@ -617,19 +2948,16 @@ impl Buffer {
                 }
             }
             "SaveAs" => {
-                if action_args.is_empty() {
-                    return Ok(vec![
-                        ActionReturn::State(KeymapState::LineInsert),
-                        ActionReturn::Notice("Enter file name: ".to_string()),
-                        ActionReturn::Excute(Action {
-                            name: "SaveAs".to_string(),
-                            args: vec![],
-                        }),
-                    ]);
-                } else {
-                    match self.save(Some(action_args[0].as_ref().unwrap())) {
+                if let Some(name) = action_args.first().cloned().flatten() {
+                    let path = Path::new(&name);
+                    if self.text().len_chars() == 0 && !path.exists() {
+                        if let Some(template) = templates::for_path(path) {
+                            self.set_contents(&template);
+                        }
+                    }
+                    match self.save(Some(&name)) {
                         Ok(_) => {
-                            self.path = Some(PathBuf::from(action_args[0].as_ref().unwrap()));
+                            self.named(PathBuf::from(&name));
                             return Ok(vec![
                                 ActionReturn::Notice("Saved".to_string()),
                                 ActionReturn::State(KeymapState::Normal),
@@ -643,9 +2971,234 @@ impl Buffer {
                         }
                     }
                 }
+                return Ok(vec![
+                    ActionReturn::State(KeymapState::LineInsert),
+                    ActionReturn::Notice("Enter file name: ".to_string()),
+                    ActionReturn::ExcuteLine("SaveAs($line)".to_string()),
+                ]);
+            }
+            // SaveAs that deletes the old file afterwards, so a rename leaves no
+            // stale copy; MoveFile(path,answer) once the prompt is answered
+            "MoveFile" => {
+                let Some(name) = action_args.first().cloned().flatten() else {
+                    return Ok(vec![
+                        ActionReturn::State(KeymapState::LineInsert),
+                        ActionReturn::Notice("Move to: ".to_string()),
+                        ActionReturn::ExcuteLine("MoveFile($line)".to_string()),
+                    ]);
+                };
+                let old = match &self.path {
+                    Some(old) if self.remote.is_none() && self.encryption.is_none() => old.clone(),
+                    _ => return Err(anyhow::anyhow!("MoveFile: {} is not a local file, use SaveAs", self.name())),
+                };
+                // `./foo`, an absolute path or a symlink can all name the open file
+                let same = |p: &Path| p.canonicalize().ok();
+                if Path::new(&name) == old || same(Path::new(&name)).is_some_and(|p| Some(p) == same(&old)) {
+                    return Err(anyhow::anyhow!("MoveFile: {} is already {}", self.name(), name));
+                }
+                match action_args.get(1).cloned().flatten() {
+                    None => return Ok(vec![
+                        ActionReturn::State(KeymapState::LineInsert),
+                        ActionReturn::Notice(format!("Save as {} and delete {}? (y/n): ", name, old.display())),
+                        ActionReturn::ExcuteLine(format!("MoveFile({},$line)", name)),
+                    ]),
+                    Some(answer) if !answer.trim().to_lowercase().starts_with('y') => return Ok(vec![
+                        ActionReturn::Notice("Cancelled".to_string()),
+                        ActionReturn::State(KeymapState::Normal),
+                    ]),
+                    Some(_) => (),
+                }
+                if let Err(e) = self.save(Some(&name)) {
+                    return Ok(vec![ActionReturn::Err(e), ActionReturn::State(KeymapState::Normal)]);
+                }
+                self.named(PathBuf::from(&name));
+                // The text is safe under the new name whatever happens to the old file
+                if let Err(e) = std::fs::remove_file(&old) {
+                    return Ok(vec![
+                        ActionReturn::Err(anyhow::anyhow!("Saved {} but could not delete {}: {}", name, old.display(), e)),
+                        ActionReturn::State(KeymapState::Normal),
+                    ]);
+                }
+                return Ok(vec![
+                    ActionReturn::Notice(format!("Moved to {}", name)),
+                    ActionReturn::State(KeymapState::Normal),
+                ]);
             }
             _ => (),
         }
         Ok(vec![])    
     }
-}
\ No newline at end of file
+}
+
+#[async_trait]
+impl TabView for Buffer {
+    fn name(&self) -> String {
+        Buffer::name(self)
+    }
+
+    fn renamed(&self) -> &Title {
+        &self.renamed
+    }
+
+    fn renamed_mut(&mut self) -> &mut Title {
+        &mut self.renamed
+    }
+
+    fn get_cursor(&self) -> Option<Cursor> {
+        Buffer::get_cursor(self)
+    }
+
+    fn render(&self, mut write: &mut dyn Write) -> Result<()> {
+        Buffer::render(self, &mut write)
+    }
+
+    async fn process_action(&mut self, action: &Action) -> Result<Vec<ActionReturn>> {
+        if action.name == "LoadChunk" {
+            self.load_chunks();
+            return Ok(vec![]);
+        }
+        match self.locked_by.clone() {
+            Some(job) => {
+                let reason = format!("{} is read-only while {} runs", self.name(), job);
+                self.locked_action(action, reason).await
+            }
+            // Saving would write only the part read so far
+            None if self.is_loading() && (action.name.starts_with("Save") || ["MoveFile", "Reload"].contains(&action.name.as_str())) => {
+                match self.load_failed() {
+                    true => Err(anyhow::anyhow!("{} could not be read in full and is not saved", self.name())),
+                    false => Err(anyhow::anyhow!("{} is still loading", self.name())),
+                }
+            }
+            None if self.is_loading() => {
+                let reason = match self.load_failed() {
+                    true => format!("{} could not be read in full and is read-only", self.name()),
+                    false => format!("{} is read-only until it is loaded", self.name()),
+                };
+                self.locked_action(action, reason).await
+            }
+            None if self.read_only => {
+                let reason = format!("{} is read-only", TabView::name(self));
+                self.locked_action(action, reason).await
+            }
+            None if !self.cursors.is_empty() && MULTI_CURSOR_ACTIONS.contains(&action.name.as_str()) => {
+                self.at_every_cursor(action).await
+            }
+            None => Buffer::process_action(self, action).await,
+        }
+    }
+
+    fn set_tab_idx(&mut self, i: usize) {
+        self.tab_idx = i;
+    }
+
+    fn resize(&mut self, size: Size) {
+        Buffer::resize(self, size);
+    }
+
+    fn set_pos(&mut self, pos: Pos) {
+        self.pos = pos;
+    }
+
+    fn is_unsaved(&self) -> bool {
+        !self.is_saved() && self.scratch.is_none()
+    }
+
+    // The text of an encrypted buffer is not read out; the announce file is plain text on disk
+    fn cursor_text(&self) -> Option<(usize, String)> {
+        let row = self.cursor_line();
+        if self.encryption.is_some() {
+            return Some((row, "(encrypted)".to_string()));
+        }
+        Some((row, self.text().line(row).to_string().trim_end_matches(['\n', '\r']).to_string()))
+    }
+
+    fn selected(&self) -> usize {
+        self.selection().map(|(start, end)| end - start).unwrap_or(0)
+    }
+
+    fn status(&self) -> Option<String> {
+        let remote = self.remote.as_ref().map(|r| format!("ssh {}", r.destination()))
+            .or(self.encryption.as_ref().map(|e| format!("{:?} encrypted", e.cipher).to_lowercase()));
+        let words = self.setting.word_count.then(|| self.word_status());
+        let found = self.search.as_ref().map(|s| s.status()).filter(|s| !s.is_empty());
+        let locked = match self.is_loading() {
+            true if self.load_failed() => Some("load failed".to_string()),
+            true => Some("loading".to_string()),
+            false => (self.locked_by.is_some() || self.read_only).then(|| "read-only".to_string()),
+        };
+        let autosave = match self.autosave_failed {
+            true => Some("autosave failed".to_string()),
+            false => (self.setting.autosaving() && self.autosaves()).then(|| "autosave pending".to_string()),
+        };
+        let cursors = (!self.cursors.is_empty()).then(|| format!("{} cursors", self.cursors.len() + 1));
+        let text = self.text();
+        let len = text.len_chars();
+        let noeol = (self.path.is_some() && len > 0 && text.char(len - 1) != '\n').then(|| "noeol".to_string());
+        drop(text);
+        let ending = self.line_ending().status();
+        let commit = self.commit_status();
+        let selection = self.selection_status();
+        let parts: Vec<String> = [selection, found, locked, autosave, cursors, commit, noeol, ending, remote, words].into_iter().flatten().collect();
+        (!parts.is_empty()).then(|| parts.join("  "))
+    }
+}
+
+fn load_chunk(buffer: usize) -> Action {
+    crate::actions::targeted(buffer, Action { name: "LoadChunk".to_string(), args: vec![] })
+}
+
+// Reads a large file a chunk at a time for the buffer `buffer`, which takes
+// each one with LoadChunk; stops early when the buffer is closed. On an error
+// the buffer is told too, before the job reports it
+async fn load(path: PathBuf, buffer: usize, loading: Arc<Mutex<Loading>>, reporter: JobReporter) -> Result<Vec<Action>> {
+    let read = read_chunks(&path, buffer, &loading, &reporter).await;
+    if read.is_err() {
+        loading.lock().unwrap().failed = true;
+        reporter.run(vec![load_chunk(buffer)]);
+    }
+    read
+}
+
+async fn read_chunks(path: &Path, buffer: usize, loading: &Arc<Mutex<Loading>>, reporter: &JobReporter) -> Result<Vec<Action>> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let size = file.metadata().await?.len().max(1);
+    let load_chunk = || load_chunk(buffer);
+    let (mut read, mut pending, mut ending) = (0, Vec::new(), None);
+    loop {
+        if Arc::strong_count(loading) == 1 {
+            return Ok(vec![]);
+        }
+        let mut bytes = vec![0; LOAD_CHUNK];
+        let n = file.read(&mut bytes).await?;
+        read += n as u64;
+        pending.extend_from_slice(&bytes[..n]);
+        // A chunk ends on a whole char, and not between the \r and \n of a line break
+        let mut end = match std::str::from_utf8(&pending) {
+            Ok(_) => pending.len(),
+            Err(e) if e.error_len().is_none() && n > 0 => e.valid_up_to(),
+            Err(e) => return Err(anyhow::anyhow!("{}: {}", path.display(), e)),
+        };
+        if n > 0 && end > 0 && pending[end - 1] == b'\r' {
+            end -= 1;
+        }
+        let chunk = String::from_utf8(pending.drain(..end).collect())?;
+        {
+            let mut loading = loading.lock().unwrap();
+            if ending.is_none() {
+                ending = Some(LineEnding::detect(&chunk));
+                loading.ending = ending;
+            }
+            let chunk = match ending {
+                Some(LineEnding::Crlf) => chunk.replace("\r\n", "\n"),
+                _ => chunk,
+            };
+            loading.chunks.push_back(chunk);
+            loading.done = n == 0;
+        }
+        if n == 0 {
+            return Ok(vec![load_chunk()]);
+        }
+        reporter.progress(read as f32 / size as f32, &format!("{} of {} MB", read >> 20, size >> 20));
+        reporter.run(vec![load_chunk()]);
+    }
+}
@@ -0,0 +1,168 @@
+use std::io::Write;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use crossterm::{cursor, queue, style::{Color, Print, Stylize}};
+
+use crate::{actions::ActionReturn, diff::{self, Kind, Row}, Action};
+
+use super::{numlen, Cursor, Pos, Size, TabView, Title};
+
+// Two buffers side by side from DiffBuffers, scrolled together. Changed
+// lines are yellow, removed ones red on the left and added ones green on the
+// right; NextBlock and PreviousBlock go from change to change
+#[derive(Debug)]
+pub struct Diff {
+    pub tab_idx: usize,
+    // Set by RenameTab and shown in the tab bar instead of the name
+    renamed: Title,
+    names: (String, String),
+    left: Vec<String>,
+    right: Vec<String>,
+    rows: Vec<Row>,
+    scroll: usize,
+    selected: usize,
+    pos: Pos,
+    size: Size,
+}
+
+impl Diff {
+    pub fn new(names: (String, String), left: &str, right: &str, pos: Pos, size: Size, tab_idx: usize) -> Self {
+        let lines = |s: &str| s.lines().map(|l| l.replace('\t', "    ")).collect::<Vec<_>>();
+        let (left, right) = (lines(left), lines(right));
+        let rows = diff::align(
+            &left.iter().map(String::as_str).collect::<Vec<_>>(),
+            &right.iter().map(String::as_str).collect::<Vec<_>>(),
+        );
+        Self { tab_idx, renamed: Title::default(), names, left, right, rows, scroll: 0, selected: 0, pos, size }
+    }
+
+    fn changes(&self) -> usize {
+        self.rows.iter().enumerate()
+            .filter(|(i, r)| r.kind != Kind::Same && (*i == 0 || self.rows[i - 1].kind == Kind::Same))
+            .count()
+    }
+
+    // One side of a row: the line number and as much of the line as fits
+    fn side(&self, line: Option<usize>, lines: &[String], width: usize, kind: Kind) -> Vec<crossterm::style::StyledContent<String>> {
+        let numpad = numlen(self.left.len().max(self.right.len())) + 1;
+        let Some(line) = line else {
+            return vec![" ".repeat(width).on(Color::DarkGrey)];
+        };
+        let number = format!("{:>w$} ", line + 1, w = numpad - 1);
+        let text: String = lines[line].chars().take(width.saturating_sub(numpad)).collect();
+        let text = format!("{:<w$}", text, w = width.saturating_sub(numpad));
+        let text = match kind {
+            Kind::Same => text.reset(),
+            Kind::Changed => text.black().on(Color::DarkYellow),
+            Kind::Removed => text.black().on(Color::DarkRed),
+            Kind::Added => text.black().on(Color::DarkGreen),
+        };
+        vec![number.dark_grey(), text]
+    }
+
+    fn adj_scroll(&mut self) {
+        let height = self.size.height as usize;
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if height > 0 && self.selected >= self.scroll + height {
+            self.scroll = self.selected + 1 - height;
+        }
+    }
+
+    // The first row of the next or previous run of changed rows
+    fn step_change(&mut self, forward: bool) {
+        let starts = |i: usize| self.rows[i].kind != Kind::Same && (i == 0 || self.rows[i - 1].kind == Kind::Same);
+        let found = match forward {
+            true => (self.selected + 1..self.rows.len()).find(|i| starts(*i)),
+            false => (0..self.selected).rev().find(|i| starts(*i)),
+        };
+        if let Some(i) = found {
+            self.selected = i;
+            self.adj_scroll();
+        }
+    }
+}
+
+#[async_trait]
+impl TabView for Diff {
+    fn name(&self) -> String {
+        format!("Diff: {} | {}", self.names.0, self.names.1)
+    }
+
+    fn renamed(&self) -> &Title {
+        &self.renamed
+    }
+
+    fn renamed_mut(&mut self) -> &mut Title {
+        &mut self.renamed
+    }
+
+    fn get_cursor(&self) -> Option<Cursor> {
+        let row = self.selected.checked_sub(self.scroll).filter(|r| *r < self.size.height as usize)?;
+        Some(Cursor { row: self.pos.row + row as u16, col: self.pos.col })
+    }
+
+    fn render(&self, mut write: &mut dyn Write) -> Result<()> {
+        let write = &mut write;
+        let width = self.size.width as usize;
+        let half = width.saturating_sub(1) / 2;
+        for i in 0..self.size.height as usize {
+            queue!(write, cursor::MoveTo(self.pos.col, self.pos.row + i as u16))?;
+            let Some(row) = self.rows.get(i + self.scroll) else {
+                queue!(write, Print(" ".repeat(width)))?;
+                continue;
+            };
+            for s in self.side(row.left, &self.left, half, row.kind) {
+                queue!(write, Print(s))?;
+            }
+            queue!(write, Print("│".dark_grey()))?;
+            for s in self.side(row.right, &self.right, width.saturating_sub(half + 1), row.kind) {
+                queue!(write, Print(s))?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn process_action(&mut self, action: &Action) -> Result<Vec<ActionReturn>> {
+        match action.name.as_str() {
+            "CursorUp" => {
+                self.selected = self.selected.saturating_sub(1);
+                self.adj_scroll();
+            }
+            "CursorDown" => {
+                self.selected = (self.selected + 1).min(self.rows.len().saturating_sub(1));
+                self.adj_scroll();
+            }
+            "NextBlock" => self.step_change(true),
+            "PreviousBlock" => self.step_change(false),
+            _ => (),
+        }
+        Ok(vec![])
+    }
+
+    fn set_tab_idx(&mut self, i: usize) {
+        self.tab_idx = i;
+    }
+
+    fn resize(&mut self, size: Size) {
+        self.size = size;
+        self.adj_scroll();
+    }
+
+    fn set_pos(&mut self, pos: Pos) {
+        self.pos = pos;
+    }
+
+    fn status(&self) -> Option<String> {
+        Some(match self.changes() {
+            0 => "No differences".to_string(),
+            1 => "1 change".to_string(),
+            n => format!("{} changes", n),
+        })
+    }
+
+    fn selected(&self) -> usize {
+        self.selected
+    }
+}
@@ -1,4 +1,4 @@
-use std::{io::Write, iter, path::PathBuf};
+use std::{collections::HashMap, io::Write, iter, path::{Path, PathBuf}, process::Stdio};
 
 use anyhow::Result;
 use async_trait::async_trait;
@@ -6,38 +6,88 @@ use crossterm::{cursor, execute, queue, style::{style, Print, Stylize}, terminal
 use log::debug;
 use syntect::highlighting::Theme;
 
-use crate::actions::ActionReturn;
+use crate::{actions::{ActionReturn, Unsaved}, templates, Action, KeymapState};
 
-use super::{Cursor, Pos, Size, Tab};
+use super::{Cursor, Pos, Size, Tab, TabView, Title};
 
 #[derive(Debug)]
 pub struct Directory {
     pub tab_idx: usize,
+    // Set by RenameTab and shown in the tab bar instead of the name
+    renamed: Title,
     path: PathBuf,
     files: Vec<PathBuf>,
     scroll: usize,
     selected: usize,
     pos : Pos,
     size: Size,
+    // (scroll, selected) of directories left, restored when coming back
+    visited: HashMap<PathBuf, (usize, usize)>,
+    // Opening a file closes this tab instead of keeping it next to the file
+    close_on_open: bool,
+}
+
+fn read_files(path: &Path) -> Result<Vec<PathBuf>> {
+    let files = std::fs::read_dir(path)?
+        .map(|res| res.map(|e| e.path()))
+        .collect::<std::result::Result<Vec<_>, std::io::Error>>()?;
+    Ok(files)
 }
 
 impl Directory {
-    pub fn new(path: PathBuf, pos: Pos, size: Size, tab_idx:usize) -> Result<Self> {
-        let files = std::fs::read_dir(&path).unwrap()
-            .map(|res| res.map(|e| e.path()))
-            .collect::<std::result::Result<Vec<_>, std::io::Error>>()?;
+    pub fn new(path: PathBuf, pos: Pos, size: Size, tab_idx:usize, close_on_open: bool) -> Result<Self> {
+        let files = read_files(&path)?;
 
         Ok(Self {
             tab_idx,
+            renamed: Title::default(),
             path,
             files,
             scroll: 0,
             selected: 0,
             pos,
             size,
+            visited: HashMap::new(),
+            close_on_open,
         })
     }
 
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Selects the entry for `file` if it is in this directory
+    pub fn select(&mut self, file: &Path) {
+        let name = file.file_name();
+        if let Some(i) = self.files.iter().position(|f| f.file_name() == name) {
+            self.selected = i;
+            self.adj_scroll();
+        }
+    }
+
+    // Shows `path` in this tab; going up selects the directory that was left
+    fn chdir(&mut self, path: PathBuf) -> Result<()> {
+        let files = read_files(&path)?;
+        let left = std::mem::replace(&mut self.path, path);
+        self.visited.insert(left.clone(), (self.scroll, self.selected));
+        self.files = files;
+        (self.scroll, self.selected) = match self.visited.get(&self.path) {
+            Some(&(scroll, selected)) => (scroll, selected.min(self.files.len())),
+            None => (0, self.files.iter().position(|f| *f == left).unwrap_or(0)),
+        };
+        self.adj_scroll();
+        Ok(())
+    }
+
+    fn adj_scroll(&mut self) {
+        let height = self.size.height as usize;
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + height {
+            self.scroll = self.selected + 1 - height;
+        }
+    }
+
     pub fn render<W>(&self, write: &mut W) -> Result<()> 
         where W: Write
     {
@@ -47,7 +97,7 @@ impl Directory {
             if i >= self.size.height as usize {
                 break;
             }
-            if i == self.selected {
+            if i + self.scroll == self.selected {
                 queue!(
                     write,
                     cursor::MoveTo(self.pos.col, self.pos.row + i as u16),
@@ -66,6 +116,15 @@ impl Directory {
                 Clear(ClearType::UntilNewLine),
             )?;
         }
+        // Rows left over from a longer directory shown before
+        let shown = (self.files.len() + 1).saturating_sub(self.scroll);
+        for i in shown..self.size.height as usize {
+            queue!(
+                write,
+                cursor::MoveTo(self.pos.col, self.pos.row + i as u16),
+                Clear(ClearType::UntilNewLine),
+            )?;
+        }
         Ok(())
     }
     pub fn get_cursor(&self) -> Option<Cursor> {
@@ -74,44 +133,91 @@ impl Directory {
     pub fn name(&self) -> String {
         self.path.to_str().unwrap().to_string()
     }
-    fn get_pos(&self) -> Pos {
-        self.pos
-    }
-    fn get_size(&self) -> Size {
-        self.size
-    }
     pub async fn process_action(&mut self, action: &crate::Action) -> anyhow::Result<Vec<ActionReturn>> {
         let select_len = self.files.len() + 1;
         match action.name.as_str() {
             "CursorUp" => {
                 self.selected = (self.selected + select_len - 1) % select_len;
+                self.adj_scroll();
             }
             "CursorDown" => {
                 self.selected = (self.selected + 1) % select_len;
+                self.adj_scroll();
             }
             "InsertNewline" => {
                 let mut path;
                 if self.selected == select_len - 1 {
-                    path = self.path.parent().unwrap().to_path_buf();
-                    if path.to_str().unwrap() == "" {
-                        path = self.path.canonicalize().unwrap().parent().unwrap().to_path_buf();
+                    // `/` has no parent and `dir` has an empty one; both go by the full path
+                    path = self.path.parent().unwrap_or(Path::new("")).to_path_buf();
+                    if path.as_os_str().is_empty() {
+                        path = self.path.canonicalize()?.parent().unwrap_or(Path::new("/")).to_path_buf();
                     }
                 }
                 else {
                     path = self.files[self.selected].clone();
                 }
                 if path.is_dir() {
-                    return Ok(vec![ActionReturn::NewDir(path), ActionReturn::CloseTab(self.tab_idx)]);
+                    self.chdir(path)?;
+                } else if path.is_file() && self.close_on_open {
+                    return Ok(vec![ActionReturn::NewBuffer(Some(path)), ActionReturn::CloseTab(self.tab_idx, Unsaved::Discard)]);
                 } else if path.is_file() {
                     return Ok(vec![ActionReturn::NewBuffer(Some(path))]);
                 }
             }
+            // NewFile(name) creates the file here, from its template if there is one
+            "NewFile" => {
+                let Some(name) = action.args.first().cloned().flatten() else {
+                    return Ok(vec![
+                        ActionReturn::State(KeymapState::LineInsert),
+                        ActionReturn::Notice("New file: ".to_string()),
+                        ActionReturn::ExcuteLine("NewFile($line)".to_string()),
+                    ]);
+                };
+                let path = self.path.join(&name);
+                if path.exists() {
+                    return Ok(vec![ActionReturn::Notice(format!("{} already exists", path.display()))]);
+                }
+                std::fs::write(&path, templates::for_path(&path).unwrap_or_default())?;
+                self.files = read_files(&self.path)?;
+                self.select(&path);
+                return Ok(vec![ActionReturn::NewBuffer(Some(path)), ActionReturn::State(KeymapState::Normal)]);
+            }
+            "OpenExternal" => {
+                let Some(path) = self.files.get(self.selected).cloned() else {
+                    return Ok(vec![ActionReturn::Notice("OpenExternal: no file selected".to_string())]);
+                };
+                return Ok(vec![open_external(path)]);
+            }
             _ => {}
         }
         Ok(vec![])
     }
 }
 
+// Hands `path` to the program the system opens its kind of file with
+fn open_external(path: PathBuf) -> ActionReturn {
+    let (program, args): (&str, &[&str]) = if cfg!(target_os = "macos") {
+        ("open", &[])
+    } else if cfg!(windows) {
+        ("cmd", &["/C", "start", ""])
+    } else {
+        ("xdg-open", &[])
+    };
+    ActionReturn::Job(format!("open {}", path.display()), Box::new(move |_| Box::pin(async move {
+        let output = tokio::process::Command::new(program)
+            .args(args)
+            .arg(&path)
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .map_err(|e| anyhow::anyhow!("{}: {}", program, e))?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!("{} exited with {}: {}", program, output.status, String::from_utf8_lossy(&output.stderr).trim()));
+        }
+        Ok(vec![])
+    })))
+}
+
 fn get_file_icon(file: &PathBuf) -> String {
     if file.is_dir() {
         return "".to_string();
@@ -165,3 +271,43 @@ fn get_file_icon(file: &PathBuf) -> String {
         _ => "",
     }.to_string()
 }
+
+#[async_trait]
+impl TabView for Directory {
+    fn name(&self) -> String {
+        Directory::name(self)
+    }
+
+    fn renamed(&self) -> &Title {
+        &self.renamed
+    }
+
+    fn renamed_mut(&mut self) -> &mut Title {
+        &mut self.renamed
+    }
+
+    fn get_cursor(&self) -> Option<Cursor> {
+        Directory::get_cursor(self)
+    }
+
+    fn render(&self, mut write: &mut dyn Write) -> Result<()> {
+        Directory::render(self, &mut write)
+    }
+
+    async fn process_action(&mut self, action: &Action) -> Result<Vec<ActionReturn>> {
+        Directory::process_action(self, action).await
+    }
+
+    fn set_tab_idx(&mut self, i: usize) {
+        self.tab_idx = i;
+    }
+
+    fn resize(&mut self, size: Size) {
+        self.size = size;
+        self.adj_scroll();
+    }
+
+    fn set_pos(&mut self, pos: Pos) {
+        self.pos = pos;
+    }
+}
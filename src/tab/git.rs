@@ -0,0 +1,293 @@
+use std::{io::Write, path::{Path, PathBuf}, process::Stdio};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use crossterm::{cursor, queue, style::{style, Print, Stylize}, terminal::{Clear, ClearType}};
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use crate::{actions::ActionReturn, hunk, Action};
+
+use super::{Cursor, Pos, Size, TabView, Title};
+
+#[derive(Debug, Clone)]
+struct Entry {
+    staged: char,
+    unstaged: char,
+    path: String,
+}
+
+impl Entry {
+    fn is_staged(&self) -> bool {
+        self.staged != ' ' && self.staged != '?'
+    }
+}
+
+// Runs git in `root` and returns its stdout; awaited, so the editor keeps
+// drawing while git works
+pub async fn git(root: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git").arg("-C").arg(root).args(args).output().await?;
+    if !output.status.success() {
+        return Err(anyhow!("git {}: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+// Like `git`, with `input` written to its stdin
+async fn git_with_input(root: &Path, args: &[&str], input: &str) -> Result<String> {
+    let mut child = Command::new("git").arg("-C").arg(root).args(args)
+        .stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped())
+        .spawn()?;
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(input.as_bytes()).await?;
+    drop(stdin);
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        return Err(anyhow!("git {}: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+pub async fn repo_root(dir: &Path) -> Result<PathBuf> {
+    Ok(PathBuf::from(git(dir, &["rev-parse", "--show-toplevel"]).await?.trim()))
+}
+
+async fn message_path(root: &Path) -> Result<PathBuf> {
+    let path = PathBuf::from(git(root, &["rev-parse", "--git-path", "COMMIT_EDITMSG"]).await?.trim());
+    Ok(if path.is_absolute() { path } else { root.join(path) })
+}
+
+// Commits the message saved in COMMIT_EDITMSG; run as a job
+pub async fn commit() -> Result<Vec<Action>> {
+    let root = repo_root(&std::env::current_dir()?).await?;
+    let message = message_path(&root).await?;
+    let output = git(&root, &["commit", "--cleanup=strip", "-F", &message.to_string_lossy()]).await?;
+    Ok(vec![Action {
+        name: "ShowPopup".to_string(),
+        args: vec![Some("Commit".to_string()), Some(output)],
+    }])
+}
+
+// Stages the change around `line` (from 1) of the saved file, or unstages
+// the staged one there, leaving the rest of the file's changes alone
+pub async fn stage_hunk(path: &Path, line: usize, unstage: bool) -> Result<String> {
+    let file = path.canonicalize()?;
+    let root = repo_root(file.parent().unwrap_or(Path::new("."))).await?;
+    let rel = file.strip_prefix(&root)?.to_string_lossy().to_string();
+    let mut diff = vec!["diff", "-U0", "--no-color", "--no-ext-diff"];
+    if unstage {
+        diff.push("--cached");
+    }
+    let patch = hunk::patch_at(&git(&root, &[diff, vec!["--", &rel]].concat()).await?, line)
+        .ok_or(anyhow!("No {} change at line {}", if unstage { "staged" } else { "unstaged" }, line))?;
+    let mut apply = vec!["apply", "--cached", "--unidiff-zero"];
+    if unstage {
+        apply.push("--reverse");
+    }
+    git_with_input(&root, &[apply, vec!["-"]].concat(), &patch).await?;
+    Ok(format!("{} the change at line {}", if unstage { "Unstaged" } else { "Staged" }, line))
+}
+
+// Status of the working tree; Space stages/unstages, `c` writes a commit message
+#[derive(Debug)]
+pub struct GitStatus {
+    pub tab_idx: usize,
+    // Set by RenameTab and shown in the tab bar instead of the name
+    renamed: Title,
+    root: PathBuf,
+    branch: String,
+    entries: Vec<Entry>,
+    scroll: usize,
+    selected: usize,
+    pos: Pos,
+    size: Size,
+}
+
+impl GitStatus {
+    pub async fn new(pos: Pos, size: Size, tab_idx: usize) -> Result<Self> {
+        let root = repo_root(&std::env::current_dir()?).await?;
+        let mut status = Self {
+            tab_idx,
+            renamed: Title::default(),
+            root,
+            branch: String::new(),
+            entries: Vec::new(),
+            scroll: 0,
+            selected: 0,
+            pos,
+            size,
+        };
+        status.refresh().await?;
+        Ok(status)
+    }
+
+    async fn refresh(&mut self) -> Result<()> {
+        self.branch = git(&self.root, &["branch", "--show-current"]).await?.trim().to_string();
+        self.entries = git(&self.root, &["status", "--porcelain=v1", "-uall"]).await?
+            .lines()
+            .filter(|l| l.len() > 3)
+            .map(|l| {
+                let mut chars = l.chars();
+                let staged = chars.next().unwrap();
+                let unstaged = chars.next().unwrap();
+                let path = &l[3..];
+                let path = path.split(" -> ").last().unwrap_or(path);
+                Entry { staged, unstaged, path: path.trim_matches('"').to_string() }
+            })
+            .collect();
+        self.selected = self.selected.min(self.entries.len().saturating_sub(1));
+        Ok(())
+    }
+
+    async fn toggle(&mut self) -> Result<()> {
+        let entry = match self.entries.get(self.selected) {
+            Some(e) => e.clone(),
+            None => return Ok(()),
+        };
+        if entry.is_staged() {
+            // reset needs HEAD; before the first commit drop the file from the index instead
+            if git(&self.root, &["reset", "-q", "--", &entry.path]).await.is_err() {
+                git(&self.root, &["rm", "-q", "--cached", "--", &entry.path]).await?;
+            }
+        } else {
+            git(&self.root, &["add", "--", &entry.path]).await?;
+        }
+        self.refresh().await
+    }
+
+    async fn write_message(&self) -> Result<PathBuf> {
+        let path = message_path(&self.root).await?;
+        let mut template = String::from("\n# Write the commit message above, save, then run GitCommit.\n# Staged:\n");
+        for entry in self.entries.iter().filter(|e| e.is_staged()) {
+            template.push_str(&format!("#\t{} {}\n", entry.staged, entry.path));
+        }
+        std::fs::write(&path, template)?;
+        Ok(path)
+    }
+
+    pub fn render<W>(&self, write: &mut W) -> Result<()>
+        where W: Write
+    {
+        queue!(
+            write,
+            cursor::MoveTo(self.pos.col, self.pos.row),
+            Print(format!("On branch {}", self.branch).bold()),
+            Clear(ClearType::UntilNewLine),
+        )?;
+        for i in 1..self.size.height as usize {
+            queue!(write, cursor::MoveTo(self.pos.col, self.pos.row + i as u16))?;
+            let idx = i - 1 + self.scroll;
+            if let Some(entry) = self.entries.get(idx) {
+                let path: String = entry.path.chars().take((self.size.width as usize).saturating_sub(4)).collect();
+                queue!(
+                    write,
+                    Print(style(entry.staged).green()),
+                    Print(style(entry.unstaged).red()),
+                    Print(" "),
+                )?;
+                if idx == self.selected {
+                    queue!(write, Print(style(path).reverse()))?;
+                } else {
+                    queue!(write, Print(path))?;
+                }
+            }
+            queue!(write, Clear(ClearType::UntilNewLine))?;
+        }
+        Ok(())
+    }
+
+    pub fn get_cursor(&self) -> Option<Cursor> {
+        None
+    }
+
+    pub fn name(&self) -> String {
+        format!("Git: {}", self.branch)
+    }
+
+    fn adj_scroll(&mut self) {
+        let height = (self.size.height as usize).saturating_sub(1);
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if height > 0 && self.selected >= self.scroll + height {
+            self.scroll = self.selected + 1 - height;
+        }
+    }
+
+    pub async fn process_action(&mut self, action: &Action) -> Result<Vec<ActionReturn>> {
+        let len = self.entries.len().max(1);
+        match action.name.as_str() {
+            "CursorUp" => {
+                self.selected = (self.selected + len - 1) % len;
+                self.adj_scroll();
+            }
+            "CursorDown" => {
+                self.selected = (self.selected + 1) % len;
+                self.adj_scroll();
+            }
+            "InsertSpace" => {
+                self.toggle().await?;
+            }
+            "Insert" => {
+                match action.args[0].as_deref() {
+                    Some("c") | Some("C") => {
+                        let path = self.write_message().await?;
+                        return Ok(vec![ActionReturn::NewBuffer(Some(path))]);
+                    }
+                    Some("r") | Some("R") => {
+                        self.refresh().await?;
+                    }
+                    _ => {}
+                }
+            }
+            "InsertNewline" => {
+                if let Some(entry) = self.entries.get(self.selected) {
+                    let path = self.root.join(&entry.path);
+                    if path.is_file() {
+                        return Ok(vec![ActionReturn::NewBuffer(Some(path))]);
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(vec![])
+    }
+}
+
+#[async_trait]
+impl TabView for GitStatus {
+    fn name(&self) -> String {
+        GitStatus::name(self)
+    }
+
+    fn renamed(&self) -> &Title {
+        &self.renamed
+    }
+
+    fn renamed_mut(&mut self) -> &mut Title {
+        &mut self.renamed
+    }
+
+    fn get_cursor(&self) -> Option<Cursor> {
+        GitStatus::get_cursor(self)
+    }
+
+    fn render(&self, mut write: &mut dyn Write) -> Result<()> {
+        GitStatus::render(self, &mut write)
+    }
+
+    async fn process_action(&mut self, action: &Action) -> Result<Vec<ActionReturn>> {
+        GitStatus::process_action(self, action).await
+    }
+
+    fn set_tab_idx(&mut self, i: usize) {
+        self.tab_idx = i;
+    }
+
+    fn resize(&mut self, size: Size) {
+        self.size = size;
+        self.adj_scroll();
+    }
+
+    fn set_pos(&mut self, pos: Pos) {
+        self.pos = pos;
+    }
+}
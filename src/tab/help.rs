@@ -0,0 +1,595 @@
+use std::{collections::HashMap, io::Write};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use crossterm::{cursor, queue, style::{style, Print, Stylize}, terminal::{Clear, ClearType}};
+use regex::Regex;
+use serde_json::Value;
+
+use crate::{actions::ActionReturn, key::Keymap, messages, workspace::Workspace, Action, KeymapState};
+
+use super::{Cursor, Pos, Size, TabView, Title};
+
+const MODES: [KeymapState; 5] = [KeymapState::Normal, KeymapState::Insert, KeymapState::Cmd, KeymapState::Find, KeymapState::LineInsert];
+
+const INDEX: &str = "\
+Zutto help
+
+Move with Up/Down, Tab jumps to the next line with a link, Enter follows
+the first link on the line and Backspace goes back.
+
+  |modes|        Normal, Insert, Cmd, Find and LineInsert
+  |commandline|  what can be typed after LineMode
+  |actions|      every action and the keys bound to it
+  |settings|     settings/default.json and their current values
+  |keymap|       writing keymaps in settings/keymap.json or .zutto.json
+
+Any action name is a topic too, e.g. |Save| or |Open|.";
+
+const MODES_TEXT: &str = "\
+Modes
+
+Normal      Typing inserts text; Ctrl and Alt chords move and edit.
+            |CmdMode| leaves it, |NormalMode| comes back. With the modal
+            setting letters move and edit instead, e.g. h j k l and x,
+            and i, a or o go to Insert.
+Insert      Typing inserts text as Normal does without the modal setting;
+            Esc goes back to Normal.
+Cmd         Single keys run commands, e.g. s saves and o opens a file.
+Find        Typed text searches the buffer, jumping to the nearest match.
+LineInsert  The bottom line takes text: a prompt's answer or a
+            |commandline|. Enter runs it, Alt+Enter starts another
+            line of it, |NormalMode| leaves it.
+
+The current mode is shown at the left of the status bar. See |actions|
+for what each mode binds.";
+
+const COMMANDLINE: &str = "\
+Command line
+
+|LineMode| opens the bottom line. What is typed there runs as an action:
+
+  Name                  e.g. Quit or Format
+  Name(arg,arg)         e.g. GotoTab(3) or Open(src/main.rs)
+  = expr                shows the value of expr, see |Calc|
+  => expr               inserts the value of expr, see |CalcInsert|
+  help [topic]          opens this help, see |Help|
+  messages              lists past notices and alerts, see |Messages|
+  10,20 Name            runs Name on lines 10 to 20, see below
+  %s/old/new/           |Replace| old with new in the whole buffer
+
+A line range selects whole lines before |SortLines|, |Filter|, |Replace|,
+|Indent|, |Dedent| and the text transforms run: `12` is one line, `.` the
+cursor line, `$` the last line and `%` all of them, as in
+`.,$ Filter(sort -u)`. A bare `s/old/new/` works on the cursor line.
+
+The line keeps a history: |LinePrevious| and |LineNext| step through it and
+|LineSearchHistory| finds older lines holding what was typed. The shell
+tab's prompt edits the same way with the Normal mode keys.";
+
+const KEYMAP: &str = r#"Keymaps
+
+settings/keymap.json maps each mode to actions and the keys that run them;
+the `keymap` object of a project's .zutto.json is merged over it, so it
+only needs the bindings that change:
+
+  {"keymap": {"Cmd": {"Format": [["Ctrl", {"Char": "F"}]]}}}
+
+Each action takes a list of alternatives, each a list of keys pressed
+together. Keys are Ctrl, Alt, Shift, {"Char": "X"}, {"F": 5}, Enter, Tab,
+BackTab, Space, Comma, Backspace, Delete, Esc, Up, Down, Left, Right, Home,
+End, PageUp, PageDown and Insert. Chars are written upper case; Shift
+with a letter is a separate binding.
+
+Arguments may use:
+  $char   the typed char, with CharAny as the key
+  $line   the text typed after a LineInsert prompt
+  $idx    the current tab's index
+
+The Insert and Modal objects only hold what differs from Normal: Insert
+mode is Normal with Insert merged over it, and with the modal setting
+Normal has Modal merged over it. An action bound to [] is unbound there.
+
+See |modes| for the mode names and |actions| for the action names."#;
+
+// Shown on the action pages; bound actions without an entry still get a page
+const ACTIONS: &[(&str, &str)] = &[
+    ("Insert", "Inserts the typed char. Typing with a selection replaces it, as pasting does."),
+    ("InsertUpper", "Inserts the typed char with Shift held."),
+    ("InsertSpace", "Inserts a space."),
+    ("InsertTab", "Inserts a tab, or spaces when tab_type is Space, see |settings|."),
+    ("InsertNewline", "Breaks the line at the cursor, keeping the indent."),
+    ("InsertNewlineBelow", "Opens a new line below the current one."),
+    ("InsertNewlineAbove", "Opens a new line above the current one."),
+    ("InsertComma", "Inserts a comma."),
+    ("Delete", "Deletes the char before the cursor."),
+    ("CursorForward", "Moves the cursor one char right."),
+    ("CursorBackward", "Moves the cursor one char left."),
+    ("CursorUp", "Moves the cursor one line up."),
+    ("CursorDown", "Moves the cursor one line down."),
+    ("CursorStart", "Moves the cursor to the start of the line."),
+    ("CursorEnd", "Moves the cursor to the end of the line."),
+    ("CursorForwardWord", "Moves the cursor to the next word."),
+    ("CursorBackwardWord", "Moves the cursor to the previous word."),
+    ("SelectStart", "Starts a selection at the cursor, or clears it."),
+    ("AddCursorBelow", "Adds a cursor on the line below the lowest one, at the same column. Typing, deleting and moving then happen at every cursor."),
+    ("AddCursorAtNextMatch", "Adds a cursor at the next place the word under the cursor appears as a whole word, going around the end of the file."),
+    ("ClearCursors", "Leaves only the main cursor; a mouse click does too."),
+    ("ScrollLeft", "Scrolls the view left by half its width without moving the cursor. Not used with soft wrap."),
+    ("ScrollRight", "Scrolls the view right by half its width, up to the end of the longest line on screen, without moving the cursor."),
+    ("MoveLineUp", "Swaps the cursor line, or the lines of the selection, with the line above; the cursor stays on the moved text."),
+    ("MoveLineDown", "Swaps the cursor line, or the lines of the selection, with the line below."),
+    ("Indent", "Shifts the cursor line, or the lines of the selection, one tab stop right: a tab, or tab_size spaces when tab_type is Space. Blank lines are left alone. Takes a line range, e.g. `10,20 Indent`."),
+    ("Dedent", "Shifts the cursor line, or the lines of the selection, one tab stop left, taking a leading tab or up to tab_size spaces from each. Takes a line range."),
+    ("DeleteWordForward", "Deletes from the cursor to where CursorForwardWord would go."),
+    ("DeleteWordBackward", "Deletes from where CursorBackwardWord would go to the cursor."),
+    ("DeleteToEnd", "Deletes from the cursor to the end of the line, or the line break when the cursor is already at the end."),
+    ("DeleteLine", "Deletes the cursor line, or the lines of the selection, with their line break."),
+    ("DuplicateLine", "Puts a copy of the cursor line, or of the lines of the selection, below it and moves the cursor to the copy."),
+    ("Copy", "Copies the selection, or the current line, to the yank history."),
+    ("Cut", "Cuts the selection, or the current line, to the yank history."),
+    ("RepeatLast", "Repeats the last run of edits at the cursor, n times with RepeatLast(n)."),
+    ("Paste", "Pastes the newest entry of the yank history, replacing the selection if there is one."),
+    ("PasteFromHistory", "Picks an older entry of the yank history to paste; Up and Down choose, Enter pastes."),
+    ("CmdMode", "Switches to Cmd mode, see |modes|."),
+    ("NormalMode", "Switches to Normal mode, see |modes|."),
+    ("Autosave", "Saves every modified file without formatting it, skipping remote and encrypted files. Sent on its own with the autosave settings, see |settings|."),
+    ("InsertMode", "Switches to Insert mode after running the actions given, if any, in order, e.g. InsertMode(CursorEnd) or InsertMode(InsertNewlineBelow,CursorDown). See |modes|."),
+    ("FindMode", "Switches to Find mode, see |modes|."),
+    ("FindAccept", "Leaves Find mode with the cursor on the match."),
+    ("FindNext", "Moves to the next match, going around to the first after the last."),
+    ("FindPrevious", "Moves to the previous match, going around to the last before the first."),
+    ("FindToggle", "FindToggle(case) toggles matching case, FindToggle(word) whole words only and FindToggle(regex) reading the query as a regular expression; the status bar shows the flags."),
+    ("FindCancel", "Leaves Find mode with the cursor back where the search started."),
+    ("LineMode", "Opens the |commandline|."),
+    ("NextTab", "Shows the next tab."),
+    ("PrevTab", "Shows the previous tab."),
+    ("LastTab", "Shows the previously shown tab."),
+    ("GotoTab", "Shows the tab with the given number, counted from 1."),
+    ("DiffBuffers", "DiffBuffers(a,b) opens a tab showing buffer tabs a and b side by side, scrolled together, with changed lines yellow, removed ones red and added ones green. DiffBuffers(b) compares the focused tab with b and without arguments it asks. NextBlock and PreviousBlock jump between changes."),
+    ("MoveTabLeft", "Moves the current tab one place left."),
+    ("MoveTabRight", "Moves the current tab one place right."),
+    ("Split", "Split(right) shows another tab beside the focused one and Split(down) below it: the tab focused last, or the first one not on screen. Switching tabs changes what the focused pane shows."),
+    ("NextPane", "Focuses the next pane of a split; clicking a pane does too."),
+    ("PrevPane", "Focuses the previous pane of a split."),
+    ("ClosePane", "Takes the focused pane out of the split; its tab stays open."),
+    ("Shell", "Opens a shell tab."),
+    ("RunCommand", "RunCommand(cmd) runs a command once, without a terminal, and shows what it prints in a read-only scratch tab. The tab title ends with the exit code when it finishes; CancelJob stops it."),
+    ("CommandOutput", "Adds output to the tab of a RunCommand; sent by the running command."),
+    ("Replace", "Replace(pattern,replacement) from the command line goes through the matches in the focused buffer, following the Find flags, asking y to replace one, n to skip it, a to replace the rest and q to stop. In regex mode $1 in the replacement is the first group. With a selection only its matches are asked about."),
+    ("RenameTab", "RenameTab(name) shows name in the tab bar for the focused tab, e.g. RenameTab(server); RenameTab() goes back to its own name."),
+    ("CloseTab", "Closes the tab with the given index. An unsaved buffer asks first: s saves it, d discards the changes and anything else keeps the tab."),
+    ("CloseOtherTabs", "Closes every tab but the current one, asking first about unsaved buffers."),
+    ("CloseTabsRight", "Closes the tabs right of the current one, asking first about unsaved buffers."),
+    ("NewView", "Opens another view of the current buffer in a new tab."),
+    ("Hover", "Shows the language server's hover text for the cursor."),
+    ("GotoDefinition", "Jumps to the definition of the symbol under the cursor."),
+    ("FindReferences", "Lists the references to the symbol under the cursor."),
+    ("WorkspaceSymbols", "Asks the language server for the symbols of the whole project matching a query, e.g. WorkspaceSymbols(parse), and lists them best match first; the query's chars only need to appear in order. A single match is opened right away. Asks for the query without arguments."),
+    ("JumpBack", "Returns to where the last jump started."),
+    ("GitStatus", "Opens the git status tab; Space stages or unstages a file."),
+    ("GitCommit", "Commits the staged files with the message written from the git status tab."),
+    ("StageHunk", "Stages only the change at the cursor line of the saved file, leaving its other changes unstaged."),
+    ("UnstageHunk", "Unstages the staged change at the cursor line."),
+    ("Quit", "Quits the editor, asking first about unsaved buffers: s saves them all, d discards them and anything else goes back to editing. A buffer without a file asks for a name when saved. Killed by SIGTERM or SIGHUP instead, it writes unsaved buffers to the project's .zutto/recover first."),
+    ("SaveQuit", "Saves the focused buffer and quits, for when the editor was opened by git or another program waiting on it. Editing COMMIT_EDITMSG or MERGE_MSG also shows the summary line's length against 50 and body lines' against 72 in the status bar, with a guide at that column and red past it."),
+    ("RestoreSession", "Asks whether to reopen the tabs, cursors and command history the last run left in .zutto/session.json. The session is saved every 30 seconds and removed on quit, so it only remains after a crash; asked at startup when no files are given."),
+    ("Save", "Saves the buffer; an unnamed buffer asks for a file name."),
+    ("MoveFile", "MoveFile(path) saves the buffer under a new file name like SaveAs, then deletes the old file once you answer y, so renaming leaves no stale copy behind."),
+    ("SaveAs", "Saves the buffer under a new file name. An empty buffer saved as a new file starts from settings/templates/template.<ext> when there is one; {name}, {date} and {time} in it are filled in."),
+    ("OpenExternal", "In a directory tab, opens the selected entry with the program the system uses for it, through xdg-open, open or start, e.g. for images and PDFs."),
+    ("NewFile", "In a directory tab, creates a file there from its template, like SaveAs, and opens it."),
+    ("Open", "Opens a file, directory or ssh:// url in a new tab; a pasted path:line:col, stack trace or log line opens at that position. A file over 64 MB shows at once while a job reads the rest of it in; until then it is read-only and the status bar says loading."),
+    ("CancelJob", "Cancels the running background job."),
+    ("Format", "Runs the formatter configured for the file type, see |settings|."),
+    ("Filter", "Pipes the selection, or the whole buffer, through a shell command."),
+    ("SortLines", "Sorts the lines of the selection, or of the whole buffer. Takes a line range, e.g. 10,20 SortLines."),
+    ("Build", "Runs build_command and lists the errors, see |settings|. Open files are read-only while it runs and reload afterwards."),
+    ("ToggleProfiler", "Shows or hides frame and action timings."),
+    ("ToggleScreenReader", "Turns announcing changes to announce_path on or off, see |settings|."),
+    ("WordCount", "Shows the word and char count of the buffer or selection."),
+    ("ProseMode", "Toggles soft wrapping and breaking lines at textwidth."),
+    ("ToggleAbbreviations", "Turns expanding settings/abbreviations.json on or off for this buffer."),
+    ("SpellCheck", "Highlights words spell_command reports as misspelled."),
+    ("DescribeChar", "Shows the codepoint, UTF-8 bytes, name and width of the char under the cursor."),
+    ("GotoMatch", "Jumps to the bracket matching the one at the cursor, or right before it, among ()[]{}. Brackets in strings and comments are skipped; the pair is highlighted while the cursor is on one."),
+    ("Goto", "Moves the cursor to a line, and a column when given, both counted from 1, and scrolls it to the middle of the view, e.g. Goto(120) or Goto(120,8). Asks for them without arguments. A line past the end of the buffer is an error."),
+    ("InsertUnicode", "Inserts a char given as U+XXXX or as a two char digraph."),
+    ("ReadCommand", "Inserts the output of a shell command at the cursor."),
+    ("Calc", "Shows the value of an arithmetic expression."),
+    ("CalcInsert", "Inserts the value of an arithmetic expression at the cursor."),
+    ("SetLocal", "SetLocal(key,value) overrides a setting for this tab only, e.g. SetLocal(wrap,true); without a value it shows the current one. Kept for the file in the project's .zutto/views.json."),
+    ("WriteSettings", "Writes what SetLocal set in this tab, or WriteSettings(key) one of them, into the settings of the project's .zutto.json, which is created if needed. The rest of the file is kept as it was written. New tabs use the settings at once."),
+    ("SetLineEnding", "SetLineEnding(lf) or SetLineEnding(crlf) sets the line endings the file is saved with. A file read with CRLF or mixed endings shows it in the status bar and is saved with the endings it had until then."),
+    ("NewScratch", "NewScratch(name) opens a throwaway buffer that never asks to be saved; output like a failed Build goes to one too."),
+    ("BookmarkToggle", "Bookmarks the cursor line, marked with * in the line numbers, or removes its bookmark. Bookmarks are kept for the file in the project's .zutto/views.json."),
+    ("BookmarkNext", "Moves to the next bookmarked line, wrapping around."),
+    ("BookmarkPrev", "Moves to the previous bookmarked line, wrapping around."),
+    ("BookmarkList", "Picks from the bookmarks of every open buffer."),
+    ("RevealInDirectory", "Shows the buffer's file selected in a directory tab of its folder."),
+    ("Messages", "Lists the notices and alerts shown so far, also `messages` on the |commandline|."),
+    ("Help", "Opens help on a topic, the |index| without one."),
+    ("LineExecute", "Runs the typed line or answers the prompt."),
+    ("LineBreak", "Starts another line of the answer, e.g. for a replacement of several lines. The status bar grows upward to show them all; |LineExecute| still sends the whole text."),
+    ("LinePrevious", "Shows the previous line of the |commandline| history."),
+    ("LineNext", "Shows the next line of the |commandline| history."),
+    ("LineCursorForwardWord", "Moves to the end of the next word of the line."),
+    ("LineCursorBackwardWord", "Moves to the start of the previous word of the line."),
+    ("LineKillEnd", "Cuts the line from the cursor to the end into the yank history, for |LineYank| or |Paste| in a buffer."),
+    ("LineYank", "Inserts the newest entry of the yank history, copied in any tab or cut by |LineKillEnd|."),
+    ("LineSearchHistory", "Loads the newest earlier line holding what was typed; again for an older one."),
+    ("KillLineEnd", "In a shell tab, cuts the prompt from the cursor to the end into the yank history; Paste there inserts its newest entry."),
+    ("SearchHistory", "In a shell tab, loads the newest earlier command holding what was typed."),
+];
+
+const SETTINGS: &[(&str, &str)] = &[
+    ("line_numbers", "Show line numbers."),
+    ("tab_size", "Width of a tab in columns."),
+    ("tab_type", "Tab inserts a Tab char or Space chars."),
+    ("show_spaces", "Draw spaces and tabs visibly."),
+    ("wrap", "Soft wrap lines longer than the window instead of scrolling sideways."),
+    ("theme", "Syntax theme, e.g. base16-ocean.dark or high-contrast."),
+    ("lsp_servers", "Language server command per file extension."),
+    ("formatters", "Formatter command per file extension; {file} is the file path, already quoted for the shell."),
+    ("format_on_save", "Run the formatter before saving."),
+    ("build_command", "Command run by |Build|."),
+    ("word_count", "Show the word count in the status bar."),
+    ("color_swatches", "Draw a swatch after color values like #RRGGBB; off by default."),
+    ("textwidth", "Column |ProseMode| breaks lines at, 0 to not break them."),
+    ("spell_command", "Reads text on stdin and prints misspelled words, used by |SpellCheck|."),
+    ("screen_reader", "Start with |ToggleScreenReader| on."),
+    ("announce_path", "File screen reader announcements are appended to."),
+    ("notice_timeout", "Seconds a notice or alert stays in the status bar, 0 to keep it until the next one."),
+    ("close_directory_on_open", "Opening a file from a directory tab closes the tab; otherwise it stays open where it was."),
+    ("final_newline", "Always adds a line break at the end of a file on save, Never removes the ones there and Preserve leaves the file as it is. The status bar shows noeol while a file does not end with one."),
+    ("word_chars", "Chars besides letters and digits that count as part of a word for CursorForwardWord, CursorBackwardWord and double click, per file extension with \"*\" for the rest, e.g. {\"*\": \"_\", \"css\": \"_-\"}. Other punctuation like . and :: stops a word motion."),
+    ("clip_markers", "Two chars, like \"<>\", drawn over the first and last column of a line that goes on past the left or right edge of the view when lines are not soft wrapped. Empty to draw none."),
+    ("autosave", "Seconds between saves of every modified file, 0 for none. Remote files, encrypted ones and scratch buffers are left alone. The status bar shows autosave pending until a modified file is saved, and autosave failed when writing it did not work."),
+    ("autosave_on_focus_lost", "Save every modified file, as autosave does, when the terminal loses focus."),
+    ("backup", "Before a save writes over a file, copy what it held to <file>~, or into backup_dir. A failed or wrong save can then be undone from the copy; if the copy cannot be made, the file is not saved."),
+    ("backup_dir", "Directory backups go to when not empty, created if needed. Each backup is named by the file's whole path with % for the slashes, so files of the same name do not clash."),
+    ("persist_settings", "SetLocal also writes the setting into the project's .zutto.json, as WriteSettings does, so it holds for every file of the project from then on."),
+    ("modal", "Modal editing: keys in Normal mode move and edit, typing happens in Insert mode. The keys come from the Modal object of the keymap, see |keymap| and |modes|."),
+    ("abbreviations", "File extensions, or \"*\" for the rest, whose abbreviations expand at a word boundary."),
+    ("smart_paste", "Re-indent pasted lines to the indent at the cursor."),
+    ("detect_indent", "Follow the tabs or spaces an opened file already uses instead of tab_type and tab_size."),
+];
+
+// Keymap entries like `GotoTab(1)` and `Open($line)` are documented by name
+fn base_name(action: &str) -> &str {
+    action.split('(').next().unwrap_or(action)
+}
+
+fn messages_page() -> Vec<String> {
+    let mut out = vec!["Messages".to_string(), String::new()];
+    let lines = messages::lines();
+    if lines.is_empty() {
+        out.push("No messages yet.".to_string());
+    }
+    out.extend(lines);
+    out
+}
+
+// Keymaps and settings the pages are generated from
+#[derive(Debug)]
+struct Context {
+    keymaps: HashMap<KeymapState, Keymap>,
+    setting: Value,
+}
+
+impl Context {
+    fn load() -> Result<Self> {
+        let workspace = Workspace::detect(None)?;
+        let setting = workspace.setting()?;
+        Ok(Self {
+            keymaps: workspace.keymaps(setting.modal)?,
+            setting: serde_json::to_value(setting)?,
+        })
+    }
+
+    // Key combinations per mode for `name`, e.g. ("Cmd", "S")
+    fn keys(&self, name: &str) -> Vec<(&'static str, String)> {
+        let mut keys = Vec::new();
+        for mode in MODES {
+            let Some(keymap) = self.keymaps.get(&mode) else { continue };
+            for (action, combos) in keymap.bindings() {
+                if base_name(&action) == name {
+                    keys.push((mode.into(), format!("{:<20} {}", action, combos.join(", "))));
+                }
+            }
+        }
+        keys
+    }
+
+    fn page(&self, topic: &str) -> Result<Vec<String>> {
+        let lines = |s: &str| s.lines().map(str::to_string).collect::<Vec<_>>();
+        match topic.trim().to_lowercase().as_str() {
+            "" | "index" | "help" => Ok(lines(INDEX)),
+            "modes" => Ok(lines(MODES_TEXT)),
+            "commandline" => Ok(lines(COMMANDLINE)),
+            "keymap" => Ok(lines(KEYMAP)),
+            "actions" => Ok(self.actions_page()),
+            "settings" => Ok(self.settings_page()),
+            "messages" => Ok(messages_page()),
+            _ => self.action_page(topic.trim()),
+        }
+    }
+
+    fn actions_page(&self) -> Vec<String> {
+        let mut out = vec!["Actions".to_string(), String::new()];
+        let mut bound = Vec::new();
+        for mode in MODES {
+            let Some(keymap) = self.keymaps.get(&mode) else { continue };
+            out.push(format!("{} mode", <&'static str>::from(mode)));
+            for (action, combos) in keymap.bindings() {
+                let name = base_name(&action).to_string();
+                let link = format!("|{}|{}", name, &action[name.len()..]);
+                out.push(format!("  {:<26} {}", link, combos.join(", ")));
+                bound.push(name);
+            }
+            out.push(String::new());
+        }
+        out.push("Not bound to a key, run from the |commandline|".to_string());
+        for (name, _) in ACTIONS.iter().filter(|(name, _)| !bound.iter().any(|b| b == name)) {
+            out.push(format!("  |{}|", name));
+        }
+        out
+    }
+
+    fn settings_page(&self) -> Vec<String> {
+        let mut out = vec![
+            "Settings".to_string(),
+            String::new(),
+            "settings/default.json, with the `settings` of .zutto.json merged over it.".to_string(),
+            "Current values:".to_string(),
+            String::new(),
+        ];
+        let Value::Object(setting) = &self.setting else { return out };
+        for (name, value) in setting {
+            out.push(format!("{} = {}", name, value));
+            if let Some((_, description)) = SETTINGS.iter().find(|(n, _)| n == name) {
+                out.push(format!("    {}", description));
+            }
+        }
+        out
+    }
+
+    fn action_page(&self, topic: &str) -> Result<Vec<String>> {
+        let described = ACTIONS.iter().find(|(name, _)| name.eq_ignore_ascii_case(topic));
+        let name = match described {
+            Some((name, _)) => name.to_string(),
+            None => self.keymaps.values()
+                .flat_map(|k| k.bindings())
+                .map(|(action, _)| base_name(&action).to_string())
+                .find(|name| name.eq_ignore_ascii_case(topic))
+                .ok_or(anyhow!("No help for {}", topic))?,
+        };
+        let mut out = vec![name.clone(), String::new()];
+        out.push(described.map(|(_, d)| d.to_string()).unwrap_or_else(|| "No description yet.".to_string()));
+        out.push(String::new());
+        let keys = self.keys(&name);
+        if keys.is_empty() {
+            out.push(format!("Not bound to a key; run it from the |commandline| as {}.", name));
+        } else {
+            out.push("Keys:".to_string());
+            for (mode, binding) in keys {
+                out.push(format!("  {:<11} {}", mode, binding));
+            }
+        }
+        out.push(String::new());
+        out.push("See |actions| for the other actions, |keymap| to bind it.".to_string());
+        Ok(out)
+    }
+}
+
+fn links(line: &str) -> Vec<(usize, usize)> {
+    Regex::new(r"\|([A-Za-z][\w-]*)\|").unwrap()
+        .find_iter(line)
+        .map(|m| (m.start(), m.end()))
+        .collect()
+}
+
+// Read-only help pages; `|topic|` marks a link to another page
+#[derive(Debug)]
+pub struct Help {
+    pub tab_idx: usize,
+    // Set by RenameTab and shown in the tab bar instead of the name
+    renamed: Title,
+    context: Context,
+    topic: String,
+    lines: Vec<String>,
+    // Topics and selected lines to go back to
+    history: Vec<(String, usize)>,
+    scroll: usize,
+    selected: usize,
+    pos: Pos,
+    size: Size,
+}
+
+impl Help {
+    pub fn new(topic: &str, pos: Pos, size: Size, tab_idx: usize) -> Result<Self> {
+        let context = Context::load()?;
+        let lines = context.page(topic)?;
+        Ok(Self {
+            tab_idx,
+            renamed: Title::default(),
+            context,
+            topic: topic.trim().to_string(),
+            lines,
+            history: Vec::new(),
+            scroll: 0,
+            selected: 0,
+            pos,
+            size,
+        })
+    }
+
+    fn show(&mut self, topic: &str, selected: usize) -> Result<()> {
+        self.lines = self.context.page(topic)?;
+        self.topic = topic.to_string();
+        self.selected = selected.min(self.lines.len().saturating_sub(1));
+        self.scroll = 0;
+        self.adj_scroll();
+        Ok(())
+    }
+
+    fn follow(&mut self) -> Result<()> {
+        let Some(line) = self.lines.get(self.selected) else { return Ok(()) };
+        let Some((start, end)) = links(line).first().copied() else { return Ok(()) };
+        let topic = line[start + 1..end - 1].to_string();
+        let back = (self.topic.clone(), self.selected);
+        self.show(&topic, 0)?;
+        self.history.push(back);
+        Ok(())
+    }
+
+    fn next_link(&mut self) {
+        let len = self.lines.len();
+        if let Some(i) = (1..=len).map(|d| (self.selected + d) % len).find(|i| !links(&self.lines[*i]).is_empty()) {
+            self.selected = i;
+            self.adj_scroll();
+        }
+    }
+
+    fn adj_scroll(&mut self) {
+        let height = self.size.height as usize;
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if height > 0 && self.selected >= self.scroll + height {
+            self.scroll = self.selected + 1 - height;
+        }
+    }
+
+    pub fn render<W>(&self, write: &mut W) -> Result<()>
+        where W: Write
+    {
+        let width = self.size.width as usize;
+        for i in 0..self.size.height as usize {
+            queue!(write, cursor::MoveTo(self.pos.col, self.pos.row + i as u16))?;
+            if let Some(line) = self.lines.get(i + self.scroll) {
+                let selected = i + self.scroll == self.selected;
+                let mut last = 0;
+                let mut shown = 0;
+                for (start, end) in links(line).into_iter().chain([(line.len(), line.len())]) {
+                    let text: String = line[last..start].chars().take(width - shown).collect();
+                    shown += text.chars().count();
+                    queue!(write, Print(if selected { style(text).reverse() } else { style(text) }))?;
+                    let link: String = line[start..end].chars().take(width - shown).collect();
+                    shown += link.chars().count();
+                    queue!(write, Print(if selected { style(link).cyan().underlined().reverse() } else { style(link).cyan().underlined() }))?;
+                    last = end;
+                }
+            }
+            queue!(write, Clear(ClearType::UntilNewLine))?;
+        }
+        Ok(())
+    }
+
+    pub fn get_cursor(&self) -> Option<Cursor> {
+        None
+    }
+
+    pub fn name(&self) -> String {
+        match self.topic.as_str() {
+            "" => "Help".to_string(),
+            topic => format!("Help: {}", topic),
+        }
+    }
+
+    pub async fn process_action(&mut self, action: &Action) -> Result<Vec<ActionReturn>> {
+        let len = self.lines.len().max(1);
+        match action.name.as_str() {
+            "CursorUp" => {
+                self.selected = (self.selected + len - 1) % len;
+                self.adj_scroll();
+            }
+            "CursorDown" => {
+                self.selected = (self.selected + 1) % len;
+                self.adj_scroll();
+            }
+            "InsertTab" => {
+                self.next_link();
+            }
+            "InsertNewline" => {
+                self.follow()?;
+            }
+            "Delete" => {
+                if let Some((topic, selected)) = self.history.pop() {
+                    self.show(&topic, selected)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(vec![])
+    }
+}
+
+#[async_trait]
+impl TabView for Help {
+    fn name(&self) -> String {
+        Help::name(self)
+    }
+
+    fn renamed(&self) -> &Title {
+        &self.renamed
+    }
+
+    fn renamed_mut(&mut self) -> &mut Title {
+        &mut self.renamed
+    }
+
+    fn get_cursor(&self) -> Option<Cursor> {
+        Help::get_cursor(self)
+    }
+
+    fn render(&self, mut write: &mut dyn Write) -> Result<()> {
+        Help::render(self, &mut write)
+    }
+
+    async fn process_action(&mut self, action: &Action) -> Result<Vec<ActionReturn>> {
+        Help::process_action(self, action).await
+    }
+
+    fn set_tab_idx(&mut self, i: usize) {
+        self.tab_idx = i;
+    }
+
+    fn resize(&mut self, size: Size) {
+        self.size = size;
+        self.adj_scroll();
+    }
+
+    fn set_pos(&mut self, pos: Pos) {
+        self.pos = pos;
+    }
+
+    fn cursor_text(&self) -> Option<(usize, String)> {
+        self.lines.get(self.selected).map(|l| (self.selected, l.clone()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn context() -> Context {
+        Context {
+            keymaps: crate::key::open_keymaps("settings/keymap.json").unwrap(),
+            setting: serde_json::json!({"tab_size": 4}),
+        }
+    }
+
+    #[test]
+    fn pages_link_each_other() {
+        let context = context();
+        let actions = context.page("actions").unwrap();
+        assert!(actions.iter().any(|l| l.starts_with("  |Save|") && l.ends_with(" S")));
+        assert!(actions.iter().any(|l| l.starts_with("  |GotoTab|(1)")));
+        let save = context.page("save").unwrap();
+        assert_eq!(save[0], "Save");
+        assert!(save.iter().any(|l| l.trim_start().starts_with("Cmd")));
+        assert!(context.page("settings").unwrap().iter().any(|l| l == "tab_size = 4"));
+        assert!(context.page("NoSuchTopic").is_err());
+        assert_eq!(links("  |Save|(x) and |keymap|"), [(2, 8), (16, 24)]);
+    }
+}
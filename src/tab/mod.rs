@@ -1,16 +1,19 @@
-use std::{fmt::Debug, io::Write};
+use std::{collections::HashMap, fmt::Debug, future::Future, io::Write, pin::Pin};
 
 use anyhow::Result;
 use async_trait::async_trait;
-use syntect::highlighting::Theme;
 
 use crate::{actions::ActionReturn, Action};
 
 pub mod buffer;
 pub mod directory;
 pub mod shell;
+pub mod results;
+pub mod git;
+pub mod help;
+pub mod diff;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Pos {
     pub row: u16,
     pub col: u16,
@@ -23,17 +26,146 @@ pub struct Cursor {
 }
 
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Size {
     pub width: u16,
     pub height: u16,
 }
 
+// What the editor needs from any tab; kinds without a variant below are
+// boxed into `Tab::Custom` and created by name through `Registry`
+#[async_trait]
+pub trait TabView: Debug + Send + Sync {
+    // The tab's own name; `label` is what is shown
+    fn name(&self) -> String;
+    fn get_cursor(&self) -> Option<Cursor>;
+    fn render(&self, write: &mut dyn Write) -> Result<()>;
+    async fn process_action(&mut self, action: &Action) -> Result<Vec<ActionReturn>>;
+    fn set_tab_idx(&mut self, i: usize);
+    // The area below the tab bar changed with the terminal
+    fn resize(&mut self, size: Size);
+    // Where the top left corner is drawn, moved by splits
+    fn set_pos(&mut self, pos: Pos);
+    // Where the tab keeps the title RenameTab gives it
+    fn renamed(&self) -> &Title;
+    fn renamed_mut(&mut self) -> &mut Title;
+    // The title if the tab was renamed, else the name; for the tab bar and prompts
+    fn label(&self) -> String {
+        self.renamed().0.clone().unwrap_or_else(|| self.name())
+    }
+    // None goes back to the name
+    fn rename(&mut self, name: Option<String>) {
+        self.renamed_mut().0 = name;
+    }
+    fn is_unsaved(&self) -> bool {
+        false
+    }
+    // Extra text for the status bar
+    fn status(&self) -> Option<String> {
+        None
+    }
+    // Line number and text under the cursor, read out in screen reader mode
+    fn cursor_text(&self) -> Option<(usize, String)> {
+        None
+    }
+    fn selected(&self) -> usize {
+        0
+    }
+}
+
+// Title from RenameTab, shown instead of a tab's name until cleared
+#[derive(Debug, Clone, Default)]
+pub struct Title(Option<String>);
+
 #[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
 pub enum Tab{
     Buffer(buffer::Buffer),
     Directory(directory::Directory),
-    Shell(shell::Shell),
+    Results(results::Results),
+    Custom(Box<dyn TabView>),
+}
+
+impl Tab {
+    pub fn view(&self) -> &dyn TabView {
+        match self {
+            Tab::Buffer(b) => b,
+            Tab::Directory(d) => d,
+            Tab::Results(r) => r,
+            Tab::Custom(c) => c.as_ref(),
+        }
+    }
+
+    pub fn view_mut(&mut self) -> &mut dyn TabView {
+        match self {
+            Tab::Buffer(b) => b,
+            Tab::Directory(d) => d,
+            Tab::Results(r) => r,
+            Tab::Custom(c) => c.as_mut(),
+        }
+    }
+
+    pub fn set_tab_idx(&mut self, i: usize) {
+        self.view_mut().set_tab_idx(i);
+    }
+
+    pub fn is_unsaved(&self) -> bool {
+        self.view().is_unsaved()
+    }
+}
+
+pub type TabFuture = Pin<Box<dyn Future<Output = Result<Box<dyn TabView>>> + Send>>;
+// Creates a tab from the arguments of `NewTab(kind, args...)`; awaited, for
+// tabs that run a program to fill themselves
+pub type Factory = Box<dyn Fn(Vec<Option<String>>, Pos, Size, usize) -> TabFuture + Send + Sync>;
+
+pub struct Registry {
+    kinds: HashMap<String, Factory>,
+}
+
+impl Debug for Registry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.kinds.keys()).finish()
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        let mut registry = Self { kinds: HashMap::new() };
+        registry.register("shell", Box::new(|_, pos, size, idx| Box::pin(async move {
+            Ok(Box::new(shell::Shell::new(pos, size, idx)) as Box<dyn TabView>)
+        })));
+        registry.register("git", Box::new(|_, pos, size, idx| Box::pin(async move {
+            Ok(Box::new(git::GitStatus::new(pos, size, idx).await?) as Box<dyn TabView>)
+        })));
+        registry.register("help", Box::new(|args, pos, size, idx| Box::pin(async move {
+            let topic = args.first().cloned().flatten().unwrap_or_default();
+            Ok(Box::new(help::Help::new(&topic, pos, size, idx)?) as Box<dyn TabView>)
+        })));
+        registry
+    }
+
+    pub fn register(&mut self, kind: &str, factory: Factory) {
+        self.kinds.insert(kind.to_string(), factory);
+    }
+
+    pub async fn create(&self, kind: &str, args: &[Option<String>], pos: Pos, size: Size, tab_idx: usize) -> Result<Tab> {
+        let factory = self.kinds.get(kind).ok_or(anyhow::anyhow!("Unknown tab kind: {}", kind))?;
+        Ok(Tab::Custom(factory(args.to_vec(), pos, size, tab_idx).await?))
+    }
+}
+
+// Keeps every tab's tab_idx equal to its position after tabs move or close
+pub fn reindex(tabs: &mut [Tab]) {
+    for (i, tab) in tabs.iter_mut().enumerate() {
+        tab.set_tab_idx(i);
+    }
 }
 
 pub fn numlen (mut num: usize) -> usize {
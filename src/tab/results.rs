@@ -0,0 +1,271 @@
+use std::{fmt, io::Write, path::PathBuf};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use crossterm::{cursor, queue, style::{style, Print, Stylize}, terminal::{Clear, ClearType}};
+use regex::Regex;
+
+use crate::{actions::ActionReturn, Action};
+
+use super::{Cursor, Pos, Size, TabView, Title};
+
+// A position in a file; line and col are zero based, displayed one based
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub path: PathBuf,
+    pub line: usize,
+    pub col: usize,
+    pub text: String,
+}
+
+impl Location {
+    // Parses `path`, `path:line`, `path:line:col` and `path:line:col: text`
+    pub fn parse(s: &str) -> Result<Self> {
+        let mut parts = s.splitn(4, ':');
+        let path = parts.next().filter(|p| !p.is_empty()).ok_or(anyhow!("Invalid location: {}", s))?;
+        let mut nums = Vec::new();
+        let mut text = String::new();
+        for part in parts {
+            match part.trim().parse::<usize>() {
+                Ok(n) if nums.len() < 2 && text.is_empty() => nums.push(n.max(1) - 1),
+                _ => {
+                    if !text.is_empty() {
+                        text.push(':');
+                    }
+                    text.push_str(part);
+                }
+            }
+        }
+        Ok(Self {
+            path: PathBuf::from(path),
+            line: nums.first().copied().unwrap_or(0),
+            col: nums.get(1).copied().unwrap_or(0),
+            text: text.trim().to_string(),
+        })
+    }
+
+    // Finds a location in a line copied from a terminal, stack trace or log:
+    // `path:line:col` anywhere in it, `--> path:line:col`, `at f (path:line:col)`,
+    // Python's `File "path", line N` and MSVC's `path(line,col)`
+    pub fn find(s: &str) -> Option<Self> {
+        let patterns = [
+            r#"File "([^"]+)", line (\d+)()"#,
+            r"([^\s:()]+)\((\d+)(?:,(\d+))?\)",
+            r"([^\s:()]+):(\d+)(?::(\d+))?",
+        ];
+        patterns.iter().find_map(|p| {
+            let c = Regex::new(p).unwrap().captures(s)?;
+            let num = |i| c.get(i).and_then(|m| m.as_str().parse::<usize>().ok()).unwrap_or(1).max(1) - 1;
+            Some(Self {
+                path: PathBuf::from(&c[1]),
+                line: num(2),
+                col: num(3),
+                text: String::new(),
+            })
+        })
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.path.display(), self.line + 1, self.col + 1)?;
+        if !self.text.is_empty() {
+            write!(f, ": {}", self.text)?;
+        }
+        Ok(())
+    }
+}
+
+// Collects `file:line:col: message` lines and rustc style `--> file:line:col` blocks
+pub fn parse_compiler_output(output: &str) -> Vec<Location> {
+    let plain = Regex::new(r"^([^\s:][^:]*):(\d+):(\d+):\s*(.*)$").unwrap();
+    let arrow = Regex::new(r"^\s*--> ([^:]+):(\d+):(\d+)").unwrap();
+    let mut locations = Vec::new();
+    let mut message = String::new();
+    for line in output.lines() {
+        if let Some(c) = arrow.captures(line) {
+            locations.push(Location {
+                path: PathBuf::from(&c[1]),
+                line: c[2].parse::<usize>().unwrap_or(1).max(1) - 1,
+                col: c[3].parse::<usize>().unwrap_or(1).max(1) - 1,
+                text: message.clone(),
+            });
+        } else if let Some(c) = plain.captures(line) {
+            locations.push(Location {
+                path: PathBuf::from(&c[1]),
+                line: c[2].parse::<usize>().unwrap_or(1).max(1) - 1,
+                col: c[3].parse::<usize>().unwrap_or(1).max(1) - 1,
+                text: c[4].to_string(),
+            });
+        } else if line.starts_with("error") || line.starts_with("warning") {
+            message = line.to_string();
+        }
+    }
+    // Errors first, keeping compiler order otherwise
+    locations.sort_by_key(|l| !l.text.starts_with("error"));
+    locations
+}
+
+// List of locations (references, build errors, search hits); Enter opens one
+#[derive(Debug)]
+pub struct Results {
+    pub tab_idx: usize,
+    // Set by RenameTab and shown in the tab bar instead of the name
+    renamed: Title,
+    title: String,
+    items: Vec<Location>,
+    scroll: usize,
+    selected: usize,
+    pos: Pos,
+    size: Size,
+}
+
+impl Results {
+    pub fn new(title: String, items: Vec<Location>, pos: Pos, size: Size, tab_idx: usize) -> Self {
+        Self {
+            tab_idx,
+            renamed: Title::default(),
+            title,
+            items,
+            scroll: 0,
+            selected: 0,
+            pos,
+            size,
+        }
+    }
+
+    pub fn render<W>(&self, write: &mut W) -> Result<()>
+        where W: Write
+    {
+        for i in 0..self.size.height as usize {
+            queue!(write, cursor::MoveTo(self.pos.col, self.pos.row + i as u16))?;
+            if let Some(item) = self.items.get(i + self.scroll) {
+                let line: String = item.to_string().chars().take(self.size.width as usize).collect();
+                if i + self.scroll == self.selected {
+                    queue!(write, Print(style(line).reverse()))?;
+                } else {
+                    queue!(write, Print(line))?;
+                }
+            }
+            queue!(write, Clear(ClearType::UntilNewLine))?;
+        }
+        Ok(())
+    }
+
+    pub fn get_cursor(&self) -> Option<Cursor> {
+        None
+    }
+
+    pub fn name(&self) -> String {
+        format!("{} ({})", self.title, self.items.len())
+    }
+
+    fn adj_scroll(&mut self) {
+        let height = self.size.height as usize;
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if height > 0 && self.selected >= self.scroll + height {
+            self.scroll = self.selected + 1 - height;
+        }
+    }
+
+    pub async fn process_action(&mut self, action: &crate::Action) -> anyhow::Result<Vec<ActionReturn>> {
+        if self.items.is_empty() {
+            return Ok(vec![]);
+        }
+        let len = self.items.len();
+        match action.name.as_str() {
+            "CursorUp" => {
+                self.selected = (self.selected + len - 1) % len;
+                self.adj_scroll();
+            }
+            "CursorDown" => {
+                self.selected = (self.selected + 1) % len;
+                self.adj_scroll();
+            }
+            "InsertNewline" => {
+                return Ok(vec![ActionReturn::OpenAt(self.items[self.selected].clone())]);
+            }
+            _ => {}
+        }
+        Ok(vec![])
+    }
+}
+
+#[async_trait]
+impl TabView for Results {
+    fn name(&self) -> String {
+        Results::name(self)
+    }
+
+    fn renamed(&self) -> &Title {
+        &self.renamed
+    }
+
+    fn renamed_mut(&mut self) -> &mut Title {
+        &mut self.renamed
+    }
+
+    fn get_cursor(&self) -> Option<Cursor> {
+        Results::get_cursor(self)
+    }
+
+    fn render(&self, mut write: &mut dyn Write) -> Result<()> {
+        Results::render(self, &mut write)
+    }
+
+    async fn process_action(&mut self, action: &Action) -> Result<Vec<ActionReturn>> {
+        Results::process_action(self, action).await
+    }
+
+    fn set_tab_idx(&mut self, i: usize) {
+        self.tab_idx = i;
+    }
+
+    fn resize(&mut self, size: Size) {
+        self.size = size;
+        self.adj_scroll();
+    }
+
+    fn set_pos(&mut self, pos: Pos) {
+        self.pos = pos;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_location() {
+        let l = Location::parse("src/main.rs:12:5: error: oops").unwrap();
+        assert_eq!((l.path, l.line, l.col, l.text.as_str()), (PathBuf::from("src/main.rs"), 11, 4, "error: oops"));
+        let l = Location::parse("src/main.rs:3").unwrap();
+        assert_eq!((l.line, l.col), (2, 0));
+        let l = Location::parse("README.md").unwrap();
+        assert_eq!((l.line, l.col), (0, 0));
+        assert_eq!(Location::parse("a.rs:1:2: x").unwrap().to_string(), "a.rs:1:2: x");
+        assert!(Location::parse("").is_err());
+    }
+
+    #[test]
+    fn find_pasted_location() {
+        let found = |s| Location::find(s).map(|l| (l.path.display().to_string(), l.line, l.col));
+        assert_eq!(found("  --> src/lib.rs:177:29"), Some(("src/lib.rs".to_string(), 176, 28)));
+        assert_eq!(found("    at run (/app/src/index.js:12:5)"), Some(("/app/src/index.js".to_string(), 11, 4)));
+        assert_eq!(found(r#"  File "tools/gen.py", line 40, in main"#), Some(("tools/gen.py".to_string(), 39, 0)));
+        assert_eq!(found(r"src\main.cs(8,13): error CS1002"), Some((r"src\main.cs".to_string(), 7, 12)));
+        assert_eq!(found("main.c:3: warning"), Some(("main.c".to_string(), 2, 0)));
+        assert_eq!(found("nothing here"), None);
+    }
+
+    #[test]
+    fn compiler_output() {
+        let output = "warning: unused import\n  --> src/a.rs:1:5\nerror[E0425]: cannot find value\n   --> src/lib.rs:177:29\nmain.c:3:10: error: expected ';'\n";
+        let locations = parse_compiler_output(output);
+        assert_eq!(locations.len(), 3);
+        assert_eq!(locations[0].to_string(), "src/lib.rs:177:29: error[E0425]: cannot find value");
+        assert_eq!(locations[1].to_string(), "main.c:3:10: error: expected ';'");
+        assert_eq!(locations[2].to_string(), "src/a.rs:1:5: warning: unused import");
+    }
+}
@@ -1,4 +1,4 @@
-use std::{process::Stdio, sync::Arc, thread::spawn};
+use std::{process::Stdio, sync::Arc, time::Duration};
 
 use anyhow::Result;
 use async_trait::async_trait;
@@ -6,23 +6,32 @@ use crossterm::{cursor, queue, style::Print, terminal::{Clear, ClearType}};
 use log::{debug, error};
 use ropey::Rope;
 use syntect::highlighting::Theme;
-use tokio::{io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader}, process::{Child, ChildStdin, Command}, sync::{mpsc::{Receiver, Sender}, Mutex}};
+use tokio::{io::{AsyncRead, AsyncReadExt, AsyncWriteExt}, process::{ChildStdin, Command}, sync::{mpsc::{Receiver, Sender}, Mutex}};
 
-use super::{Pos, Size, Tab};
+use crate::{actions::ActionReturn, lineedit::LineEdit, Action};
+
+use super::{Cursor, Pos, Size, Tab, TabView, Title};
+
+// Output is appended to the log once this much is pending, or every FLUSH_INTERVAL
+const CHUNK: usize = 4096;
+const FLUSH_INTERVAL: Duration = Duration::from_millis(10);
+// Each batch of stream_command output is an action and a redraw, so they come slower
+const STREAM_INTERVAL: Duration = Duration::from_millis(100);
+// Output is sent tagged with the index of its stream here, and each stream
+// keeps its own pending bytes so a char split between reads is not cut by
+// the other stream's output
+const STREAMS: [&str; 2] = ["stdout", "stderr"];
 
 #[derive(Debug)]
 pub struct Shell {
     pub tab_idx: usize,
+    // Set by RenameTab and shown in the tab bar instead of the name
+    renamed: Title,
     log: Arc<Mutex<Rope>>,
     pub size: Size,
-    stdout_rx: Arc<Mutex<Receiver<u8>>>,
-    stderr_rx: Arc<Mutex<Receiver<u8>>>,
     stdin: ChildStdin,
-    line_input: String,
-    cursor: usize,
+    line_input: LineEdit,
     pub pos: Pos,
-    out_buf: Arc<Mutex<[u8; 4]>>,
-    err_buf: Arc<Mutex<[u8; 4]>>,
 }
 
 
@@ -33,158 +42,108 @@ impl Shell {
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
+            // Ends the shell with the editor, even when it is killed by a signal
+            .kill_on_drop(true)
             .spawn()
             .unwrap();
         let stdout = shell.stdout.take().unwrap();
         let stderr = shell.stderr.take().unwrap();
         let stdin = shell.stdin.take().unwrap();
-        let (stdout_tx, stdout_rx) = tokio::sync::mpsc::channel(10000);
-        let (stderr_tx, stderr_rx) = tokio::sync::mpsc::channel(10000);
-        Self::spawn_reader(stdout_tx, stderr_tx, BufReader::new(stdout), BufReader::new(stderr));
+        let (output_tx, output_rx) = tokio::sync::mpsc::channel(64);
+        Self::spawn_reader(output_tx.clone(), stdout, 0);
+        Self::spawn_reader(output_tx, stderr, 1);
         tokio::spawn(async move {
             let status = shell.wait().await.unwrap();
             debug!("Shell exited with: {}", status);
         });
 
         let log = Arc::new(Mutex::new(Rope::new()));
-        let out_buf = Arc::new(Mutex::new([0; 4]));
-        let err_buf = Arc::new(Mutex::new([0; 4]));
-        let stdout_rx = Arc::new(Mutex::new(stdout_rx));
-        let stderr_rx = Arc::new(Mutex::new(stderr_rx));
-
-        let log_clone = Arc::clone(&log);
-        let out_buf_clone = Arc::clone(&out_buf);
-        let err_buf_clone = Arc::clone(&err_buf);
-        let stdout_rx_clone = Arc::clone(&stdout_rx);
-        let stderr_rx_clone = Arc::clone(&stderr_rx);
-
-        tokio::spawn(
-            async move {
-            loop {
-                Self::read_stdout(stdout_rx_clone.clone(), out_buf_clone.clone(), log_clone.clone()).await;
-                Self::read_stderr(stderr_rx_clone.clone(), err_buf_clone.clone(), log_clone.clone()).await;
-            }
-        });
+        tokio::spawn(Self::collect(output_rx, log.clone()));
 
         Self {
             tab_idx,
+            renamed: Title::default(),
             log,
-            stdout_rx,
-            stderr_rx,
             stdin,
             size,
             pos,
-            out_buf,
-            err_buf,
-            line_input: String::new(),
-            cursor: 0,
+            line_input: LineEdit::new(),
         }
     }
-    fn spawn_reader(stdout_tx: Sender<u8>, stderr_tx: Sender<u8>, mut reader: BufReader<tokio::process::ChildStdout>, mut err_reader: BufReader<tokio::process::ChildStderr>) {
+
+    fn spawn_reader<R>(tx: Sender<(usize, Vec<u8>)>, mut reader: R, stream: usize)
+    where R: AsyncRead + Unpin + Send + 'static
+    {
         tokio::spawn(async move {
-            let mut buf = [0];
             loop {
+                let mut buf = vec![0; CHUNK];
                 match reader.read(&mut buf).await {
+                    Ok(0) => break,
                     Ok(n) => {
-                        if n != 0 {
-                            stdout_tx.send(buf[0]).await.unwrap();
+                        buf.truncate(n);
+                        if tx.send((stream, buf)).await.is_err() {
+                            break;
                         }
                     }
                     Err(e) => {
-                        error!("Error reading stdout: {}", e);
+                        error!("Error reading {}: {}", STREAMS[stream], e);
                         break;
                     }
                 }
             }
         });
-        tokio::spawn(async move {
-            let mut buf = [0];
-            loop {
-                match err_reader.read(&mut buf).await {
-                    Ok(n) => {
-                        if n != 0 {
-                            stderr_tx.send(buf[0]).await.unwrap();
+    }
+
+    // Batches output so a burst takes the log lock once per chunk instead of once per byte
+    async fn collect(mut output_rx: Receiver<(usize, Vec<u8>)>, log: Arc<Mutex<Rope>>) {
+        let mut pending = [Vec::new(), Vec::new()];
+        let mut tick = tokio::time::interval(FLUSH_INTERVAL);
+        loop {
+            tokio::select! {
+                chunk = output_rx.recv() => match chunk {
+                    Some((stream, chunk)) => {
+                        pending[stream].extend(chunk);
+                        if pending[stream].len() < CHUNK {
+                            continue;
                         }
                     }
-                    Err(e) => {
-                        error!("Error reading stderr: {}", e);
-                        break;
+                    None => {
+                        Self::flush(&mut pending, &log, true).await;
+                        return;
+                    }
+                },
+                _ = tick.tick() => {
+                    if pending.iter().all(Vec::is_empty) {
+                        continue;
                     }
                 }
             }
-        });
-    }
-
-    async fn read_stdout(stdout_rx_mut: Arc<Mutex<Receiver<u8>>>, buf_mut: Arc<Mutex<[u8; 4]>>, rope: Arc<Mutex<Rope>>){
-        let mut stdout_rx = stdout_rx_mut.lock().await;
-        let mut buf = buf_mut.lock().await;
-        while let Ok(line) = stdout_rx.try_recv() {
-            let mut len = 0;
-            for (i, c) in buf.iter_mut().enumerate() {
-                if c == &0 {
-                    *c = line;
-                    len = i + 1;
-                    break;
-                }
-            }
-            if let Ok(s) = String::from_utf8(buf[..len].to_vec()) {
-                *buf = [0; 4];
-                let mut rope = rope.lock().await;
-                rope.append(s.into());
-            }
-        }
-    }
-
-    async fn read_stderr(stderr_rx_mut: Arc<Mutex<Receiver<u8>>>, buf_mut: Arc<Mutex<[u8; 4]>>, rope: Arc<Mutex<Rope>>){
-        let mut stderr_rx = stderr_rx_mut.lock().await;
-        let mut buf = buf_mut.lock().await;
-        while let Ok(line) = stderr_rx.try_recv() {
-            let mut len = 0;
-            for (i , c) in buf.iter_mut().enumerate() {
-                if c == &0 {
-                    *c = line;
-                    len = i + 1;
-                    break;
-                }
-            }
-            if let Ok(s) = String::from_utf8(buf[..len].to_vec()) {
-                *buf = [0; 4];
-                let mut rope = rope.lock().await;
-                rope.append(s.into());
-            }
-        }
-    }
-
-    fn insert_char(&mut self, c: char, upper: bool) {
-        let c = if upper { c } else { c.to_lowercase().next().unwrap() };
-        debug!("Inserting char: {}", c);
-        self.line_input.insert(self.cursor, c);
-        self.cursor_forward();
-    }
-
-    fn cursor_forward(&mut self) {
-        if self.cursor < self.line_input.len() {
-            self.cursor += 1;
+            Self::flush(&mut pending, &log, false).await;
         }
     }
 
-    fn cursor_backward(&mut self) {
-        if self.cursor > 0 {
-            self.cursor -= 1;
-        }
+    // Appends the pending output of both streams to the log
+    async fn flush(pending: &mut [Vec<u8>; 2], log: &Mutex<Rope>, last: bool) {
+        let text: String = pending.iter_mut().map(|p| take_text(p, last)).collect();
+        let mut log = log.lock().await;
+        let end = log.len_chars();
+        log.insert(end, &text);
     }
 
-    pub async fn render<W>(&self, write: &mut W) -> Result<()> 
+    pub fn render<W>(&self, write: &mut W) -> Result<()> 
     where W: std::io::Write
     {
         queue!(
             write,
             cursor::MoveTo(self.pos.col, self.pos.row + self.size.height as u16 - 1),
             Clear(ClearType::UntilNewLine),
-            Print("> "),
-            Print(self.line_input.as_str())
+            Print(self.prompt()),
+            Print(self.line_input.text.as_str())
         )?;
-        let log= self.log.lock().await;
+        // The reader task holds the log while appending; draw it next frame instead
+        let Ok(log) = self.log.try_lock() else {
+            return Ok(());
+        };
         for (i, line) in log.lines().enumerate() {
             queue!(
                 write,
@@ -198,46 +157,117 @@ impl Shell {
     pub fn get_cursor(&self) -> Option<super::Cursor> {
         Some(super::Cursor {
             row: self.pos.row + self.size.height - 1,
-            col: self.pos.col + 2 + self.cursor as u16,
+            col: self.pos.col + self.prompt().chars().count() as u16 + self.line_input.cur as u16,
         })
     }
     
     pub fn name(&self) -> String {
         "Shell".to_string()
     }
+
+    fn prompt(&self) -> String {
+        match self.line_input.searching() {
+            Some(query) => format!("(history `{}`) ", query),
+            None => "> ".to_string(),
+        }
+    }
     pub async fn process_action(&mut self, action: &crate::Action) -> anyhow::Result<Vec<super::ActionReturn>> {
-        match action.name.as_str() {
-            "Insert" => {
-                self.insert_char(action.args[0].as_ref().unwrap().chars().next().unwrap(), false);
-            }
-            "InsertUpper" => {
-                self.insert_char(action.args[0].as_ref().unwrap().chars().next().unwrap(), true);
-            }
-            "InsertSpace" => {
-                self.insert_char(' ', false);
-            }
-            "Delete" => {
-                self.line_input.pop();
-                self.cursor_backward();
-            }
-            "CursorForward" => {
-                self.cursor_forward();
-            }
-            "CursorBackward" => {
-                self.cursor_backward();
-            }
-            "InsertNewline" => {
-                self.stdin.write(self.line_input.as_bytes()).await?;
-                self.stdin.write(b"\n").await?;
-                self.line_input.clear();
-                self.cursor = 0;
-            }
-            _ => {}
+        // The buffer's cursor and history keys drive the prompt line
+        let name = match action.name.as_str() {
+            "CursorStart" => "Start",
+            "CursorEnd" => "End",
+            "CursorUp" => "Previous",
+            "CursorDown" => "Next",
+            "KillLineEnd" => "KillEnd",
+            "Paste" => "Yank",
+            name => name,
+        };
+        if self.line_input.process(name, action.args.first().cloned().flatten().as_deref()) {
+            return Ok(vec![]);
+        }
+        if action.name == "InsertNewline" {
+            let line = self.line_input.clear(true);
+            self.stdin.write_all(line.as_bytes()).await?;
+            self.stdin.write_all(b"\n").await?;
         }
         Ok(vec![])
     }
 }
 
+// The pending output as text; a char split between reads waits for its
+// remaining bytes unless this is the last of it
+fn take_text(pending: &mut Vec<u8>, last: bool) -> String {
+    let valid = match std::str::from_utf8(pending) {
+        Err(e) if e.error_len().is_none() && !last => e.valid_up_to(),
+        _ => pending.len(),
+    };
+    let text = String::from_utf8_lossy(&pending[..valid]).to_string();
+    pending.drain(..valid);
+    text
+}
+
+// Runs a one-shot command through sh without a terminal, handing what it
+// prints on stdout and stderr to `output` in batches as it comes
+pub async fn stream_command(cmd: &str, mut output: impl FnMut(String)) -> Result<std::process::ExitStatus> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let (output_tx, mut output_rx) = tokio::sync::mpsc::channel(64);
+    Shell::spawn_reader(output_tx.clone(), child.stdout.take().unwrap(), 0);
+    Shell::spawn_reader(output_tx, child.stderr.take().unwrap(), 1);
+    let mut pending = [Vec::new(), Vec::new()];
+    let mut tick = tokio::time::interval(STREAM_INTERVAL);
+    loop {
+        tokio::select! {
+            chunk = output_rx.recv() => match chunk {
+                Some((stream, chunk)) => {
+                    pending[stream].extend(chunk);
+                    if pending[stream].len() < CHUNK {
+                        continue;
+                    }
+                }
+                None => break,
+            },
+            _ = tick.tick() => {
+                if pending.iter().all(Vec::is_empty) {
+                    continue;
+                }
+            }
+        }
+        output(pending.iter_mut().map(|p| take_text(p, false)).collect());
+    }
+    if pending.iter().any(|p| !p.is_empty()) {
+        output(pending.iter_mut().map(|p| take_text(p, true)).collect());
+    }
+    Ok(child.wait().await?)
+}
+
+// Runs a one-shot command through sh, feeding `input` to its stdin
+pub async fn run_command(cmd: &str, input: Option<String>) -> Result<std::process::Output> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    if let Some(input) = input {
+        let mut stdin = child.stdin.take().unwrap();
+        tokio::spawn(async move {
+            if let Err(e) = stdin.write_all(input.as_bytes()).await {
+                debug!("run_command: stdin closed early: {}", e);
+            }
+        });
+    }
+    Ok(child.wait_with_output().await?)
+}
+
 fn char_to_buf (c: char) -> Vec<u8> {
     let mut buf = Vec::new();
     let mut arr = [0; 4];
@@ -249,4 +279,43 @@ fn char_to_buf (c: char) -> Vec<u8> {
         buf.push(*byte); 
     }
     buf
-}
\ No newline at end of file
+}
+
+#[async_trait]
+impl TabView for Shell {
+    fn name(&self) -> String {
+        Shell::name(self)
+    }
+
+    fn renamed(&self) -> &Title {
+        &self.renamed
+    }
+
+    fn renamed_mut(&mut self) -> &mut Title {
+        &mut self.renamed
+    }
+
+    fn get_cursor(&self) -> Option<Cursor> {
+        Shell::get_cursor(self)
+    }
+
+    fn render(&self, mut write: &mut dyn std::io::Write) -> Result<()> {
+        Shell::render(self, &mut write)
+    }
+
+    async fn process_action(&mut self, action: &Action) -> Result<Vec<ActionReturn>> {
+        Shell::process_action(self, action).await
+    }
+
+    fn set_tab_idx(&mut self, i: usize) {
+        self.tab_idx = i;
+    }
+
+    fn resize(&mut self, size: Size) {
+        self.size = size;
+    }
+
+    fn set_pos(&mut self, pos: Pos) {
+        self.pos = pos;
+    }
+}
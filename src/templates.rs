@@ -0,0 +1,31 @@
+// New files start from settings/templates/template.<ext> when there is one,
+// e.g. a license header for .rs or an HTML skeleton. `{name}` becomes the
+// file name without its extension, `{date}` and `{time}` as in abbreviations
+
+use std::path::Path;
+
+use crate::abbrev;
+
+const TEMPLATES: &str = "settings/templates";
+
+pub fn for_path(path: &Path) -> Option<String> {
+    let ext = path.extension()?.to_str()?;
+    let text = std::fs::read_to_string(Path::new(TEMPLATES).join(format!("template.{}", ext))).ok()?;
+    let name = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    Some(fill(&text, &name))
+}
+
+fn fill(text: &str, name: &str) -> String {
+    abbrev::placeholders(&text.replace("{name}", name))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fills_template() {
+        assert_eq!(fill("<title>{name}</title>", "index"), "<title>index</title>");
+        assert!(!fill("// {date}", "main").contains('{'));
+    }
+}
@@ -0,0 +1,167 @@
+// Text transforms applied to the selection, e.g. to inspect encoded payloads
+
+use anyhow::Result;
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const HEX_WIDTH: usize = 16;
+
+// Action names handled by `apply`
+pub const ACTIONS: [&str; 7] = ["Base64Encode", "Base64Decode", "UrlEncode", "UrlDecode", "HexDump", "HexUndump", "SortLines"];
+
+pub fn apply(action: &str, input: &str) -> Result<String> {
+    match action {
+        "Base64Encode" => Ok(base64_encode(input.as_bytes())),
+        "Base64Decode" => utf8(base64_decode(input)?),
+        "UrlEncode" => Ok(url_encode(input)),
+        "UrlDecode" => utf8(url_decode(input)?),
+        "HexDump" => Ok(hex_dump(input.as_bytes())),
+        "HexUndump" => utf8(hex_undump(input)?),
+        "SortLines" => Ok(sort_lines(input)),
+        _ => Err(anyhow::anyhow!("Unknown transform {}", action)),
+    }
+}
+
+fn utf8(bytes: Vec<u8>) -> Result<String> {
+    String::from_utf8(bytes).map_err(|_| anyhow::anyhow!("Decoded bytes are not valid UTF-8"))
+}
+
+pub fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+// Whitespace is skipped so wrapped input decodes too
+pub fn base64_decode(s: &str) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut n = 0u32;
+    let mut bits = 0;
+    for c in s.bytes().filter(|c| !c.is_ascii_whitespace() && *c != b'=') {
+        let v = BASE64.iter().position(|x| *x == c)
+            .ok_or(anyhow::anyhow!("Invalid base64 character '{}'", c as char))?;
+        n = n << 6 | v as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((n >> bits) as u8);
+            n &= (1 << bits) - 1;
+        }
+    }
+    Ok(out)
+}
+
+// Percent-encodes everything except the unreserved characters of RFC 3986
+pub fn url_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-_.~".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+// `+` is read as a space, as in query strings
+pub fn url_decode(s: &str) -> Result<Vec<u8>> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = s.get(i + 1..i + 3).ok_or(anyhow::anyhow!("Incomplete escape at {}", i))?;
+                out.push(u8::from_str_radix(hex, 16).map_err(|_| anyhow::anyhow!("Invalid escape %{}", hex))?);
+                i += 3;
+                continue;
+            }
+            b'+' => out.push(b' '),
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    Ok(out)
+}
+
+// `offset  hex bytes  ascii`, HEX_WIDTH bytes per line
+pub fn hex_dump(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for (i, chunk) in bytes.chunks(HEX_WIDTH).enumerate() {
+        let hex = chunk.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ");
+        let ascii: String = chunk.iter()
+            .map(|b| if b.is_ascii_graphic() || *b == b' ' { *b as char } else { '.' })
+            .collect();
+        out.push_str(&format!("{:08x}  {:<w$}  {}\n", i * HEX_WIDTH, hex, ascii, w = HEX_WIDTH * 3 - 1));
+    }
+    out
+}
+
+// Reads the hex column back, ignoring the offsets and the ascii column
+pub fn hex_undump(s: &str) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    for line in s.lines().filter(|l| !l.trim().is_empty()) {
+        let hex = line.split("  ").nth(1).unwrap_or("");
+        for byte in hex.split_whitespace() {
+            out.push(u8::from_str_radix(byte, 16).map_err(|_| anyhow::anyhow!("Invalid hex byte {}", byte))?);
+        }
+    }
+    Ok(out)
+}
+
+// The text keeps whether it ended with a line break
+pub fn sort_lines(s: &str) -> String {
+    let mut lines: Vec<&str> = s.lines().collect();
+    lines.sort();
+    let mut out = lines.join("\n");
+    if s.ends_with('\n') {
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn base64_round_trip() {
+        assert_eq!(base64_encode(b"Man"), "TWFu");
+        assert_eq!(base64_encode(b"Ma"), "TWE=");
+        assert_eq!(base64_encode(b"M"), "TQ==");
+        for s in ["", "a", "ab", "abc", "héllo wörld"] {
+            assert_eq!(base64_decode(&base64_encode(s.as_bytes())).unwrap(), s.as_bytes());
+        }
+        assert!(base64_decode("a$b").is_err());
+    }
+
+    #[test]
+    fn url_round_trip() {
+        assert_eq!(url_encode("a b&c=d/é"), "a%20b%26c%3Dd%2F%C3%A9");
+        assert_eq!(url_decode("a+b%20c").unwrap(), b"a b c");
+        assert!(url_decode("%4").is_err());
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        let text = "hello, hex dump\nsecond line";
+        let dump = hex_dump(text.as_bytes());
+        assert!(dump.starts_with("00000000  68 65 6c 6c 6f"));
+        assert_eq!(hex_undump(&dump).unwrap(), text.as_bytes());
+    }
+
+    #[test]
+    fn sorts_lines() {
+        assert_eq!(sort_lines("b\nc\na\n"), "a\nb\nc\n");
+        assert_eq!(sort_lines("b\na"), "a\nb");
+    }
+}
@@ -0,0 +1,201 @@
+// `--tutor`: a lesson buffer for the default keymap, checked by a middleware
+// that watches the actions the exercises ask for
+
+use tokio::sync::mpsc;
+
+use crate::{middleware::{Context, Flow, Middleware}, Action};
+
+struct Lesson {
+    title: &'static str,
+    text: &'static str,
+    // Actions to run in order, each the given number of times
+    steps: &'static [(&'static str, usize)],
+}
+
+const LESSONS: [Lesson; 9] = [
+    Lesson {
+        title: "Moving around",
+        text: "\
+Down (or Ctrl+S) moves the cursor down a line, Up (or Ctrl+W) moves it up.
+Left and Right (or Ctrl+A and Ctrl+D) move by a char.
+
+Move down three lines.",
+        steps: &[("CursorDown", 3)],
+    },
+    Lesson {
+        title: "Words and lines",
+        text: "\
+Alt+D and Alt+A move by words, Ctrl+Q and Ctrl+E to the start and the end
+of the line.
+
+Move forward two words on the line below, then to its start and its end.
+
+    the quick brown fox jumps over the lazy dog",
+        steps: &[("CursorForwardWord", 2), ("CursorStart", 1), ("CursorEnd", 1)],
+    },
+    Lesson {
+        title: "Typing",
+        text: "\
+Normal mode inserts what you type. Backspace deletes the char before the
+cursor.
+
+Type five chars at the end of the line below, then delete one.
+
+    Practice here:",
+        steps: &[("Insert", 5), ("Delete", 1)],
+    },
+    Lesson {
+        title: "New lines",
+        text: "\
+Enter breaks the line at the cursor, Ctrl+Enter opens a line below.
+
+Break a line with Enter.",
+        steps: &[("InsertNewline", 1)],
+    },
+    Lesson {
+        title: "Cmd mode",
+        text: "\
+Ctrl+Space switches to Cmd mode, where single keys are commands: s saves,
+o opens a file, q quits. Space goes back to Normal mode. The status bar
+shows the current mode.
+
+Switch to Cmd mode and back.",
+        steps: &[("CmdMode", 1), ("NormalMode", 1)],
+    },
+    Lesson {
+        title: "Selecting",
+        text: "\
+Alt+Space starts a selection at the cursor; moving the cursor extends it.
+In Cmd mode, Shift+C counts the words of the selection.
+
+Select a word below and count it.
+
+    one two three",
+        steps: &[("SelectStart", 1), ("CursorForwardWord", 1), ("WordCount", 1)],
+    },
+    Lesson {
+        title: "The command line",
+        text: "\
+Ctrl+L opens the command line at the bottom. It runs any action by name,
+and `= expr` shows the value of an expression.
+
+Open the command line, type = 6*7 and press Enter.",
+        steps: &[("LineMode", 1), ("Calc", 1)],
+    },
+    Lesson {
+        title: "Help and tabs",
+        text: "\
+F1, or `help topic` on the command line, opens help in a new tab. Ctrl+Tab
+shows the next tab.
+
+Open help, then come back here with Ctrl+Tab.",
+        steps: &[("Help", 1), ("NextTab", 1)],
+    },
+    Lesson {
+        title: "Saving and quitting",
+        text: "\
+In Cmd mode, s saves (an unnamed buffer like this one asks for a file
+name) and q quits. This buffer is a copy, so nothing needs saving.
+
+Save this buffer somewhere to finish.",
+        steps: &[("Save", 1)],
+    },
+];
+
+pub fn text() -> String {
+    let mut out = String::from("\
+Zutto tutor
+
+Each lesson below ends with an exercise. Do them in order; the status bar
+tells you when one is done. Edit this buffer freely, it is not saved
+anywhere unless you save it.
+");
+    for (i, lesson) in LESSONS.iter().enumerate() {
+        out.push_str(&format!("\n\nLesson {}: {}\n\n{}\n", i + 1, lesson.title, lesson.text));
+    }
+    out
+}
+
+// Notice for lesson `done` (zero based) being finished
+pub fn progress(done: usize) -> String {
+    match LESSONS.get(done + 1) {
+        Some(next) => format!("Lesson {} done. Next, lesson {}: {}", done + 1, done + 2, next.title),
+        None => "Tutorial complete. `help` on the command line has the rest".to_string(),
+    }
+}
+
+pub fn intro() -> String {
+    format!("Lesson 1: {}", LESSONS[0].title)
+}
+
+// Tracks the current lesson and reports each finished one as TutorProgress
+#[derive(Debug)]
+pub struct Tutor {
+    lesson: usize,
+    step: usize,
+    count: usize,
+    tx: mpsc::Sender<String>,
+}
+
+impl Tutor {
+    pub fn new(tx: mpsc::Sender<String>) -> Self {
+        Self { lesson: 0, step: 0, count: 0, tx }
+    }
+
+    // Returns the lesson `name` finished, if any
+    fn advance(&mut self, name: &str) -> Option<usize> {
+        let lesson = LESSONS.get(self.lesson)?;
+        let (action, times) = lesson.steps[self.step];
+        if name != action {
+            return None;
+        }
+        self.count += 1;
+        if self.count < times {
+            return None;
+        }
+        self.count = 0;
+        self.step += 1;
+        if self.step < lesson.steps.len() {
+            return None;
+        }
+        self.step = 0;
+        self.lesson += 1;
+        Some(self.lesson - 1)
+    }
+}
+
+impl Middleware for Tutor {
+    fn name(&self) -> &str {
+        "tutor"
+    }
+
+    fn handle(&mut self, action: Action, _: &Context) -> Flow {
+        if let Some(done) = self.advance(&action.name) {
+            // The action queue may be full, a missed notice is harmless
+            let _ = self.tx.try_send(format!("TutorProgress({})", done));
+        }
+        Flow::Continue(action)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lessons_advance_in_order() {
+        let (tx, _rx) = mpsc::channel(1);
+        let mut tutor = Tutor::new(tx);
+        assert_eq!(tutor.advance("CursorDown"), None);
+        assert_eq!(tutor.advance("CursorUp"), None);
+        assert_eq!(tutor.advance("CursorDown"), None);
+        assert_eq!(tutor.advance("CursorDown"), Some(0));
+        assert_eq!(tutor.advance("CursorEnd"), None);
+        assert_eq!(tutor.advance("CursorForwardWord"), None);
+        assert_eq!(tutor.advance("CursorForwardWord"), None);
+        assert_eq!(tutor.advance("CursorStart"), None);
+        assert_eq!(tutor.advance("CursorEnd"), Some(1));
+        assert!(text().contains("Lesson 9: Saving and quitting"));
+        assert!(progress(LESSONS.len() - 1).starts_with("Tutorial complete"));
+    }
+}
@@ -0,0 +1,132 @@
+// Character names for DescribeChar; only the ranges that can be named without a
+// full Unicode database, which covers the invisible and lookalike characters
+
+const C0: [&str; 32] = [
+    "NULL", "START OF HEADING", "START OF TEXT", "END OF TEXT", "END OF TRANSMISSION", "ENQUIRY",
+    "ACKNOWLEDGE", "BELL", "BACKSPACE", "CHARACTER TABULATION", "LINE FEED", "LINE TABULATION",
+    "FORM FEED", "CARRIAGE RETURN", "SHIFT OUT", "SHIFT IN", "DATA LINK ESCAPE", "DEVICE CONTROL ONE",
+    "DEVICE CONTROL TWO", "DEVICE CONTROL THREE", "DEVICE CONTROL FOUR", "NEGATIVE ACKNOWLEDGE",
+    "SYNCHRONOUS IDLE", "END OF TRANSMISSION BLOCK", "CANCEL", "END OF MEDIUM", "SUBSTITUTE",
+    "ESCAPE", "INFORMATION SEPARATOR FOUR", "INFORMATION SEPARATOR THREE",
+    "INFORMATION SEPARATOR TWO", "INFORMATION SEPARATOR ONE",
+];
+
+const ASCII_PUNCT: [(char, &str); 32] = [
+    (' ', "SPACE"), ('!', "EXCLAMATION MARK"), ('"', "QUOTATION MARK"), ('#', "NUMBER SIGN"),
+    ('$', "DOLLAR SIGN"), ('%', "PERCENT SIGN"), ('&', "AMPERSAND"), ('\'', "APOSTROPHE"),
+    ('(', "LEFT PARENTHESIS"), (')', "RIGHT PARENTHESIS"), ('*', "ASTERISK"), ('+', "PLUS SIGN"),
+    (',', "COMMA"), ('-', "HYPHEN-MINUS"), ('.', "FULL STOP"), ('/', "SOLIDUS"), (':', "COLON"),
+    (';', "SEMICOLON"), ('<', "LESS-THAN SIGN"), ('=', "EQUALS SIGN"), ('>', "GREATER-THAN SIGN"),
+    ('?', "QUESTION MARK"), ('@', "COMMERCIAL AT"), ('[', "LEFT SQUARE BRACKET"),
+    ('\\', "REVERSE SOLIDUS"), (']', "RIGHT SQUARE BRACKET"), ('^', "CIRCUMFLEX ACCENT"),
+    ('_', "LOW LINE"), ('`', "GRAVE ACCENT"), ('{', "LEFT CURLY BRACKET"), ('|', "VERTICAL LINE"),
+    ('}', "RIGHT CURLY BRACKET"),
+];
+
+const SPECIAL: [(char, &str); 24] = [
+    ('~', "TILDE"), ('\u{7f}', "DELETE"), ('\u{a0}', "NO-BREAK SPACE"), ('\u{ad}', "SOFT HYPHEN"),
+    ('\u{2002}', "EN SPACE"), ('\u{2003}', "EM SPACE"), ('\u{2009}', "THIN SPACE"),
+    ('\u{200b}', "ZERO WIDTH SPACE"), ('\u{200c}', "ZERO WIDTH NON-JOINER"),
+    ('\u{200d}', "ZERO WIDTH JOINER"), ('\u{200e}', "LEFT-TO-RIGHT MARK"),
+    ('\u{200f}', "RIGHT-TO-LEFT MARK"), ('\u{2010}', "HYPHEN"), ('\u{2013}', "EN DASH"),
+    ('\u{2014}', "EM DASH"), ('\u{2018}', "LEFT SINGLE QUOTATION MARK"),
+    ('\u{2019}', "RIGHT SINGLE QUOTATION MARK"), ('\u{201c}', "LEFT DOUBLE QUOTATION MARK"),
+    ('\u{201d}', "RIGHT DOUBLE QUOTATION MARK"), ('\u{2026}', "HORIZONTAL ELLIPSIS"),
+    ('\u{2028}', "LINE SEPARATOR"), ('\u{2029}', "PARAGRAPH SEPARATOR"),
+    ('\u{3000}', "IDEOGRAPHIC SPACE"), ('\u{feff}', "ZERO WIDTH NO-BREAK SPACE"),
+];
+
+const DIGITS: [&str; 10] = ["ZERO", "ONE", "TWO", "THREE", "FOUR", "FIVE", "SIX", "SEVEN", "EIGHT", "NINE"];
+
+// Romanized jamo used in Hangul syllable names
+const CHO: [&str; 19] = ["G", "GG", "N", "D", "DD", "R", "M", "B", "BB", "S", "SS", "", "J", "JJ", "C", "K", "T", "P", "H"];
+const JUNG: [&str; 21] = ["A", "AE", "YA", "YAE", "EO", "E", "YEO", "YE", "O", "WA", "WAE", "OE", "YO", "U", "WEO", "WE", "WI", "YU", "EU", "YI", "I"];
+const JONG: [&str; 28] = ["", "G", "GG", "GS", "N", "NJ", "NH", "D", "L", "LG", "LM", "LB", "LS", "LT", "LP", "LH", "M", "B", "BS", "S", "SS", "NG", "J", "C", "K", "T", "P", "H"];
+
+// RFC 1345 style two character shorthands, as in vim
+const DIGRAPHS: [(&str, char); 64] = [
+    ("a:", 'ä'), ("o:", 'ö'), ("u:", 'ü'), ("A:", 'Ä'), ("O:", 'Ö'), ("U:", 'Ü'), ("ss", 'ß'),
+    ("e'", 'é'), ("e!", 'è'), ("e>", 'ê'), ("a'", 'á'), ("a!", 'à'), ("i'", 'í'), ("o'", 'ó'),
+    ("u'", 'ú'), ("n?", 'ñ'), ("c,", 'ç'), ("a*", 'α'), ("b*", 'β'), ("g*", 'γ'), ("d*", 'δ'),
+    ("e*", 'ε'), ("l*", 'λ'), ("m*", 'μ'), ("p*", 'π'), ("s*", 'σ'), ("t*", 'τ'), ("f*", 'φ'),
+    ("w*", 'ω'), ("D*", 'Δ'), ("S*", 'Σ'), ("W*", 'Ω'), ("->", '→'), ("<-", '←'), ("-!", '↑'),
+    ("-v", '↓'), ("=>", '⇒'), ("==", '⇔'), ("!=", '≠'), ("=<", '≤'), (">=", '≥'), ("?=", '≅'),
+    ("+-", '±'), ("*X", '×'), ("-:", '÷'), ("00", '∞'), ("RT", '√'), ("DE", '°'), ("My", 'µ'),
+    ("Co", '©'), ("Rg", '®'), ("TM", '™'), ("SE", '§'), ("PI", '¶'), ("Eu", '€'), ("Pd", '£'),
+    ("Ye", '¥'), ("Ct", '¢'), ("<<", '«'), (">>", '»'), ("..", '…'), ("-N", '–'), ("-M", '—'),
+    ("NS", '\u{a0}'),
+];
+
+// `U+XXXX` (or just the hex digits) or a digraph from DIGRAPHS
+pub fn parse(s: &str) -> anyhow::Result<char> {
+    let s = s.trim();
+    if let Some((_, c)) = DIGRAPHS.iter().find(|(d, _)| *d == s) {
+        return Ok(*c);
+    }
+    let hex = s.strip_prefix("U+").or(s.strip_prefix("u+")).unwrap_or(s);
+    u32::from_str_radix(hex, 16).ok()
+        .and_then(char::from_u32)
+        .ok_or(anyhow::anyhow!("{} is not a codepoint or digraph", s))
+}
+
+pub fn name(c: char) -> Option<String> {
+    let code = c as u32;
+    if code < 0x20 {
+        return Some(C0[code as usize].to_string());
+    }
+    if let Some((_, name)) = ASCII_PUNCT.iter().chain(SPECIAL.iter()).find(|(x, _)| *x == c) {
+        return Some(name.to_string());
+    }
+    match c {
+        'A'..='Z' => Some(format!("LATIN CAPITAL LETTER {}", c)),
+        'a'..='z' => Some(format!("LATIN SMALL LETTER {}", c.to_ascii_uppercase())),
+        '0'..='9' => Some(format!("DIGIT {}", DIGITS[c as usize - '0' as usize])),
+        '\u{ac00}'..='\u{d7a3}' => {
+            let s = code as usize - 0xac00;
+            Some(format!("HANGUL SYLLABLE {}{}{}", CHO[s / (21 * 28)], JUNG[s / 28 % 21], JONG[s % 28]))
+        }
+        '\u{4e00}'..='\u{9fff}' | '\u{3400}'..='\u{4dbf}' => Some(format!("CJK UNIFIED IDEOGRAPH-{:04X}", code)),
+        '\u{80}'..='\u{9f}' => Some("<control>".to_string()),
+        '\u{e000}'..='\u{f8ff}' => Some("<private use>".to_string()),
+        _ => None,
+    }
+}
+
+// One line summary shown by DescribeChar
+pub fn describe(c: char, width: usize) -> String {
+    let mut utf8 = [0; 4];
+    let bytes = c.encode_utf8(&mut utf8).bytes().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ");
+    let shown = if c.is_control() { ' ' } else { c };
+    format!(
+        "U+{:04X} '{}' {}, UTF-8 {}, width {}",
+        c as u32,
+        shown,
+        name(c).unwrap_or_else(|| "<unknown name>".to_string()),
+        bytes,
+        width,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn names() {
+        assert_eq!(name('A').unwrap(), "LATIN CAPITAL LETTER A");
+        assert_eq!(name('\u{200b}').unwrap(), "ZERO WIDTH SPACE");
+        assert_eq!(name('한').unwrap(), "HANGUL SYLLABLE HAN");
+        assert_eq!(name('\t').unwrap(), "CHARACTER TABULATION");
+        assert_eq!(describe('é', 1), "U+00E9 'é' <unknown name>, UTF-8 C3 A9, width 1");
+    }
+
+    #[test]
+    fn parses_codepoints_and_digraphs() {
+        assert_eq!(parse("U+00E9").unwrap(), 'é');
+        assert_eq!(parse("2192").unwrap(), '→');
+        assert_eq!(parse("->").unwrap(), '→');
+        assert_eq!(parse("a*").unwrap(), 'α');
+        assert!(parse("U+D800").is_err());
+        assert!(parse("zz").is_err());
+    }
+}
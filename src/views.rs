@@ -0,0 +1,86 @@
+// Bookmarks and SetLocal overrides of each file, kept in the project's
+// .zutto/views.json keyed by the path from the project root, and restored
+// when the file is opened again
+
+use std::{fs, path::{Path, PathBuf}};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::workspace::Workspace;
+
+pub const VIEWS_FILE: &str = ".zutto/views.json";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct View {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bookmarks: Vec<usize>,
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub settings: Map<String, Value>,
+}
+
+impl View {
+    fn is_empty(&self) -> bool {
+        self.bookmarks.is_empty() && self.settings.is_empty()
+    }
+}
+
+// The store of the project `path` is in and the key of `path` in it
+fn locate(path: &Path) -> Option<(PathBuf, String)> {
+    let path = path.canonicalize().ok()?;
+    let root = Workspace::detect(Some(&path)).ok()?.root;
+    let key = path.strip_prefix(&root).ok()?.to_string_lossy().to_string();
+    Some((root.join(VIEWS_FILE), key))
+}
+
+fn read(store: &Path) -> Map<String, Value> {
+    fs::read_to_string(store).ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+pub fn load(path: &Path) -> Option<View> {
+    let (store, key) = locate(path)?;
+    serde_json::from_value(read(&store).remove(&key)?).ok()
+}
+
+pub fn save(path: &Path, view: &View) -> Result<()> {
+    let Some((store, key)) = locate(path) else { return Ok(()) };
+    let mut views = read(&store);
+    if !set(&mut views, key, view) {
+        return Ok(());
+    }
+    if let Some(dir) = store.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&store, serde_json::to_string_pretty(&views)?)?;
+    Ok(())
+}
+
+// An empty view removes the entry; false when nothing changed
+fn set(views: &mut Map<String, Value>, key: String, view: &View) -> bool {
+    let old = views.get(&key).cloned();
+    if view.is_empty() {
+        views.remove(&key);
+    } else {
+        views.insert(key.clone(), serde_json::to_value(view).unwrap());
+    }
+    views.get(&key) != old.as_ref()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn set_views() {
+        let mut views = Map::new();
+        let view = View { bookmarks: vec![3, 10], ..Default::default() };
+        assert!(set(&mut views, "src/main.rs".to_string(), &view));
+        assert!(!set(&mut views, "src/main.rs".to_string(), &view));
+        assert_eq!(serde_json::to_string(&views).unwrap(), r#"{"src/main.rs":{"bookmarks":[3,10]}}"#);
+        assert!(set(&mut views, "src/main.rs".to_string(), &View::default()));
+        assert!(views.is_empty());
+    }
+}
@@ -0,0 +1,256 @@
+use std::{collections::HashMap, ops::Range, path::{Path, PathBuf}};
+
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::{key::{keymaps_from_value, Keymap}, KeymapState, Setting};
+
+pub const CONFIG_FILE: &str = ".zutto.json";
+const SETTINGS: &str = "settings/default.json";
+const KEYMAP: &str = "settings/keymap.json";
+
+// Contents of `.zutto.json`; `settings` and `keymap` are merged over the
+// files in settings/, so they only need the keys a project changes
+#[derive(Debug, Clone, Default, Deserialize)]
+struct Config {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    settings: Value,
+    #[serde(default)]
+    keymap: Value,
+}
+
+// Project root; finder, grep and session files are scoped to it
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    pub root: PathBuf,
+    config: Config,
+}
+
+impl Workspace {
+    pub fn open(root: &Path) -> Result<Self> {
+        let root = root.canonicalize()?;
+        let file = root.join(CONFIG_FILE);
+        let config = if file.is_file() {
+            serde_json::from_reader(std::fs::File::open(&file)?)
+                .map_err(|e| anyhow::anyhow!("{}: {}", file.display(), e))?
+        } else {
+            Config::default()
+        };
+        Ok(Self { root, config })
+    }
+
+    // Root for the path given on the command line: a directory is its own root,
+    // otherwise the nearest parent with `.zutto.json`, falling back to the cwd
+    pub fn detect(path: Option<&Path>) -> Result<Self> {
+        let cwd = std::env::current_dir()?;
+        if let Some(p) = path.filter(|p| p.is_dir()) {
+            return Self::open(p);
+        }
+        let start = match path.and_then(|p| p.parent()) {
+            Some(parent) if !parent.as_os_str().is_empty() => cwd.join(parent),
+            _ => cwd.clone(),
+        };
+        let root = start.ancestors()
+            .find(|dir| dir.join(CONFIG_FILE).is_file())
+            .map(Path::to_path_buf)
+            .unwrap_or(cwd);
+        Self::open(&root)
+    }
+
+    pub fn name(&self) -> String {
+        match &self.config.name {
+            Some(name) => name.clone(),
+            None => self.root.file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| self.root.display().to_string()),
+        }
+    }
+
+    pub fn setting(&self) -> Result<Setting> {
+        let mut json: Value = serde_json::from_reader(std::fs::File::open(SETTINGS)?)?;
+        merge(&mut json, &self.config.settings);
+        Ok(serde_json::from_value(json)?)
+    }
+
+    pub fn keymaps(&self, modal: bool) -> Result<HashMap<KeymapState, Keymap>> {
+        let mut json: Value = serde_json::from_reader(std::fs::File::open(KEYMAP)?)?;
+        merge(&mut json, &self.config.keymap);
+        keymaps_from_value(json, modal)
+    }
+
+    // Writes `settings` into the `settings` of `.zutto.json`, creating either
+    // when missing. The file is edited in place, so its other keys, order,
+    // spacing and indentation stay as they were
+    pub fn write_settings(&mut self, settings: &Map<String, Value>) -> Result<PathBuf> {
+        let file = self.root.join(CONFIG_FILE);
+        let text = match std::fs::read_to_string(&file) {
+            Ok(text) if !text.trim().is_empty() => text,
+            Ok(_) => "{}\n".to_string(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => "{}\n".to_string(),
+            Err(e) => return Err(e.into()),
+        };
+        let text = with_settings(&text, settings).map_err(|e| anyhow::anyhow!("{}: {}", file.display(), e))?;
+        self.config = serde_json::from_str(&text).map_err(|e| anyhow::anyhow!("{}: {}", file.display(), e))?;
+        std::fs::write(&file, text)?;
+        Ok(file)
+    }
+}
+
+fn with_settings(text: &str, settings: &Map<String, Value>) -> Result<String> {
+    let root = skip_space(text.as_bytes(), 0);
+    if text.as_bytes().get(root) != Some(&b'{') {
+        return Err(anyhow::anyhow!("not a JSON object"));
+    }
+    let mut text = text.to_string();
+    if !members(&text, root)?.0.iter().any(|(key, _)| key == "settings") {
+        text = set_member(&text, root, "settings", "{}")?;
+    }
+    let open = members(&text, root)?.0.into_iter().find(|(key, _)| key == "settings").unwrap().1.start;
+    if text.as_bytes()[open] != b'{' {
+        return Err(anyhow::anyhow!("settings is not an object"));
+    }
+    // Edits inside the object leave where it starts alone
+    for (key, value) in settings {
+        text = set_member(&text, open, key, &serde_json::to_string(value)?)?;
+    }
+    Ok(text)
+}
+
+// Replaces the value of `key` in the object starting at `open`, or adds it
+// after the last member, indented like it
+fn set_member(text: &str, open: usize, key: &str, value: &str) -> Result<String> {
+    let (members, close) = members(text, open)?;
+    if let Some((_, span)) = members.iter().find(|(k, _)| k == key) {
+        return Ok(format!("{}{}{}", &text[..span.start], value, &text[span.end..]));
+    }
+    let key = serde_json::to_string(key)?;
+    let indent_at = |i: usize| {
+        let line = text[..i].rfind('\n').map_or(0, |n| n + 1);
+        text[line..].chars().take_while(|c| *c == ' ' || *c == '\t').collect::<String>()
+    };
+    let Some((_, last)) = members.last() else {
+        let indent = indent_at(open);
+        return Ok(format!("{}{{\n{}    {}: {}\n{}}}{}", &text[..open], indent, key, value, indent, &text[close + 1..]));
+    };
+    let entry = match text[open..close].contains('\n') {
+        true => format!(",\n{}{}: {}", indent_at(last.start), key, value),
+        false => format!(", {}: {}", key, value),
+    };
+    Ok(format!("{}{}{}", &text[..last.end], entry, &text[last.end..]))
+}
+
+// Keys of an object with the byte range of each value
+type Members = Vec<(String, Range<usize>)>;
+
+// Members of the object starting at `open`, and where it closes
+fn members(text: &str, open: usize) -> Result<(Members, usize)> {
+    let b = text.as_bytes();
+    let broken = || anyhow::anyhow!("not valid JSON");
+    let mut members = Vec::new();
+    let mut i = skip_space(b, open + 1);
+    if b.get(i) == Some(&b'}') {
+        return Ok((members, i));
+    }
+    loop {
+        let key_end = value_end(b, i)?;
+        let key: String = serde_json::from_str(&text[i..key_end])?;
+        i = skip_space(b, key_end);
+        if b.get(i) != Some(&b':') {
+            return Err(broken());
+        }
+        let start = skip_space(b, i + 1);
+        let end = value_end(b, start)?;
+        members.push((key, start..end));
+        i = skip_space(b, end);
+        match b.get(i) {
+            Some(b',') => i = skip_space(b, i + 1),
+            Some(b'}') => return Ok((members, i)),
+            _ => return Err(broken()),
+        }
+    }
+}
+
+fn skip_space(b: &[u8], mut i: usize) -> usize {
+    while b.get(i).is_some_and(u8::is_ascii_whitespace) {
+        i += 1;
+    }
+    i
+}
+
+// Where the value starting at `start` ends
+fn value_end(b: &[u8], start: usize) -> Result<usize> {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut i = start;
+    while i < b.len() {
+        match b[i] {
+            b'\\' if in_string => i += 1,
+            b'"' if in_string => {
+                in_string = false;
+                if depth == 0 {
+                    return Ok(i + 1);
+                }
+            }
+            _ if in_string => (),
+            b'"' => in_string = true,
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' | b',' if depth == 0 => break,
+            b'}' | b']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(i + 1);
+                }
+            }
+            c if depth == 0 && c.is_ascii_whitespace() => break,
+            _ => (),
+        }
+        i += 1;
+    }
+    match depth == 0 && !in_string && i > start {
+        true => Ok(i),
+        false => Err(anyhow::anyhow!("not valid JSON")),
+    }
+}
+
+// Objects are merged key by key, anything else in `overlay` replaces `base`
+fn merge(base: &mut Value, overlay: &Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                merge(base.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+        (_, Value::Null) => {}
+        (base, overlay) => *base = overlay.clone(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn merge_objects() {
+        let mut base = serde_json::json!({"tab_size": 4, "formatters": {"rs": "rustfmt"}, "Normal": {"Quit": [["Esc"]]}});
+        let overlay = serde_json::json!({"tab_size": 2, "formatters": {"py": "black"}, "Normal": {"Quit": [["F10"]]}});
+        merge(&mut base, &overlay);
+        assert_eq!(base, serde_json::json!({"tab_size": 2, "formatters": {"rs": "rustfmt", "py": "black"}, "Normal": {"Quit": [["F10"]]}}));
+    }
+
+    #[test]
+    fn writes_settings_in_place() {
+        let settings = |v: Value| v.as_object().unwrap().clone();
+        let text = "{\n  \"name\": \"a, {b}\",\n  \"settings\": {\n    \"wrap\": false,\n    \"formatters\": {\"rs\": \"rustfmt\"}\n  },\n  \"extra\": [1, 2]\n}\n";
+        let written = with_settings(text, &settings(serde_json::json!({"wrap": true, "tab_size": 2}))).unwrap();
+        assert_eq!(written, "{\n  \"name\": \"a, {b}\",\n  \"settings\": {\n    \"wrap\": true,\n    \"formatters\": {\"rs\": \"rustfmt\"},\n    \"tab_size\": 2\n  },\n  \"extra\": [1, 2]\n}\n");
+        let written = with_settings("{\"name\": \"x\"}", &settings(serde_json::json!({"wrap": true}))).unwrap();
+        assert_eq!(written, "{\"name\": \"x\", \"settings\": {\n    \"wrap\": true\n}}");
+        let written = with_settings("{}\n", &settings(serde_json::json!({"wrap": true}))).unwrap();
+        assert_eq!(written, "{\n    \"settings\": {\n        \"wrap\": true\n    }\n}\n");
+        assert!(with_settings("{\"settings\": 1}", &Map::new()).is_err());
+        assert!(with_settings("[1]", &Map::new()).is_err());
+    }
+}